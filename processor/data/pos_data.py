@@ -2,14 +2,15 @@
 # https://github.com/tatuylonen/wiktextract/blob/master/wiktextract/parts_of_speech.py,
 # as it was on 2023-05-11. It generates a list of wiktextract-recognized parts
 # of speech using that code. Then there is original code tacked on at the end to
-# generate a rust file which defines a phf ordered set (cf.
-# https://docs.rs/phf/latest/phf/macro.phf_ordered_set.html), saving it as
-# src/pos_phf.rs.
+# save it as a json list in data/pos.json, which is compiled into the
+# processor.
 #
 # This should be called from the base directory of the repo. Usage:
 # 
 # $ python processor/data/pos_data.py
 
+import json
+
 ############### BEGIN wiktextract/parts_of_speech.py
 
 # Definitions of extracted parts of speech codes and a mapping from
@@ -423,13 +424,8 @@ PARTS_OF_SPEECH = set(x["pos"] for x in part_of_speech_map.values())
 ############### END OF wiktextract/parts_of_speech.py
 
 # Might want to revisit this to set custom abbreviations for different pos, or
-# exclude some.
-with open("processor/src/pos_phf.rs", "w") as f:
-    f.write("// This file was generated by data/phf/pos.py, see there for details.\n\n")
-    f.write("#![allow(clippy::all)]\n\n")
-    f.write("use phf::{phf_ordered_set, OrderedSet};\n\n")
-    f.write("pub(crate) static POS: OrderedSet<&'static str> = phf_ordered_set! {\n")
-    for pos in PARTS_OF_SPEECH:
-        f.write(f'    "{pos}",\n')
-    f.write("};\n")
-
+# exclude some. Parts of speech that wiktextract gives but are missing here are
+# still registered when processing, and reported with their counts.
+with open("processor/data/pos.json", "w", encoding="utf-8") as f:
+    json.dump(sorted(PARTS_OF_SPEECH), f, indent=2)
+    f.write("\n")
@@ -0,0 +1,75 @@
+# Regenerate languages.json and families.json from the current Wiktionary
+# modules, so that lang codes added on Wiktionary since they were last
+# generated (e.g. new etymology-only codes) are recognized rather than the
+# templates using them being skipped. This downloads the latest English
+# Wiktionary dump (unless one is given) and runs language_data.py on it.
+# Rebuild the processor afterwards, since the lang data is compiled into it.
+#
+# This should be run from the root directory of the repo.
+#
+# Usage:
+#
+# python processor/data/update_language_data.py [--dump enwiktionary_dump_file]
+
+import argparse
+import json
+import os
+import subprocess
+import sys
+
+import requests
+
+DUMP_URL = "https://dumps.wikimedia.org/enwiktionary/latest/enwiktionary-latest-pages-articles.xml.bz2"
+LANGUAGES_PATH = "processor/data/languages.json"
+FAMILIES_PATH = "processor/data/families.json"
+
+
+def download_dump(path: str) -> None:
+    print(f"Downloading {DUMP_URL} to {path}...")
+    with requests.get(DUMP_URL, stream=True) as r:
+        r.raise_for_status()
+        with open(path, "wb") as fout:
+            for chunk in r.iter_content(chunk_size=1 << 20):
+                fout.write(chunk)
+
+
+def codes(path: str) -> set:
+    if not os.path.exists(path):
+        return set()
+    with open(path, "r", encoding="utf-8") as fin:
+        return set(json.load(fin))
+
+
+if __name__ == "__main__":
+    parser = argparse.ArgumentParser(
+        description="Regenerate the language data from the latest Wiktionary modules"
+    )
+    parser.add_argument(
+        "--dump",
+        type=str,
+        help="Wiktionary xml dump file path. If missing, the latest dump is downloaded to data/",
+    )
+    args = parser.parse_args()
+    dump = args.dump
+    if dump is None:
+        dump = os.path.join("data", os.path.basename(DUMP_URL))
+        if not os.path.exists(dump):
+            download_dump(dump)
+    old_codes = codes(LANGUAGES_PATH)
+    subprocess.run(
+        [
+            sys.executable,
+            os.path.join(os.path.dirname(__file__), "language_data.py"),
+            dump,
+            "--languages",
+            LANGUAGES_PATH,
+            "--families",
+            FAMILIES_PATH,
+        ],
+        check=True,
+    )
+    new_codes = codes(LANGUAGES_PATH)
+    added = sorted(new_codes - old_codes)
+    removed = sorted(old_codes - new_codes)
+    print(f"Added {len(added)} lang codes: {', '.join(added)}")
+    print(f"Removed {len(removed)} lang codes: {', '.join(removed)}")
@@ -0,0 +1,343 @@
+//! Checkpoints of the intermediate state of processing, so that a run that
+//! crashes, e.g. hours into generating embeddings, can be resumed from the last
+//! completed stage rather than from scratch.
+
+use crate::{
+    embeddings,
+    input::{self, LineRange},
+    languages, HashMap, ProcessingOptions,
+};
+
+use std::{
+    fmt,
+    fs::{self, File},
+    hash::Hash,
+    io::{BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use anyhow::{bail, Context, Ok, Result};
+use clap::ValueEnum;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use indicatif::HumanDuration;
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer};
+
+/// The stages of processing after which a checkpoint is written, in order.
+#[derive(Clone, Copy)]
+pub(crate) enum Stage {
+    // the items and raw templates parsed from the wiktextract data
+    Parsed,
+    // the text hashes of the items' embeddings in the embeddings cache
+    Embedded,
+    // the items once the ety graph has been generated
+    Graph,
+}
+
+impl Stage {
+    const ALL: [Stage; 3] = [Stage::Parsed, Stage::Embedded, Stage::Graph];
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stage::Parsed => write!(f, "parsed"),
+            Stage::Embedded => write!(f, "embedded"),
+            Stage::Graph => write!(f, "graph"),
+        }
+    }
+}
+
+// What the checkpoints were made from, and the options and embeddings model
+// that the parsed items and their embeddings depend on, so that a run is not
+// resumed from the checkpoints of a different dump, or mixes items or
+// embeddings made under different options.
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Source {
+    wiktextract_path: PathBuf,
+    // unknown for stdin and URLs
+    wiktextract_len: Option<u64>,
+    #[serde(default)]
+    line_range: LineRange,
+    granularity: String,
+    multi_word_policy: String,
+    raw_glosses: bool,
+    // "from=into" pairs of lang codes
+    lang_merges: Vec<String>,
+    embeddings_model: String,
+}
+
+fn value_name(value: &impl ValueEnum) -> String {
+    value
+        .to_possible_value()
+        .expect("no skipped variants")
+        .get_name()
+        .to_string()
+}
+
+impl Source {
+    fn new(
+        wiktextract_path: &Path,
+        embeddings_config: &embeddings::Config,
+        options: ProcessingOptions,
+    ) -> Result<Self> {
+        let wiktextract_len = if input::is_file(wiktextract_path) {
+            Some(fs::metadata(wiktextract_path)?.len())
+        } else {
+            None
+        };
+        Ok(Self {
+            wiktextract_path: wiktextract_path.to_path_buf(),
+            wiktextract_len,
+            line_range: options.line_range,
+            granularity: value_name(&options.granularity),
+            multi_word_policy: value_name(&options.multi_word_policy),
+            raw_glosses: options.raw_glosses,
+            lang_merges: languages::lang_merges(),
+            embeddings_model: embeddings_config.cache_tree_name(),
+        })
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.wiktextract_path.display())?;
+        if let Some(len) = self.wiktextract_len {
+            write!(f, " ({len} bytes)")?;
+        }
+        if !self.line_range.is_all() {
+            write!(f, " (lines {:?})", self.line_range)?;
+        }
+        write!(
+            f,
+            " with granularity {}, multi-word policy {}, {}glosses, lang merges [{}] and embeddings model {}",
+            self.granularity,
+            self.multi_word_policy,
+            if self.raw_glosses { "raw " } else { "cleaned " },
+            self.lang_merges.join(", "),
+            self.embeddings_model
+        )
+    }
+}
+
+/// Where checkpoints are written, and whether to resume from those already
+/// there.
+pub struct Checkpoints {
+    dir: PathBuf,
+    resume: bool,
+}
+
+impl Checkpoints {
+    /// Write checkpoints to the directory `dir`. If `resume`, processing picks
+    /// up from the last checkpoint there, which must have been made from the
+    /// same `wiktextract_path`, with the same embeddings model and the same
+    /// options that parsing depends on (`options.line_range`, granularity,
+    /// multi-word policy, raw glosses and lang merges). Otherwise, any
+    /// checkpoints there are removed. Nothing else in `dir` is touched.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the directory cannot be prepared, or if `resume`
+    /// and its checkpoints were made from different wiktextract data or lines
+    /// of it, or with a different embeddings model or options.
+    pub fn new(
+        dir: &Path,
+        resume: bool,
+        wiktextract_path: &Path,
+        embeddings_config: &embeddings::Config,
+        options: ProcessingOptions,
+    ) -> Result<Self> {
+        let source = Source::new(wiktextract_path, embeddings_config, options)?;
+        let source_path = dir.join("source.json");
+        if resume && source_path.exists() {
+            let checkpointed: Source = serde_json::from_reader(File::open(&source_path)?)
+                .with_context(|| {
+                    format!(
+                        "reading {}, which may be from an older version; run without --resume",
+                        source_path.display()
+                    )
+                })?;
+            if checkpointed != source {
+                bail!(
+                    "the checkpoints in {} were made from {checkpointed}, not {source}",
+                    dir.display(),
+                );
+            }
+        } else {
+            fs::create_dir_all(dir)?;
+            for stage in Stage::ALL {
+                let path = Self::stage_path(dir, stage);
+                for path in [path.with_extension("tmp"), path] {
+                    if path.exists() {
+                        fs::remove_file(path)?;
+                    }
+                }
+            }
+            serde_json::to_writer(File::create(&source_path)?, &source)?;
+        }
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            resume,
+        })
+    }
+
+    fn stage_path(dir: &Path, stage: Stage) -> PathBuf {
+        dir.join(format!("{stage}.json.gz"))
+    }
+
+    fn path(&self, stage: Stage) -> PathBuf {
+        Self::stage_path(&self.dir, stage)
+    }
+
+    /// The checkpoint of `stage`, if resuming and there is one.
+    pub(crate) fn load<T: DeserializeOwned>(&self, stage: Stage) -> Result<Option<T>> {
+        let path = self.path(stage);
+        if !self.resume || !path.exists() {
+            return Ok(None);
+        }
+        let t = Instant::now();
+        println!("Resuming from the {stage} checkpoint {}...", path.display());
+        let reader = GzDecoder::new(BufReader::new(File::open(&path)?));
+        let value = serde_json::from_reader(reader)
+            .with_context(|| format!("deserializing checkpoint {}", path.display()))?;
+        println!("Finished. Took {}.", HumanDuration(t.elapsed()));
+        Ok(Some(value))
+    }
+
+    pub(crate) fn save<T: Serialize>(&self, stage: Stage, value: &T) -> Result<()> {
+        let t = Instant::now();
+        let path = self.path(stage);
+        println!("Writing the {stage} checkpoint to {}...", path.display());
+        // Write to a temporary file first, so that a crash while writing
+        // doesn't leave a truncated checkpoint to resume from.
+        let tmp_path = path.with_extension("tmp");
+        let mut writer = GzEncoder::new(
+            BufWriter::new(File::create(&tmp_path)?),
+            Compression::fast(),
+        );
+        serde_json::to_writer(&mut writer, value)?;
+        writer.finish()?.flush()?;
+        fs::rename(&tmp_path, &path)?;
+        println!("Finished. Took {}.", HumanDuration(t.elapsed()));
+        Ok(())
+    }
+}
+
+// Generic so that the items can be serialized by reference and deserialized
+// by value.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ItemsCheckpoint<P, I> {
+    pub(crate) string_pool: P,
+    pub(crate) items: I,
+}
+
+/// For maps with keys that can't be JSON object keys, e.g. `LangTerm`s: with
+/// `#[serde(with = "pairs")]`, the map is serialized as a list of pairs.
+pub(crate) mod pairs {
+    use super::{Deserialize, Deserializer, Hash, HashMap, Serialize, Serializer};
+
+    pub(crate) fn serialize<K: Serialize, V: Serialize, S: Serializer>(
+        map: &HashMap<K, V>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(map)
+    }
+
+    pub(crate) fn deserialize<'de, K, V, D>(deserializer: D) -> Result<HashMap<K, V>, D::Error>
+    where
+        K: Deserialize<'de> + Eq + Hash,
+        V: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        Ok(Vec::<(K, V)>::deserialize(deserializer)?
+            .into_iter()
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        items::{ItemId, Items},
+        languages::Lang,
+        string_pool::StringPool,
+        Granularity,
+    };
+
+    use std::{env, process, str::FromStr};
+
+    fn embeddings_config(model_name: &str) -> embeddings::Config {
+        embeddings::Config {
+            model_name: model_name.to_string(),
+            model_revision: embeddings::DEFAULT_MODEL_REVISION.to_string(),
+            batch_size: embeddings::DEFAULT_BATCH_SIZE,
+            cache_path: PathBuf::new(),
+            url: None,
+        }
+    }
+
+    #[test]
+    fn items_checkpoint_round_trip() {
+        let string_pool = StringPool::new();
+        let mut items = Items::new(ProcessingOptions::default()).unwrap();
+        let langterm = Lang::from_str("en")
+            .unwrap()
+            .new_langterm(&string_pool, "water");
+        items
+            .dupes
+            .insert(langterm, vec![ItemId::new(0), ItemId::new(1)]);
+        let json = serde_json::to_string(&ItemsCheckpoint {
+            string_pool: &string_pool,
+            items: &items,
+        })
+        .unwrap();
+        let checkpoint: ItemsCheckpoint<StringPool, Items> = serde_json::from_str(&json).unwrap();
+        assert_eq!(checkpoint.items.dupes, items.dupes);
+        assert_eq!(langterm.term.resolve(&checkpoint.string_pool), "water");
+    }
+
+    #[test]
+    fn checkpoints_are_only_resumed_with_the_same_options_and_model() {
+        let dir = env::temp_dir().join(format!("wety-checkpoints-{}", process::id()));
+        let wiktextract_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml");
+        let config = embeddings_config(embeddings::DEFAULT_MODEL);
+        let options = ProcessingOptions::default();
+        Checkpoints::new(&dir, false, &wiktextract_path, &config, options).unwrap();
+        assert!(Checkpoints::new(&dir, true, &wiktextract_path, &config, options).is_ok());
+        let by_pos = ProcessingOptions {
+            granularity: Granularity::Pos,
+            ..options
+        };
+        assert!(Checkpoints::new(&dir, true, &wiktextract_path, &config, by_pos).is_err());
+        let raw_glosses = ProcessingOptions {
+            raw_glosses: true,
+            ..options
+        };
+        assert!(Checkpoints::new(&dir, true, &wiktextract_path, &config, raw_glosses).is_err());
+        let other_model = embeddings_config("sentence-transformers/all-mpnet-base-v2");
+        assert!(Checkpoints::new(&dir, true, &wiktextract_path, &other_model, options).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn clearing_checkpoints_leaves_other_files() {
+        let dir = env::temp_dir().join(format!("wety-checkpoints-clear-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let other_path = dir.join("notes.txt");
+        fs::write(&other_path, "not a checkpoint").unwrap();
+        let wiktextract_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml");
+        let config = embeddings_config(embeddings::DEFAULT_MODEL);
+        let options = ProcessingOptions::default();
+        let checkpoints =
+            Checkpoints::new(&dir, false, &wiktextract_path, &config, options).unwrap();
+        checkpoints.save(Stage::Parsed, &"parsed").unwrap();
+        assert!(checkpoints.path(Stage::Parsed).exists());
+        Checkpoints::new(&dir, false, &wiktextract_path, &config, options).unwrap();
+        assert!(!checkpoints.path(Stage::Parsed).exists());
+        assert!(other_path.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
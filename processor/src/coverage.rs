@@ -0,0 +1,268 @@
+//! A dry run over the wiktextract data, counting how many of each template
+//! were parsed or skipped, by template name and lang. This shows which
+//! templates most need handling, without generating embeddings or the graph.
+//! A sample of the args of the skipped templates is also kept, so that there
+//! are examples at hand when writing a handler for them.
+
+use crate::{
+    descendants::process_json_desc_line_template,
+    etymology::process_json_ety_template,
+    input::LineRange,
+    languages::Lang,
+    root::{process_json_root_template, ROOT_TEMPLATES},
+    string_pool::StringPool,
+    wiktextract_json::{
+        wiktextract_lines, WiktextractJson, WiktextractJsonItem, WiktextractJsonValidStr,
+    },
+    HashMap,
+};
+
+use std::{fs::File, io::BufWriter, path::Path, time::Instant};
+
+use anyhow::{Ok, Result};
+use indicatif::HumanDuration;
+use itertools::Itertools;
+use serde::Serialize;
+use serde_json::{json, Value};
+use simd_json::{to_borrowed_value, ValueAccess};
+
+// How many templates with the most skips are printed after the dry run.
+const SUMMARY_LEN: usize = 20;
+
+// How many examples of each skipped template are kept.
+const MAX_EXAMPLES: usize = 10;
+
+#[derive(Serialize, Hash, Eq, PartialEq, Debug, Copy, Clone)]
+#[serde(rename_all = "camelCase")]
+enum Section {
+    Etymology,
+    Root,
+    Descendants,
+}
+
+#[derive(Default, Clone, Copy)]
+struct Counts {
+    parsed: usize,
+    skipped: usize,
+}
+
+impl Counts {
+    fn add(&mut self, other: Counts) {
+        self.parsed += other.parsed;
+        self.skipped += other.skipped;
+    }
+}
+
+#[derive(Default)]
+struct TemplateCoverage {
+    counts: HashMap<(Section, String, Lang), Counts>,
+    examples: HashMap<(Section, String), Vec<Value>>,
+}
+
+impl TemplateCoverage {
+    fn record(
+        &mut self,
+        section: Section,
+        json_item: &WiktextractJsonItem,
+        template: &WiktextractJson,
+        lang: Lang,
+        parsed: bool,
+    ) -> Result<()> {
+        let name = template.get_valid_str("name").unwrap_or_default();
+        let counts = self
+            .counts
+            .entry((section, name.to_string(), lang))
+            .or_default();
+        if parsed {
+            counts.parsed += 1;
+            return Ok(());
+        }
+        counts.skipped += 1;
+        let examples = self
+            .examples
+            .entry((section, name.to_string()))
+            .or_default();
+        if examples.len() < MAX_EXAMPLES {
+            examples.push(json!({
+                "lang": lang.code(),
+                "term": json_item.json.get_valid_str("word"),
+                "args": serde_json::to_value(template.get("args"))?,
+            }));
+        }
+        Ok(())
+    }
+
+    // Templates without a name are counted under "".
+    fn add_item(
+        &mut self,
+        string_pool: &StringPool,
+        json_item: &WiktextractJsonItem,
+    ) -> Result<()> {
+        let Some(lang) = json_item.get_lang() else {
+            return Ok(());
+        };
+        for template in json_item
+            .json
+            .get_array("etymology_templates")
+            .into_iter()
+            .flatten()
+        {
+            let name = template.get_valid_str("name").unwrap_or_default();
+            if ROOT_TEMPLATES.contains(&name) {
+                let parsed = process_json_root_template(string_pool, template, lang).is_some();
+                self.record(Section::Root, json_item, template, lang, parsed)?;
+            } else {
+                let parsed = process_json_ety_template(string_pool, template, lang).is_some();
+                self.record(Section::Etymology, json_item, template, lang, parsed)?;
+            }
+        }
+        for desc_line in json_item
+            .json
+            .get_array("descendants")
+            .into_iter()
+            .flatten()
+        {
+            let is_derivation = desc_line.get_array("tags").map_or(false, |tags| {
+                tags.iter().any(|tag| tag.as_str() == Some("derived"))
+            });
+            for template in desc_line.get_array("templates").into_iter().flatten() {
+                let parsed =
+                    process_json_desc_line_template(string_pool, template, is_derivation).is_some();
+                self.record(Section::Descendants, json_item, template, lang, parsed)?;
+            }
+        }
+        Ok(())
+    }
+
+    // The counts summed over langs, most skipped first.
+    fn by_name(&self) -> Vec<((Section, &str), Counts)> {
+        let mut by_name = HashMap::<(Section, &str), Counts>::default();
+        for ((section, name, _), &counts) in &self.counts {
+            by_name
+                .entry((*section, name.as_str()))
+                .or_default()
+                .add(counts);
+        }
+        by_name
+            .into_iter()
+            .sorted_by_key(|&((section, name), counts)| {
+                (std::cmp::Reverse(counts.skipped), section as u8, name)
+            })
+            .collect()
+    }
+
+    // The examples of the skipped templates, most skipped first.
+    fn examples_json(&self) -> Value {
+        self.by_name()
+            .into_iter()
+            .filter_map(|((section, name), counts)| {
+                let examples = self.examples.get(&(section, name.to_string()))?;
+                Some(json!({
+                    "section": section,
+                    "name": name,
+                    "skipped": counts.skipped,
+                    "examples": examples,
+                }))
+            })
+            .collect()
+    }
+
+    fn json(&self) -> Value {
+        let mut totals = HashMap::<Section, Counts>::default();
+        for ((section, _, _), &counts) in &self.counts {
+            totals.entry(*section).or_default().add(counts);
+        }
+        let counts_json =
+            |counts: Counts| json!({"parsed": counts.parsed, "skipped": counts.skipped});
+        json!({
+            "totals": totals
+                .into_iter()
+                .sorted_by_key(|&(section, _)| section as u8)
+                .map(|(section, counts)| json!({"section": section, "counts": counts_json(counts)}))
+                .collect_vec(),
+            "templates": self
+                .by_name()
+                .into_iter()
+                .map(|((section, name), counts)| {
+                    json!({"section": section, "name": name, "counts": counts_json(counts)})
+                })
+                .collect_vec(),
+            "templateLangs": self
+                .counts
+                .iter()
+                .sorted_by_key(|&((section, name, lang), counts)| {
+                    (std::cmp::Reverse(counts.skipped), *section as u8, name, lang.code())
+                })
+                .map(|((section, name, lang), &counts)| {
+                    json!({
+                        "section": section,
+                        "name": name,
+                        "lang": lang.code(),
+                        "counts": counts_json(counts),
+                    })
+                })
+                .collect_vec(),
+        })
+    }
+}
+
+/// Parse the lines in `line_range` of the wiktextract data, counting how many
+/// etymology, root and descendants templates were parsed or skipped, by
+/// template name and lang.
+/// The counts are written as JSON to `report_path`, and a summary of the
+/// templates with the most skips is printed. A sample of the args of each
+/// skipped template, with the lang and term of the item it was found in, is
+/// written as JSON to `examples_path`.
+///
+/// # Errors
+///
+/// Will return `Err` if any unexpected issue arises parsing the wiktextract
+/// data or writing the report.
+pub fn report_template_coverage(
+    wiktextract_path: &Path,
+    line_range: LineRange,
+    report_path: &Path,
+    examples_path: &Path,
+) -> Result<()> {
+    let t = Instant::now();
+    println!(
+        "Counting parsed and skipped templates in {}...",
+        wiktextract_path.display()
+    );
+    let string_pool = StringPool::new();
+    let mut coverage = TemplateCoverage::default();
+    let mut malformed = 0;
+    for (_, mut line) in line_range.numbered(wiktextract_lines(wiktextract_path)?) {
+        let Some(json) = to_borrowed_value(&mut line).ok() else {
+            malformed += 1;
+            continue;
+        };
+        if !json.contains_key("redirect") {
+            coverage.add_item(&string_pool, &WiktextractJsonItem { json })?;
+        }
+    }
+    println!("Finished. Took {}.", HumanDuration(t.elapsed()));
+    if malformed > 0 {
+        println!("Skipped {malformed} lines that aren't valid JSON.");
+    }
+    println!("Templates with the most skips:");
+    for ((section, name), counts) in coverage.by_name().into_iter().take(SUMMARY_LEN) {
+        println!(
+            "  {section:?} {name:?}: {} skipped, {} parsed",
+            counts.skipped, counts.parsed
+        );
+    }
+    println!(
+        "Writing template coverage report to {}...",
+        report_path.display()
+    );
+    let writer = BufWriter::new(File::create(report_path)?);
+    serde_json::to_writer_pretty(writer, &coverage.json())?;
+    println!(
+        "Writing examples of skipped templates to {}...",
+        examples_path.display()
+    );
+    let writer = BufWriter::new(File::create(examples_path)?);
+    serde_json::to_writer_pretty(writer, &coverage.examples_json())?;
+    Ok(())
+}
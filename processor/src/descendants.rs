@@ -1,23 +1,25 @@
 use crate::{
     embeddings::{Embeddings, ItemEmbedding},
+    ety_graph::{EdgeProvenance, EdgeSource},
     etymology_templates::EtyMode,
     gloss::Gloss,
     items::{ItemId, Items, Retrieval},
     langterm::{LangTerm, Term},
     languages::Lang,
     progress_bar,
-    string_pool::StringPool,
+    string_pool::{StringPool, Symbol},
     wiktextract_json::{WiktextractJson, WiktextractJsonItem, WiktextractJsonValidStr},
-    HashSet,
+    HashMap, HashSet,
 };
 
 use std::{mem, str::FromStr};
 
-use anyhow::{Ok, Result};
+use anyhow::{ensure, Ok, Result};
 use itertools::izip;
+use serde::{Deserialize, Serialize};
 use simd_json::ValueAccess;
 
-#[derive(Hash, Eq, PartialEq, Debug)]
+#[derive(Hash, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub(crate) struct RawDescendants {
     pub(crate) lines: Box<[RawDescLine]>,
 }
@@ -30,13 +32,13 @@ impl From<Vec<RawDescLine>> for RawDescendants {
     }
 }
 
-#[derive(Hash, Eq, PartialEq, Debug)]
+#[derive(Clone, Hash, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub(crate) struct RawDescLine {
     depth: u8,
     kind: RawDescLineKind,
 }
 
-#[derive(Hash, Eq, PartialEq, Debug)]
+#[derive(Clone, Hash, Eq, PartialEq, Debug, Serialize, Deserialize)]
 enum RawDescLineKind {
     Desc { desc: RawDesc },
     // e.g. {{desc|osp|-}}, {{desc|itc-pro|}},
@@ -51,14 +53,38 @@ enum RawDescLineKind {
 
 // some combination of desc, l, desctree templates that together provide one or
 // more descendant lang, term, mode combos
-#[derive(Hash, Eq, PartialEq, Debug)]
+#[derive(Clone, Hash, Eq, PartialEq, Debug, Serialize, Deserialize)]
 struct RawDesc {
     lang: Lang,
     terms: Box<[Term]>,
     modes: Box<[EtyMode]>,
+    // the name of the first template on the line that was parsed
+    #[serde(default)]
+    name: Option<Symbol>,
+    // whether each term is marked uncertain, e.g. with unc=1
+    #[serde(default)]
+    uncertain: Box<[bool]>,
 }
+
+// How much the confidence of a link to a descendant marked uncertain is scaled
+// by. It is never lowered below the similarity threshold, so the link is kept.
+const UNCERTAIN_CONFIDENCE_FACTOR: f32 = 0.5;
+
+// The lang of the descendant terms given by a template, with the mode of each
+// and whether each is marked uncertain.
+type DescTemplateTerms = (Lang, Vec<Term>, Vec<EtyMode>, Vec<bool>);
+
+// Link templates specific to a lang, used in place of {{l}} in descendants
+// lines for e.g. CJK langs, with the code of the lang they imply.
+const LANG_LINK_TEMPLATES: &[(&str, &str)] = &[
+    ("ja-r", "ja"),
+    ("ja-l", "ja"),
+    ("zh-l", "zh"),
+    ("ko-l", "ko"),
+    ("th-l", "th"),
+];
 impl WiktextractJsonItem<'_> {
-    pub(crate) fn get_descendants(&self, string_pool: &mut StringPool) -> Option<RawDescendants> {
+    pub(crate) fn get_descendants(&self, string_pool: &StringPool) -> Option<RawDescendants> {
         let json_descendants = self.json.get_array("descendants")?;
         let mut descendants: Vec<RawDescLine> = vec![];
         for desc_line in json_descendants {
@@ -68,10 +94,75 @@ impl WiktextractJsonItem<'_> {
         (!descendants.is_empty()).then_some(())?;
         Some(descendants.into())
     }
+
+    // For the line errors. A template of a kind that is handled but that fails
+    // to parse would otherwise silently leave its line as Other, or lose its
+    // terms from a Desc line.
+    pub(crate) fn ensure_desc_templates_parse(&self, string_pool: &StringPool) -> Result<()> {
+        for desc_line in self.json.get_array("descendants").into_iter().flatten() {
+            let is_derivation = desc_line.get_array("tags").map_or(false, |tags| {
+                tags.iter().any(|tag| tag.as_str() == Some("derived"))
+            });
+            for template in desc_templates(desc_line) {
+                ensure!(
+                    process_json_desc_line_template(string_pool, template, is_derivation).is_some(),
+                    "could not parse descendants template {template}"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    // For the line errors. Lang-specific link templates imply a known lang.
+    pub(crate) fn ensure_known_desc_template_langs(&self) -> Result<()> {
+        for desc_line in self.json.get_array("descendants").into_iter().flatten() {
+            for template in desc_templates(desc_line) {
+                if let Some(code) = template
+                    .get("args")
+                    .and_then(|args| args.get_valid_str("1"))
+                    .filter(|_| !is_lang_link_template(template))
+                {
+                    ensure!(
+                        Lang::from_str(code).is_ok(),
+                        "unknown lang code {code} in descendants template {template}"
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_lang_link_template(template: &WiktextractJson) -> bool {
+    template.get_valid_str("name").is_some_and(|name| {
+        LANG_LINK_TEMPLATES
+            .iter()
+            .any(|&(link_name, _)| link_name == name)
+    })
+}
+
+// The templates on the line of a kind that is handled.
+fn desc_templates<'a>(
+    desc_line: &'a WiktextractJson<'a>,
+) -> impl Iterator<Item = &'a WiktextractJson<'a>> {
+    desc_line
+        .get_array("templates")
+        .into_iter()
+        .flatten()
+        .filter(|template| {
+            template.get("args").is_some()
+                && (is_lang_link_template(template)
+                    || template.get_valid_str("name").is_some_and(|name| {
+                        matches!(
+                            name,
+                            "desc" | "descendant" | "l" | "link" | "desctree" | "descendants tree"
+                        )
+                    }))
+        })
 }
 
 fn process_json_desc_line(
-    string_pool: &mut StringPool,
+    string_pool: &StringPool,
     desc_line: &WiktextractJson,
 ) -> Option<RawDescLine> {
     let depth = desc_line.get_u8("depth")?;
@@ -104,20 +195,62 @@ fn process_json_desc_line(
     });
     let mut lang = Lang::from_str("en").unwrap(); // dummy assignment
     let (mut langs, mut terms, mut modes) = (HashSet::default(), vec![], vec![]);
+    let mut uncertain = vec![];
+    let mut name = None;
+    // The lang, mode and uncertainty given by a {{desc}} without a term, e.g.
+    // {{desc|ja|-|bor=1}}, which are those of the terms of any lang-specific
+    // link templates after it, e.g. {{ja-r|漢字|かんじ}}.
+    let mut bare_desc = None;
     for template in templates {
-        if let Some((template_lang, template_terms, template_modes)) =
-            process_json_desc_line_template(string_pool, template, is_derivation)
+        if let Some((
+            mut template_lang,
+            template_terms,
+            mut template_modes,
+            mut template_uncertain,
+        )) = process_json_desc_line_template(string_pool, template, is_derivation)
         {
+            let template_name = template.get_valid_str("name").unwrap_or_default();
+            if matches!(template_name, "desc" | "descendant")
+                && template_terms.is_empty()
+                && let Some(args) = template.get("args")
+            {
+                bare_desc = Some((
+                    template_lang,
+                    get_desc_mode(args, 1),
+                    has_desc_flag(args, "unc", 1),
+                ));
+            } else if LANG_LINK_TEMPLATES
+                .iter()
+                .any(|&(link_name, _)| link_name == template_name)
+                && let Some((desc_lang, desc_mode, desc_uncertain)) = bare_desc
+            {
+                template_lang = desc_lang;
+                template_modes.fill(desc_mode);
+                template_uncertain.fill(desc_uncertain);
+            }
+            if name.is_none() {
+                name = template
+                    .get_valid_str("name")
+                    .map(|name| string_pool.get_or_intern(name));
+            }
             lang = template_lang;
             langs.insert(template_lang);
             terms.extend(template_terms);
             modes.extend(template_modes);
+            uncertain.extend(template_uncertain);
         }
     }
     if langs.len() == 1 && !terms.is_empty() && terms.len() == modes.len() {
         let terms = terms.into_boxed_slice();
         let modes = modes.into_boxed_slice();
-        let desc = RawDesc { lang, terms, modes };
+        let uncertain = uncertain.into_boxed_slice();
+        let desc = RawDesc {
+            lang,
+            terms,
+            modes,
+            name,
+            uncertain,
+        };
         let kind = RawDescLineKind::Desc { desc };
         return Some(RawDescLine { depth, kind });
     }
@@ -127,11 +260,11 @@ fn process_json_desc_line(
     })
 }
 
-fn process_json_desc_line_template(
-    string_pool: &mut StringPool,
+pub(crate) fn process_json_desc_line_template(
+    string_pool: &StringPool,
     template: &WiktextractJson,
     is_derivation: bool,
-) -> Option<(Lang, Vec<Term>, Vec<EtyMode>)> {
+) -> Option<DescTemplateTerms> {
     let name = template.get_valid_str("name")?;
     let args = template.get("args")?;
     match name {
@@ -140,19 +273,24 @@ fn process_json_desc_line_template(
         "desctree" | "descendants tree" => {
             process_json_desc_line_desctree_template(string_pool, args)
         }
-        _ => None,
+        _ => {
+            let &(_, lang) = LANG_LINK_TEMPLATES
+                .iter()
+                .find(|&&(link_name, _)| link_name == name)?;
+            process_json_desc_line_lang_link_template(string_pool, args, lang, is_derivation)
+        }
     }
 }
 
 // cf. https://en.wiktionary.org/wiki/Template:descendant
 fn process_json_desc_line_desc_template(
-    string_pool: &mut StringPool,
+    string_pool: &StringPool,
     args: &WiktextractJson,
-) -> Option<(Lang, Vec<Term>, Vec<EtyMode>)> {
+) -> Option<DescTemplateTerms> {
     let lang = args.get_valid_str("1")?;
     let lang = Lang::from_str(lang).ok()?;
 
-    let (mut terms, mut modes) = (vec![], vec![]);
+    let (mut terms, mut modes, mut uncertain) = (vec![], vec![], vec![]);
     // Confusingly, "2" corresponds to the first term and "alt" to its alt,
     // while "3" corresponds to the second term, and "alt2" to its alt, etc.
     let mut n = 1;
@@ -164,21 +302,21 @@ fn process_json_desc_line_desc_template(
         .map(|term| Term::new(string_pool, term))
     {
         terms.push(term);
-        let mode = get_desc_mode(args, n);
-        modes.push(mode);
+        modes.push(get_desc_mode(args, n));
+        uncertain.push(has_desc_flag(args, "unc", n));
         n += 1;
         n_str = (n + 1).to_string();
         n_alt_str = format!("alt{n}");
     }
-    Some((lang, terms, modes))
+    Some((lang, terms, modes, uncertain))
 }
 
 // cf. https://en.wiktionary.org/wiki/Template:link
 fn process_json_desc_line_l_template(
-    string_pool: &mut StringPool,
+    string_pool: &StringPool,
     args: &WiktextractJson,
     is_derivation: bool,
-) -> Option<(Lang, Vec<Term>, Vec<EtyMode>)> {
+) -> Option<DescTemplateTerms> {
     let lang = args.get_valid_str("1")?;
     let lang = Lang::from_str(lang).ok()?;
     let term = args
@@ -199,7 +337,34 @@ fn process_json_desc_line_l_template(
     } else {
         EtyMode::Derived
     };
-    Some((lang, vec![term], vec![mode]))
+    Some((lang, vec![term], vec![mode], vec![false]))
+}
+
+// cf. https://en.wiktionary.org/wiki/Template:ja-r,
+// https://en.wiktionary.org/wiki/Template:zh-l, etc. The term is the first
+// arg, and the lang is implied by the template (or given by a {{desc}} before
+// it on the line, see process_json_desc_line). {{zh-l}} may give the
+// traditional and simplified forms separated by a slash, of which the first is
+// taken, and {{ja-r}} may split the term into parts with "%".
+fn process_json_desc_line_lang_link_template(
+    string_pool: &StringPool,
+    args: &WiktextractJson,
+    lang: &str,
+    is_derivation: bool,
+) -> Option<DescTemplateTerms> {
+    let lang = Lang::from_str(lang).ok()?;
+    let term = args.get_valid_term("1")?;
+    let term = term.split('/').next()?.replace('%', "");
+    let term = term.trim();
+    (!term.is_empty()).then_some(())?;
+    let term = Term::new(string_pool, term);
+    // As for {{l}}, see above.
+    let mode = if is_derivation {
+        EtyMode::MorphologicalDerivation
+    } else {
+        EtyMode::Derived
+    };
+    Some((lang, vec![term], vec![mode], vec![false]))
 }
 
 // cf. https://en.wiktionary.org/wiki/Template:descendants_tree While
@@ -209,9 +374,9 @@ fn process_json_desc_line_l_template(
 // descendant generating the tree, until we find that listing multiple has
 // any currency (how would that even work?).
 fn process_json_desc_line_desctree_template(
-    string_pool: &mut StringPool,
+    string_pool: &StringPool,
     args: &WiktextractJson,
-) -> Option<(Lang, Vec<Term>, Vec<EtyMode>)> {
+) -> Option<DescTemplateTerms> {
     let lang = args.get_valid_str("1")?;
     let lang = Lang::from_str(lang).ok()?;
     let term = args
@@ -219,19 +384,35 @@ fn process_json_desc_line_desctree_template(
         .map(|term| Term::new(string_pool, term))?;
     // It's conceivable that another mode could be specified by template arg
     let mode = get_desc_mode(args, 1);
-    Some((lang, vec![term], vec![mode]))
+    let uncertain = has_desc_flag(args, "unc", 1);
+    Some((lang, vec![term], vec![mode], vec![uncertain]))
+}
+
+// Whether a flag is given for the nth term. Each term has its own flags, e.g.
+// "bor2" for the second term. Those of the first term can be given without
+// the number, e.g. "bor", which doesn't apply to the other terms.
+fn has_desc_flag(args: &WiktextractJson, flag: &str, n: usize) -> bool {
+    (n == 1 && args.contains_key(flag)) || args.contains_key(format!("{flag}{n}").as_str())
 }
 
 fn get_desc_mode(args: &WiktextractJson, n: usize) -> EtyMode {
-    // what about "der"?
     const MODES: [&str; 7] = ["bor", "lbor", "slb", "clq", "pclq", "sml", "translit"];
     const DEFAULT: EtyMode = EtyMode::Inherited;
+    // der=1 marks a term derived from the parent rather than inherited. Along
+    // with a borrowing flag, e.g. for a term borrowed and then derived from,
+    // there is no one mode for the link, so it is just "derived".
+    let is_derived = has_desc_flag(args, "der", n);
     for mode in MODES {
-        let mode_n = format!("{mode}{n}");
-        if args.contains_key(mode) || args.contains_key(mode_n.as_str()) {
+        if has_desc_flag(args, mode, n) {
+            if is_derived {
+                return EtyMode::Derived;
+            }
             return mode.parse().ok().unwrap_or(DEFAULT);
         }
     }
+    if is_derived {
+        return EtyMode::MorphologicalDerivation;
+    }
     DEFAULT
 }
 
@@ -287,6 +468,115 @@ impl Ancestors<ItemId> {
 }
 
 impl Items {
+    // Descendants lines often give an etymology-only variety, e.g. a dialect,
+    // whose terms have their entries under the variety's regular lang. A term
+    // is looked up in the regular lang if it has an entry there, and is
+    // otherwise kept in the variety.
+    fn desc_langterm(&self, lang: Lang, term: Term) -> LangTerm {
+        let non_ety = lang.ety2non();
+        if non_ety != lang {
+            let langterm = LangTerm::new(non_ety, term);
+            if self.get_dupes(langterm).is_some() {
+                return langterm;
+            }
+        }
+        LangTerm::new(lang, term)
+    }
+
+    // The item that a {{desctree}} line links to, if it is unambiguous.
+    fn desctree_target(&self, string_pool: &StringPool, line: &RawDescLine) -> Option<ItemId> {
+        let RawDescLineKind::Desc { desc } = &line.kind else {
+            return None;
+        };
+        let name = string_pool.resolve(desc.name?);
+        if !matches!(name, "desctree" | "descendants tree") || desc.terms.len() != 1 {
+            return None;
+        }
+        match self.get_dupes(self.desc_langterm(desc.lang, desc.terms[0]))?[..] {
+            [target] => Some(target),
+            _ => None,
+        }
+    }
+
+    // The descendants lines of the last item in `path`, with the trees of any
+    // {{desctree}} lines grafted under them, or None if there were none to
+    // graft. The items in `path` aren't grafted again, in case of cycles, so
+    // also returns whether any weren't. Otherwise, the lines don't depend on
+    // `path`, so are kept in `expanded`, and each item is expanded only once
+    // however many trees it is grafted into.
+    fn expanded_desc_lines(
+        &self,
+        string_pool: &StringPool,
+        path: &mut Vec<ItemId>,
+        expanded: &mut HashMap<ItemId, Option<Vec<RawDescLine>>>,
+    ) -> (Option<Vec<RawDescLine>>, bool) {
+        let Some(&item) = path.last() else {
+            return (None, false);
+        };
+        if let Some(lines) = expanded.get(&item) {
+            return (lines.clone(), false);
+        }
+        let Some(raw_descendants) = self.raw_templates.desc.get(&item) else {
+            return (None, false);
+        };
+        let mut lines = vec![];
+        let mut grafted = false;
+        let mut cut = false;
+        for (i, line) in raw_descendants.lines.iter().enumerate() {
+            lines.push(line.clone());
+            // If the lines after are deeper, the tree was already expanded.
+            if let Some(target) = self.desctree_target(string_pool, line)
+                && raw_descendants
+                    .lines
+                    .get(i + 1)
+                    .map_or(true, |next| next.depth <= line.depth)
+            {
+                if path.contains(&target) {
+                    cut = true;
+                    continue;
+                }
+                path.push(target);
+                let (target_lines, target_cut) =
+                    self.expanded_desc_lines(string_pool, path, expanded);
+                path.pop();
+                cut |= target_cut;
+                let target_lines = target_lines
+                    .or_else(|| Some(self.raw_templates.desc.get(&target)?.lines.to_vec()));
+                for mut target_line in target_lines.into_iter().flatten() {
+                    target_line.depth = target_line.depth.saturating_add(line.depth);
+                    lines.push(target_line);
+                    grafted = true;
+                }
+            }
+        }
+        let lines = grafted.then_some(lines);
+        if !cut {
+            expanded.insert(item, lines.clone());
+        }
+        (lines, cut)
+    }
+
+    // {{desctree}} transcludes the descendants tree of the term it links to,
+    // but is only given as the one line in the wiktextract data. So the lines
+    // of the linked item's own descendants are grafted under it, recursively,
+    // e.g. so that the Proto-West Germanic tree includes everything under an
+    // Old English {{desctree}}.
+    pub(crate) fn expand_desctrees(&mut self, string_pool: &StringPool) {
+        let mut memo = HashMap::default();
+        let expanded = self
+            .raw_templates
+            .desc
+            .keys()
+            .filter_map(|&item| {
+                let (lines, _) = self.expanded_desc_lines(string_pool, &mut vec![item], &mut memo);
+                Some((item, lines?))
+            })
+            .collect::<Vec<_>>();
+        for (item, lines) in expanded {
+            self.raw_templates.desc.insert(item, lines.into());
+        }
+    }
+
     pub(crate) fn get_desc_items_needing_embedding(
         &self,
         item: ItemId,
@@ -300,7 +590,7 @@ impl Items {
             let mut has_imputed_child = false;
             if let RawDescLineKind::Desc { desc } = &line.kind {
                 for (i, &term) in desc.terms.iter().enumerate() {
-                    let desc_langterm = LangTerm::new(desc.lang, term);
+                    let desc_langterm = self.desc_langterm(desc.lang, term);
                     if let Some(desc_items) = self.get_dupes(desc_langterm) {
                         if i == 0 {
                             possible_ancestors.add(desc_items, line.depth);
@@ -326,12 +616,18 @@ impl Items {
         items_needing_embedding
     }
 
-    pub(crate) fn process_raw_descendants(&mut self, embeddings: &Embeddings) -> Result<()> {
+    pub(crate) fn process_raw_descendants(
+        &mut self,
+        string_pool: &StringPool,
+        embeddings: &Embeddings,
+    ) -> Result<()> {
         let n = self.raw_templates.desc.len();
         let pb = progress_bar(n, "Processing descendants")?;
         let raw_templates_desc = mem::take(&mut self.raw_templates.desc);
+        let item_lines = self.item_lines();
         for (item_id, desc) in raw_templates_desc {
-            self.process_item_raw_descendants(embeddings, item_id, &desc)?;
+            let line = item_lines.get(&item_id).copied();
+            self.process_item_raw_descendants(string_pool, embeddings, item_id, line, &desc)?;
             pb.inc(1);
         }
 
@@ -341,8 +637,10 @@ impl Items {
 
     pub(crate) fn process_item_raw_descendants(
         &mut self,
+        string_pool: &StringPool,
         embeddings: &Embeddings,
         item: ItemId,
+        item_line: Option<usize>,
         raw_descendants: &RawDescendants,
     ) -> Result<()> {
         let item_lang = self.get(item).lang();
@@ -354,7 +652,9 @@ impl Items {
                     if desc.terms.is_empty() || desc.terms.len() != desc.modes.len() {
                         continue;
                     }
-                    let (mut desc_items, mut confidences, mut modes) = (vec![], vec![], vec![]);
+                    let (mut desc_items, mut confidences, mut modes, mut varieties) =
+                        (vec![], vec![], vec![], vec![]);
+                    let threshold = self.options.similarity_threshold;
                     for (i, (&term, &mode)) in desc.terms.iter().zip(desc.modes.iter()).enumerate()
                     {
                         // Sometimes a within-language compound is listed as a
@@ -371,28 +671,50 @@ impl Items {
                         if desc.terms.len() > 1 && desc.lang == item_lang {
                             continue 'lines;
                         }
-                        let langterm = LangTerm::new(desc.lang, term);
-                        let Retrieval {
+                        let langterm = self.desc_langterm(desc.lang, term);
+                        let Some(Retrieval {
                             item_id: desc_item,
                             confidence,
-                        } = self.get_or_impute_item(
+                        }) = self.get_or_impute_item(
+                            string_pool,
                             embeddings,
                             &ancestors.embeddings(self, embeddings)?,
                             item,
+                            parent,
                             langterm,
-                        )?;
+                            false,
+                        )?
+                        else {
+                            continue;
+                        };
                         // Only use the first term in a multi-term desc line as
                         // the ancestor for any deeper-nested lines below it.
                         if i == 0 {
                             ancestors.add(&desc_item, line.depth);
                         }
                         desc_items.push(desc_item);
-                        confidences.push(confidence);
+                        if desc.uncertain.get(i).copied().unwrap_or(false) {
+                            confidences
+                                .push((confidence * UNCERTAIN_CONFIDENCE_FACTOR).max(threshold));
+                        } else {
+                            confidences.push(confidence);
+                        }
                         modes.push(mode);
+                        varieties.push((langterm.lang != desc.lang).then_some(desc.lang));
                     }
-                    for (desc_item, confidence, mode) in izip!(desc_items, confidences, modes) {
-                        self.graph
-                            .add_ety(desc_item, mode, Some(0), &[parent], &[confidence]);
+                    let provenance =
+                        EdgeProvenance::new(EdgeSource::Descendants, desc.name, item_line);
+                    for (desc_item, confidence, mode, variety) in
+                        izip!(desc_items, confidences, modes, varieties)
+                    {
+                        self.add_ety(
+                            desc_item,
+                            mode,
+                            Some(0),
+                            &[parent],
+                            &[confidence],
+                            provenance.with_variety(variety),
+                        );
                     }
                 }
                 // Might want to do something for the other cases in the future,
@@ -413,3 +735,142 @@ impl Items {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{items::test_item, ProcessingOptions};
+
+    use simd_json::json;
+
+    #[test]
+    fn desc_der_and_unc_flags() {
+        let string_pool = StringPool::new();
+        let args: WiktextractJson = json!({
+            "1": "en", "2": "foo", "3": "bar", "4": "baz", "der2": "1", "bor3": "1", "der3": "1",
+            "unc2": "1"
+        })
+        .into();
+        let (_, _, modes, uncertain) =
+            process_json_desc_line_desc_template(&string_pool, &args).unwrap();
+        assert_eq!(
+            modes,
+            [
+                EtyMode::Inherited,
+                EtyMode::MorphologicalDerivation,
+                EtyMode::Derived
+            ]
+        );
+        assert_eq!(uncertain, [false, true, false]);
+    }
+
+    #[test]
+    fn desc_flags_apply_per_term() {
+        let string_pool = StringPool::new();
+        let args: WiktextractJson = json!({
+            "1": "en", "2": "foo", "3": "bar", "4": "baz", "bor": "1", "lbor2": "1", "clq3": "1"
+        })
+        .into();
+        let (_, _, modes, _) = process_json_desc_line_desc_template(&string_pool, &args).unwrap();
+        assert_eq!(
+            modes,
+            [
+                EtyMode::Borrowed,
+                EtyMode::LearnedBorrowing,
+                EtyMode::Calque
+            ]
+        );
+    }
+
+    #[test]
+    fn desc_lang_link_templates() {
+        let string_pool = StringPool::new();
+        let line: WiktextractJson = json!({
+            "depth": 1,
+            "templates": [
+                {"name": "desc", "args": {"1": "ja", "2": "-", "bor": "1"}},
+                {"name": "ja-r", "args": {"1": "漢%字", "2": "かん%じ"}}
+            ]
+        })
+        .into();
+        let RawDescLineKind::Desc { desc } =
+            process_json_desc_line(&string_pool, &line).unwrap().kind
+        else {
+            panic!("expected a desc line");
+        };
+        assert_eq!(desc.lang, Lang::from_str("ja").unwrap());
+        assert_eq!(desc.terms[0].resolve(&string_pool), "漢字");
+        assert_eq!(&*desc.modes, [EtyMode::Borrowed]);
+
+        let args: WiktextractJson = json!({"1": "漢字/汉字"}).into();
+        let (lang, terms, ..) =
+            process_json_desc_line_lang_link_template(&string_pool, &args, "zh", false).unwrap();
+        assert_eq!(lang, Lang::from_str("zh").unwrap());
+        assert_eq!(terms[0].resolve(&string_pool), "漢字");
+    }
+
+    #[test]
+    fn desc_template_line_errors() {
+        let string_pool = StringPool::new();
+        let item = |templates: WiktextractJson| WiktextractJsonItem {
+            json: json!({"descendants": [{"depth": 1, "templates": templates}]}).into(),
+        };
+        let ok = item(
+            json!([
+                {"name": "desc", "args": {"1": "ja", "2": "-"}},
+                {"name": "ja-r", "args": {"1": "漢字"}},
+                {"name": "PIE root see", "args": {"1": "x"}}
+            ])
+            .into(),
+        );
+        assert!(ok.ensure_desc_templates_parse(&string_pool).is_ok());
+        assert!(ok.ensure_known_desc_template_langs().is_ok());
+        let unknown_lang =
+            item(json!([{"name": "l", "args": {"1": "xx-nope", "2": "foo"}}]).into());
+        assert!(unknown_lang.ensure_known_desc_template_langs().is_err());
+        let no_term = item(json!([{"name": "desctree", "args": {"1": "en"}}]).into());
+        assert!(no_term.ensure_known_desc_template_langs().is_ok());
+        assert!(no_term.ensure_desc_templates_parse(&string_pool).is_err());
+    }
+
+    #[test]
+    fn desctree_cycles_are_cut() {
+        let string_pool = StringPool::new();
+        let mut items = Items::new(ProcessingOptions::default()).unwrap();
+        let lang = Lang::from_str("en").unwrap();
+        let mut add = |term: &str| items.add_real(test_item(&string_pool, "en", term)).0;
+        let (a, b) = (add("a"), add("b"));
+        let line = |name: &str, term: &str| RawDescLine {
+            depth: 1,
+            kind: RawDescLineKind::Desc {
+                desc: RawDesc {
+                    lang,
+                    terms: Box::from([Term::new(&string_pool, term)]),
+                    modes: Box::from([EtyMode::Inherited]),
+                    name: Some(string_pool.get_or_intern(name)),
+                    uncertain: Box::from([false]),
+                },
+            },
+        };
+        // a {{desctree}}s b, which {{desctree}}s a back and also has c
+        let a_lines = vec![line("desctree", "b")];
+        let b_lines = vec![line("desctree", "a"), line("l", "c")];
+        items.raw_templates.desc.insert(a, a_lines.into());
+        items.raw_templates.desc.insert(b, b_lines.into());
+        items.expand_desctrees(&string_pool);
+        let terms = |item: ItemId| {
+            items.raw_templates.desc[&item]
+                .lines
+                .iter()
+                .map(|line| {
+                    let RawDescLineKind::Desc { desc } = &line.kind else {
+                        panic!("expected a desc line");
+                    };
+                    (desc.terms[0].resolve(&string_pool), line.depth)
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(terms(a), [("b", 1), ("a", 2), ("c", 2)]);
+        assert_eq!(terms(b), [("a", 1), ("b", 2), ("c", 1)]);
+    }
+}
@@ -0,0 +1,311 @@
+//! Comparison of two processed datasets, e.g. from consecutive dumps or from
+//! before and after a change to template handling, for spotting regressions.
+//! Items are matched across the datasets by their stable ids.
+
+use crate::{
+    ety_graph::{EtyEdge, EtyEdgeAccess},
+    items::ItemId,
+    languages::Lang,
+    processed::Data,
+    HashMap,
+};
+
+use std::fmt::Write;
+
+use itertools::Itertools;
+use serde_json::{json, Value};
+
+// How many of each kind of change are listed, beyond their counts.
+const MAX_EXAMPLES: usize = 100;
+// Ety links in both datasets whose confidences differ by more than this are
+// reported as confidence shifts.
+const CONFIDENCE_SHIFT: f32 = 0.1;
+// How many langs are listed in the summary.
+const SUMMARY_LANGS: usize = 20;
+
+struct Side<'a> {
+    data: &'a Data,
+    // by stable id
+    items: HashMap<String, ItemId>,
+    // by the stable ids of the child and parent
+    edges: HashMap<(String, String), EtyEdge<'a>>,
+}
+
+impl<'a> Side<'a> {
+    fn new(data: &'a Data) -> Self {
+        let items = data
+            .graph
+            .iter()
+            .map(|(item, _)| (data.stable_id(item), item))
+            .collect();
+        let edges = data
+            .graph
+            .edges()
+            .map(|e| {
+                let key = (data.stable_id(e.child()), data.stable_id(e.parent()));
+                (key, e)
+            })
+            .collect();
+        Self { data, items, edges }
+    }
+
+    fn edge_json(&self, edge: EtyEdge<'_>) -> Value {
+        json!({
+            "child": self.data.snapshot_label(edge.child()),
+            "parent": self.data.snapshot_label(edge.parent()),
+            "etyMode": edge.mode().as_str(),
+            "confidence": edge.confidence(),
+        })
+    }
+}
+
+// Sort ety link jsons by their child and parent, for stable examples.
+fn sort_edges<T>(edges: &mut [T], edge: impl Fn(&T) -> &Value) {
+    edges.sort_unstable_by(|a, b| {
+        let key = |e: &Value| (e["child"].to_string(), e["parent"].to_string());
+        key(edge(a)).cmp(&key(edge(b)))
+    });
+}
+
+#[derive(Default)]
+struct LangDelta {
+    old: usize,
+    new: usize,
+    added: usize,
+    removed: usize,
+}
+
+/// The differences between an old and a new processed dataset.
+pub struct Diff {
+    old_items: usize,
+    new_items: usize,
+    old_edges: usize,
+    new_edges: usize,
+    added_items: Vec<String>,
+    removed_items: Vec<String>,
+    added_edges: Vec<Value>,
+    removed_edges: Vec<Value>,
+    // old edge, new edge
+    mode_changes: Vec<(Value, Value)>,
+    // old edge, new edge, by decreasing shift
+    confidence_shifts: Vec<(Value, Value)>,
+    langs: Vec<(Lang, LangDelta)>,
+}
+
+impl Diff {
+    #[must_use]
+    pub fn new(old: &Data, new: &Data) -> Self {
+        let old = Side::new(old);
+        let new = Side::new(new);
+        let mut langs = HashMap::<Lang, LangDelta>::default();
+        for (item, _) in old.data.graph.iter() {
+            langs.entry(old.data.lang(item)).or_default().old += 1;
+        }
+        for (item, _) in new.data.graph.iter() {
+            langs.entry(new.data.lang(item)).or_default().new += 1;
+        }
+        let mut removed_items = vec![];
+        for (hash, &item) in &old.items {
+            if !new.items.contains_key(hash) {
+                langs.entry(old.data.lang(item)).or_default().removed += 1;
+                removed_items.push(old.data.snapshot_label(item));
+            }
+        }
+        let mut added_items = vec![];
+        for (hash, &item) in &new.items {
+            if !old.items.contains_key(hash) {
+                langs.entry(new.data.lang(item)).or_default().added += 1;
+                added_items.push(new.data.snapshot_label(item));
+            }
+        }
+        removed_items.sort_unstable();
+        added_items.sort_unstable();
+
+        let mut removed_edges = vec![];
+        let mut mode_changes = vec![];
+        let mut confidence_shifts = vec![];
+        for (key, &old_edge) in &old.edges {
+            let Some(&new_edge) = new.edges.get(key) else {
+                removed_edges.push(old.edge_json(old_edge));
+                continue;
+            };
+            if old_edge.mode() != new_edge.mode() {
+                mode_changes.push((old.edge_json(old_edge), new.edge_json(new_edge)));
+            }
+            let shift = (new_edge.confidence() - old_edge.confidence()).abs();
+            if shift > CONFIDENCE_SHIFT {
+                confidence_shifts.push((shift, old.edge_json(old_edge), new.edge_json(new_edge)));
+            }
+        }
+        let mut added_edges = new
+            .edges
+            .iter()
+            .filter(|(key, _)| !old.edges.contains_key(key))
+            .map(|(_, &edge)| new.edge_json(edge))
+            .collect_vec();
+        sort_edges(&mut added_edges, |e| e);
+        sort_edges(&mut removed_edges, |e| e);
+        sort_edges(&mut mode_changes, |(old, _)| old);
+        let confidence_shifts = confidence_shifts
+            .into_iter()
+            .sorted_by(|(a, ..), (b, ..): &(f32, _, _)| b.total_cmp(a))
+            .map(|(_, old, new)| (old, new))
+            .collect();
+
+        let langs = langs
+            .into_iter()
+            .filter(|(_, delta)| delta.added > 0 || delta.removed > 0)
+            .sorted_unstable_by_key(|(lang, delta)| {
+                (std::cmp::Reverse(delta.added + delta.removed), lang.code())
+            })
+            .collect();
+        Self {
+            old_items: old.data.graph.len(),
+            new_items: new.data.graph.len(),
+            old_edges: old.data.graph.graph.edge_count(),
+            new_edges: new.data.graph.graph.edge_count(),
+            added_items,
+            removed_items,
+            added_edges,
+            removed_edges,
+            mode_changes,
+            confidence_shifts,
+            langs,
+        }
+    }
+
+    /// The differences as json, with the count of each kind of change and up
+    /// to 100 examples of it.
+    #[must_use]
+    pub fn json(&self) -> Value {
+        let examples = |values: &[Value]| values.iter().take(MAX_EXAMPLES).cloned().collect_vec();
+        let changes = |changes: &[(Value, Value)]| {
+            changes
+                .iter()
+                .take(MAX_EXAMPLES)
+                .map(|(old, new)| json!({"old": old, "new": new}))
+                .collect_vec()
+        };
+        json!({
+            "items": {
+                "old": self.old_items,
+                "new": self.new_items,
+                "addedCount": self.added_items.len(),
+                "added": self.added_items.iter().take(MAX_EXAMPLES).collect_vec(),
+                "removedCount": self.removed_items.len(),
+                "removed": self.removed_items.iter().take(MAX_EXAMPLES).collect_vec(),
+            },
+            "etyLinks": {
+                "old": self.old_edges,
+                "new": self.new_edges,
+                "addedCount": self.added_edges.len(),
+                "added": examples(&self.added_edges),
+                "removedCount": self.removed_edges.len(),
+                "removed": examples(&self.removed_edges),
+                "modeChangedCount": self.mode_changes.len(),
+                "modeChanged": changes(&self.mode_changes),
+                "confidenceShiftedCount": self.confidence_shifts.len(),
+                "confidenceShifted": changes(&self.confidence_shifts),
+            },
+            "langs": self
+                .langs
+                .iter()
+                .map(|(lang, delta)| {
+                    json!({
+                        "lang": lang.code(),
+                        "old": delta.old,
+                        "new": delta.new,
+                        "added": delta.added,
+                        "removed": delta.removed,
+                    })
+                })
+                .collect_vec(),
+        })
+    }
+
+    /// A human-readable summary of the differences, with the langs with the
+    /// most added or removed items.
+    #[must_use]
+    pub fn summary(&self) -> String {
+        let mut summary = String::new();
+        writeln!(
+            summary,
+            "Items: {} -> {} (+{} added, -{} removed)",
+            self.old_items,
+            self.new_items,
+            self.added_items.len(),
+            self.removed_items.len()
+        )
+        .unwrap();
+        writeln!(
+            summary,
+            "Ety links: {} -> {} (+{} added, -{} removed, {} changed mode, {} shifted confidence by more than {CONFIDENCE_SHIFT})",
+            self.old_edges,
+            self.new_edges,
+            self.added_edges.len(),
+            self.removed_edges.len(),
+            self.mode_changes.len(),
+            self.confidence_shifts.len()
+        )
+        .unwrap();
+        if !self.langs.is_empty() {
+            writeln!(summary, "Langs with the most changed items:").unwrap();
+        }
+        for (lang, delta) in self.langs.iter().take(SUMMARY_LANGS) {
+            writeln!(
+                summary,
+                "  {}: {} -> {} (+{}, -{})",
+                lang.code(),
+                delta.old,
+                delta.new,
+                delta.added,
+                delta.removed
+            )
+            .unwrap();
+        }
+        summary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ety_graph::{EdgeProvenance, EdgeSource, EtyGraph},
+        etymology_templates::EtyMode,
+        items::{test_item, Item},
+        string_pool::StringPool,
+    };
+
+    // en water from gem-pro watōr, and if `with_la`, la aqua as well
+    fn data(with_la: bool, confidence: f32) -> Data {
+        let string_pool = StringPool::new();
+        let mut graph = EtyGraph::default();
+        let mut add =
+            |lang: &str, term: &str| graph.add(Item::Real(test_item(&string_pool, lang, term)));
+        let gem = add("gem-pro", "watōr");
+        let en = add("en", "water");
+        if with_la {
+            add("la", "aqua");
+        }
+        let provenance = EdgeProvenance::new(EdgeSource::Etymology, None, None);
+        graph.add_ety(
+            en,
+            EtyMode::Inherited,
+            Some(0),
+            &[gem],
+            &[confidence],
+            provenance,
+        );
+        Data::new(string_pool, graph, HashMap::default(), None, None)
+    }
+
+    #[test]
+    fn diff_reports_added_items_and_confidence_shifts() {
+        let diff = Diff::new(&data(false, 1.0), &data(true, 0.5)).json();
+        assert_eq!(diff["items"]["added"], json!(["la aqua 1"]));
+        assert_eq!(diff["items"]["removedCount"], 0);
+        assert_eq!(diff["etyLinks"]["confidenceShiftedCount"], 1);
+        assert_eq!(diff["langs"][0]["lang"], "la");
+    }
+}
@@ -4,18 +4,27 @@ use crate::{
     HashMap,
 };
 
-use std::{mem, path::PathBuf, rc::Rc};
+use std::{
+    env, fs, mem,
+    path::{Path, PathBuf},
+    rc::Rc,
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
 
-use anyhow::{Error, Result};
+use anyhow::{anyhow, bail, ensure, Error, Result};
 
+use futures_executor::block_on;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
 use simd_json::ValueAccess;
-use sled::{self, Db, IVec};
+use sled::{self, Db, IVec, Tree};
 use xxhash_rust::xxh3::xxh3_64;
 
-type Embedding = Vec<f32>;
+pub(crate) type Embedding = Vec<f32>;
 
-/// Only retrieve items with similarity greater than this threshold
-pub(crate) const SIMILARITY_THRESHOLD: f32 = 0.0;
+/// Only retrieve items with similarity greater than this threshold, by default
+pub(crate) const DEFAULT_SIMILARITY_THRESHOLD: f32 = 0.0;
 
 /// For an `imputed_item` embedding, we use the embedding for
 /// `imputed_item.from`, weighted by this discount factor
@@ -77,14 +86,14 @@ impl ToEmbedding for IVec {
 struct Batch {
     max_size: usize,
     model: Rc<Model>,
-    cache: Rc<Db>,
+    cache: Rc<Tree>,
     items: Vec<ItemId>,
     texts: Vec<String>,
     text_hashes: Vec<TextHash>,
 }
 
 impl Batch {
-    fn new(model: &Rc<Model>, size: usize, cache: &Rc<Db>) -> Self {
+    fn new(model: &Rc<Model>, size: usize, cache: &Rc<Tree>) -> Self {
         Self {
             items: Vec::with_capacity(size),
             texts: Vec::with_capacity(size),
@@ -136,14 +145,19 @@ impl Batch {
         let text_hashes = mem::take(&mut self.text_hashes);
         let texts = mem::take(&mut self.texts);
         let embeddings = self.model.encode(texts)?;
+        ensure!(
+            embeddings.len() == text_hashes.len(),
+            "got {} embeddings for {} texts",
+            embeddings.len(),
+            text_hashes.len()
+        );
         self.cache(&text_hashes, &embeddings)?;
         self.clear();
         Ok((items, text_hashes))
     }
 
-    fn cache(&self, text_hashes: &[TextHash], embeddings: &Tensor) -> Result<()> {
+    fn cache(&self, text_hashes: &[TextHash], embeddings: &[Embedding]) -> Result<()> {
         let mut batch = sled::Batch::default();
-        let embeddings = embeddings.to_vec2::<f32>()?;
         for (text_hash, embedding) in text_hashes.iter().zip(embeddings.iter()) {
             batch.insert(&text_hash.to_bytes(), embedding.to_bytes());
         }
@@ -155,11 +169,11 @@ impl Batch {
 struct EmbeddingsMap {
     batch: Batch,
     map: HashMap<ItemId, TextHash>,
-    cache: Rc<Db>,
+    cache: Rc<Tree>,
 }
 
 impl EmbeddingsMap {
-    fn new(model: &Rc<Model>, batch_size: usize, cache: &Rc<Db>) -> Self {
+    fn new(model: &Rc<Model>, batch_size: usize, cache: &Rc<Tree>) -> Self {
         Self {
             batch: Batch::new(model, batch_size, cache),
             map: HashMap::default(),
@@ -218,9 +232,9 @@ use candle_core::{
     utils::{cuda_is_available, metal_is_available},
     Device, Tensor,
 };
-use candle_nn::VarBuilder;
+use candle_nn::{VarBuilder, VarMap};
 use candle_transformers::models::bert::{self, BertModel, HiddenAct, DTYPE};
-use hf_hub::{api::sync::Api, Repo, RepoType};
+use hf_hub::{api::sync::Api, Cache, Repo, RepoType};
 use tokenizers::{PaddingParams, Tokenizer};
 
 fn device() -> Result<Device> {
@@ -259,29 +273,44 @@ fn device() -> Result<Device> {
     Ok(Device::Cpu)
 }
 
-struct Model {
-    device: Device,
-    inner: BertModel,
-    tokenizer: Tokenizer,
+/// The weights of a fine-tuned model revision, which is only in the local
+/// Hugging Face cache (see `fine_tune`).
+pub(crate) const FINE_TUNED_WEIGHTS: &str = "model.safetensors";
+
+enum Weights {
+    Pth(PathBuf),
+    Safetensors(PathBuf),
 }
 
-// adapted from https://github.com/huggingface/candle/blob/main/candle-examples/examples/bert/main.rs
-impl Model {
-    fn new(model_name: String, revision: String) -> Result<Self> {
-        let device = device()?;
+/// The files of a model revision in the Hugging Face cache, downloaded from
+/// the hub if they aren't there yet.
+pub(crate) struct ModelFiles {
+    pub(crate) config: PathBuf,
+    pub(crate) tokenizer: PathBuf,
+    weights: Weights,
+}
 
+impl ModelFiles {
+    fn get(model_name: String, revision: String) -> Result<Self> {
         let repo = Repo::with_revision(model_name, RepoType::Model, revision);
-
-        let (config_filename, tokenizer_filename, weights_filename) = {
-            let api = Api::new()?;
-            let api = api.repo(repo);
-            let config = api.get("config.json")?;
-            let tokenizer = api.get("tokenizer.json")?;
-            let weights = api.get("pytorch_model.bin")?;
-            (config, tokenizer, weights)
+        let fine_tuned = Cache::default().repo(repo.clone()).get(FINE_TUNED_WEIGHTS);
+        let api = Api::new()?;
+        let api = api.repo(repo);
+        let config = api.get("config.json")?;
+        let tokenizer = api.get("tokenizer.json")?;
+        let weights = match fine_tuned {
+            Some(weights) => Weights::Safetensors(weights),
+            None => Weights::Pth(api.get("pytorch_model.bin")?),
         };
+        Ok(Self {
+            config,
+            tokenizer,
+            weights,
+        })
+    }
 
-        let mut tokenizer = Tokenizer::from_file(tokenizer_filename).map_err(Error::msg)?;
+    fn tokenizer(&self) -> Result<Tokenizer> {
+        let mut tokenizer = Tokenizer::from_file(&self.tokenizer).map_err(Error::msg)?;
         if let Some(pp) = tokenizer.get_padding_mut() {
             pp.strategy = tokenizers::PaddingStrategy::BatchLongest;
         } else {
@@ -291,23 +320,90 @@ impl Model {
             };
             tokenizer.with_padding(Some(pp));
         }
+        Ok(tokenizer)
+    }
 
-        let vb = VarBuilder::from_pth(&weights_filename, DTYPE, &device)?;
-
-        let config = std::fs::read_to_string(config_filename)?;
+    fn bert_config(&self) -> Result<bert::Config> {
+        let config = std::fs::read_to_string(&self.config)?;
         let mut config: bert::Config = serde_json::from_str(&config)?;
         config.hidden_act = HiddenAct::GeluApproximate;
+        Ok(config)
+    }
+}
 
-        let model = BertModel::load(vb, &config)?;
+pub(crate) struct LocalModel {
+    device: Device,
+    inner: BertModel,
+    tokenizer: Tokenizer,
+}
 
+// adapted from https://github.com/huggingface/candle/blob/main/candle-examples/examples/bert/main.rs
+impl LocalModel {
+    fn new(model_name: String, revision: String) -> Result<Self> {
+        let device = device()?;
+        let files = ModelFiles::get(model_name, revision)?;
+        let vb = match &files.weights {
+            Weights::Pth(weights) => VarBuilder::from_pth(weights, DTYPE, &device)?,
+            // SAFETY: the weights aren't modified while they are mapped.
+            Weights::Safetensors(weights) => unsafe {
+                VarBuilder::from_mmaped_safetensors(&[weights], DTYPE, &device)?
+            },
+        };
+        let model = BertModel::load(vb, &files.bert_config()?)?;
         Ok(Self {
             device,
             inner: model,
-            tokenizer,
+            tokenizer: files.tokenizer()?,
         })
     }
 
-    fn encode(&self, texts: Vec<String>) -> Result<Tensor> {
+    /// The model with its weights in a `VarMap`, so that they can be trained,
+    /// along with the files it was loaded from.
+    pub(crate) fn trainable(
+        model_name: String,
+        revision: String,
+    ) -> Result<(Self, ModelFiles, VarMap)> {
+        let device = device()?;
+        let files = ModelFiles::get(model_name, revision)?;
+        let mut varmap = VarMap::new();
+        let vb = VarBuilder::from_varmap(&varmap, DTYPE, &device);
+        let model = BertModel::load(vb, &files.bert_config()?)?;
+        match &files.weights {
+            Weights::Safetensors(weights) => varmap.load(weights)?,
+            Weights::Pth(weights) => {
+                // The pytorch weights also have buffers and a pooler that the
+                // model doesn't use.
+                for (name, tensor) in candle_core::pickle::read_all(weights)? {
+                    if varmap
+                        .data()
+                        .lock()
+                        .expect("varmap not poisoned")
+                        .contains_key(&name)
+                    {
+                        varmap.set_one(name, tensor)?;
+                    }
+                }
+            }
+        }
+        let model = Self {
+            device,
+            inner: model,
+            tokenizer: files.tokenizer()?,
+        };
+        Ok((model, files, varmap))
+    }
+
+    pub(crate) fn device(&self) -> &Device {
+        &self.device
+    }
+
+    fn encode(&self, texts: Vec<String>) -> Result<Vec<Embedding>> {
+        Ok(self.embed(texts)?.to_vec2::<f32>()?)
+    }
+
+    /// The L2-normalized embeddings of the texts, as a tensor of one row per
+    /// text.
+    pub(crate) fn embed(&self, texts: Vec<String>) -> Result<Tensor> {
         let tokens = self
             .tokenizer
             .encode_batch(texts, true)
@@ -349,33 +445,326 @@ fn normalize_l2(v: &Tensor) -> Result<Tensor> {
     Ok(v.broadcast_div(&v.sqr()?.sum_keepdim(1)?.sqrt()?)?)
 }
 
+/// How many times a request to a remote embeddings endpoint is retried after
+/// a transport error or a 429 or 5xx response, with exponential backoff.
+const REMOTE_RETRIES: u32 = 5;
+const REMOTE_INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const REMOTE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// The environment variable with the API key sent as a bearer token to a
+/// remote embeddings endpoint, if it needs one.
+const EMBEDDINGS_API_KEY_VAR: &str = "WETY_EMBEDDINGS_API_KEY";
+
+#[derive(Deserialize)]
+struct RemoteEmbedding {
+    index: usize,
+    embedding: Embedding,
+}
+
+#[derive(Deserialize)]
+struct RemoteEmbeddingsResponse {
+    data: Vec<RemoteEmbedding>,
+}
+
+// A model served by an OpenAI-compatible embeddings endpoint, e.g. a hosted
+// service, or a local text-embeddings-inference or vLLM server.
+struct RemoteModel {
+    agent: ureq::Agent,
+    url: String,
+    model_name: String,
+    api_key: Option<String>,
+}
+
+impl RemoteModel {
+    fn new(base_url: &str, model_name: String) -> Self {
+        println!("Getting embeddings from {base_url}.");
+        Self {
+            agent: ureq::AgentBuilder::new().timeout(REMOTE_TIMEOUT).build(),
+            url: format!("{}/embeddings", base_url.trim_end_matches('/')),
+            model_name,
+            api_key: env::var(EMBEDDINGS_API_KEY_VAR).ok(),
+        }
+    }
+
+    fn encode(&self, texts: Vec<String>) -> Result<Vec<Embedding>> {
+        let body = json!({ "model": self.model_name, "input": texts });
+        let mut backoff = REMOTE_INITIAL_BACKOFF;
+        let mut retries = 0;
+        let response = loop {
+            let mut request = self.agent.post(&self.url);
+            if let Some(api_key) = &self.api_key {
+                request = request.set("Authorization", &format!("Bearer {api_key}"));
+            }
+            let error = match request.send_json(&body) {
+                Ok(response) => break response,
+                Err(ureq::Error::Status(status, response)) if status != 429 && status < 500 => {
+                    let message = response.into_string().unwrap_or_default();
+                    bail!(
+                        "embeddings endpoint {} returned {status}: {message}",
+                        self.url
+                    );
+                }
+                Err(error) => error,
+            };
+            ensure!(
+                retries < REMOTE_RETRIES,
+                "embeddings endpoint {} failed after {REMOTE_RETRIES} retries: {error}",
+                self.url
+            );
+            println!("Embeddings request failed ({error}). Retrying in {backoff:?}...");
+            thread::sleep(backoff);
+            backoff *= 2;
+            retries += 1;
+        };
+        let response = response.into_json::<RemoteEmbeddingsResponse>()?;
+        Ok(response.embeddings())
+    }
+}
+
+impl RemoteEmbeddingsResponse {
+    // The embeddings should be in the order of the texts, but the index is
+    // what says which text each is of. Not all endpoints normalize them.
+    fn embeddings(mut self) -> Vec<Embedding> {
+        self.data.sort_unstable_by_key(|e| e.index);
+        self.data
+            .into_iter()
+            .map(|e| {
+                let norm = e.embedding.iter().map(|v| v * v).sum::<f32>().sqrt();
+                e.embedding.into_iter().map(|v| v / norm).collect()
+            })
+            .collect()
+    }
+}
+
+enum Model {
+    Local(LocalModel),
+    Remote(RemoteModel),
+}
+
+impl Model {
+    fn new(config: &Config) -> Result<Self> {
+        Ok(match &config.url {
+            Some(url) => Self::Remote(RemoteModel::new(url, config.model_name.clone())),
+            None => Self::Local(LocalModel::new(
+                config.model_name.clone(),
+                config.model_revision.clone(),
+            )?),
+        })
+    }
+
+    // L2-normalized embeddings of the texts, in their order.
+    fn encode(&self, texts: Vec<String>) -> Result<Vec<Embedding>> {
+        match self {
+            Self::Local(model) => model.encode(texts),
+            Self::Remote(model) => model.encode(texts),
+        }
+    }
+}
+
 pub struct Config {
     pub model_name: String,
     pub model_revision: String,
     pub batch_size: usize,
     pub cache_path: PathBuf,
+    /// The base url of an OpenAI-compatible embeddings endpoint to get the
+    /// embeddings from, rather than running the model locally.
+    pub url: Option<String>,
+}
+
+impl Config {
+    // Embeddings from different models are incompatible, so each model gets
+    // its own tree in the cache. A remote endpoint may pool or truncate
+    // differently than the local model even if it serves the same one, so its
+    // embeddings are kept apart too.
+    pub(crate) fn cache_tree_name(&self) -> String {
+        match &self.url {
+            Some(url) => format!("{}@{url}", self.model_name),
+            None => local_cache_tree_name(&self.model_name, &self.model_revision),
+        }
+    }
+}
+
+fn local_cache_tree_name(model_name: &str, model_revision: &str) -> String {
+    format!("{model_name}@{model_revision}")
+}
+
+// Before embeddings were cached by model, they were all cached in the db's
+// default tree. They are moved to the tree of the default model, which was the
+// one almost always used, rather than being left unused while they are all
+// generated again.
+fn migrate_default_tree(db: &Db) -> Result<()> {
+    if db.is_empty() {
+        return Ok(());
+    }
+    let name = local_cache_tree_name(DEFAULT_MODEL, DEFAULT_MODEL_REVISION);
+    println!(
+        "Moving {} embeddings cached before embeddings were cached by model to the cache of the default model, {name}...",
+        db.len()
+    );
+    let tree = open_cache_tree(db, &name)?;
+    for entry in db.iter() {
+        let (key, value) = entry?;
+        tree.insert(key, value)?;
+    }
+    tree.flush()?;
+    db.clear()?;
+    db.flush()?;
+    Ok(())
+}
+
+/// How often to checkpoint the embeddings cache to disk while generating
+/// embeddings. Writes since the last checkpoint may be lost on power loss.
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(60);
+
+// Open the embeddings cache db. If sled cannot recover the db at all, we move
+// it aside and start a fresh one rather than failing the run.
+fn open_cache_db(path: &Path) -> Result<Db> {
+    match sled::open(path) {
+        Err(sled::Error::Corruption { .. }) => {
+            let mut corrupt_path = path.as_os_str().to_owned();
+            corrupt_path.push(".corrupt");
+            println!(
+                "Embeddings cache {} is corrupt and could not be recovered. Moving it to {} and starting a new one...",
+                path.display(),
+                Path::new(&corrupt_path).display()
+            );
+            fs::rename(path, &corrupt_path)?;
+            Ok(sled::open(path)?)
+        }
+        db => Ok(db?),
+    }
+}
+
+// Open a tree in the embeddings cache db. If the tree is corrupt, we drop and
+// rebuild it, leaving any other trees in the db untouched.
+fn open_cache_tree(db: &Db, name: &str) -> Result<Tree> {
+    match db.open_tree(name) {
+        Err(sled::Error::Corruption { .. }) => {
+            println!("Embeddings cache tree {name} is corrupt. Rebuilding it...");
+            db.drop_tree(name)?;
+            Ok(db.open_tree(name)?)
+        }
+        tree => Ok(tree?),
+    }
+}
+
+/// Check every tree in the embeddings cache at `path` for entries that cannot
+/// be read or do not decode to embeddings of a consistent dimension, printing
+/// a summary for each tree.
+///
+/// # Errors
+///
+/// Will return `Err` if the cache cannot be opened or any problems are found.
+pub fn verify_cache(path: &Path) -> Result<()> {
+    let db = sled::open(path)?;
+    let mut ok = true;
+    for name in db.tree_names() {
+        let display_name = String::from_utf8_lossy(&name);
+        let tree = match db.open_tree(&name) {
+            Ok(tree) => tree,
+            Err(e) => {
+                println!("{display_name}: could not be opened: {e}");
+                ok = false;
+                continue;
+            }
+        };
+        let mut entries = 0;
+        let mut bad_entries = 0;
+        let mut dimension = None;
+        for entry in &tree {
+            entries += 1;
+            let Ok((key, value)) = entry else {
+                bad_entries += 1;
+                continue;
+            };
+            let value_dimension = value.len() / 4;
+            if key.len() != 8
+                || value.is_empty()
+                || value.len() % 4 != 0
+                || dimension.is_some_and(|d| d != value_dimension)
+            {
+                bad_entries += 1;
+                continue;
+            }
+            dimension = Some(value_dimension);
+        }
+        println!(
+            "{display_name}: {entries} entries, {bad_entries} bad, dimension {}",
+            dimension.map_or("n/a".to_string(), |d| d.to_string())
+        );
+        ok &= bad_entries == 0;
+    }
+    ensure!(ok, "embeddings cache {} has problems", path.display());
+    Ok(())
+}
+
+/// Which embeddings in the cache are those of which items. This is all that
+/// needs to be checkpointed after generating embeddings, as the embeddings
+/// themselves are in the cache.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct TextHashes {
+    ety: HashMap<ItemId, TextHash>,
+    glosses: HashMap<ItemId, TextHash>,
 }
 
 pub(crate) struct Embeddings {
     ety: EmbeddingsMap,
     glosses: EmbeddingsMap,
-    cache: Rc<Db>,
+    cache: Rc<Tree>,
+    last_checkpoint: Instant,
+    pending_checkpoint: Option<JoinHandle<sled::Result<usize>>>,
 }
 
 impl Embeddings {
     pub(crate) fn new(config: &Config) -> Result<Self> {
-        let model = Rc::from(Model::new(
-            config.model_name.clone(),
-            config.model_revision.clone(),
-        )?);
-        let cache = Rc::from(sled::open(&config.cache_path)?);
+        let model = Rc::from(Model::new(config)?);
+        let db = open_cache_db(&config.cache_path)?;
+        migrate_default_tree(&db)?;
+        let cache = Rc::from(open_cache_tree(&db, &config.cache_tree_name())?);
         Ok(Self {
             ety: EmbeddingsMap::new(&model, config.batch_size, &cache),
             glosses: EmbeddingsMap::new(&model, config.batch_size, &cache),
             cache,
+            last_checkpoint: Instant::now(),
+            pending_checkpoint: None,
         })
     }
 
+    pub(crate) fn from_text_hashes(config: &Config, text_hashes: TextHashes) -> Result<Self> {
+        let mut embeddings = Self::new(config)?;
+        embeddings.ety.map = text_hashes.ety;
+        embeddings.glosses.map = text_hashes.glosses;
+        Ok(embeddings)
+    }
+
+    pub(crate) fn text_hashes(&self) -> TextHashes {
+        TextHashes {
+            ety: self.ety.map.clone(),
+            glosses: self.glosses.map.clone(),
+        }
+    }
+
+    // Wait for the previous checkpoint to finish, if there is one.
+    fn finish_checkpoint(&mut self) -> Result<()> {
+        if let Some(pending) = self.pending_checkpoint.take() {
+            pending
+                .join()
+                .map_err(|_| anyhow!("embeddings cache checkpoint panicked"))??;
+        }
+        Ok(())
+    }
+
+    // Flush the cache to disk in the background, so that a crash loses at most
+    // the embeddings generated since the last checkpoint. There is at most one
+    // checkpoint in flight at a time.
+    fn checkpoint(&mut self) -> Result<()> {
+        self.finish_checkpoint()?;
+        let cache = Tree::clone(&self.cache);
+        self.pending_checkpoint = Some(thread::spawn(move || block_on(cache.flush_async())));
+        self.last_checkpoint = Instant::now();
+        Ok(())
+    }
+
     pub(crate) fn add(
         &mut self,
         json_item: &WiktextractJson,
@@ -418,28 +807,41 @@ impl Embeddings {
                 self.glosses.update(item_id, glosses_text.to_string())?;
             }
         }
+        if self.last_checkpoint.elapsed() >= CHECKPOINT_INTERVAL {
+            self.checkpoint()?;
+        }
         Ok(())
     }
 
     pub(crate) fn flush(&mut self) -> Result<()> {
         self.ety.flush()?;
         self.glosses.flush()?;
+        self.finish_checkpoint()?;
         self.cache.flush()?;
         Ok(())
     }
 
     pub(crate) fn get(&self, item: &Item, item_id: ItemId) -> Result<ItemEmbedding> {
         Ok(match item {
-            Item::Real(_) => ItemEmbedding {
-                ety: self.ety.get(item_id)?,
-                glosses: self.glosses.get(item_id)?,
-                discount: 1.0,
-            },
-            Item::Imputed(imputed) => ItemEmbedding {
-                ety: self.ety.get(imputed.from)?,
-                glosses: self.glosses.get(imputed.from)?,
-                discount: IMPUTATION_DISCOUNT,
-            },
+            Item::Real(_) => self.item_embedding(item_id, 1.0)?,
+            Item::Imputed(imputed) => self.item_embedding(imputed.from, IMPUTATION_DISCOUNT)?,
+        })
+    }
+
+    /// The embeddings of the ety text and glosses of a real item, if it has
+    /// them.
+    pub(crate) fn get_vectors(
+        &self,
+        item_id: ItemId,
+    ) -> Result<(Option<Embedding>, Option<Embedding>)> {
+        Ok((self.ety.get(item_id)?, self.glosses.get(item_id)?))
+    }
+
+    pub(crate) fn item_embedding(&self, item_id: ItemId, discount: f32) -> Result<ItemEmbedding> {
+        Ok(ItemEmbedding {
+            ety: self.ety.get(item_id)?,
+            glosses: self.glosses.get(item_id)?,
+            discount,
         })
     }
 }
@@ -560,6 +962,7 @@ mod tests {
             model_revision: DEFAULT_MODEL_REVISION.to_string(),
             batch_size: 1,
             cache_path: cache_path.to_path_buf(),
+            url: None,
         };
         Embeddings::new(&config).unwrap()
     }
@@ -570,11 +973,7 @@ mod tests {
 
     impl Embeddings {
         fn get_real(&self, item_id: ItemId) -> Result<ItemEmbedding> {
-            Ok(ItemEmbedding {
-                ety: self.ety.get(item_id)?,
-                glosses: self.glosses.get(item_id)?,
-                discount: 1.0,
-            })
+            self.item_embedding(item_id, 1.0)
         }
     }
 
@@ -718,4 +1117,33 @@ mod tests {
         let b = xxh3_64("test".as_bytes());
         assert_eq!(a, b);
     }
+
+    #[test]
+    fn remote_embeddings_in_order_and_normalized() {
+        let response: RemoteEmbeddingsResponse = serde_json::from_str(
+            r#"{"object": "list", "data": [
+                {"object": "embedding", "index": 1, "embedding": [0.0, 2.0]},
+                {"object": "embedding", "index": 0, "embedding": [3.0, 4.0]}
+            ], "model": "test"}"#,
+        )
+        .unwrap();
+        assert_eq!(response.embeddings(), vec![vec![0.6, 0.8], vec![0.0, 1.0]]);
+    }
+
+    #[test]
+    fn default_tree_is_migrated_to_the_default_model() {
+        let cache = PathBuf::from("tmp-embeddings-tests-migration");
+        let db = sled::open(&cache).unwrap();
+        let embedding = vec![0.6, 0.8];
+        db.insert(1u64.to_bytes(), embedding.to_bytes()).unwrap();
+        migrate_default_tree(&db).unwrap();
+        assert!(db.is_empty());
+        let tree = db
+            .open_tree(local_cache_tree_name(DEFAULT_MODEL, DEFAULT_MODEL_REVISION))
+            .unwrap();
+        let migrated = tree.get(1u64.to_bytes()).unwrap().unwrap();
+        assert_eq!(migrated.to_embedding(), embedding);
+        drop(db);
+        delete_cache(&cache);
+    }
 }
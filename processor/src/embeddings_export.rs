@@ -0,0 +1,238 @@
+//! Export of the cached embeddings of items, for clustering, visualizing or
+//! probing them with other tools without encoding their texts again. The
+//! embeddings are written as two matrices, one of ety text embeddings and one
+//! of gloss embeddings, along with a TSV index of the items they are of, by
+//! stable id (see [`crate::Data::stable_id`]).
+
+use crate::{
+    embeddings::Embeddings,
+    items::{Items, StableIds},
+    processed::sibling_path,
+    string_pool::StringPool,
+};
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    time::Instant,
+};
+
+use anyhow::{ensure, Ok, Result};
+use clap::ValueEnum;
+use indicatif::HumanDuration;
+use serde_json::{json, Map, Value};
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum EmbeddingsFormat {
+    /// A single .safetensors file with an "ety" and a "glosses" tensor
+    Safetensors,
+    /// An .ety.npy and a .glosses.npy file
+    Npy,
+}
+
+// Embeddings of the same dimension, as rows.
+#[derive(Default)]
+struct Matrix {
+    values: Vec<f32>,
+    rows: usize,
+    dimension: usize,
+}
+
+impl Matrix {
+    // Add the embedding as a row, returning its index.
+    fn push(&mut self, embedding: &[f32]) -> Result<usize> {
+        if self.rows == 0 {
+            self.dimension = embedding.len();
+        }
+        ensure!(
+            embedding.len() == self.dimension,
+            "embeddings of different dimensions ({} and {}) in the cache",
+            self.dimension,
+            embedding.len()
+        );
+        self.values.extend_from_slice(embedding);
+        self.rows += 1;
+        Ok(self.rows - 1)
+    }
+
+    fn shape(&self) -> [usize; 2] {
+        [self.rows, self.dimension]
+    }
+
+    fn write_le(&self, writer: &mut impl Write) -> Result<()> {
+        for value in &self.values {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+/// Write the embeddings of the items to files named after `output_path`, in
+/// `format`, with a .tsv index of which rows are the embeddings of which items.
+pub(crate) fn write_embeddings(
+    string_pool: &StringPool,
+    items: &Items,
+    embeddings: &Embeddings,
+    output_path: &Path,
+    format: EmbeddingsFormat,
+) -> Result<()> {
+    let t = Instant::now();
+    let index_path = sibling_path(output_path, "tsv");
+    println!(
+        "Writing the index of the embeddings to {}...",
+        index_path.display()
+    );
+    let mut index = BufWriter::new(File::create(index_path)?);
+    writeln!(index, "stableId\tlang\tterm\tetyNum\tetyRow\tglossesRow")?;
+    let mut ety = Matrix::default();
+    let mut glosses = Matrix::default();
+    let row = |row: Option<usize>| row.map_or_else(String::new, |row| row.to_string());
+    let stable_ids = StableIds::new(&items.graph, string_pool);
+    for (item_id, item) in items.graph.iter() {
+        if item.is_imputed() {
+            continue;
+        }
+        let (ety_embedding, glosses_embedding) = embeddings.get_vectors(item_id)?;
+        if ety_embedding.is_none() && glosses_embedding.is_none() {
+            continue;
+        }
+        let ety_row = ety_embedding.map(|e| ety.push(&e)).transpose()?;
+        let glosses_row = glosses_embedding.map(|e| glosses.push(&e)).transpose()?;
+        // Terms are on a single line, but just in case.
+        let term = item.term().resolve(string_pool).replace(['\t', '\n'], " ");
+        writeln!(
+            index,
+            "{}\t{}\t{term}\t{}\t{}\t{}",
+            stable_ids.id(item_id, item.stable_id_hash(string_pool)),
+            item.lang().code(),
+            item.ety_num(),
+            row(ety_row),
+            row(glosses_row),
+        )?;
+    }
+    index.flush()?;
+    match format {
+        EmbeddingsFormat::Safetensors => {
+            let path = sibling_path(output_path, "safetensors");
+            println!("Writing the embeddings to {}...", path.display());
+            write_safetensors(&path, &[("ety", &ety), ("glosses", &glosses)])?;
+        }
+        EmbeddingsFormat::Npy => {
+            for (name, matrix) in [("ety", &ety), ("glosses", &glosses)] {
+                let path = sibling_path(output_path, &format!("{name}.npy"));
+                println!("Writing the embeddings to {}...", path.display());
+                write_npy(&path, matrix)?;
+            }
+        }
+    }
+    println!(
+        "Finished. Wrote {} ety and {} gloss embeddings. Took {}.",
+        ety.rows,
+        glosses.rows,
+        HumanDuration(t.elapsed())
+    );
+    Ok(())
+}
+
+// See https://github.com/huggingface/safetensors#format: the length of the
+// json header as a little-endian u64, the header, and then the little-endian
+// data of the tensors back to back.
+fn safetensors_header(tensors: &[(&str, &Matrix)]) -> Vec<u8> {
+    let mut header = Map::new();
+    let mut offset = 0;
+    for &(name, matrix) in tensors {
+        let len = matrix.values.len() * 4;
+        header.insert(
+            name.to_string(),
+            json!({
+                "dtype": "F32",
+                "shape": matrix.shape(),
+                "data_offsets": [offset, offset + len],
+            }),
+        );
+        offset += len;
+    }
+    let mut header = Value::Object(header).to_string().into_bytes();
+    // The data should be aligned to 8 bytes, so the header is padded with
+    // spaces.
+    header.resize(header.len().next_multiple_of(8), b' ');
+    header
+}
+
+fn write_safetensors(path: &Path, tensors: &[(&str, &Matrix)]) -> Result<()> {
+    let header = safetensors_header(tensors);
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(&(header.len() as u64).to_le_bytes())?;
+    writer.write_all(&header)?;
+    for (_, matrix) in tensors {
+        matrix.write_le(&mut writer)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+// See https://numpy.org/doc/stable/reference/generated/numpy.lib.format.html:
+// the magic string, the version (1.0), the length of the header as a
+// little-endian u16, and the header, padded with spaces and a newline such
+// that the data that follows is aligned to 64 bytes.
+fn npy_header(matrix: &Matrix) -> Vec<u8> {
+    let [rows, dimension] = matrix.shape();
+    let dict =
+        format!("{{'descr': '<f4', 'fortran_order': False, 'shape': ({rows}, {dimension}), }}");
+    let prefix_len = 10;
+    let len = (prefix_len + dict.len() + 1).next_multiple_of(64) - prefix_len;
+    let mut header = b"\x93NUMPY\x01\x00".to_vec();
+    header.extend_from_slice(&u16::try_from(len).expect("short header").to_le_bytes());
+    header.extend_from_slice(dict.as_bytes());
+    header.resize(prefix_len + len - 1, b' ');
+    header.push(b'\n');
+    header
+}
+
+fn write_npy(path: &Path, matrix: &Matrix) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(&npy_header(matrix))?;
+    matrix.write_le(&mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matrix(rows: &[&[f32]]) -> Matrix {
+        let mut matrix = Matrix::default();
+        for row in rows {
+            matrix.push(row).unwrap();
+        }
+        matrix
+    }
+
+    #[test]
+    fn embeddings_file_headers() {
+        let ety = matrix(&[&[1.0, 2.0], &[3.0, 4.0]]);
+        let glosses = matrix(&[&[5.0, 6.0]]);
+        assert!(Matrix::default().push(&[1.0]).is_ok());
+        assert!(matrix(&[&[1.0]]).push(&[1.0, 2.0]).is_err());
+
+        let header = safetensors_header(&[("ety", &ety), ("glosses", &glosses)]);
+        assert_eq!(header.len() % 8, 0);
+        let header: Value = serde_json::from_slice(&header).unwrap();
+        assert_eq!(header["ety"]["shape"], json!([2, 2]));
+        assert_eq!(header["ety"]["data_offsets"], json!([0, 16]));
+        assert_eq!(header["glosses"]["data_offsets"], json!([16, 24]));
+
+        let header = npy_header(&ety);
+        assert_eq!(header.len() % 64, 0);
+        assert!(header.starts_with(b"\x93NUMPY\x01\x00"));
+        assert_eq!(
+            usize::from(u16::from_le_bytes([header[8], header[9]])),
+            header.len() - 10
+        );
+        let dict = String::from_utf8_lossy(&header[10..]);
+        assert!(dict.contains("'shape': (2, 2)"));
+        assert!(dict.ends_with('\n'));
+    }
+}
@@ -1,31 +1,101 @@
 use crate::{
-    embeddings,
     etymology_templates::EtyMode,
-    items::{Item, ItemId},
+    items::{CycleStrategy, Item, ItemId},
     languages::Lang,
+    processed::{QueryTimeout, TreeLimits},
+    string_pool::{StringPool, Symbol},
     HashMap, HashSet,
 };
 
-use std::collections::VecDeque;
+use std::collections::{hash_map::Entry, VecDeque};
 
 use anyhow::{Ok, Result};
 use itertools::{izip, Itertools};
 use petgraph::{
-    algo::greedy_feedback_arc_set,
+    algo::{greedy_feedback_arc_set, tarjan_scc, toposort},
     stable_graph::{EdgeIndex, EdgeReference, StableDiGraph},
-    visit::{EdgeRef, IntoNodeReferences},
+    visit::{EdgeRef, IntoEdgeReferences, IntoNodeReferences},
     Direction,
 };
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 
 pub(crate) type EtyEdge<'a> = EdgeReference<'a, EtyEdgeData>;
 
-#[derive(Serialize, Deserialize)]
+/// The kind of evidence an ety link was found in.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum EdgeSource {
+    Etymology,
+    Descendants,
+    RootImputation,
+    // the term is an alt or form of its parent, in lieu of an ety section
+    FormFallback,
+    // a pos item linked to its ety item, with pos granularity
+    PosItem,
+}
+
+impl EdgeSource {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::Etymology => "etymology",
+            Self::Descendants => "descendants",
+            Self::RootImputation => "rootImputation",
+            Self::FormFallback => "formFallback",
+            Self::PosItem => "posItem",
+        }
+    }
+}
+
+/// How an ety link was created, for debugging wrong links and filtering links
+/// by the kind of evidence for them.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub(crate) struct EdgeProvenance {
+    pub(crate) source: EdgeSource,
+    // the name of the template the link was parsed from, if any
+    pub(crate) template: Option<Symbol>,
+    // the line in the wiktextract data of the item whose section it was
+    // found in, if known
+    pub(crate) line: Option<usize>,
+    // the etymology-only variety (e.g. a dialect) that the link's child was
+    // given in, if it was found as a term of the variety's regular lang
+    #[serde(default)]
+    pub(crate) variety: Option<Lang>,
+}
+
+impl EdgeProvenance {
+    pub(crate) fn new(source: EdgeSource, template: Option<Symbol>, line: Option<usize>) -> Self {
+        Self {
+            source,
+            template,
+            line,
+            variety: None,
+        }
+    }
+
+    pub(crate) fn with_variety(self, variety: Option<Lang>) -> Self {
+        Self { variety, ..self }
+    }
+
+    pub(crate) fn json(&self, string_pool: &StringPool) -> Value {
+        json!({
+            "source": self.source.as_str(),
+            "template": self.template.map(|t| string_pool.resolve(t)),
+            "line": self.line,
+            "variety": self.variety.map(|v| v.code()),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub(crate) struct EtyEdgeData {
     pub(crate) mode: EtyMode,
     pub(crate) order: u8,
     pub(crate) head: bool,
     confidence: f32,
+    // missing in data processed before it was recorded
+    #[serde(default)]
+    provenance: Option<EdgeProvenance>,
 }
 
 pub(crate) trait EtyEdgeAccess {
@@ -35,6 +105,7 @@ pub(crate) trait EtyEdgeAccess {
     fn head(&self) -> bool;
     fn mode(&self) -> EtyMode;
     fn confidence(&self) -> f32;
+    fn provenance(&self) -> Option<EdgeProvenance>;
 }
 
 impl EtyEdgeAccess for EtyEdge<'_> {
@@ -56,6 +127,9 @@ impl EtyEdgeAccess for EtyEdge<'_> {
     fn confidence(&self) -> f32 {
         self.weight().confidence
     }
+    fn provenance(&self) -> Option<EdgeProvenance> {
+        self.weight().provenance
+    }
 }
 
 // the parents of some item
@@ -71,11 +145,59 @@ impl ImmediateEty {
     }
 }
 
+/// A set of parents of an item other than those it is linked to in the graph,
+/// e.g. from a descendants section that disagrees with the item's ety section.
+/// These are kept so that the uncertainty isn't erased, but aren't traversed.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub(crate) struct AlternativeEty {
+    pub(crate) items: Box<[ItemId]>,
+    pub(crate) confidences: Box<[f32]>,
+    pub(crate) head: Option<u8>,
+    pub(crate) mode: EtyMode,
+    #[serde(default)]
+    pub(crate) provenance: Option<EdgeProvenance>,
+}
+
+impl AlternativeEty {
+    /// The confidence of the set as a whole, as for choosing between sets.
+    pub(crate) fn confidence(&self) -> f32 {
+        self.confidences
+            .iter()
+            .copied()
+            .min_by(f32::total_cmp)
+            .expect("at least one")
+    }
+}
+
+/// An ety link removed to break a cycle, for reporting upstream.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct RemovedEdge {
+    pub(crate) child: ItemId,
+    pub(crate) parent: ItemId,
+    pub(crate) mode: EtyMode,
+    pub(crate) confidence: f32,
+    pub(crate) provenance: Option<EdgeProvenance>,
+    // Whether the link was the one chosen to break the cycle, rather than
+    // removed along with it so as not to leave its child with a partial ety.
+    pub(crate) in_cycle: bool,
+}
+
 pub(crate) type ItemIndex = u32;
 
 #[derive(Default, Serialize, Deserialize)]
 pub(crate) struct EtyGraph {
     pub(crate) graph: StableDiGraph<Item, EtyEdgeData, ItemIndex>,
+    // The alternatives to each item's parents in the graph, in the order they
+    // were found. An alternative's index is its position here plus one, the
+    // parents in the graph being alternative 0.
+    #[serde(default)]
+    pub(crate) alternatives: HashMap<ItemId, Vec<AlternativeEty>>,
+    // The surface analysis of each item that has one, e.g. from {{surf}}.
+    // This describes how the item can be analysed synchronically rather than
+    // what it descends from, so it is kept apart from the item's parents and
+    // their alternatives rather than competing with them.
+    #[serde(default)]
+    pub(crate) surface_analyses: HashMap<ItemId, AlternativeEty>,
 }
 
 impl EtyGraph {
@@ -101,6 +223,11 @@ impl EtyGraph {
         self.graph.node_count()
     }
 
+    /// All of the edges in the graph.
+    pub(crate) fn edges(&self) -> impl Iterator<Item = EtyEdge<'_>> + '_ {
+        self.graph.edge_references()
+    }
+
     pub(crate) fn immediate_ety(&self, id: ItemId) -> Option<ImmediateEty> {
         let mut parents = vec![];
         let mut order = vec![];
@@ -125,31 +252,205 @@ impl EtyGraph {
         })
     }
 
-    pub(crate) fn remove_cycles(&mut self) -> Result<()> {
-        print!("  Checking for ety link feedback arc set... ");
-        let fas: Vec<EdgeIndex> = greedy_feedback_arc_set(&self.graph)
-            .map(|e| e.id())
-            .collect();
-        if fas.is_empty() {
-            println!("Found none.");
-        } else {
-            println!("Found set of size {}. Removing.", fas.len());
-            for edge in fas {
+    /// The alternatives to the item's parents in the graph.
+    pub(crate) fn alternative_etys(&self, id: ItemId) -> &[AlternativeEty] {
+        self.alternatives.get(&id).map_or(&[], Vec::as_slice)
+    }
+
+    /// The surface analysis of the item, if it has one.
+    pub(crate) fn surface_analysis(&self, id: ItemId) -> Option<&AlternativeEty> {
+        self.surface_analyses.get(&id)
+    }
+
+    // Keep the first surface analysis found for the item.
+    pub(crate) fn add_surface_analysis(&mut self, item: ItemId, analysis: AlternativeEty) {
+        self.surface_analyses.entry(item).or_insert(analysis);
+    }
+
+    // Keep an alternative to the item's parents, unless it is the same as the
+    // parents or an alternative already kept.
+    fn add_alternative_ety(&mut self, item: ItemId, alternative: AlternativeEty) {
+        if self
+            .immediate_ety(item)
+            .is_some_and(|ety| *ety.items == *alternative.items)
+        {
+            return;
+        }
+        let alternatives = self.alternatives.entry(item).or_default();
+        if !alternatives.iter().any(|a| a.items == alternative.items) {
+            alternatives.push(alternative);
+        }
+    }
+
+    // The item's parents in the graph, as an alternative to new ones.
+    fn current_as_alternative(&self, item: ItemId) -> Option<AlternativeEty> {
+        let ety = self.immediate_ety(item)?;
+        let mut confidences = vec![0.0; ety.items.len()];
+        let mut provenance = None;
+        for edge in self.graph.edges(item) {
+            confidences[edge.order() as usize] = edge.confidence();
+            provenance = edge.provenance();
+        }
+        Some(AlternativeEty {
+            items: ety.items.into_boxed_slice(),
+            confidences: confidences.into_boxed_slice(),
+            head: ety.head,
+            mode: ety.mode,
+            provenance,
+        })
+    }
+
+    /// Merge the item `from` into the item `into`, so that its children link
+    /// to `into` instead. If `into` has no parents, it takes those of `from`,
+    /// otherwise they are kept as an alternative. `from` is then removed.
+    /// Returns false, changing nothing, if this would link `into` to itself
+    /// or link a child to `into` twice.
+    pub(crate) fn merge_item(&mut self, from: ItemId, into: ItemId) -> bool {
+        if self.graph.contains_edge(from, into)
+            || self.graph.contains_edge(into, from)
+            || self
+                .child_edges(from)
+                .any(|e| self.graph.contains_edge(e.child(), into))
+        {
+            return false;
+        }
+        if self.immediate_ety(into).is_none() {
+            let parent_edges = self
+                .parent_edges(from)
+                .map(|e| (e.parent(), e.weight().clone()))
+                .collect_vec();
+            for (parent, data) in parent_edges {
+                self.graph.add_edge(into, parent, data);
+            }
+        } else if let Some(alternative) = self.current_as_alternative(from) {
+            self.add_alternative_ety(into, alternative);
+        }
+        let child_edges = self
+            .child_edges(from)
+            .map(|e| (e.child(), e.weight().clone()))
+            .collect_vec();
+        for (child, data) in child_edges {
+            self.graph.add_edge(child, into, data);
+        }
+        for alternative in self.alternatives.remove(&from).into_iter().flatten() {
+            self.add_alternative_ety(into, alternative);
+        }
+        for alternative in self.alternatives.values_mut().flatten() {
+            for item in alternative.items.iter_mut().filter(|item| **item == from) {
+                *item = into;
+            }
+        }
+        if let Some(alternatives) = self.alternatives.get_mut(&into) {
+            alternatives.retain(|a| !a.items.contains(&into));
+        }
+        if let Some(analysis) = self.surface_analyses.remove(&from) {
+            self.add_surface_analysis(into, analysis);
+        }
+        for analysis in self.surface_analyses.values_mut() {
+            for item in analysis.items.iter_mut().filter(|item| **item == from) {
+                *item = into;
+            }
+        }
+        if self
+            .surface_analyses
+            .get(&into)
+            .is_some_and(|a| a.items.contains(&into))
+        {
+            self.surface_analyses.remove(&into);
+        }
+        // This also removes all the edges of from.
+        self.graph.remove_node(from);
+        true
+    }
+
+    /// Whether the item is in the graph, i.e. wasn't merged into another.
+    pub(crate) fn contains(&self, id: ItemId) -> bool {
+        self.graph.contains_node(id)
+    }
+
+    pub(crate) fn remove_cycles(&mut self, strategy: CycleStrategy) -> Result<Vec<RemovedEdge>> {
+        print!("  Checking for ety link cycles... ");
+        let mut removed = vec![];
+        loop {
+            let cycle_edges: HashSet<EdgeIndex> = match strategy {
+                CycleStrategy::FeedbackArcSet => greedy_feedback_arc_set(&self.graph)
+                    .map(|e| e.id())
+                    .collect(),
+                CycleStrategy::LowestConfidence => self.least_confident_cycle_edges(false),
+                CycleStrategy::PreferImputed => self.least_confident_cycle_edges(true),
+            };
+            if cycle_edges.is_empty() {
+                break;
+            }
+            for &edge in &cycle_edges {
                 if let Some((source, _)) = self.graph.edge_endpoints(edge) {
-                    // We take not only the edges forming the fas, but all edges
-                    // that share the same source of any of the fas edges (recall:
-                    // the edge source is a child and the edge target is an
-                    // etymological parent). This is to ensure there are no
-                    // degenerate etys in the graph once we remove the edges.
-                    let edges_from_source: Vec<EdgeIndex> =
-                        self.graph.edges(source).map(|e| e.id()).collect();
-                    for e in edges_from_source {
+                    // We take not only the edges chosen to break the cycles,
+                    // but all edges that share the same source of any of them
+                    // (recall: the edge source is a child and the edge target
+                    // is an etymological parent). This is to ensure there are
+                    // no degenerate etys in the graph once we remove the edges.
+                    let edges_from_source = self
+                        .graph
+                        .edges(source)
+                        .map(|e| {
+                            let removed_edge = RemovedEdge {
+                                child: e.child(),
+                                parent: e.parent(),
+                                mode: e.mode(),
+                                confidence: e.confidence(),
+                                provenance: e.provenance(),
+                                in_cycle: cycle_edges.contains(&e.id()),
+                            };
+                            (e.id(), removed_edge)
+                        })
+                        .collect_vec();
+                    for (e, removed_edge) in edges_from_source {
                         self.graph.remove_edge(e);
+                        removed.push(removed_edge);
                     }
                 }
             }
+            // A feedback arc set breaks every cycle at once, whereas removing
+            // one edge from each strongly connected component may leave
+            // other cycles within it.
+            if strategy == CycleStrategy::FeedbackArcSet {
+                break;
+            }
+        }
+        if removed.is_empty() {
+            println!("Found none.");
+        } else {
+            println!("Removed {} ety links.", removed.len());
         }
-        Ok(())
+        Ok(removed)
+    }
+
+    // The least confident edge within each strongly connected component of
+    // the graph, every one of which is in a cycle. With prefer_imputed, edges
+    // with an imputed item at either end are taken before any others.
+    fn least_confident_cycle_edges(&self, prefer_imputed: bool) -> HashSet<EdgeIndex> {
+        let is_real = |e: &EtyEdge| {
+            prefer_imputed
+                && !self.item(e.child()).is_imputed()
+                && !self.item(e.parent()).is_imputed()
+        };
+        tarjan_scc(&self.graph)
+            .into_iter()
+            .filter(|component| component.len() > 1)
+            .filter_map(|component| {
+                let component: HashSet<ItemId> = component.into_iter().collect();
+                component
+                    .iter()
+                    .flat_map(|&item| self.graph.edges(item))
+                    .filter(|e| component.contains(&e.parent()))
+                    .min_by(|a, b| {
+                        is_real(a)
+                            .cmp(&is_real(b))
+                            .then(a.confidence().total_cmp(&b.confidence()))
+                    })
+                    .map(|e| e.id())
+            })
+            .collect()
     }
 
     pub(crate) fn add_ety(
@@ -159,23 +460,19 @@ impl EtyGraph {
         head: Option<u8>,
         ety_items: &[ItemId],
         confidences: &[f32],
+        provenance: EdgeProvenance,
     ) {
-        // Don't add ety connection if the confidence is too low. This currently
-        // should never get applied, as items.get_or_impute_item() returns a min
-        // confidence of SIMILARITY_THRESHOLD
         let min_new_confidence = confidences
             .iter()
             .min_by(|a, b| a.total_cmp(b))
             .expect("at least one");
-        if min_new_confidence < &embeddings::SIMILARITY_THRESHOLD {
-            return;
-        }
         // StableGraph allows adding multiple parallel edges from one node to
         // another. So we have to be careful to check for any already existing
         // ety links. If there are some, we keep them and don't add any new
         // ones, unless the least confidence for the new ety links is greater
         // than the greatest confidence for the old ety links. In that case, we
-        // delete all the old ones and add the new ones in their stead.
+        // delete all the old ones and add the new ones in their stead. Either
+        // way, the set of links that loses out is kept as an alternative.
         let mut old_edges = self.graph.edges(item).peekable();
         if old_edges.peek().is_some() {
             let max_old_confidence = old_edges
@@ -183,8 +480,23 @@ impl EtyGraph {
                 .max_by(|a, b| a.total_cmp(b))
                 .expect("at least one");
             if min_new_confidence <= &max_old_confidence {
+                let alternative = AlternativeEty {
+                    items: ety_items.into(),
+                    confidences: confidences.into(),
+                    head,
+                    mode,
+                    provenance: Some(provenance),
+                };
+                self.add_alternative_ety(item, alternative);
                 return;
             }
+            if let Some(alternative) = self.current_as_alternative(item) {
+                let alternatives = self.alternatives.entry(item).or_default();
+                alternatives.retain(|a| *a.items != *ety_items);
+                if !alternatives.iter().any(|a| a.items == alternative.items) {
+                    alternatives.push(alternative);
+                }
+            }
             // println!("Replacing ety for item {item:?}");
             let old_edge_ids = self.graph.edges(item).map(|e| e.id()).collect_vec();
             for old_edge_id in old_edge_ids {
@@ -199,6 +511,7 @@ impl EtyGraph {
                 order: i,
                 head: head.map_or(false, |head| head == i),
                 confidence,
+                provenance: Some(provenance),
             };
             self.graph.add_edge(item, ety_item, ety_link);
         }
@@ -275,6 +588,23 @@ impl EtyGraph {
         }
     }
 
+    /// The ancestry path from `item` (inclusive) to its head progenitor,
+    /// following the "head" parent at each step.
+    pub(crate) fn head_path(&self, item: ItemId) -> Vec<ItemId> {
+        let mut path = vec![item];
+        let mut current = item;
+        while let Some(parent) = self
+            .parent_edges(current)
+            .find(|e| e.head())
+            .map(|e| e.parent())
+            && !path.contains(&parent)
+        {
+            path.push(parent);
+            current = parent;
+        }
+        path
+    }
+
     pub(crate) fn all_progenitors(&self) -> HashMap<ItemId, Progenitors> {
         let mut progenitors = HashMap::default();
         for (item_id, _) in self.iter() {
@@ -284,6 +614,27 @@ impl EtyGraph {
         }
         progenitors
     }
+
+    /// The number of links in the longest chain from each item up to one of
+    /// its progenitors. Items with no parents, i.e. of depth 0, are left out.
+    pub(crate) fn all_depths(&self) -> HashMap<ItemId, u16> {
+        let mut depths = HashMap::default();
+        // Cycles have been removed, so in a topological order every item
+        // comes before its parents.
+        let Some(order) = toposort(&self.graph, None).ok() else {
+            return depths;
+        };
+        for &item in order.iter().rev() {
+            if let Some(depth) = self
+                .parent_edges(item)
+                .map(|e| depths.get(&e.parent()).map_or(1, |depth| depth + 1))
+                .max()
+            {
+                depths.insert(item, depth);
+            }
+        }
+        depths
+    }
 }
 
 /// Breadth-first iterator over the edges connecting `item` and its descendants.
@@ -339,6 +690,23 @@ impl EtyGraph {
     }
 }
 
+type AncestorTree<'a> = HashMap<ItemId, (usize, Option<EtyEdge<'a>>)>;
+
+// A shared ancestor, with the edges leading up to it from each of two items.
+type Connection<'a> = (ItemId, Vec<EtyEdge<'a>>, Vec<EtyEdge<'a>>);
+
+// The edges leading from the root of `tree` up to `ancestor`, in order.
+fn path_to<'a>(tree: &AncestorTree<'a>, ancestor: ItemId) -> Vec<EtyEdge<'a>> {
+    let mut path = vec![];
+    let mut current = ancestor;
+    while let Some(&(_, Some(edge))) = tree.get(&current) {
+        path.push(edge);
+        current = edge.child();
+    }
+    path.reverse();
+    path
+}
+
 /// Breadth-first iterator over the edges connecting `item` and its ancestors.
 struct AncestorEdgeIterator<'a> {
     graph: &'a EtyGraph,
@@ -372,6 +740,65 @@ impl EtyGraph {
         }
     }
 
+    // For `item` and each of its ancestors, the distance from `item` and the
+    // edge through which the ancestor is first reached going breadth-first,
+    // i.e. the last edge of a shortest path from `item` to it. Ancestors past
+    // the limits aren't expanded.
+    fn ancestor_tree(
+        &self,
+        item: ItemId,
+        limits: &TreeLimits,
+    ) -> Result<AncestorTree<'_>, QueryTimeout> {
+        let mut tree = AncestorTree::default();
+        tree.insert(item, (0, None));
+        limits.add_node();
+        let mut queue = VecDeque::from([(item, 0)]);
+        while let Some((current, distance)) = queue.pop_front() {
+            limits.check_timeout()?;
+            if !limits.can_expand(distance) {
+                continue;
+            }
+            for edge in self.parent_edges(current) {
+                if let Entry::Vacant(e) = tree.entry(edge.parent()) {
+                    e.insert((distance + 1, Some(edge)));
+                    limits.add_node();
+                    queue.push_back((edge.parent(), distance + 1));
+                }
+            }
+        }
+        Result::Ok(tree)
+    }
+
+    /// Find the shortest connection between `a` and `b` via a shared ancestor,
+    /// which may be `a` or `b` itself, among the ancestors found within
+    /// `limits`. Returns the ancestor together with the edges leading up to it
+    /// from `a` and from `b`, in order.
+    pub(crate) fn connection(
+        &self,
+        a: ItemId,
+        b: ItemId,
+        limits: &TreeLimits,
+    ) -> Result<Option<Connection<'_>>, QueryTimeout> {
+        let tree_a = self.ancestor_tree(a, limits)?;
+        let tree_b = self.ancestor_tree(b, limits)?;
+        let Some((_, ancestor)) = tree_a
+            .iter()
+            .filter_map(|(&ancestor, &(distance_a, _))| {
+                tree_b
+                    .get(&ancestor)
+                    .map(|&(distance_b, _)| (distance_a + distance_b, ancestor))
+            })
+            .min()
+        else {
+            return Result::Ok(None);
+        };
+        Result::Ok(Some((
+            ancestor,
+            path_to(&tree_a, ancestor),
+            path_to(&tree_b, ancestor),
+        )))
+    }
+
     /// Get all ancestors of `item` within `langs`.
     pub(crate) fn ancestors_in_langs<'a>(
         &'a self,
@@ -383,3 +810,110 @@ impl EtyGraph {
             .map(|e| e.parent())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{items::ImputedItem, string_pool::StringPool};
+
+    use std::str::FromStr;
+
+    fn add_item(graph: &mut EtyGraph, string_pool: &StringPool, term: &str) -> ItemId {
+        let lang = Lang::from_str("en").unwrap();
+        graph.add(Item::Imputed(ImputedItem {
+            ety_num: 1,
+            lang,
+            term: lang.new_langterm(string_pool, term).term,
+            romanization: None,
+            from: ItemId::new(0),
+        }))
+    }
+
+    #[test]
+    fn losing_ety_links_are_kept_as_alternatives() {
+        let string_pool = StringPool::new();
+        let mut graph = EtyGraph::default();
+        let child = add_item(&mut graph, &string_pool, "child");
+        let x = add_item(&mut graph, &string_pool, "x");
+        let y = add_item(&mut graph, &string_pool, "y");
+        let ety = EdgeProvenance::new(EdgeSource::Etymology, None, Some(1));
+        let desc = EdgeProvenance::new(EdgeSource::Descendants, None, Some(2));
+        graph.add_ety(child, EtyMode::Derived, Some(0), &[x], &[0.5], ety);
+        graph.add_ety(child, EtyMode::Borrowed, Some(0), &[y], &[0.2], desc);
+        graph.add_ety(child, EtyMode::Borrowed, Some(0), &[y], &[0.3], desc);
+        assert_eq!(graph.immediate_ety(child).unwrap().items, vec![x]);
+        let alternatives = graph.alternative_etys(child);
+        assert_eq!(alternatives.len(), 1);
+        assert_eq!(*alternatives[0].items, [y]);
+        // A more confident set replaces the parents, which become an
+        // alternative in turn.
+        graph.add_ety(child, EtyMode::Borrowed, Some(0), &[y], &[0.9], desc);
+        assert_eq!(graph.immediate_ety(child).unwrap().items, vec![y]);
+        assert!(graph
+            .parent_edges(child)
+            .all(|e| e.provenance() == Some(desc)));
+        let alternatives = graph.alternative_etys(child);
+        assert_eq!(alternatives.len(), 1);
+        assert_eq!(*alternatives[0].items, [x]);
+        assert!((alternatives[0].confidence() - 0.5).abs() < f32::EPSILON);
+        assert_eq!(alternatives[0].provenance, Some(ety));
+    }
+
+    #[test]
+    fn depth_is_that_of_the_longest_chain_to_a_progenitor() {
+        let string_pool = StringPool::new();
+        let mut graph = EtyGraph::default();
+        let a = add_item(&mut graph, &string_pool, "a");
+        let b = add_item(&mut graph, &string_pool, "b");
+        let c = add_item(&mut graph, &string_pool, "c");
+        let ety = EdgeProvenance::new(EdgeSource::Etymology, None, None);
+        graph.add_ety(a, EtyMode::Compound, None, &[b, c], &[1.0, 1.0], ety);
+        graph.add_ety(b, EtyMode::Inherited, Some(0), &[c], &[1.0], ety);
+        let depths = graph.all_depths();
+        assert_eq!(depths.get(&a), Some(&2));
+        assert_eq!(depths.get(&b), Some(&1));
+        assert_eq!(depths.get(&c), None);
+    }
+
+    #[test]
+    fn merged_item_is_replaced_by_the_item_merged_into() {
+        let string_pool = StringPool::new();
+        let mut graph = EtyGraph::default();
+        let child = add_item(&mut graph, &string_pool, "child");
+        let imputed = add_item(&mut graph, &string_pool, "x");
+        let real = add_item(&mut graph, &string_pool, "x");
+        let parent = add_item(&mut graph, &string_pool, "parent");
+        let ety = EdgeProvenance::new(EdgeSource::Etymology, None, None);
+        graph.add_ety(child, EtyMode::Borrowed, Some(0), &[imputed], &[0.5], ety);
+        graph.add_ety(imputed, EtyMode::Inherited, Some(0), &[parent], &[0.5], ety);
+        assert!(graph.merge_item(imputed, real));
+        assert!(!graph.contains(imputed));
+        assert_eq!(graph.immediate_ety(child).unwrap().items, vec![real]);
+        assert_eq!(graph.immediate_ety(real).unwrap().items, vec![parent]);
+        // Merging an item into its own parent would link the item to itself.
+        assert!(!graph.merge_item(real, parent));
+    }
+
+    #[test]
+    fn lowest_confidence_cycle_link_is_removed_with_its_ety() {
+        let string_pool = StringPool::new();
+        let mut graph = EtyGraph::default();
+        let a = add_item(&mut graph, &string_pool, "a");
+        let b = add_item(&mut graph, &string_pool, "b");
+        let c = add_item(&mut graph, &string_pool, "c");
+        let ety = EdgeProvenance::new(EdgeSource::Etymology, None, None);
+        graph.add_ety(a, EtyMode::Inherited, Some(0), &[b], &[0.9], ety);
+        graph.add_ety(b, EtyMode::Compound, Some(0), &[a, c], &[0.3, 0.8], ety);
+        let removed = graph
+            .remove_cycles(CycleStrategy::LowestConfidence)
+            .unwrap();
+        assert_eq!(graph.immediate_ety(a).unwrap().items, vec![b]);
+        assert!(graph.immediate_ety(b).is_none());
+        let removed = removed
+            .iter()
+            .map(|e| (e.child, e.parent, e.in_cycle))
+            .sorted_by_key(|&(_, parent, _)| parent)
+            .collect_vec();
+        assert_eq!(removed, [(b, a, true), (b, c, false)]);
+    }
+}
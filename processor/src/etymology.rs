@@ -1,11 +1,14 @@
 use crate::{
     embeddings::Embeddings,
+    ety_graph::{AlternativeEty, EdgeProvenance, EdgeSource},
     etymology_templates::{EtyMode, TemplateKind},
     items::{ItemId, Items, Retrieval},
     langterm::LangTerm,
     languages::Lang,
     progress_bar,
-    string_pool::StringPool,
+    root::ROOT_TEMPLATES,
+    string_pool::{StringPool, Symbol},
+    validation::AnomalyKind,
     wiktextract_json::{Affix, WiktextractJson, WiktextractJsonItem, WiktextractJsonValidStr},
     HashSet,
 };
@@ -13,14 +16,77 @@ use crate::{
 use std::{mem, str::FromStr};
 
 use anyhow::{anyhow, ensure, Ok, Result};
+use serde::{Deserialize, Serialize};
 use simd_json::ValueAccess;
 
+// How much the confidence of a link drops with each step down a chain of
+// templates, e.g. for the link to Z in "From X, from Y, from Z". It is never
+// lowered below the similarity threshold, so the link is kept.
+const CHAIN_CONFIDENCE_DECAY: f32 = 0.9;
+
 // models the basic info from a wiktionary etymology template
-#[derive(Hash, Eq, PartialEq, Debug)]
+#[derive(Hash, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub(crate) struct RawEtyTemplate {
     pub(crate) langterms: Box<[LangTerm]>, // e.g. "en" "re-", "en" "do"
     pub(crate) mode: EtyMode,              // e.g. Prefix
     pub(crate) head: Option<u8>,           // e.g. 1 (the index of "do")
+    #[serde(default)]
+    pub(crate) name: Option<Symbol>, // e.g. "prefix", None if not from a template
+    #[serde(default)]
+    pub(crate) hedge: Option<Hedge>, // e.g. Possibly for "Possibly from {{der|...}}"
+}
+
+// Wording in the ety text that hedges the link given by a template, which
+// Wiktionary otherwise only marks in prose.
+#[derive(Clone, Copy, Hash, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub(crate) enum Hedge {
+    // e.g. "probably", "likely"
+    Probably,
+    // e.g. "possibly", "perhaps", "uncertain"
+    Possibly,
+}
+
+impl Hedge {
+    // The words that hedge a link, lowercased.
+    const PROBABLY: &'static [&'static str] = &["probably", "likely", "presumably"];
+    const POSSIBLY: &'static [&'static str] = &[
+        "possibly",
+        "perhaps",
+        "maybe",
+        "conceivably",
+        "uncertain",
+        "doubtful",
+        "dubious",
+    ];
+
+    // The hedge in the clause of the ety text before the template's expansion,
+    // if there is one. The expansion is looked for from `cursor` on, which is
+    // moved past it, so that repeated expansions are found in turn.
+    fn before(ety_text: &str, expansion: &str, cursor: &mut usize) -> Option<Self> {
+        let start = *cursor + ety_text.get(*cursor..)?.find(expansion)?;
+        *cursor = start + expansion.len();
+        let before = &ety_text[..start];
+        let clause_start = before.rfind(['.', ';', ',', '(']).map_or(0, |i| i + 1);
+        let mut hedge = None;
+        for word in before[clause_start..].split(|c: char| !c.is_alphabetic()) {
+            let word = word.to_lowercase();
+            if Self::POSSIBLY.contains(&word.as_str()) {
+                return Some(Self::Possibly);
+            }
+            if Self::PROBABLY.contains(&word.as_str()) {
+                hedge = Some(Self::Probably);
+            }
+        }
+        hedge
+    }
+
+    // How much the confidence of a hedged link is scaled by.
+    pub(crate) fn confidence_factor(self) -> f32 {
+        match self {
+            Self::Probably => 0.8,
+            Self::Possibly => 0.5,
+        }
+    }
 }
 
 impl RawEtyTemplate {
@@ -29,21 +95,33 @@ impl RawEtyTemplate {
             langterms: Box::from([langterm]),
             mode,
             head: Some(0),
+            name: None,
+            hedge: None,
         }
     }
 }
 
-#[derive(Hash, Eq, PartialEq, Debug)]
+#[derive(Hash, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub(crate) enum ParsedRawEtyTemplate {
     Parsed(RawEtyTemplate),
     Skipped,
 }
 
-#[derive(Hash, Eq, PartialEq, Debug)]
+#[derive(Hash, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub(crate) struct RawEtymology {
     pub(crate) templates: Box<[ParsedRawEtyTemplate]>,
 }
 
+impl RawEtymology {
+    // Whether any template gives parents. Surface analyses don't count, since
+    // they aren't linked as parents.
+    pub(crate) fn has_parsed_template(&self) -> bool {
+        self.templates.iter().any(
+            |t| matches!(t, ParsedRawEtyTemplate::Parsed(t) if t.mode != EtyMode::SurfaceAnalysis),
+        )
+    }
+}
+
 impl From<Vec<ParsedRawEtyTemplate>> for RawEtymology {
     fn from(templates: Vec<ParsedRawEtyTemplate>) -> Self {
         Self {
@@ -53,7 +131,7 @@ impl From<Vec<ParsedRawEtyTemplate>> for RawEtymology {
 }
 
 fn process_derived_kind_json_template(
-    string_pool: &mut StringPool,
+    string_pool: &StringPool,
     args: &WiktextractJson,
     mode: EtyMode,
 ) -> Option<RawEtyTemplate> {
@@ -69,7 +147,7 @@ fn process_derived_kind_json_template(
 }
 
 fn process_abbrev_kind_json_template(
-    string_pool: &mut StringPool,
+    string_pool: &StringPool,
     args: &WiktextractJson,
     mode: EtyMode,
     lang: Lang,
@@ -80,7 +158,7 @@ fn process_abbrev_kind_json_template(
 }
 
 fn process_prefix_json_template(
-    string_pool: &mut StringPool,
+    string_pool: &StringPool,
     args: &WiktextractJson,
     lang: Lang,
 ) -> Option<RawEtyTemplate> {
@@ -92,11 +170,13 @@ fn process_prefix_json_template(
         langterms: Box::new([ety_prefix, ety_term]),
         mode: EtyMode::Prefix,
         head: Some(1),
+        name: None,
+        hedge: None,
     })
 }
 
 fn process_suffix_json_template(
-    string_pool: &mut StringPool,
+    string_pool: &StringPool,
     args: &WiktextractJson,
     lang: Lang,
 ) -> Option<RawEtyTemplate> {
@@ -108,11 +188,13 @@ fn process_suffix_json_template(
         langterms: Box::new([ety_term, ety_suffix]),
         mode: EtyMode::Suffix,
         head: Some(0),
+        name: None,
+        hedge: None,
     })
 }
 
 fn process_circumfix_json_template(
-    string_pool: &mut StringPool,
+    string_pool: &StringPool,
     args: &WiktextractJson,
     lang: Lang,
 ) -> Option<RawEtyTemplate> {
@@ -127,11 +209,13 @@ fn process_circumfix_json_template(
         langterms: Box::new([ety_term, ety_circumfix]),
         mode: EtyMode::Circumfix,
         head: Some(0),
+        name: None,
+        hedge: None,
     })
 }
 
 fn process_infix_json_template(
-    string_pool: &mut StringPool,
+    string_pool: &StringPool,
     args: &WiktextractJson,
     lang: Lang,
 ) -> Option<RawEtyTemplate> {
@@ -144,11 +228,13 @@ fn process_infix_json_template(
         langterms: Box::new([ety_term, ety_infix]),
         mode: EtyMode::Infix,
         head: Some(0),
+        name: None,
+        hedge: None,
     })
 }
 
 fn process_confix_json_template(
-    string_pool: &mut StringPool,
+    string_pool: &StringPool,
     args: &WiktextractJson,
     lang: Lang,
 ) -> Option<RawEtyTemplate> {
@@ -163,6 +249,8 @@ fn process_confix_json_template(
             langterms: Box::new([ety_prefix, ety_term, ety_suffix]),
             mode: EtyMode::Confix,
             head: Some(1),
+            name: None,
+            hedge: None,
         });
     }
     let ety_suffix = format!("-{ety2}");
@@ -171,6 +259,8 @@ fn process_confix_json_template(
         langterms: Box::new([ety_prefix, ety_suffix]),
         mode: EtyMode::Confix,
         head: None, // no true head here
+        name: None,
+        hedge: None,
     })
 }
 
@@ -215,7 +305,7 @@ fn affixation_kind(affixes: &[Affix], n_base_terms: usize) -> EtyMode {
 }
 
 fn process_compound_kind_json_template(
-    string_pool: &mut StringPool,
+    string_pool: &StringPool,
     args: &WiktextractJson,
     mode: EtyMode,
     lang: Lang,
@@ -263,13 +353,15 @@ fn process_compound_kind_json_template(
                 mode
             },
             head: (n_base_terms == 1).then_some(head), // see above
+            name: None,
+            hedge: None,
         });
     }
     None
 }
 
 fn process_vrddhi_kind_json_template(
-    string_pool: &mut StringPool,
+    string_pool: &StringPool,
     args: &WiktextractJson,
     ety_mode: EtyMode,
 ) -> Option<RawEtyTemplate> {
@@ -289,8 +381,8 @@ pub(crate) fn validate_ety_template_lang(args: &WiktextractJson, lang: Lang) ->
     Ok(())
 }
 
-fn process_json_ety_template(
-    string_pool: &mut StringPool,
+pub(crate) fn process_json_ety_template(
+    string_pool: &StringPool,
     template: &WiktextractJson,
     lang: Lang,
 ) -> Option<RawEtyTemplate> {
@@ -301,33 +393,133 @@ fn process_json_ety_template(
     // vrddhi-kind templates are unusual in that their "1" arg is not the lang
     // of the term whose ety is being described. Therefore we avoid calling
     // validate_ety_template_lang() on them.
-    if template_kind == Some(TemplateKind::Vrddhi) {
-        return process_vrddhi_kind_json_template(string_pool, args, ety_mode);
-    }
-    validate_ety_template_lang(args, lang).ok()?;
-    match template_kind {
-        Some(TemplateKind::Derived) => {
-            process_derived_kind_json_template(string_pool, args, ety_mode)
-        }
-        Some(TemplateKind::Abbreviation) => {
-            process_abbrev_kind_json_template(string_pool, args, ety_mode, lang)
+    let mut raw_ety_template = if template_kind == Some(TemplateKind::Vrddhi) {
+        process_vrddhi_kind_json_template(string_pool, args, ety_mode)
+    } else {
+        validate_ety_template_lang(args, lang).ok()?;
+        match template_kind {
+            Some(TemplateKind::Derived) => {
+                process_derived_kind_json_template(string_pool, args, ety_mode)
+            }
+            Some(TemplateKind::Abbreviation) => {
+                process_abbrev_kind_json_template(string_pool, args, ety_mode, lang)
+            }
+            Some(TemplateKind::Compound) => match ety_mode {
+                EtyMode::Prefix => process_prefix_json_template(string_pool, args, lang),
+                EtyMode::Suffix => process_suffix_json_template(string_pool, args, lang),
+                EtyMode::Circumfix => process_circumfix_json_template(string_pool, args, lang),
+                EtyMode::Infix => process_infix_json_template(string_pool, args, lang),
+                EtyMode::Confix => process_confix_json_template(string_pool, args, lang),
+                _ => process_compound_kind_json_template(string_pool, args, ety_mode, lang),
+            },
+            _ => None,
         }
-        Some(TemplateKind::Compound) => match ety_mode {
-            EtyMode::Prefix => process_prefix_json_template(string_pool, args, lang),
-            EtyMode::Suffix => process_suffix_json_template(string_pool, args, lang),
-            EtyMode::Circumfix => process_circumfix_json_template(string_pool, args, lang),
-            EtyMode::Infix => process_infix_json_template(string_pool, args, lang),
-            EtyMode::Confix => process_confix_json_template(string_pool, args, lang),
-            _ => process_compound_kind_json_template(string_pool, args, ety_mode, lang),
-        },
-        _ => None,
-    }
+    }?;
+    raw_ety_template.name = Some(string_pool.get_or_intern(name));
+    Some(raw_ety_template)
 }
 
 impl WiktextractJsonItem<'_> {
+    // For the line errors. Any ety template that we recognize but fail to
+    // parse would otherwise silently become a Skipped template.
+    pub(crate) fn ensure_ety_templates_parse(
+        &self,
+        string_pool: &StringPool,
+        lang: Lang,
+    ) -> Result<()> {
+        let Some(templates) = self.json.get_array("etymology_templates") else {
+            return Ok(());
+        };
+        for template in templates {
+            if let Some(name) = template.get_valid_str("name")
+                && EtyMode::from_str(name).is_ok_and(|mode| mode.template_kind().is_some())
+            {
+                ensure!(
+                    process_json_ety_template(string_pool, template, lang).is_some(),
+                    "could not parse ety template {template}"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    // For the line errors. A template with an unknown lang would otherwise
+    // silently become a Skipped template, or lose the terms in that lang. The
+    // item's own lang in "1" isn't checked, since it must match the item's.
+    pub(crate) fn ensure_known_ety_template_langs(&self) -> Result<()> {
+        let Some(templates) = self.json.get_array("etymology_templates") else {
+            return Ok(());
+        };
+        for template in templates {
+            let (Some(name), Some(args)) = (template.get_valid_str("name"), template.get("args"))
+            else {
+                continue;
+            };
+            let mut codes = vec![];
+            match EtyMode::from_str(name)
+                .ok()
+                .and_then(EtyMode::template_kind)
+            {
+                _ if matches!(name, "mention" | "m") => codes.extend(args.get_valid_str("1")),
+                Some(TemplateKind::Derived) => {
+                    codes.extend(
+                        args.get_valid_str("2")
+                            .into_iter()
+                            .flat_map(|c| c.split(',')),
+                    );
+                }
+                Some(TemplateKind::Vrddhi) => codes.extend(args.get_valid_str("1")),
+                Some(TemplateKind::Compound) => {
+                    for n in (2..).take_while(|n| args.get_valid_term(&n.to_string()).is_some()) {
+                        codes.extend(args.get_valid_str(&format!("lang{n}")));
+                    }
+                }
+                _ => {}
+            }
+            for code in codes {
+                ensure!(
+                    Lang::from_str(code).is_ok(),
+                    "unknown lang code {code} in ety template {template}"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    // For the line errors. Templates of a kind that is handled but that fail
+    // to parse are unparsed ety template errors, and root templates are
+    // parsed separately, so the rest of the skipped templates are of a kind
+    // that isn't handled. Only a standard ety has skipped templates, with one
+    // raw template for each template in the ety section.
+    pub(crate) fn ensure_no_skipped_ety_templates(
+        &self,
+        raw_etymology: &RawEtymology,
+    ) -> Result<()> {
+        let Some(templates) = self.json.get_array("etymology_templates") else {
+            return Ok(());
+        };
+        let skipped = raw_etymology
+            .templates
+            .iter()
+            .zip(templates)
+            .filter(|(raw, _)| matches!(raw, ParsedRawEtyTemplate::Skipped))
+            .map(|(_, template)| template.get_valid_str("name").unwrap_or_default())
+            .filter(|name| {
+                !ROOT_TEMPLATES.contains(name)
+                    && !EtyMode::from_str(name).is_ok_and(|mode| mode.template_kind().is_some())
+            })
+            .collect::<Vec<_>>();
+        ensure!(
+            skipped.is_empty(),
+            "skipped ety templates of kinds that aren't handled: {}",
+            skipped.join(", ")
+        );
+        Ok(())
+    }
+
     // Many ety sections contain a single {{m}} template and no others, and
     // consist only of "From {{m..." (or similar). This is to handle this case.
-    fn get_single_mention_ety(&self, string_pool: &mut StringPool) -> Option<RawEtymology> {
+    fn get_single_mention_ety(&self, string_pool: &StringPool) -> Option<RawEtymology> {
         // Since we handle {{root}} separately, we still want to get etys where
         // there is say one {{root}} then one "From {{m..."
         let mut templates = self
@@ -354,15 +546,28 @@ impl WiktextractJsonItem<'_> {
         let mention_term = args.get_valid_term("2")?;
         let mention_lang = Lang::from_str(mention_lang).ok()?;
         let mention_langterm = mention_lang.new_langterm(string_pool, mention_term);
-        let ety = RawEtyTemplate::new(mention_langterm, EtyMode::Mention);
+        let mut ety = RawEtyTemplate::new(mention_langterm, EtyMode::Mention);
+        ety.name = Some(string_pool.get_or_intern(name));
+        ety.hedge = self.hedge(template, &mut 0);
         Some(vec![ParsedRawEtyTemplate::Parsed(ety)].into())
     }
 
-    fn get_standard_ety(&self, string_pool: &mut StringPool, lang: Lang) -> Option<RawEtymology> {
+    // The hedge on the template in the ety text, looking from `cursor` on.
+    fn hedge(&self, template: &WiktextractJson, cursor: &mut usize) -> Option<Hedge> {
+        let ety_text = self.json.get_valid_str("etymology_text")?;
+        let expansion = template.get_valid_str("expansion")?;
+        Hedge::before(ety_text, expansion, cursor)
+    }
+
+    fn get_standard_ety(&self, string_pool: &StringPool, lang: Lang) -> Option<RawEtymology> {
         let templates = self.json.get_array("etymology_templates")?;
         let mut raw_ety_templates = Vec::with_capacity(templates.len());
+        let mut cursor = 0;
         for template in templates {
-            if let Some(raw_ety_template) = process_json_ety_template(string_pool, template, lang) {
+            if let Some(mut raw_ety_template) =
+                process_json_ety_template(string_pool, template, lang)
+            {
+                raw_ety_template.hedge = self.hedge(template, &mut cursor);
                 raw_ety_templates.push(ParsedRawEtyTemplate::Parsed(raw_ety_template));
             } else {
                 raw_ety_templates.push(ParsedRawEtyTemplate::Skipped);
@@ -375,7 +580,7 @@ impl WiktextractJsonItem<'_> {
     // is listed as a "form_of" (item.senses[0].form_of[0].word)
     // or "alt_of" (item.senses[0].alt_of[0].word) another term.
     // e.g. "happenin'" is listed as an alt_of of "happening".
-    fn get_form_ety(&self, string_pool: &mut StringPool, lang: Lang) -> Option<RawEtymology> {
+    fn get_form_ety(&self, string_pool: &StringPool, lang: Lang) -> Option<RawEtymology> {
         let alt_term = self
             .json
             .get_array("senses")
@@ -394,7 +599,7 @@ impl WiktextractJsonItem<'_> {
 
     pub(crate) fn get_etymology(
         &self,
-        string_pool: &mut StringPool,
+        string_pool: &StringPool,
         lang: Lang,
     ) -> Option<RawEtymology> {
         self.get_single_mention_ety(string_pool)
@@ -445,36 +650,62 @@ impl Items {
         items_needing_embedding
     }
 
-    // For now we'll just take the first template, unless imputation is requred,
-    // in which case we impute a chain of relations until the first known item
-    // is hit. But cf. notes.md. Only to be called once all json items have been
-    // processed into items.
+    // The templates are processed as a chain, e.g. "From X, from Y, from Z",
+    // each giving the parents of the (single) parent given by the last. If a
+    // parent is imputed, the chain goes on from it, as far as the imputation
+    // policy allows. If it is real, the chain only goes on from it if it has no
+    // ety of its own, which would otherwise be the more reliable source for its
+    // parents. Links further down the chain get less confidence. But cf.
+    // notes.md. Only to be called once all json items have been processed
+    // into items.
     fn process_item_raw_etymology(
         &mut self,
+        string_pool: &StringPool,
         embeddings: &Embeddings,
         item: ItemId,
+        line: Option<usize>,
         raw_etymology: &RawEtymology,
+        with_own_ety: &HashSet<ItemId>,
     ) -> Result<()> {
         let mut current_item = item; // for tracking possibly imputed items
         let mut next_item = item; // for tracking possibly imputed items
         let mut item_embeddings = vec![];
         let mut imputation_chain_in_progress = false;
+        // the number of links already made down the chain
+        let mut chain_depth = 0;
         for template in &*raw_etymology.templates {
             match template {
+                // A surface analysis isn't descent, so it is kept apart from the
+                // item's ety rather than competing with it.
+                ParsedRawEtyTemplate::Parsed(template)
+                    if template.mode == EtyMode::SurfaceAnalysis =>
+                {
+                    self.add_surface_analysis(string_pool, embeddings, item, line, template)?;
+                }
                 ParsedRawEtyTemplate::Parsed(template) => {
                     item_embeddings.push(embeddings.get(self.get(current_item), current_item)?);
                     let mut ety_items = Vec::with_capacity(template.langterms.len());
                     let mut confidences = Vec::with_capacity(template.langterms.len());
                     for &ety_langterm in &*template.langterms {
-                        let Retrieval {
+                        let Some(Retrieval {
                             item_id: ety_item,
-                            confidence,
-                        } = self.get_or_impute_item(
+                            mut confidence,
+                        }) = self.get_or_impute_item(
+                            string_pool,
                             embeddings,
                             &item_embeddings,
                             item,
+                            current_item,
                             ety_langterm,
-                        )?;
+                            true,
+                        )?
+                        else {
+                            // The imputation policy doesn't allow imputing an
+                            // item for this lang-term, so we can't go further.
+                            self.anomalies
+                                .record(AnomalyKind::ImputationTruncation, item, None);
+                            return Ok(());
+                        };
                         if self.get(ety_item).is_imputed() {
                             if template.langterms.len() == 1
                             // $$$ It would be better to have language timespan data and
@@ -497,30 +728,67 @@ impl Items {
                                 // compound-kind template. We won't bother trying to do
                                 // convoluted ety link imputations for such cases at the
                                 // moment. So we stop processing templates here.
+                                self.anomalies.record(
+                                    AnomalyKind::ImputationTruncation,
+                                    item,
+                                    Some(ety_item),
+                                );
                                 return Ok(());
                             }
                         } else {
                             imputation_chain_in_progress = false;
+                            next_item = ety_item;
+                        }
+                        if chain_depth > 0 {
+                            confidence = (confidence * CHAIN_CONFIDENCE_DECAY.powi(chain_depth))
+                                .max(self.options.similarity_threshold);
+                        }
+                        // A hedged link is also kept, if less confidently.
+                        if let Some(hedge) = template.hedge {
+                            confidence = (confidence * hedge.confidence_factor())
+                                .max(self.options.similarity_threshold);
                         }
                         ety_items.push(ety_item);
                         confidences.push(confidence);
                     }
 
-                    self.graph.add_ety(
+                    let source = if template.mode == EtyMode::Form {
+                        EdgeSource::FormFallback
+                    } else {
+                        EdgeSource::Etymology
+                    };
+                    self.add_ety(
                         current_item,
                         template.mode,
                         template.head,
                         &ety_items,
                         &confidences,
+                        EdgeProvenance::new(source, template.name, line),
                     );
 
-                    if !imputation_chain_in_progress {
+                    let chain_goes_on = if imputation_chain_in_progress {
+                        self.options.imputation_policy.allows_chains()
+                    } else {
+                        template.langterms.len() == 1 && !with_own_ety.contains(&next_item)
+                    };
+                    if !chain_goes_on {
                         return Ok(());
                     }
                     current_item = next_item;
+                    chain_depth += 1;
                 }
                 ParsedRawEtyTemplate::Skipped => {
                     if imputation_chain_in_progress {
+                        self.anomalies.record(
+                            AnomalyKind::ImputationTruncation,
+                            item,
+                            Some(current_item),
+                        );
+                        return Ok(());
+                    }
+                    // A skipped template may have been the next link in the
+                    // chain, so it can't go on past it.
+                    if chain_depth > 0 {
                         return Ok(());
                     }
                 }
@@ -529,15 +797,103 @@ impl Items {
         Ok(())
     }
 
-    pub(crate) fn process_raw_etymologies(&mut self, embeddings: &Embeddings) -> Result<()> {
+    fn add_surface_analysis(
+        &mut self,
+        string_pool: &StringPool,
+        embeddings: &Embeddings,
+        item: ItemId,
+        line: Option<usize>,
+        template: &RawEtyTemplate,
+    ) -> Result<()> {
+        let embedding = embeddings.get(self.get(item), item)?;
+        let mut items = Vec::with_capacity(template.langterms.len());
+        let mut confidences = Vec::with_capacity(template.langterms.len());
+        for &langterm in &*template.langterms {
+            let Some(Retrieval {
+                item_id: part,
+                confidence,
+            }) = self.get_or_impute_item(
+                string_pool,
+                embeddings,
+                &embedding,
+                item,
+                item,
+                langterm,
+                false,
+            )?
+            else {
+                return Ok(());
+            };
+            if part == item {
+                self.anomalies.record(AnomalyKind::SelfLoop, item, None);
+                return Ok(());
+            }
+            items.push(part);
+            confidences.push(confidence);
+        }
+        let analysis = AlternativeEty {
+            items: items.into(),
+            confidences: confidences.into(),
+            head: template.head,
+            mode: template.mode,
+            provenance: Some(EdgeProvenance::new(
+                EdgeSource::Etymology,
+                template.name,
+                line,
+            )),
+        };
+        self.graph.add_surface_analysis(item, analysis);
+        Ok(())
+    }
+
+    pub(crate) fn process_raw_etymologies(
+        &mut self,
+        string_pool: &StringPool,
+        embeddings: &Embeddings,
+    ) -> Result<()> {
         let n = self.raw_templates.ety.len();
         let pb = progress_bar(n, "Processing etymologies")?;
         let raw_templates_ety = mem::take(&mut self.raw_templates.ety);
+        let item_lines = self.item_lines();
+        let with_own_ety = raw_templates_ety
+            .iter()
+            .filter(|(_, ety)| ety.has_parsed_template())
+            .map(|(&item_id, _)| item_id)
+            .collect();
         for (item_id, ety) in raw_templates_ety {
-            self.process_item_raw_etymology(embeddings, item_id, &ety)?;
+            let line = item_lines.get(&item_id).copied();
+            self.process_item_raw_etymology(
+                string_pool,
+                embeddings,
+                item_id,
+                line,
+                &ety,
+                &with_own_ety,
+            )?;
             pb.inc(1);
         }
         pb.finish();
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hedges_before_templates() {
+        let text = "Possibly from Old French foo, from Latin bar. Probably not from Greek baz.";
+        let mut cursor = 0;
+        assert_eq!(
+            Hedge::before(text, "Old French foo", &mut cursor),
+            Some(Hedge::Possibly)
+        );
+        assert_eq!(Hedge::before(text, "Latin bar", &mut cursor), None);
+        assert_eq!(
+            Hedge::before(text, "Greek baz", &mut cursor),
+            Some(Hedge::Probably)
+        );
+        assert_eq!(Hedge::before(text, "Latin bar", &mut cursor), None);
+    }
+}
@@ -1,5 +1,5 @@
 use serde::{Deserialize, Serialize};
-use strum::{AsRefStr, EnumString, IntoStaticStr};
+use strum::{AsRefStr, EnumIter, EnumString, IntoStaticStr};
 
 #[derive(
     Hash,
@@ -11,6 +11,7 @@ use strum::{AsRefStr, EnumString, IntoStaticStr};
     AsRefStr,
     IntoStaticStr,
     EnumString,
+    EnumIter,
     Serialize,
     Deserialize,
 )]
@@ -255,6 +256,10 @@ pub(crate) enum EtyMode {
         to_string = "form", // not a wiktionary template, only used for writing
     )]
     Form, // ad-hoc mode used when term is wiktextract alt or form of another
+    #[strum(
+        to_string = "sense of", // not a wiktionary template, only used for writing
+    )]
+    SenseOf, // ad-hoc mode linking a pos item to its ety item, with pos granularity
     #[strum(
         to_string = "morphological derivation", // not a wiktionary template, only used for writing
     )]
@@ -0,0 +1,279 @@
+//! Evaluation of disambiguation against a gold set of curated cases, each a
+//! child item and the items of its parent's lang and term, one of which is its
+//! actual parent. Each case is disambiguated as in generating the ety graph,
+//! with the configured model and similarity threshold, so that these can be
+//! compared by how often they pick the right parent.
+
+use crate::{
+    embeddings::{self, Comparand, Embeddings},
+    items::ItemId,
+    languages::Lang,
+    ProcessingOptions,
+};
+
+use std::{
+    fs::{self, File},
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use anyhow::{ensure, Context, Ok, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use simd_json::to_borrowed_value;
+
+#[derive(Deserialize)]
+struct GoldItem {
+    lang: String,
+    term: String,
+    ety: Option<String>,
+    #[serde(default)]
+    glosses: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct GoldCandidate {
+    ety: Option<String>,
+    #[serde(default)]
+    glosses: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GoldCase {
+    child: GoldItem,
+    parent_lang: String,
+    parent_term: String,
+    candidates: Vec<GoldCandidate>,
+    // the index of the actual parent in candidates
+    correct: usize,
+}
+
+fn read_gold(path: &Path) -> Result<Vec<GoldCase>> {
+    let mut cases = vec![];
+    for (i, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let case: GoldCase = serde_json::from_str(&line)
+            .with_context(|| format!("malformed gold case on line {}", i + 1))?;
+        ensure!(
+            case.correct < case.candidates.len(),
+            "the correct candidate of the gold case on line {} is out of range",
+            i + 1
+        );
+        cases.push(case);
+    }
+    Ok(cases)
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Outcome {
+    Correct,
+    Wrong,
+    // no candidate was similar enough to link
+    Unlinked,
+}
+
+impl Outcome {
+    // The outcome of disambiguating among candidates of these similarities,
+    // the earliest of equally similar candidates winning.
+    fn of(similarities: &[f32], correct: usize, threshold: f32) -> Self {
+        let best = similarities.iter().enumerate().reduce(|best, candidate| {
+            if candidate.1 > best.1 {
+                candidate
+            } else {
+                best
+            }
+        });
+        match best {
+            Some((_, &similarity)) if similarity < threshold => Self::Unlinked,
+            Some((i, _)) if i == correct => Self::Correct,
+            _ => Self::Wrong,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Correct => "correct",
+            Self::Wrong => "wrong",
+            Self::Unlinked => "unlinked",
+        }
+    }
+}
+
+fn add(
+    embeddings: &mut Embeddings,
+    lang: Lang,
+    term: &str,
+    ety: Option<&String>,
+    glosses: &[String],
+    item_id: ItemId,
+) -> Result<()> {
+    // The texts are embedded as if they were a line of the wiktextract data.
+    let senses = glosses
+        .iter()
+        .map(|gloss| json!({ "glosses": [gloss] }))
+        .collect::<Vec<_>>();
+    let mut line = serde_json::to_vec(&json!({
+        "etymology_text": ety.map_or("", String::as_str),
+        "senses": senses,
+    }))?;
+    let json_item = to_borrowed_value(&mut line)?;
+    embeddings.add(&json_item, lang.name(), term, item_id)
+}
+
+/// Disambiguate each case of the gold set at `gold_path` and report how many
+/// were disambiguated correctly, optionally writing the outcome of each case
+/// to `report_path` as JSON.
+pub(crate) fn evaluate(
+    gold_path: &Path,
+    config: &embeddings::Config,
+    options: ProcessingOptions,
+    report_path: Option<&Path>,
+) -> Result<()> {
+    let cases = read_gold(gold_path)?;
+    println!(
+        "Evaluating disambiguation on {} gold cases from {}...",
+        cases.len(),
+        gold_path.display()
+    );
+    let mut embeddings = Embeddings::new(config)?;
+    let mut next_id = 0u32;
+    let mut ids = vec![];
+    for case in &cases {
+        let child_lang = case.child.lang.parse()?;
+        let parent_lang = case.parent_lang.parse()?;
+        let child_id = ItemId::from(next_id);
+        next_id += 1;
+        let child = &case.child;
+        add(
+            &mut embeddings,
+            child_lang,
+            &child.term,
+            child.ety.as_ref(),
+            &child.glosses,
+            child_id,
+        )?;
+        let mut candidate_ids = vec![];
+        for candidate in &case.candidates {
+            let candidate_id = ItemId::from(next_id);
+            next_id += 1;
+            add(
+                &mut embeddings,
+                parent_lang,
+                &case.parent_term,
+                candidate.ety.as_ref(),
+                &candidate.glosses,
+                candidate_id,
+            )?;
+            candidate_ids.push(candidate_id);
+        }
+        ids.push((child_id, candidate_ids));
+    }
+    embeddings.flush()?;
+
+    let mut counts = [0; 3];
+    let mut total_margin = 0.0;
+    let mut results = vec![];
+    for (case, (child_id, candidate_ids)) in cases.iter().zip(ids) {
+        let child = embeddings.item_embedding(child_id, 1.0)?;
+        // The candidates are homographs, so their term similarities to the
+        // child are all the same, and don't count.
+        let similarities = candidate_ids
+            .into_iter()
+            .map(|candidate_id| {
+                let candidate = embeddings.item_embedding(candidate_id, 1.0)?;
+                Ok(child.cosine_similarity(&candidate))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let outcome = Outcome::of(&similarities, case.correct, options.similarity_threshold);
+        counts[outcome as usize] += 1;
+        // How much more similar the actual parent is than the most similar
+        // other candidate, which shows improvements that don't (yet) change
+        // the outcome.
+        let margin = similarities[case.correct]
+            - similarities
+                .iter()
+                .enumerate()
+                .filter(|&(i, _)| i != case.correct)
+                .map(|(_, &similarity)| similarity)
+                .fold(f32::NEG_INFINITY, f32::max);
+        if margin.is_finite() {
+            total_margin += margin;
+        }
+        if outcome != Outcome::Correct {
+            println!(
+                "{}: {} {} -> {} {}",
+                outcome.as_str(),
+                case.child.lang,
+                case.child.term,
+                case.parent_lang,
+                case.parent_term
+            );
+        }
+        results.push(json!({
+            "child": {"lang": case.child.lang, "term": case.child.term},
+            "parent": {"lang": case.parent_lang, "term": case.parent_term},
+            "correct": case.correct,
+            "similarities": similarities,
+            "outcome": outcome.as_str(),
+        }));
+    }
+
+    let [correct, wrong, unlinked] = counts;
+    #[allow(clippy::cast_precision_loss)]
+    let share = |n: usize, of: usize| if of == 0 { 0.0 } else { n as f32 / of as f32 };
+    let accuracy = share(correct, cases.len());
+    let precision = share(correct, correct + wrong);
+    #[allow(clippy::cast_precision_loss)]
+    let mean_margin = total_margin / cases.len().max(1) as f32;
+    println!(
+        "Correct: {correct}, wrong: {wrong}, unlinked: {unlinked}. Accuracy {accuracy:.3}, precision {precision:.3}, mean margin {mean_margin:.4}."
+    );
+    if let Some(report_path) = report_path {
+        let report = json!({
+            "model": config.model_name,
+            "modelRevision": config.model_revision,
+            "embeddingsUrl": config.url,
+            "similarityThreshold": options.similarity_threshold,
+            "correct": correct,
+            "wrong": wrong,
+            "unlinked": unlinked,
+            "accuracy": accuracy,
+            "precision": precision,
+            "meanMargin": mean_margin,
+            "cases": Value::from(results),
+        });
+        fs::write(report_path, serde_json::to_string_pretty(&report)?)?;
+        println!("Wrote the evaluation report to {}.", report_path.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disambiguation_outcomes() {
+        assert_eq!(Outcome::of(&[0.2, 0.5], 1, 0.0), Outcome::Correct);
+        assert_eq!(Outcome::of(&[0.5, 0.2], 1, 0.0), Outcome::Wrong);
+        assert_eq!(Outcome::of(&[0.2, 0.5], 1, 0.6), Outcome::Unlinked);
+        assert_eq!(Outcome::of(&[0.5, 0.5], 0, 0.0), Outcome::Correct);
+        assert_eq!(Outcome::of(&[0.5, 0.5], 1, 0.0), Outcome::Wrong);
+    }
+
+    #[test]
+    fn gold_cases_are_well_formed() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("data/disambiguation_gold.jsonl");
+        let cases = read_gold(&path).unwrap();
+        assert!(!cases.is_empty());
+        for case in cases {
+            assert!(case.child.lang.parse::<Lang>().is_ok());
+            assert!(case.parent_lang.parse::<Lang>().is_ok());
+            assert!(case.candidates.len() > 1);
+        }
+    }
+}
@@ -0,0 +1,268 @@
+//! Fine-tuning of the embeddings model on the ety links that needed no
+//! disambiguation, i.e. those whose parent is the only item of its lang and
+//! term. The child and parent of such a link are taken to be a match, and the
+//! model is trained with a contrastive objective to embed the child's texts
+//! nearer to its parent's than to the other parents in the batch. The result
+//! is saved as a new revision of the model in the local Hugging Face cache, so
+//! that it can be used like any other with `--embeddings-model-revision`.
+
+use crate::{
+    embeddings::{self, LocalModel, FINE_TUNED_WEIGHTS},
+    ety_graph::{EdgeSource, EtyEdgeAccess},
+    items::{Item, Items},
+    langterm::LangTerm,
+    progress::progress_bar,
+    string_pool::StringPool,
+};
+
+use std::{fs, time::Instant};
+
+use anyhow::{ensure, Ok, Result};
+use candle_core::Tensor;
+use candle_nn::{loss::cross_entropy, AdamW, Optimizer};
+use hf_hub::{Cache, Repo, RepoType};
+use indicatif::HumanDuration;
+use itertools::Itertools;
+use xxhash_rust::xxh3::xxh3_64_with_seed;
+
+pub const DEFAULT_EPOCHS: usize = 1;
+pub const DEFAULT_LEARNING_RATE: f64 = 2e-5;
+pub const DEFAULT_BATCH_SIZE: usize = 32;
+
+// The cosine similarities are scaled by this before the softmax, as in
+// sentence-transformers' MultipleNegativesRankingLoss, since they are too
+// close together for the loss to distinguish them otherwise.
+const SIMILARITY_SCALE: f64 = 20.0;
+
+pub struct FineTuning {
+    /// The name of the revision to save the fine-tuned model as.
+    pub revision: String,
+    pub epochs: usize,
+    pub learning_rate: f64,
+    /// How many pairs to train on at once. The other pairs in a batch are the
+    /// negatives for each pair, so larger batches give a harder objective.
+    pub batch_size: usize,
+    /// Train on at most this many pairs of each kind.
+    pub max_pairs: Option<usize>,
+}
+
+// The texts of a child and its parent, embedded as in `Embeddings::add`.
+#[derive(Debug, PartialEq)]
+struct TrainingPair {
+    child: String,
+    parent: String,
+}
+
+fn ety_text(string_pool: &StringPool, item: &Item) -> Option<String> {
+    let ety_text = item.ety_text()?.to_string(string_pool);
+    Some(format!(
+        "{} {}. {ety_text}",
+        item.lang().name(),
+        item.term().resolve(string_pool)
+    ))
+}
+
+fn glosses_text(string_pool: &StringPool, item: &Item) -> Option<String> {
+    let glosses = item.gloss()?;
+    (!glosses.is_empty()).then(|| {
+        glosses
+            .iter()
+            .map(|gloss| gloss.to_string(string_pool))
+            .join(" ")
+    })
+}
+
+// The pairs of ety texts and of glosses of the children and parents of the ety
+// links that were found in an ety or descendants section and whose parent was
+// unambiguous.
+fn training_pairs(
+    string_pool: &StringPool,
+    items: &Items,
+) -> (Vec<TrainingPair>, Vec<TrainingPair>) {
+    let mut ety_pairs = vec![];
+    let mut glosses_pairs = vec![];
+    for edge in items.graph.edges() {
+        if !edge.provenance().is_some_and(|provenance| {
+            matches!(
+                provenance.source,
+                EdgeSource::Etymology | EdgeSource::Descendants
+            )
+        }) {
+            continue;
+        }
+        let child = items.get(edge.child());
+        let parent = items.get(edge.parent());
+        if child.is_imputed() || parent.is_imputed() {
+            continue;
+        }
+        let unambiguous = items
+            .get_dupes(LangTerm::new(parent.lang(), parent.term()))
+            .is_some_and(|dupes| dupes.len() == 1);
+        if !unambiguous {
+            continue;
+        }
+        if let Some(child) = ety_text(string_pool, child)
+            && let Some(parent) = ety_text(string_pool, parent)
+        {
+            ety_pairs.push(TrainingPair { child, parent });
+        }
+        if let Some(child) = glosses_text(string_pool, child)
+            && let Some(parent) = glosses_text(string_pool, parent)
+        {
+            glosses_pairs.push(TrainingPair { child, parent });
+        }
+    }
+    (ety_pairs, glosses_pairs)
+}
+
+// The pairs in an order that is different for each epoch but the same across
+// runs.
+fn shuffle(pairs: &mut [TrainingPair], epoch: usize) {
+    pairs.sort_by_cached_key(|pair| {
+        xxh3_64_with_seed(pair.child.as_bytes(), epoch as u64)
+            ^ xxh3_64_with_seed(pair.parent.as_bytes(), epoch as u64)
+    });
+}
+
+// The multiple negatives ranking loss of a batch: the cross entropy of each
+// child's scaled similarities to all the parents in the batch, against its own
+// parent.
+fn batch_loss(model: &LocalModel, batch: &[TrainingPair]) -> Result<Tensor> {
+    let children = model.embed(batch.iter().map(|pair| pair.child.clone()).collect())?;
+    let parents = model.embed(batch.iter().map(|pair| pair.parent.clone()).collect())?;
+    let scores = children
+        .matmul(&parents.t()?)?
+        .affine(SIMILARITY_SCALE, 0.0)?;
+    let len = u32::try_from(batch.len())?;
+    let targets = Tensor::arange(0u32, len, model.device())?;
+    Ok(cross_entropy(&scores, &targets)?)
+}
+
+/// Fine-tune the embeddings model of `config` on the unambiguous ety links of
+/// the items, saving it in the Hugging Face cache as a new revision of the
+/// model.
+pub(crate) fn fine_tune(
+    string_pool: &StringPool,
+    items: &Items,
+    config: &embeddings::Config,
+    fine_tuning: &FineTuning,
+) -> Result<()> {
+    ensure!(
+        config.url.is_none(),
+        "a model served from an embeddings endpoint can't be fine-tuned"
+    );
+    ensure!(
+        !fine_tuning.revision.is_empty() && fine_tuning.revision != config.model_revision,
+        "the fine-tuned model needs a revision name other than that of the model it is tuned from"
+    );
+    ensure!(
+        fine_tuning.batch_size > 1,
+        "training batches need more than one pair, since the pairs are each other's negatives"
+    );
+    let t = Instant::now();
+    println!("Extracting training pairs from unambiguous ety links...");
+    let (mut ety_pairs, mut glosses_pairs) = training_pairs(string_pool, items);
+    if let Some(max_pairs) = fine_tuning.max_pairs {
+        for pairs in [&mut ety_pairs, &mut glosses_pairs] {
+            shuffle(pairs, 0);
+            pairs.truncate(max_pairs);
+        }
+    }
+    println!(
+        "Finished. Extracted {} ety text and {} gloss pairs. Took {}.",
+        ety_pairs.len(),
+        glosses_pairs.len(),
+        HumanDuration(t.elapsed())
+    );
+
+    let (model, files, varmap) =
+        LocalModel::trainable(config.model_name.clone(), config.model_revision.clone())?;
+    let mut optimizer = AdamW::new_lr(varmap.all_vars(), fine_tuning.learning_rate)?;
+    for epoch in 1..=fine_tuning.epochs {
+        let t = Instant::now();
+        for pairs in [&mut ety_pairs, &mut glosses_pairs] {
+            shuffle(pairs, epoch);
+        }
+        // Ety texts and glosses are trained on in separate batches, since
+        // their embeddings are only ever compared with their own kind.
+        let batches = ety_pairs
+            .chunks(fine_tuning.batch_size)
+            .chain(glosses_pairs.chunks(fine_tuning.batch_size))
+            .filter(|batch| batch.len() > 1)
+            .collect_vec();
+        let pb = progress_bar(
+            batches.len(),
+            &format!("Fine-tuning, epoch {epoch}/{}", fine_tuning.epochs),
+        )?;
+        let mut total_loss = 0.0;
+        for batch in &batches {
+            let loss = batch_loss(&model, batch)?;
+            optimizer.backward_step(&loss)?;
+            total_loss += loss.to_scalar::<f32>()?;
+            pb.inc(1);
+        }
+        pb.finish();
+        #[allow(clippy::cast_precision_loss)]
+        let mean_loss = total_loss / batches.len().max(1) as f32;
+        println!(
+            "Finished epoch {epoch}. Mean loss {mean_loss:.4}. Took {}.",
+            HumanDuration(t.elapsed())
+        );
+    }
+
+    // The revision is saved as a snapshot of the model's repo in the cache,
+    // with a ref of the same name, which is where the hub API looks for it
+    // before trying to download it.
+    let cache = Cache::default();
+    let repo = Repo::with_revision(
+        config.model_name.clone(),
+        RepoType::Model,
+        fine_tuning.revision.clone(),
+    );
+    let snapshot = cache
+        .path()
+        .join(repo.folder_name())
+        .join("snapshots")
+        .join(&fine_tuning.revision);
+    println!("Saving the fine-tuned model to {}...", snapshot.display());
+    fs::create_dir_all(&snapshot)?;
+    fs::copy(&files.config, snapshot.join("config.json"))?;
+    fs::copy(&files.tokenizer, snapshot.join("tokenizer.json"))?;
+    varmap.save(snapshot.join(FINE_TUNED_WEIGHTS))?;
+    cache.repo(repo).create_ref(&fine_tuning.revision)?;
+    println!(
+        "Finished. Use it with --embeddings-model-revision {}.",
+        fine_tuning.revision
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pairs(n: usize) -> Vec<TrainingPair> {
+        (0..n)
+            .map(|i| TrainingPair {
+                child: format!("child {i}"),
+                parent: format!("parent {i}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn shuffle_is_deterministic_per_epoch() {
+        let mut a = pairs(50);
+        let mut b = pairs(50);
+        shuffle(&mut a, 1);
+        shuffle(&mut b, 1);
+        assert_eq!(a, b);
+        assert_ne!(a, pairs(50));
+        shuffle(&mut b, 2);
+        assert_ne!(a, b);
+        b.sort_by_key(|pair| pair.child.clone());
+        let mut sorted = pairs(50);
+        sorted.sort_by_key(|pair| pair.child.clone());
+        assert_eq!(b, sorted);
+    }
+}
@@ -1,15 +1,26 @@
 use itertools::Itertools;
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
 use serde::{Deserialize, Serialize};
 
-use crate::string_pool::{StringPool, Symbol};
+use crate::{
+    string_pool::{StringPool, Symbol},
+    HashSet,
+};
 
-#[derive(Default, Hash, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[derive(Default, Clone, Hash, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub(crate) struct Gloss {
     symbols: Box<[Symbol]>,
 }
 
 impl Gloss {
-    pub(crate) fn new(string_pool: &mut StringPool, gloss: &str) -> Self {
+    /// The gloss with any residual wiki markup cleaned from it.
+    pub(crate) fn new(string_pool: &StringPool, gloss: &str) -> Self {
+        Self::new_raw(string_pool, &clean(gloss))
+    }
+
+    /// The gloss as is.
+    pub(crate) fn new_raw(string_pool: &StringPool, gloss: &str) -> Self {
         let symbols: Box<[Symbol]> = gloss
             .split(' ')
             .map(|g| string_pool.get_or_intern(g))
@@ -17,6 +28,18 @@ impl Gloss {
         Self { symbols }
     }
 
+    /// The share of the distinct words in either that are in both.
+    #[allow(clippy::cast_precision_loss)]
+    pub(crate) fn word_overlap(&self, other: &Self) -> f32 {
+        let words = self.symbols.iter().collect::<HashSet<_>>();
+        let other_words = other.symbols.iter().collect::<HashSet<_>>();
+        let union = words.union(&other_words).count();
+        if union == 0 {
+            return 1.0;
+        }
+        words.intersection(&other_words).count() as f32 / union as f32
+    }
+
     pub(crate) fn to_string(&self, string_pool: &StringPool) -> String {
         self.symbols
             .iter()
@@ -24,3 +47,122 @@ impl Gloss {
             .join(" ")
     }
 }
+
+lazy_static! {
+    // [[target|label]] or [[target]]
+    static ref WIKILINK: Regex = Regex::new(r"\[\[(?:[^\[\]|]*\|)?([^\[\]|]*)\]\]").unwrap();
+    // [url label] or [url]
+    static ref EXTERNAL_LINK: Regex =
+        Regex::new(r"\[(?:https?:)?//[^\s\]]*(?: ([^\]]*))?\]").unwrap();
+    static ref COMMENT: Regex = Regex::new(r"(?s)<!--.*?-->").unwrap();
+    static ref TAG: Regex = Regex::new(r"</?[a-zA-Z][^<>]*>").unwrap();
+    static ref ENTITY: Regex = Regex::new(r"&(#[0-9]+|#[xX][0-9a-fA-F]+|[a-zA-Z]+);").unwrap();
+    static ref EMPTY_PARENS: Regex = Regex::new(r"\(\s*\)").unwrap();
+    // e.g. left behind by a removed template
+    static ref SPACE_BEFORE_PUNCTUATION: Regex = Regex::new(r"\s+([,.;:)])").unwrap();
+}
+
+/// Clean the residual wikitext that wiktextract sometimes leaves in glosses
+/// and ety texts: templates are dropped, links replaced by their labels, and
+/// bold and italic quotes, html comments and tags removed, with html entities
+/// decoded and whitespace collapsed.
+pub(crate) fn clean(text: &str) -> String {
+    if !text.contains(['{', '[', '<', '\'', '&']) {
+        return text.to_string();
+    }
+    let text = strip_templates(text);
+    let text = COMMENT.replace_all(&text, "");
+    let text = WIKILINK.replace_all(&text, "$1");
+    let text = EXTERNAL_LINK.replace_all(&text, "$1");
+    let text = text.replace("'''", "").replace("''", "");
+    let text = TAG.replace_all(&text, "");
+    let text = ENTITY.replace_all(&text, |caps: &Captures| {
+        decode_entity(&caps[1]).map_or_else(|| caps[0].to_string(), String::from)
+    });
+    let text = EMPTY_PARENS.replace_all(&text, "");
+    let text = text.split_whitespace().join(" ");
+    SPACE_BEFORE_PUNCTUATION
+        .replace_all(&text, "$1")
+        .into_owned()
+}
+
+// Remove {{...}}, including nested ones. An unclosed {{ is kept as is.
+fn strip_templates(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut depth = 0;
+    let mut unclosed = 0;
+    let mut rest = text;
+    while !rest.is_empty() {
+        if rest.starts_with("{{") {
+            if depth == 0 {
+                unclosed = text.len() - rest.len();
+            }
+            depth += 1;
+            rest = &rest[2..];
+        } else if depth > 0 && rest.starts_with("}}") {
+            depth -= 1;
+            rest = &rest[2..];
+        } else {
+            let c = rest.chars().next().expect("non-empty");
+            if depth == 0 {
+                stripped.push(c);
+            }
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    if depth > 0 {
+        stripped.push_str(&text[unclosed..]);
+    }
+    stripped
+}
+
+fn decode_entity(entity: &str) -> Option<char> {
+    if let Some(num) = entity.strip_prefix('#') {
+        let code = match num.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => num.parse().ok()?,
+        };
+        return char::from_u32(code);
+    }
+    match entity {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some(' '),
+        "ndash" => Some('–'),
+        "mdash" => Some('—'),
+        "hellip" => Some('…'),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clean_messy_glosses() {
+        assert_eq!(clean("water"), "water");
+        assert_eq!(
+            clean("{{lb|en|archaic}} A [[wikt:horse|horse]] or [[mule]]."),
+            "A horse or mule."
+        );
+        assert_eq!(
+            clean("to {{q|{{lb|en|transitive}} of a ship}} sail ({{m|en|sail}})"),
+            "to sail"
+        );
+        assert_eq!(clean("'''bold''' and ''italic''"), "bold and italic");
+        assert_eq!(
+            clean("Tom &amp; Jerry&nbsp;&#8211; a <span class=\"x\">cartoon</span><!-- todo -->"),
+            "Tom & Jerry – a cartoon"
+        );
+        assert_eq!(
+            clean("see [https://example.org the site] or [//example.org]"),
+            "see the site or"
+        );
+        assert_eq!(clean("broken {{lb|en"), "broken {{lb|en");
+        assert_eq!(clean("rock 'n' roll &unknown;"), "rock 'n' roll &unknown;");
+    }
+}
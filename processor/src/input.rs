@@ -0,0 +1,346 @@
+//! Opening the wiktextract data, which can be a file, `-` for stdin, or an
+//! http(s) URL that is downloaded as it is read, so that e.g. a dump can be
+//! piped in from curl without first being written to disk (though it is then
+//! spooled to a temp file, to be read again from there). Data compressed
+//! with gzip, zstd, bzip2 or xz is decompressed as it is read, whatever its
+//! name.
+
+use std::{
+    collections::BTreeMap,
+    env,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Cursor, Read, Seek, SeekFrom, Write},
+    path::Path,
+    process,
+    sync::Mutex,
+};
+
+use anyhow::{bail, Ok, Result};
+use bzip2::read::MultiBzDecoder;
+use flate2::read::MultiGzDecoder;
+use serde::{Deserialize, Serialize};
+use xz2::read::XzDecoder;
+
+/// Which lines of the wiktextract data to process, e.g. only the first few
+/// thousand, for iterating quickly on how they are processed.
+#[derive(Clone, Copy, Default, PartialEq, Debug, Serialize, Deserialize)]
+pub struct LineRange {
+    /// Skip this many lines from the start.
+    pub skip: usize,
+    /// Process at most this many lines after those skipped.
+    pub limit: Option<usize>,
+}
+
+impl LineRange {
+    pub(crate) fn is_all(self) -> bool {
+        self == Self::default()
+    }
+
+    /// The lines in the range, with the numbers they have in all the lines.
+    pub(crate) fn numbered<T>(
+        self,
+        lines: impl Iterator<Item = T>,
+    ) -> impl Iterator<Item = (usize, T)> {
+        lines
+            .enumerate()
+            .skip(self.skip)
+            .take(self.limit.unwrap_or(usize::MAX))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Compression {
+    None,
+    Gzip,
+    Zstd,
+    Bzip2,
+    Xz,
+}
+
+// The longest magic bytes, those of xz.
+const MAGIC_LEN: usize = 6;
+
+impl Compression {
+    // The compression of data starting with `header`, by its magic bytes.
+    fn detect(header: &[u8]) -> Self {
+        if header.starts_with(&[0x1f, 0x8b]) {
+            Self::Gzip
+        } else if header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Self::Zstd
+        } else if header.starts_with(b"BZh") {
+            Self::Bzip2
+        } else if header.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Self::Xz
+        } else {
+            Self::None
+        }
+    }
+}
+
+// Decompress the data as it is read, if it is compressed. Recompressed data
+// may have more than one stream (e.g. from pigz or pbzip2), so all are read.
+fn decompress(mut raw: impl Read + 'static) -> Result<Box<dyn BufRead>> {
+    let mut header = Vec::with_capacity(MAGIC_LEN);
+    raw.by_ref()
+        .take(MAGIC_LEN as u64)
+        .read_to_end(&mut header)?;
+    let compression = Compression::detect(&header);
+    let raw = BufReader::new(Cursor::new(header).chain(raw));
+    Ok(match compression {
+        Compression::None => Box::new(raw),
+        Compression::Gzip => Box::new(BufReader::new(MultiGzDecoder::new(raw))),
+        Compression::Zstd => Box::new(BufReader::new(zstd::Decoder::with_buffer(raw)?)),
+        Compression::Bzip2 => Box::new(BufReader::new(MultiBzDecoder::new(raw))),
+        Compression::Xz => Box::new(BufReader::new(XzDecoder::new_multi_decoder(raw))),
+    })
+}
+
+enum Source<'a> {
+    File(&'a Path),
+    Stdin,
+    Url(&'a str),
+}
+
+impl<'a> Source<'a> {
+    fn new(path: &'a Path) -> Self {
+        match path.to_str() {
+            Some("-") => Self::Stdin,
+            Some(url) if url.starts_with("https://") || url.starts_with("http://") => {
+                Self::Url(url)
+            }
+            _ => Self::File(path),
+        }
+    }
+}
+
+/// Whether `path` names a file, rather than stdin or a URL.
+pub(crate) fn is_file(path: &Path) -> bool {
+    matches!(Source::new(path), Source::File(_))
+}
+
+// The data is read more than once (once to parse it, and again to embed the
+// texts of the items that need it), but stdin can only be read once, and a URL
+// would be downloaded again, maybe with different data if the file behind it
+// was replaced in between, so that the line numbers taken from the first read
+// would name other lines in the second. So as stdin or a URL is first read, its
+// bytes, still compressed, are spooled to a temp file, which later reads read
+// instead. The first read may stop before the end of the source, when only
+// some lines are processed (see `LineRange`), and later reads of the same
+// lines then stop at least as early, so they are served from what was spooled.
+struct Spool {
+    file: File,
+    // whether the first read, which spools the source, has finished
+    finished: bool,
+}
+
+// The spools by source, i.e. "-" or the URL.
+static SPOOLS: Mutex<BTreeMap<String, Spool>> = Mutex::new(BTreeMap::new());
+
+// Reads a source, writing what it reads to its spool.
+struct SpoolingReader {
+    source: String,
+    reader: Box<dyn Read>,
+    spool: File,
+}
+
+impl Read for SpoolingReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.reader.read(buf)?;
+        self.spool.write_all(&buf[..n])?;
+        io::Result::Ok(n)
+    }
+}
+
+impl Drop for SpoolingReader {
+    fn drop(&mut self) {
+        let _ = self.spool.flush();
+        if let Some(spool) = SPOOLS
+            .lock()
+            .expect("spools not poisoned")
+            .get_mut(&self.source)
+        {
+            spool.finished = true;
+        }
+    }
+}
+
+// Read the source from its spool if it has been read before, or else from
+// `open` while spooling it.
+fn open_spooled(
+    source: &str,
+    open: impl FnOnce() -> Result<Box<dyn Read>>,
+) -> Result<Box<dyn Read>> {
+    let mut spools = SPOOLS.lock().expect("spools not poisoned");
+    if let Some(spool) = spools.get(source) {
+        if !spool.finished {
+            bail!("{source} was read again before its first read finished");
+        }
+        let mut file = spool.file.try_clone()?;
+        file.seek(SeekFrom::Start(0))?;
+        return Ok(Box::new(file));
+    }
+    let reader = open()?;
+    let path = env::temp_dir().join(format!("wety-spool-{}-{}", process::id(), spools.len()));
+    let file = File::options()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    // The spool only lives as long as the process has it open. Where an open
+    // file can't be removed, it is left in the temp dir.
+    let _ = fs::remove_file(&path);
+    let reader = SpoolingReader {
+        source: source.to_string(),
+        reader,
+        spool: file.try_clone()?,
+    };
+    spools.insert(
+        source.to_string(),
+        Spool {
+            file,
+            finished: false,
+        },
+    );
+    Ok(Box::new(reader))
+}
+
+fn open_url(url: &str) -> Result<Box<dyn Read>> {
+    println!("Downloading {url}...");
+    let response = ureq::get(url).call()?;
+    Ok(Box::new(response.into_reader()))
+}
+
+/// Open the wiktextract data at `path` (see the module docs), decompressing it
+/// if it is compressed.
+///
+/// # Errors
+///
+/// Will return `Err` if the file cannot be opened, the URL cannot be fetched,
+/// or stdin or the URL is opened a second time while the first read of it is
+/// still open.
+pub(crate) fn open(path: &Path) -> Result<Box<dyn BufRead>> {
+    let raw: Box<dyn Read> = match Source::new(path) {
+        Source::File(path) => Box::new(File::open(path)?),
+        Source::Stdin => open_spooled("-", || Ok(Box::new(io::stdin())))?,
+        Source::Url(url) => open_spooled(url, || open_url(url))?,
+    };
+    decompress(raw)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wiktextract_sources() {
+        assert!(matches!(Source::new(Path::new("-")), Source::Stdin));
+        assert!(matches!(
+            Source::new(Path::new(
+                "https://kaikki.org/dictionary/raw-wiktextract-data.json.gz"
+            )),
+            Source::Url(_)
+        ));
+        assert!(matches!(
+            Source::new(Path::new("data/raw-wiktextract-data.json.gz")),
+            Source::File(_)
+        ));
+        assert!(is_file(Path::new("./-")));
+    }
+
+    #[test]
+    fn line_ranges() {
+        let range = LineRange {
+            skip: 2,
+            limit: Some(3),
+        };
+        assert_eq!(
+            range.numbered("abcdefg".chars()).collect::<Vec<_>>(),
+            vec![(2, 'c'), (3, 'd'), (4, 'e')]
+        );
+        assert_eq!(LineRange::default().numbered(0..4).count(), 4);
+        assert!(LineRange::default().is_all());
+        assert!(!range.is_all());
+    }
+
+    #[test]
+    fn compressed_data_is_detected_and_decompressed() {
+        let data = b"{\"word\": \"water\"}\n{\"word\": \"wine\"}\n".to_vec();
+        let mut gzip = flate2::write::GzEncoder::new(vec![], flate2::Compression::fast());
+        gzip.write_all(&data).unwrap();
+        let gzip = gzip.finish().unwrap();
+        let zstd = zstd::encode_all(&data[..], 0).unwrap();
+        let mut bzip2 = bzip2::write::BzEncoder::new(vec![], bzip2::Compression::fast());
+        bzip2.write_all(&data).unwrap();
+        let bzip2 = bzip2.finish().unwrap();
+        let mut xz = xz2::write::XzEncoder::new(vec![], 1);
+        xz.write_all(&data).unwrap();
+        let xz = xz.finish().unwrap();
+        for (compressed, compression) in [
+            (data.clone(), Compression::None),
+            (gzip, Compression::Gzip),
+            (zstd, Compression::Zstd),
+            (bzip2, Compression::Bzip2),
+            (xz, Compression::Xz),
+        ] {
+            assert_eq!(Compression::detect(&compressed), compression);
+            let mut decompressed = vec![];
+            decompress(Cursor::new(compressed))
+                .unwrap()
+                .read_to_end(&mut decompressed)
+                .unwrap();
+            assert_eq!(decompressed, data);
+        }
+        assert_eq!(Compression::detect(b"{"), Compression::None);
+        assert_eq!(Compression::detect(b""), Compression::None);
+    }
+
+    #[test]
+    fn sources_are_read_again_from_their_spool() {
+        let source = "https://example.com/spooled.jsonl";
+        let read = |data: &'static [u8]| {
+            let mut read = vec![];
+            open_spooled(source, || Ok(Box::new(data)))
+                .unwrap()
+                .read_to_end(&mut read)
+                .unwrap();
+            read
+        };
+        assert_eq!(read(b"first"), b"first");
+        // e.g. the file behind the URL was replaced after it was first read
+        assert_eq!(read(b"second"), b"first");
+    }
+
+    #[test]
+    fn sources_read_for_some_lines_are_read_again_from_their_spool() {
+        let source = "https://example.com/limited.jsonl";
+        let data = (0..100_000)
+            .map(|i| format!("{{\"word\": \"{i}\"}}\n"))
+            .collect::<String>();
+        let mut gzip = flate2::write::GzEncoder::new(vec![], flate2::Compression::fast());
+        gzip.write_all(data.as_bytes()).unwrap();
+        let gzip: &'static [u8] = gzip.finish().unwrap().leak();
+        let range = LineRange {
+            skip: 10,
+            limit: Some(20),
+        };
+        // As the wiktextract data is read in each pass: the first pass stops
+        // once it has the lines in the range, before the end of the source.
+        let read = || {
+            let raw = open_spooled(source, || Ok(Box::new(gzip))).unwrap();
+            range
+                .numbered(decompress(raw).unwrap().lines())
+                .map(|(i, line)| (i, line.unwrap()))
+                .collect::<Vec<_>>()
+        };
+        let first = read();
+        assert_eq!(first.len(), 20);
+        let spooled = SPOOLS.lock().unwrap()[source]
+            .file
+            .metadata()
+            .unwrap()
+            .len();
+        assert!(spooled < gzip.len() as u64);
+        assert_eq!(first[0], (10, "{\"word\": \"10\"}".to_string()));
+        assert_eq!(read(), first);
+    }
+}
@@ -1,26 +1,34 @@
 use crate::{
+    checkpoint::pairs,
     descendants::RawDescendants,
     embeddings::{self, Embeddings, ItemEmbedding},
-    ety_graph::{EtyGraph, ItemIndex},
+    ety_graph::{EdgeProvenance, EdgeSource, EtyEdgeAccess, EtyGraph, ItemIndex, RemovedEdge},
     etymology::RawEtymology,
+    etymology_templates::EtyMode,
     gloss::Gloss,
-    langterm::{LangTerm, Term},
+    langterm::{is_multi_word, LangTerm, Term},
     languages::Lang,
+    line_errors::LineErrors,
+    orthography,
     pos::Pos,
     progress_bar,
     redirects::Redirects,
     root::RawRoot,
     string_pool::StringPool,
+    validation::{Anomalies, AnomalyKind},
     wiktextract_json::wiktextract_lines,
-    HashMap, HashSet,
+    HashMap, HashSet, ProcessingOptions,
 };
 
 use std::{collections::hash_map::Entry, mem, path::Path};
 
 use anyhow::{Ok, Result};
+use clap::ValueEnum;
+use itertools::Itertools;
 use petgraph::stable_graph::NodeIndex;
 use serde::{Deserialize, Serialize};
 use simd_json::to_borrowed_value;
+use xxhash_rust::xxh3::Xxh3;
 
 pub type ItemId = NodeIndex<ItemIndex>; // wiktionary has about ~10M items including imputations
 
@@ -35,12 +43,36 @@ pub(crate) struct RealItem {
     pub(crate) page_term: Option<Term>, // i.e. the term stripped of diacritics etc. at the top of the page
     pub(crate) romanization: Option<Term>,
     pub(crate) is_reconstructed: bool,
+    // IPA transcriptions of the term's pronunciations
+    #[serde(default)]
+    pub(crate) ipa: Vec<Term>,
+    // The prose of the ety section, truncated, for display alongside the ety
+    // links that were drawn from it
+    #[serde(default)]
+    pub(crate) ety_text: Option<Gloss>,
+    // wiktextract's id for the item's page, if the data has one
+    #[serde(default)]
+    pub(crate) page_id: Option<u64>,
 }
 
 impl RealItem {
+    /// The title of the item's Wiktionary page, e.g.
+    /// "Reconstruction:Proto-Indo-European/wódr̥", which with the lang
+    /// identifies the item's section in the page dumps.
+    pub(crate) fn page_title(&self, string_pool: &StringPool) -> String {
+        let page_term = self.page_term.unwrap_or(self.term).resolve(string_pool);
+        if self.is_reconstructed {
+            let lang_name = self.lang.ety2non().name();
+            return format!("Reconstruction:{lang_name}/{page_term}");
+        }
+        page_term.to_string()
+    }
+
     pub(crate) fn url(&self, string_pool: &StringPool) -> String {
         let page_term = self.page_term.unwrap_or(self.term);
-        let url_term = urlencoding::encode(page_term.resolve(string_pool));
+        // Wiktionary page urls have underscores for spaces.
+        let url_term = page_term.resolve(string_pool).replace(' ', "_");
+        let url_term = urlencoding::encode(&url_term);
         let url_lang_name = self.lang.ety2non().url_name();
         if self.is_reconstructed {
             return format!(
@@ -51,6 +83,27 @@ impl RealItem {
     }
 }
 
+/// A real item in `lang` with `term` as its term and gloss and nothing else,
+/// for tests to build items and graphs from.
+#[cfg(test)]
+pub(crate) fn test_item(string_pool: &StringPool, lang: &str, term: &str) -> RealItem {
+    use std::str::FromStr;
+
+    RealItem {
+        ety_num: 1,
+        lang: Lang::from_str(lang).unwrap(),
+        term: Term::new(string_pool, term),
+        pos: vec![],
+        gloss: vec![Gloss::new(string_pool, term)],
+        page_term: None,
+        romanization: None,
+        is_reconstructed: lang.ends_with("-pro"),
+        ipa: vec![],
+        ety_text: None,
+        page_id: None,
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub(crate) struct ImputedItem {
     pub(crate) ety_num: u8,
@@ -116,6 +169,20 @@ impl Item {
         }
     }
 
+    pub(crate) fn ipa(&self) -> Option<&Vec<Term>> {
+        match self {
+            Item::Real(real_item) => Some(&real_item.ipa),
+            Item::Imputed(_) => None,
+        }
+    }
+
+    pub(crate) fn ety_text(&self) -> Option<&Gloss> {
+        match self {
+            Item::Real(real_item) => real_item.ety_text.as_ref(),
+            Item::Imputed(_) => None,
+        }
+    }
+
     pub(crate) fn romanization(&self) -> Option<Term> {
         match self {
             Item::Real(real_item) => real_item.romanization,
@@ -123,6 +190,36 @@ impl Item {
         }
     }
 
+    pub(crate) fn page_title(&self, string_pool: &StringPool) -> Option<String> {
+        match self {
+            Item::Real(real_item) => Some(real_item.page_title(string_pool)),
+            Item::Imputed(_) => None,
+        }
+    }
+
+    // See Data::stable_id.
+    pub(crate) fn stable_id_hash(&self, string_pool: &StringPool) -> u64 {
+        let mut hasher = Xxh3::new();
+        hasher.update(self.lang().code().as_bytes());
+        hasher.update(&[0]);
+        hasher.update(self.term().resolve(string_pool).as_bytes());
+        hasher.update(&[0, self.ety_num()]);
+        // The parts of speech of an item are in the order of its entry, which
+        // may change, so are sorted.
+        for pos in self.pos().into_iter().flatten().map(|p| p.name()).sorted() {
+            hasher.update(&[0]);
+            hasher.update(pos.as_bytes());
+        }
+        hasher.digest()
+    }
+
+    pub(crate) fn page_id(&self) -> Option<u64> {
+        match self {
+            Item::Real(real_item) => real_item.page_id,
+            Item::Imputed(_) => None,
+        }
+    }
+
     pub(crate) fn url(&self, string_pool: &StringPool) -> Option<String> {
         match self {
             Item::Real(real_item) => Some(real_item.url(string_pool)),
@@ -138,7 +235,73 @@ impl Item {
     }
 }
 
+/// The stable ids of the items of a graph (see [`crate::Data::stable_id`]).
+/// Items that share a stable id hash, e.g. imputed items in different
+/// etymologies, are numbered in order of the page they are from (for an
+/// imputed item, that of the item it was imputed from), which stays the same
+/// across runs, and then of item id, which follows the order of the lines of
+/// the data. The first keeps the bare hash as its stable id, and the rest get
+/// a suffix, e.g. "-2" for the second.
 #[derive(Default)]
+pub(crate) struct StableIds {
+    items: HashMap<u64, Vec<ItemId>>,
+}
+
+impl StableIds {
+    pub(crate) fn new(graph: &EtyGraph, string_pool: &StringPool) -> Self {
+        let mut items = HashMap::<u64, Vec<ItemId>>::default();
+        for (item_id, item) in graph.iter() {
+            items
+                .entry(item.stable_id_hash(string_pool))
+                .or_default()
+                .push(item_id);
+        }
+        for items in items.values_mut().filter(|items| items.len() > 1) {
+            // items without a page id last
+            items.sort_unstable_by_key(|&item_id| {
+                let page_id = Self::page_id(graph, item_id);
+                (page_id.is_none(), page_id, item_id)
+            });
+        }
+        Self { items }
+    }
+
+    // The page the item is from, or for an imputed item, that of the item it
+    // was imputed from.
+    pub(crate) fn page_id(graph: &EtyGraph, item: ItemId) -> Option<u64> {
+        match graph.item(item) {
+            Item::Real(real_item) => real_item.page_id,
+            Item::Imputed(imputed_item) => graph
+                .contains(imputed_item.from)
+                .then(|| graph.item(imputed_item.from).page_id())
+                .flatten(),
+        }
+    }
+
+    // The stable id of the item, whose stable id hash is `hash`.
+    pub(crate) fn id(&self, item: ItemId, hash: u64) -> String {
+        match self
+            .items
+            .get(&hash)
+            .and_then(|items| items.iter().position(|&i| i == item))
+        {
+            Some(n) if n > 0 => format!("{hash:016x}-{}", n + 1),
+            _ => format!("{hash:016x}"),
+        }
+    }
+
+    pub(crate) fn get(&self, id: &str) -> Option<ItemId> {
+        let (hash, n) = match id.split_once('-') {
+            // "-1" is only ever the bare hash
+            Some((hash, n)) => (hash, n.parse::<usize>().ok().filter(|&n| n > 1)?),
+            None => (id, 1),
+        };
+        let hash = u64::from_str_radix(hash, 16).ok()?;
+        self.items.get(&hash)?.get(n - 1).copied()
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
 pub(crate) struct RawTemplates {
     pub(crate) ety: HashMap<ItemId, RawEtymology>,
     pub(crate) desc: HashMap<ItemId, RawDescendants>,
@@ -146,30 +309,72 @@ pub(crate) struct RawTemplates {
 }
 
 type Dupes = HashMap<LangTerm, Vec<ItemId>>;
+
+// Items from the same ety section share its ety text, so less than this much
+// overlap between their words means that they are from different sections.
+const SAME_ETY_TEXT_OVERLAP: f32 = 0.5;
+
+fn ety_texts_differ(a: Option<&Gloss>, b: Option<&Gloss>) -> bool {
+    a.zip(b)
+        .is_some_and(|(a, b)| a.word_overlap(b) < SAME_ETY_TEXT_OVERLAP)
+}
+
+fn push_dupe(dupes: &mut Dupes, langterm: LangTerm, id: ItemId) {
+    match dupes.entry(langterm) {
+        Entry::Occupied(mut e) => e.get_mut().push(id),
+        Entry::Vacant(e) => {
+            e.insert(vec![id]);
+        }
+    }
+}
 type Lines = HashMap<usize, ItemId>;
 
+#[derive(Serialize, Deserialize)]
 pub(crate) struct Items {
     pub(crate) graph: EtyGraph,
+    #[serde(with = "pairs")]
     pub(crate) dupes: Dupes,
+    #[serde(with = "pairs")]
     pub(crate) page_term_dupes: Dupes,
+    // Templates sometimes cite a romanized form of a term in a non-Latin
+    // script, so we also index items by their romanization.
+    #[serde(with = "pairs")]
+    pub(crate) romanization_dupes: Dupes,
+    #[serde(with = "pairs")]
     pub(crate) imputed_dupes: Dupes,
     pub(crate) redirects: Redirects,
     pub(crate) raw_templates: RawTemplates,
     pub(crate) lines: Lines,
     pub(crate) total_ok_lines_in_file: usize,
+    pub(crate) anomalies: Anomalies,
+    // errors in lines of the wiktextract data, which were skipped in part or
+    // in full
+    #[serde(default)]
+    pub(crate) line_errors: LineErrors,
+    // the ety links removed to break cycles, for the cycle report
+    #[serde(default)]
+    pub(crate) removed_cycle_edges: Vec<RemovedEdge>,
+    // not checkpointed, but given again when resuming
+    #[serde(skip)]
+    pub(crate) options: ProcessingOptions,
 }
 
 impl Items {
-    pub(crate) fn new() -> Result<Self> {
+    pub(crate) fn new(options: ProcessingOptions) -> Result<Self> {
         Ok(Self {
             graph: EtyGraph::default(),
             dupes: Dupes::default(),
             page_term_dupes: Dupes::default(),
+            romanization_dupes: Dupes::default(),
             imputed_dupes: Dupes::default(),
             redirects: Redirects::default(),
             raw_templates: RawTemplates::default(),
             lines: Lines::default(),
             total_ok_lines_in_file: 0,
+            anomalies: Anomalies::default(),
+            line_errors: LineErrors::default(),
+            removed_cycle_edges: vec![],
+            options,
         })
     }
 }
@@ -192,34 +397,89 @@ impl Items {
         self.graph.add(item)
     }
 
-    fn add_page_term_dupe(&mut self, page_langterm: LangTerm, id: ItemId) {
-        match self.page_term_dupes.entry(page_langterm) {
-            Entry::Occupied(mut e) => e.get_mut().push(id),
-            Entry::Vacant(e) => {
-                e.insert(vec![id]);
-            }
+    // The line in the wiktextract data that each item with templates to
+    // process was found on, for the provenance of the ety links from them.
+    pub(crate) fn item_lines(&self) -> HashMap<ItemId, usize> {
+        self.lines.iter().map(|(&line, &id)| (id, line)).collect()
+    }
+
+    // Index a new real item by its page term and romanization, if it has them.
+    fn add_alt_dupes(&mut self, page_langterm: Option<LangTerm>, item_id: ItemId) {
+        let item = self.get(item_id);
+        let romanization_langterm = item
+            .romanization()
+            .filter(|&r| r != item.term())
+            .map(|r| LangTerm::new(item.lang(), r));
+        if let Some(page_langterm) = page_langterm {
+            push_dupe(&mut self.page_term_dupes, page_langterm, item_id);
+        }
+        if let Some(romanization_langterm) = romanization_langterm {
+            push_dupe(&mut self.romanization_dupes, romanization_langterm, item_id);
         }
     }
 
-    // the returned bool is true if the ItemId is new, false if the RawItem
-    // got merged into an existing item and hence the ItemId is old
-    pub(crate) fn add_real(&mut self, mut item: RealItem) -> (ItemId, bool) {
+    // The item of the pos under the ety item, whether the ety item itself or
+    // one of its pos items.
+    fn pos_item(&self, ety_item: ItemId, pos: Pos) -> Option<ItemId> {
+        let has_pos = |id| self.get(id).pos().is_some_and(|p| p.contains(&pos));
+        if has_pos(ety_item) {
+            return Some(ety_item);
+        }
+        self.graph
+            .child_edges(ety_item)
+            .filter(|e| e.mode() == EtyMode::SenseOf)
+            .map(|e| e.child())
+            .find(|&id| has_pos(id))
+    }
+
+    // With pos granularity, each pos under an ety gets its own item, linked to
+    // the ety item as a sense of it. Pos items are not indexed as dupes, so ety
+    // templates elsewhere always link to the ety item.
+    fn add_pos_item(&mut self, ety_item: ItemId, item: RealItem) -> ItemId {
+        let id = self.add(Item::Real(item));
+        let provenance = EdgeProvenance::new(EdgeSource::PosItem, None, None);
+        self.graph.add_ety(
+            id,
+            EtyMode::SenseOf,
+            Some(0),
+            &[ety_item],
+            &[1.0],
+            provenance,
+        );
+        id
+    }
+
+    pub(crate) fn add_real(&mut self, mut item: RealItem) -> (ItemId, AddedReal) {
         let langterm = LangTerm::new(item.lang, item.term);
         let page_langterm = item.page_term.map(|pt| LangTerm::new(item.lang, pt));
         // If we've seen this langterm before...
         if let Some(dupes) = self.dupes.get(&langterm) {
             let mut max_ety = 0;
             let mut same_ety_id = None;
+            let mut split_ety_id = None;
             for &id in dupes {
                 let other = self.graph.item(id);
-                if other.ety_num() == item.ety_num {
+                let texts_differ = ety_texts_differ(other.ety_text(), item.ety_text.as_ref());
+                if other.ety_num() == item.ety_num && !texts_differ {
                     same_ety_id = Some(id);
+                } else if other.ety_num() > item.ety_num
+                    && other.ety_text().is_some()
+                    && item.ety_text.is_some()
+                    && !texts_differ
+                {
+                    split_ety_id = Some(id);
                 }
                 max_ety = other.ety_num().max(max_ety);
             }
+            // Defective pages with several unnumbered Etymology sections give
+            // all their items ety_num 1. An item whose ety text differs from
+            // that of the stored item with its ety_num is from another of these
+            // sections, so it goes with the item already split off for its
+            // section, if there is one, and otherwise gets a new ety_num below.
+            let same_ety_id = same_ety_id.or(split_ety_id);
             // If it shares an ety with an already stored real item...
             if let Some(same_ety_id) = same_ety_id
-                && let Item::Real(same_ety) = self.graph.item_mut(same_ety_id)
+                && let Item::Real(same_ety) = self.graph.item(same_ety_id)
                 && !(item.pos[0] == Pos::root_pos()
                     && same_ety.pos.iter().any(|&p| p == item.pos[0]))
             {
@@ -232,10 +492,28 @@ impl Items {
                 // distinct items.
                 //
                 // Otherwise, we simply append this pos and gloss to the
-                // existing item.
-                same_ety.pos.push(item.pos[0]);
-                same_ety.gloss.push(mem::take(&mut item.gloss[0]));
-                return (same_ety_id, false);
+                // existing item, or with pos granularity, to the item for this
+                // pos, if there is one yet.
+                let merge_id = match self.options.granularity {
+                    Granularity::Ety => same_ety_id,
+                    Granularity::Pos => match self.pos_item(same_ety_id, item.pos[0]) {
+                        Some(pos_id) => pos_id,
+                        None => {
+                            let pos_id = self.add_pos_item(same_ety_id, item);
+                            return (pos_id, AddedReal::Pos);
+                        }
+                    },
+                };
+                if let Item::Real(merged) = self.graph.item_mut(merge_id) {
+                    merged.pos.push(item.pos[0]);
+                    merged.gloss.push(mem::take(&mut item.gloss[0]));
+                    for ipa in item.ipa {
+                        if !merged.ipa.contains(&ipa) {
+                            merged.ipa.push(ipa);
+                        }
+                    }
+                }
+                return (merge_id, AddedReal::Merged);
             }
             // A new ety_num for an already seen langterm
             item.ety_num = max_ety + 1;
@@ -244,18 +522,14 @@ impl Items {
                 .get_mut(&langterm)
                 .expect("already found")
                 .push(id);
-            if let Some(page_langterm) = page_langterm {
-                self.add_page_term_dupe(page_langterm, id);
-            }
-            return (id, true);
+            self.add_alt_dupes(page_langterm, id);
+            return (id, AddedReal::Ety);
         }
         // A langterm that hasn't been seen yet
         let id = self.add(Item::Real(item));
         self.dupes.insert(langterm, vec![id]);
-        if let Some(page_langterm) = page_langterm {
-            self.add_page_term_dupe(page_langterm, id);
-        }
-        (id, true)
+        self.add_alt_dupes(page_langterm, id);
+        (id, AddedReal::Ety)
     }
 
     pub(crate) fn add_imputed(&mut self, mut item: ImputedItem) -> ItemId {
@@ -287,47 +561,129 @@ impl Items {
         self.dupes
             .get(&langterm)
             .or_else(|| self.page_term_dupes.get(&langterm))
+            .or_else(|| self.romanization_dupes.get(&langterm))
     }
 
     fn get_max_similarity_candidate(
         &self,
+        string_pool: &StringPool,
         embeddings: &Embeddings,
         embedding_comp: &impl embeddings::Comparand<ItemEmbedding>,
+        relative: ItemId,
         candidates: &[ItemId],
     ) -> Result<Option<(ItemId, f32)>> {
-        let mut max_similarity = 0f32;
-        let mut best_candidate = 0usize;
-        for (i, &candidate) in candidates.iter().enumerate() {
+        let mut similarities = Vec::with_capacity(candidates.len());
+        for &candidate in candidates {
             let candidate_embedding = embeddings.get(self.get(candidate), candidate)?;
-            let similarity = embedding_comp.cosine_similarity(&candidate_embedding);
-            let old_max_similarity = max_similarity;
-            max_similarity = max_similarity.max(similarity);
-            if max_similarity > old_max_similarity {
-                best_candidate = i;
-            }
+            similarities.push((
+                candidate,
+                embedding_comp.cosine_similarity(&candidate_embedding),
+            ));
         }
-        if max_similarity >= embeddings::SIMILARITY_THRESHOLD {
-            return Ok(Some((candidates[best_candidate], max_similarity)));
+        if let Some(best) = self.best_candidate(string_pool, relative, &similarities)
+            && best.similarity >= self.options.similarity_threshold
+        {
+            return Ok(Some((best.id, best.similarity)));
         }
         Ok(None)
     }
 
+    // The best of the candidates, given the similarity of each one's
+    // embeddings to the relative's.
+    fn best_candidate(
+        &self,
+        string_pool: &StringPool,
+        relative: ItemId,
+        similarities: &[(ItemId, f32)],
+    ) -> Option<CandidateRank> {
+        let relative = self.get(relative);
+        let relative_term = (relative.lang(), relative.term().resolve(string_pool));
+        // Homographs all have the same term similarity to the relative, so it
+        // only counts among candidates whose terms differ, i.e. those found by
+        // page term or romanization.
+        let terms_differ = !similarities
+            .iter()
+            .map(|&(candidate, _)| self.get(candidate).term())
+            .all_equal();
+        let mut best: Option<CandidateRank> = None;
+        for &(candidate, similarity) in similarities {
+            let candidate_item = self.get(candidate);
+            let term_similarity = terms_differ.then(|| {
+                orthography::term_similarity(
+                    relative_term,
+                    (
+                        candidate_item.lang(),
+                        candidate_item.term().resolve(string_pool),
+                    ),
+                )
+            });
+            let rank = CandidateRank {
+                id: candidate,
+                similarity,
+                score: term_similarity.map_or(similarity, |term_similarity| {
+                    orthography::blend_similarity(
+                        similarity,
+                        term_similarity,
+                        self.options.term_similarity_weight,
+                    )
+                }),
+                gloss_overlap: gloss_overlap(relative, candidate_item),
+                shared_pos: relative
+                    .pos()
+                    .zip(candidate_item.pos())
+                    .map_or(0, |(relative_pos, pos)| {
+                        pos.iter().filter(|p| relative_pos.contains(p)).count()
+                    }),
+                ety_num: candidate_item.ety_num(),
+            };
+            if best.as_ref().map_or(true, |b| rank.outranks(b)) {
+                best = Some(rank);
+            }
+        }
+        best
+    }
+
+    // relative is the item on the other end of the prospective ety link, i.e.
+    // the child if the langterm is for a parent, or vice versa.
     pub(crate) fn get_disambiguated_item_id(
         &self,
+        string_pool: &StringPool,
         embeddings: &Embeddings,
         embedding_comp: &impl embeddings::Comparand<ItemEmbedding>,
+        relative: ItemId,
         langterm: LangTerm,
     ) -> Result<Option<(ItemId, f32)>> {
         let langterm = self.redirects.rectify_langterm(langterm);
         if let Some(candidates) = self.get_dupes(langterm)
-            && let Some((item_id, similarity)) =
-                self.get_max_similarity_candidate(embeddings, embedding_comp, candidates)?
+            && let Some((item_id, similarity)) = self.get_max_similarity_candidate(
+                string_pool,
+                embeddings,
+                embedding_comp,
+                relative,
+                candidates,
+            )?
         {
             return Ok(Some((item_id, similarity)));
         }
         if let Some(candidates) = self.page_term_dupes.get(&langterm)
-            && let Some((item_id, similarity)) =
-                self.get_max_similarity_candidate(embeddings, embedding_comp, candidates)?
+            && let Some((item_id, similarity)) = self.get_max_similarity_candidate(
+                string_pool,
+                embeddings,
+                embedding_comp,
+                relative,
+                candidates,
+            )?
+        {
+            return Ok(Some((item_id, similarity)));
+        }
+        if let Some(candidates) = self.romanization_dupes.get(&langterm)
+            && let Some((item_id, similarity)) = self.get_max_similarity_candidate(
+                string_pool,
+                embeddings,
+                embedding_comp,
+                relative,
+                candidates,
+            )?
         {
             return Ok(Some((item_id, similarity)));
         }
@@ -335,6 +691,144 @@ impl Items {
     }
 }
 
+/// How the pos's and glosses of a wiktextract entry are split into items.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug, ValueEnum)]
+pub enum Granularity {
+    /// One item per ety, with all the pos's and glosses under it.
+    #[default]
+    Ety,
+    /// One item per pos under an ety, each linked as a sense of the item for
+    /// the ety's first pos, which is the one given the ety. Pos's with their
+    /// own Descendants sections then keep them apart.
+    Pos,
+}
+
+/// Which terms of more than one word, e.g. "kangaroo court", get items.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug, ValueEnum)]
+pub enum MultiWordPolicy {
+    /// Only single words, and affixes separated by whitespace, e.g. the
+    /// circumfix "ver- -en". Ety links to multi-word terms are dropped.
+    Never,
+    /// Multi-word terms, except those whose pos is a phrase.
+    #[default]
+    NonPhrases,
+    /// All multi-word terms, including phrases, e.g. Latin phrases that
+    /// univerbated in Romance.
+    All,
+}
+
+impl MultiWordPolicy {
+    pub(crate) fn allows(self, term: &str) -> bool {
+        self != Self::Never || !is_multi_word(term)
+    }
+
+    pub(crate) fn allows_phrases(self) -> bool {
+        self == Self::All
+    }
+}
+
+/// How cycles of ety links are broken. Whichever links are chosen, all the
+/// links from their children are removed, so that no item is left with a
+/// partial ety.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug, ValueEnum)]
+pub enum CycleStrategy {
+    /// Remove a greedily found set of links whose removal leaves no cycles.
+    #[default]
+    FeedbackArcSet,
+    /// Remove the least confident link in each cycle.
+    LowestConfidence,
+    /// Remove the least confident link in each cycle with an imputed item at
+    /// either end, if any, since such links rest on less evidence.
+    PreferImputed,
+}
+
+/// What became of a real item added to the items.
+pub(crate) enum AddedReal {
+    // a new ety item
+    Ety,
+    // a new pos item under an existing ety item, with pos granularity
+    Pos,
+    // merged into an existing item as another pos and gloss
+    Merged,
+}
+
+/// When to impute an item for a lang-term that has no item of its own.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug, ValueEnum)]
+pub enum ImputationPolicy {
+    /// Never impute items. Ety links to such lang-terms are dropped.
+    Never,
+    /// Only impute items that will be terminal ancestors, i.e. that will not
+    /// themselves be given any parents.
+    TerminalOnly,
+    /// Only impute items in reconstructed languages.
+    ReconstructedOnly,
+    /// Impute items wherever they are needed.
+    #[default]
+    Free,
+}
+
+impl ImputationPolicy {
+    fn allows(self, lang: Lang, as_terminal_ancestor: bool) -> bool {
+        match self {
+            Self::Never => false,
+            Self::TerminalOnly => as_terminal_ancestor,
+            Self::ReconstructedOnly => lang.is_reconstructed(),
+            Self::Free => true,
+        }
+    }
+
+    // Whether an imputed item may be given parents of its own, e.g. when
+    // processing a chain of etymology templates.
+    pub(crate) fn allows_chains(self) -> bool {
+        matches!(self, Self::ReconstructedOnly | Self::Free)
+    }
+}
+
+// How a homograph candidate ranks against the others. Candidates are compared
+// by score first. The other fields break ties deterministically, rather than
+// by candidate order. This matters most when the candidates have no
+// embeddings, since then their scores are all the same for homographs, and
+// differ at most by term similarity for other candidates.
+struct CandidateRank {
+    id: ItemId,
+    // the similarity of the embeddings, which is the confidence of a link to
+    // the candidate, and is held to the similarity threshold
+    similarity: f32,
+    // the similarity, blended with the term similarity where the candidates'
+    // terms differ
+    score: f32,
+    // the most words a gloss of the candidate shares with one of the
+    // relative's, as a share of the words in either
+    gloss_overlap: f32,
+    // how many of the candidate's pos's the relative has too
+    shared_pos: usize,
+    ety_num: u8,
+}
+
+impl CandidateRank {
+    fn outranks(&self, other: &Self) -> bool {
+        self.score
+            .total_cmp(&other.score)
+            .then(self.gloss_overlap.total_cmp(&other.gloss_overlap))
+            .then(self.shared_pos.cmp(&other.shared_pos))
+            // prefer lower ety nums, then earlier added items
+            .then(other.ety_num.cmp(&self.ety_num))
+            .then(other.id.cmp(&self.id))
+            .is_gt()
+    }
+}
+
+fn gloss_overlap(relative: &Item, candidate: &Item) -> f32 {
+    let (Some(relative_glosses), Some(glosses)) = (relative.gloss(), candidate.gloss()) else {
+        return 0.0;
+    };
+    relative_glosses
+        .iter()
+        .cartesian_product(glosses)
+        .map(|(relative_gloss, gloss)| relative_gloss.word_overlap(gloss))
+        .fold(0.0, f32::max)
+}
+
 pub(crate) struct Retrieval {
     pub(crate) item_id: ItemId,
     pub(crate) confidence: f32,
@@ -342,21 +836,44 @@ pub(crate) struct Retrieval {
 }
 
 impl Items {
+    // Returns None if there is no existing item for the lang-term and the
+    // imputation policy does not allow imputing one. as_terminal_ancestor
+    // should be true if an imputed item would not be given any parents. See
+    // get_disambiguated_item_id for relative.
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn get_or_impute_item(
         &mut self,
+        string_pool: &StringPool,
         embeddings: &Embeddings,
         embedding_comp: &impl embeddings::Comparand<ItemEmbedding>,
         from_item: ItemId,
+        relative: ItemId,
         langterm: LangTerm,
-    ) -> Result<Retrieval> {
-        if let Some((item_id, confidence)) =
-            self.get_disambiguated_item_id(embeddings, embedding_comp, langterm)?
-        {
-            return Ok(Retrieval {
+        as_terminal_ancestor: bool,
+    ) -> Result<Option<Retrieval>> {
+        if let Some((item_id, confidence)) = self.get_disambiguated_item_id(
+            string_pool,
+            embeddings,
+            embedding_comp,
+            relative,
+            langterm,
+        )? {
+            return Ok(Some(Retrieval {
                 item_id,
                 confidence,
                 // is_newly_imputed: false,
-            });
+            }));
+        }
+        if !self
+            .options
+            .imputation_policy
+            .allows(langterm.lang, as_terminal_ancestor)
+            || !self
+                .options
+                .multi_word_policy
+                .allows(langterm.term.resolve(string_pool))
+        {
+            return Ok(None);
         }
         let imputed = ImputedItem {
             ety_num: 1, // may get changed in add_imputed
@@ -366,11 +883,11 @@ impl Items {
             from: from_item,
         };
         let item_id = self.add_imputed(imputed);
-        Ok(Retrieval {
+        Ok(Some(Retrieval {
             item_id,
-            confidence: embeddings::SIMILARITY_THRESHOLD,
+            confidence: self.options.similarity_threshold,
             // is_newly_imputed: true,
-        })
+        }))
     }
 
     // We determine that an item needs an embedding if it has any
@@ -397,13 +914,16 @@ impl Items {
             items_needing_embedding
                 .extend(self.get_desc_items_needing_embedding(item_id, raw_descendants));
         }
-        if let Some(raw_root) = self.raw_templates.root.get(&item_id)
-            && let Some(root_items) = self.get_dupes(raw_root.langterm)
-            && root_items.len() > 1
-        {
-            items_needing_embedding.insert(item_id);
-            for &root_item in root_items {
-                items_needing_embedding.insert(root_item);
+        if let Some(raw_root) = self.raw_templates.root.get(&item_id) {
+            for &langterm in &raw_root.langterms {
+                if let Some(root_items) = self.get_dupes(langterm)
+                    && root_items.len() > 1
+                {
+                    items_needing_embedding.insert(item_id);
+                    for &root_item in root_items {
+                        items_needing_embedding.insert(root_item);
+                    }
+                }
             }
         }
         items_needing_embedding
@@ -437,7 +957,8 @@ impl Items {
         let pb = progress_bar(items_needing_embedding.len(), "Generating embeddings")?;
         let update_interval = embeddings_config.batch_size;
         pb.inc(0);
-        for (line_number, mut line) in wiktextract_lines(wiktextract_path)?.enumerate() {
+        let line_range = self.options.line_range;
+        for (line_number, mut line) in line_range.numbered(wiktextract_lines(wiktextract_path)?) {
             // Items were only inserted into the line map if they were added to
             // the term_map in process_json_item.
             if let Some(&item_id) = self.lines.get(&line_number)
@@ -459,13 +980,214 @@ impl Items {
         Ok(embeddings)
     }
 
-    pub(crate) fn generate_ety_graph(&mut self, embeddings: &Embeddings) -> Result<()> {
-        self.process_raw_descendants(embeddings)?;
-        self.graph.remove_cycles()?;
-        self.process_raw_etymologies(embeddings)?;
-        self.graph.remove_cycles()?;
-        self.impute_root_etys(embeddings)?;
-        self.graph.remove_cycles()?;
+    /// Add an ety link to the graph, unless the item would be one of its own
+    /// ety items.
+    pub(crate) fn add_ety(
+        &mut self,
+        item: ItemId,
+        mode: EtyMode,
+        head: Option<u8>,
+        ety_items: &[ItemId],
+        confidences: &[f32],
+        provenance: EdgeProvenance,
+    ) {
+        if ety_items.contains(&item) {
+            self.anomalies.record(AnomalyKind::SelfLoop, item, None);
+            return;
+        }
+        // Don't add ety connection if the confidence is too low. This currently
+        // should never get applied, as get_or_impute_item() returns a min
+        // confidence of the similarity threshold.
+        if confidences
+            .iter()
+            .any(|confidence| confidence < &self.options.similarity_threshold)
+        {
+            return;
+        }
+        self.graph
+            .add_ety(item, mode, head, ety_items, confidences, provenance);
+    }
+
+    pub(crate) fn generate_ety_graph(
+        &mut self,
+        string_pool: &StringPool,
+        embeddings: &Embeddings,
+    ) -> Result<()> {
+        self.note_expected_connections();
+        self.process_raw_descendants(string_pool, embeddings)?;
+        self.remove_cycles()?;
+        self.process_raw_etymologies(string_pool, embeddings)?;
+        self.remove_cycles()?;
+        self.impute_root_etys(string_pool, embeddings)?;
+        self.remove_cycles()?;
+        self.resolve_imputed_items();
+        self.remove_cycles()?;
         Ok(())
     }
+
+    // An item is imputed for a lang-term when none of the real items for it
+    // was similar enough to link to. But if there is only one real item for
+    // the lang-term, that can only be the one meant, so the imputed item is
+    // merged into it, rather than left as a duplicate of it.
+    fn resolve_imputed_items(&mut self) {
+        print!("  Resolving imputed items against real items... ");
+        let resolutions = self
+            .iter()
+            .filter_map(|(id, item)| {
+                let Item::Imputed(imputed) = item else {
+                    return None;
+                };
+                let langterm = LangTerm::new(imputed.lang, imputed.term);
+                let langterm = self.redirects.rectify_langterm(langterm);
+                let real_items = [&self.dupes, &self.page_term_dupes, &self.romanization_dupes]
+                    .into_iter()
+                    .filter_map(|dupes| dupes.get(&langterm))
+                    .flatten()
+                    .unique()
+                    .collect_vec();
+                match real_items[..] {
+                    [&real_item] => Some((id, real_item)),
+                    _ => None,
+                }
+            })
+            .collect_vec();
+        let mut merged = HashMap::<ItemId, ItemId>::default();
+        for (imputed, real) in resolutions {
+            if self.graph.merge_item(imputed, real) {
+                merged.insert(imputed, real);
+            }
+        }
+        println!("Merged {}.", merged.len());
+        if merged.is_empty() {
+            return;
+        }
+        let rectify = |id: &mut ItemId| {
+            if let Some(&real) = merged.get(id) {
+                *id = real;
+            }
+        };
+        for id in self.graph.graph.node_indices().collect_vec() {
+            if let Item::Imputed(imputed) = self.graph.item_mut(id) {
+                rectify(&mut imputed.from);
+            }
+        }
+        for dupes in self.imputed_dupes.values_mut() {
+            dupes.retain(|id| !merged.contains_key(id));
+        }
+        self.imputed_dupes.retain(|_, dupes| !dupes.is_empty());
+        self.anomalies.rectify_items(rectify);
+        for edge in &mut self.removed_cycle_edges {
+            rectify(&mut edge.child);
+            rectify(&mut edge.parent);
+        }
+    }
+
+    fn remove_cycles(&mut self) -> Result<()> {
+        let removed = self.graph.remove_cycles(self.options.cycle_strategy)?;
+        self.removed_cycle_edges.extend(removed);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn unnumbered_ety_sections_with_different_texts_are_split() {
+        let string_pool = StringPool::new();
+        let mut items = Items::new(ProcessingOptions::default()).unwrap();
+        let mut add = |pos: &str, ety_text: &str| {
+            let item = RealItem {
+                pos: vec![Pos::from_str(pos).unwrap()],
+                gloss: vec![Gloss::new(&string_pool, pos)],
+                ety_text: Some(Gloss::new(&string_pool, ety_text)),
+                ..test_item(&string_pool, "en", "bank")
+            };
+            items.add_real(item).0
+        };
+        let river = add("noun", "From Old Norse bakki");
+        let river_verb = add("verb", "From Old Norse bakki");
+        let money = add("noun", "From Italian banca");
+        let money_verb = add("verb", "From Italian banca");
+        assert_eq!(river, river_verb);
+        assert_eq!(money, money_verb);
+        assert_ne!(river, money);
+        assert_eq!(items.get(money).ety_num(), 2);
+    }
+
+    fn real_item(
+        string_pool: &StringPool,
+        lang: &str,
+        term: &str,
+        ety_num: u8,
+        pos: &str,
+        gloss: &str,
+    ) -> RealItem {
+        RealItem {
+            ety_num,
+            pos: vec![Pos::from_str(pos).unwrap()],
+            gloss: vec![Gloss::new(string_pool, gloss)],
+            ..test_item(string_pool, lang, term)
+        }
+    }
+
+    #[test]
+    fn term_similarity_ranks_only_candidates_whose_terms_differ() {
+        let string_pool = StringPool::new();
+        let mut items = Items::new(ProcessingOptions::default()).unwrap();
+        let mut add = |lang: &str, term: &str, ety_num: u8| {
+            let item = real_item(&string_pool, lang, term, ety_num, "noun", term);
+            items.add_real(item).0
+        };
+        let child = add("gem-pro", "fōts", 1);
+        let foot = add("ine-pro", "pṓds", 1);
+        let water = add("ine-pro", "wódr̥", 1);
+        let other_foot = add("ine-pro", "pṓds", 2);
+        // Though its embeddings are less similar, the term that corresponds to
+        // the child's wins, and the link's confidence is still the similarity
+        // of the embeddings.
+        let best = items
+            .best_candidate(&string_pool, child, &[(water, 0.35), (foot, 0.3)])
+            .unwrap();
+        assert_eq!(best.id, foot);
+        assert!((best.similarity - 0.3).abs() < f32::EPSILON);
+        // Homographs are told apart by their embeddings alone.
+        let best = items
+            .best_candidate(&string_pool, child, &[(foot, 0.3), (other_foot, 0.35)])
+            .unwrap();
+        assert_eq!(best.id, other_foot);
+        assert!((best.similarity - 0.35).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn ties_between_candidates_are_broken_deterministically() {
+        let string_pool = StringPool::new();
+        let mut items = Items::new(ProcessingOptions::default()).unwrap();
+        let mut add = |term: &str, ety_num: u8, pos: &str, gloss: &str| {
+            let item = real_item(&string_pool, "en", term, ety_num, pos, gloss);
+            items.add_real(item).0
+        };
+        let child = add("bankrupt", 1, "adjective", "unable to pay one's debts");
+        let river = add("bank", 1, "noun", "the edge of a river");
+        let money = add("bank", 2, "noun", "an institution where one can pay debts");
+        let verb = add("bank", 3, "verb", "tilt an aircraft");
+        let other_verb = add("bank", 4, "verb", "tilt an aircraft");
+        // Candidates without embeddings all have a similarity of 0, whatever
+        // their order.
+        let best = |candidates: &[ItemId]| {
+            let similarities = candidates.iter().map(|&c| (c, 0.0)).collect_vec();
+            items
+                .best_candidate(&string_pool, child, &similarities)
+                .unwrap()
+                .id
+        };
+        // the gloss that shares the most words with the child's
+        assert_eq!(best(&[river, money, verb]), money);
+        assert_eq!(best(&[verb, money, river]), money);
+        // the lower ety num, when nothing else tells them apart
+        assert_eq!(best(&[verb, other_verb]), verb);
+        assert_eq!(best(&[other_verb, verb]), verb);
+    }
 }
@@ -1,12 +1,13 @@
-use serde::{Deserialize, Serialize};
-
 use crate::{
     languages::Lang,
     string_pool::{StringPool, Symbol},
 };
 
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
 impl Lang {
-    pub(crate) fn new_langterm(self, string_pool: &mut StringPool, term: &str) -> LangTerm {
+    pub(crate) fn new_langterm(self, string_pool: &StringPool, term: &str) -> LangTerm {
         let term = Term::new(string_pool, term);
         LangTerm::new(self, term)
     }
@@ -24,8 +25,15 @@ impl From<Symbol> for Term {
 }
 
 impl<'a> Term {
-    pub(crate) fn new(string_pool: &mut StringPool, term: &str) -> Self {
-        let symbol = string_pool.get_or_intern(term);
+    // Multi-word terms are interned with their whitespace normalized, since
+    // templates cite them inconsistently, e.g. with doubled or non-breaking
+    // spaces, and then wouldn't match the item's term.
+    pub(crate) fn new(string_pool: &StringPool, term: &str) -> Self {
+        let symbol = if has_irregular_whitespace(term) {
+            string_pool.get_or_intern(&term.split_whitespace().join(" "))
+        } else {
+            string_pool.get_or_intern(term)
+        };
         Self { symbol }
     }
 
@@ -34,7 +42,22 @@ impl<'a> Term {
     }
 }
 
-#[derive(Hash, Eq, PartialEq, Debug, Copy, Clone)]
+fn has_irregular_whitespace(term: &str) -> bool {
+    term.starts_with(char::is_whitespace)
+        || term.ends_with(char::is_whitespace)
+        || term.contains("  ")
+        || term.contains(|c: char| c.is_whitespace() && c != ' ')
+}
+
+/// Whether the term is more than one word, e.g. "kangaroo court". Affixes
+/// separated by whitespace, e.g. the circumfix "ver- -en", count as one.
+pub(crate) fn is_multi_word(term: &str) -> bool {
+    let mut words = term.split_whitespace();
+    words.clone().nth(1).is_some()
+        && !words.all(|word| word.starts_with('-') || word.ends_with('-'))
+}
+
+#[derive(Hash, Eq, PartialEq, Debug, Copy, Clone, Serialize, Deserialize)]
 pub(crate) struct LangTerm {
     pub(crate) lang: Lang,
     pub(crate) term: Term,
@@ -45,3 +68,18 @@ impl LangTerm {
         Self { lang, term }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn multi_word_terms() {
+        let string_pool = StringPool::new();
+        let term = Term::new(&string_pool, "kangaroo court");
+        assert_eq!(Term::new(&string_pool, " kangaroo\u{a0} court"), term);
+        assert!(is_multi_word("kangaroo court"));
+        assert!(!is_multi_word("ver- -en"));
+        assert!(!is_multi_word("court"));
+    }
+}
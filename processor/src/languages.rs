@@ -1,15 +1,19 @@
 use crate::HashMap;
 
-use std::{collections::BTreeMap, str::FromStr};
+use std::{collections::BTreeMap, fmt, str::FromStr, sync::OnceLock};
 
-use anyhow::{anyhow, Ok, Result};
+use anyhow::{anyhow, bail, Ok, Result};
+use itertools::Itertools;
 use lazy_static::lazy_static;
-use serde::{Deserialize, Serialize};
+use serde::{
+    de::{self, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
 use serde_json::{json, Value};
 
 #[derive(Hash, Eq, PartialEq, Debug, Copy, Clone, Serialize, Deserialize, Default)]
 #[serde(rename_all = "kebab-case")]
-enum LangKind {
+pub(crate) enum LangKind {
     #[default]
     Regular,
     Reconstructed,
@@ -20,10 +24,11 @@ enum LangKind {
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct RawLangData {
-    // aliases: Vec<&'static str>,
+    aliases: Vec<&'static str>,
     ancestors: Vec<&'static str>,
     canonical_name: &'static str,
-    // family: Option<&'static str>,
+    #[serde(default)]
+    family: Option<&'static str>,
     kind: LangKind,
     // For regular languages, the mainCode should be the same as the code. For
     // etymology-only languages, it may not be the same. For example, Vulgar
@@ -32,17 +37,73 @@ struct RawLangData {
     non_etymology_only: &'static str,
     // other_names: Vec<&'static str>,
     // parents: Vec<&'static str>,
-    // scripts: Vec<&'static str>,
+    scripts: Vec<&'static str>,
     // varieties: Vec<&'static str>,
     // wikidata_item: &'static str,
     // wikipedia_article: &'static str,
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawFamilyData {
+    canonical_name: &'static str,
+    // From the outermost in, e.g. ["ine", "gem"] for West Germanic.
+    superfamilies: Vec<&'static str>,
+}
+
+// The pseudo-family of langs that aren't in a family, e.g. isolates, sign
+// languages and constructed languages, which is among their superfamilies.
+const NOT_A_FAMILY: &str = "qfa-not";
+
 type LangId = u16;
 
-#[derive(Default, Hash, Eq, PartialEq, Debug, Copy, Clone, Serialize, Deserialize)]
+#[derive(Default, Hash, Eq, PartialEq, Debug, Copy, Clone)]
 pub struct Lang(LangId);
 
+// Lang ids depend on the iteration order of languages.json, so they can change
+// whenever it is updated. We therefore serialize langs by their main code, so
+// that serialized data remains readable by binaries with updated lang data.
+impl Serialize for Lang {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.code())
+    }
+}
+
+struct LangVisitor;
+
+impl Visitor<'_> for LangVisitor {
+    type Value = Lang;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a lang code or lang id")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Lang, E> {
+        // Ids are accepted too, as clients send back the ids they get from
+        // Lang::json(). No lang code is purely numeric.
+        if let Result::Ok(id) = v.parse::<LangId>() {
+            return self.visit_u64(u64::from(id));
+        }
+        Lang::from_str(v).map_err(E::custom)
+    }
+
+    // Data serialized before langs were serialized by code will have ids.
+    // These are only correct if languages.json hasn't changed since.
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Lang, E> {
+        LangId::try_from(v)
+            .ok()
+            .filter(|&id| usize::from(id) < LANGUAGES.data.len())
+            .map(Lang)
+            .ok_or_else(|| E::custom(format!("unknown lang id {v}")))
+    }
+}
+
+impl<'de> Deserialize<'de> for Lang {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(LangVisitor)
+    }
+}
+
 impl From<LangId> for Lang {
     fn from(id: LangId) -> Self {
         Self(id)
@@ -54,10 +115,20 @@ impl From<LangId> for Lang {
 struct LangData {
     code: &'static str,
     name: &'static str,
+    // The codes of the scripts the lang is written in, e.g. ["Cyrl", "Latn"],
+    // the first being the main one.
+    scripts: Vec<&'static str>,
     url_name: String,
     kind: LangKind,
     non_ety: Lang,
     ancestors: Vec<Lang>,
+    // Other common names, e.g. "Anglo-Saxon" for Old English.
+    aliases: Vec<&'static str>,
+    family: Option<&'static str>,
+    family_name: Option<&'static str>,
+    // The lang's family and its superfamilies, from the outermost in. Empty if
+    // the lang isn't in an actual family, e.g. if it is an isolate.
+    families: Vec<&'static str>,
 }
 
 struct Languages {
@@ -77,6 +148,10 @@ impl Languages {
             include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/data/languages.json")),
         )
         .expect("well-formed languages.json");
+        let code2family: HashMap<&'static str, RawFamilyData> = serde_json::from_str(include_str!(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/data/families.json")
+        ))
+        .expect("well-formed families.json");
 
         let mut main_code2id = HashMap::default();
         let mut next_id: LangId = 0;
@@ -115,9 +190,20 @@ impl Languages {
                 .collect::<Vec<_>>();
             ancestors.push(lang);
 
+            let family = raw_data.family.and_then(|code| code2family.get(code));
+            let families = family
+                .map(|family| {
+                    let mut families = family.superfamilies.clone();
+                    families.push(raw_data.family.expect("has a family"));
+                    families
+                })
+                .filter(|families| !families.contains(&NOT_A_FAMILY))
+                .unwrap_or_default();
+
             let lang_data = LangData {
                 code: raw_data.main_code,
                 name: raw_data.canonical_name,
+                scripts: raw_data.scripts.clone(),
                 url_name: urlencoding::encode(&raw_data.canonical_name.replace(' ', "_"))
                     .to_string(),
                 kind: raw_data.kind,
@@ -126,6 +212,10 @@ impl Languages {
                     .map(|&id| Lang(id))
                     .expect("non etymology code should be a main code"),
                 ancestors,
+                aliases: raw_data.aliases.clone(),
+                family: raw_data.family,
+                family_name: family.map(|family| family.canonical_name),
+                families,
             };
 
             data[id as usize] = lang_data;
@@ -143,11 +233,11 @@ impl Languages {
     }
 
     fn code2lang(&self, code: &str) -> Option<Lang> {
-        self.code2id.get(code).copied()
+        self.code2id.get(code).copied().map(Lang::merged)
     }
 
     fn name2lang(&self, name: &str) -> Option<Lang> {
-        self.name2id.get(name).copied()
+        self.name2id.get(name).copied().map(Lang::merged)
     }
 }
 
@@ -155,6 +245,47 @@ lazy_static! {
     static ref LANGUAGES: Languages = Languages::new();
 }
 
+// The langs that are parsed as other langs, e.g. Norwegian Bokmål as
+// Norwegian, so that their items are merged. Set at most once, before any
+// data is parsed.
+static MERGES: OnceLock<HashMap<Lang, Lang>> = OnceLock::new();
+
+/// Parse each lang in `merges` as the lang it is paired with, wherever a lang
+/// code or name is parsed from then on, so that e.g. the items of the variants
+/// of a macrolanguage are merged into the items of the macrolanguage rather
+/// than having parallel ety trees. This applies to the whole process.
+///
+/// # Errors
+///
+/// Will return `Err` if the merges were already set, or if a lang is merged
+/// into a lang that is itself merged into another.
+pub fn merge_langs(merges: &[(Lang, Lang)]) -> Result<()> {
+    if let Some(&(from, into)) = merges
+        .iter()
+        .find(|(_, into)| merges.iter().any(|(from, _)| from == into))
+    {
+        bail!(
+            "{} is merged into {}, which is itself merged into another lang",
+            from.code(),
+            into.code()
+        );
+    }
+    MERGES
+        .set(merges.iter().copied().collect())
+        .map_err(|_| anyhow!("lang merges were already set"))
+}
+
+/// The merges set with [`merge_langs`], as "from=into" pairs of lang codes.
+pub(crate) fn lang_merges() -> Vec<String> {
+    MERGES
+        .get()
+        .into_iter()
+        .flatten()
+        .map(|(from, into)| format!("{}={}", from.code(), into.code()))
+        .sorted()
+        .collect()
+}
+
 impl FromStr for Lang {
     type Err = anyhow::Error;
 
@@ -174,6 +305,28 @@ impl Lang {
         Err(anyhow!("Unknown lang canonical name \"{name}\""))
     }
 
+    /// Parse a merge of one lang into another, given as "from=into", e.g.
+    /// "nb=no".
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is no "=" or either lang code is unknown.
+    pub fn parse_merge(merge: &str) -> Result<(Self, Self)> {
+        let Some((from, into)) = merge.split_once('=') else {
+            bail!("expected a lang merge like \"nb=no\", got \"{merge}\"");
+        };
+        Ok((Self::from_str(from.trim())?, Self::from_str(into.trim())?))
+    }
+
+    // The lang this is merged into, if any, else itself.
+    fn merged(self) -> Self {
+        MERGES
+            .get()
+            .and_then(|merges| merges.get(&self))
+            .copied()
+            .unwrap_or(self)
+    }
+
     pub(crate) fn id(self) -> LangId {
         self.0
     }
@@ -190,6 +343,19 @@ impl Lang {
         self.data().name
     }
 
+    pub(crate) fn aliases(self) -> &'static [&'static str] {
+        &self.data().aliases
+    }
+
+    /// The main script of the lang, e.g. "Latn" or "Cyrl".
+    pub(crate) fn script(self) -> Option<&'static str> {
+        self.scripts().first().copied()
+    }
+
+    pub(crate) fn scripts(self) -> &'static [&'static str] {
+        &self.data().scripts
+    }
+
     pub(crate) fn url_name(self) -> &'static str {
         &self.data().url_name
     }
@@ -198,6 +364,10 @@ impl Lang {
         self.data().non_ety
     }
 
+    pub(crate) fn kind(self) -> LangKind {
+        self.data().kind
+    }
+
     pub(crate) fn is_reconstructed(self) -> bool {
         self.data().kind == LangKind::Reconstructed
     }
@@ -206,6 +376,15 @@ impl Lang {
         &self.data().ancestors
     }
 
+    /// The code of the lang's family, e.g. "gmw" for English.
+    pub(crate) fn family(self) -> Option<&'static str> {
+        self.data().family
+    }
+
+    pub(crate) fn family_name(self) -> Option<&'static str> {
+        self.data().family_name
+    }
+
     pub(crate) fn descends_from(self, lang: Lang) -> bool {
         self.ancestors().contains(&lang)
     }
@@ -223,7 +402,7 @@ impl Lang {
         let ancestors_lang = lang.ancestors();
 
         if ancestors_self.first() != ancestors_lang.first() {
-            return None;
+            return self.family_distance_from(lang);
         }
 
         let (longer, shorter) = if ancestors_self.len() >= ancestors_lang.len() {
@@ -244,10 +423,28 @@ impl Lang {
         Some(distance)
     }
 
+    // For langs that share no ancestor, e.g. because the ancestors of one
+    // aren't known, the distance through the innermost family they share,
+    // counting each lang's own family as one step from it.
+    fn family_distance_from(self, lang: Lang) -> Option<usize> {
+        let families_self = &self.data().families;
+        let families_lang = &lang.data().families;
+        let shared = families_self
+            .iter()
+            .zip(families_lang)
+            .take_while(|(a, b)| a == b)
+            .count();
+        if shared == 0 {
+            return None;
+        }
+        Some(families_self.len() + families_lang.len() + 2 - 2 * shared)
+    }
+
     pub(crate) fn json(self) -> Value {
         json!({
             "id": self.id(),
             "name": self.name(),
+            "script": self.script(),
         })
     }
 }
@@ -281,6 +478,12 @@ mod tests {
         assert_eq!(vl.code(), "la-vul");
     }
 
+    #[test]
+    fn lang_aliases() {
+        let ang = Lang::from_str("ang").unwrap();
+        assert!(ang.aliases().contains(&"Anglo-Saxon"));
+    }
+
     #[test]
     fn lang_non_ety() {
         let vulgar_latin = Lang::from_str("la-vul").unwrap();
@@ -298,6 +501,29 @@ mod tests {
         assert!(ine_pro.is_reconstructed());
     }
 
+    #[test]
+    fn lang_serde() {
+        let vl = Lang::from_str("VL.").unwrap();
+        let serialized = serde_json::to_string(&vl).unwrap();
+        assert_eq!(serialized, "\"la-vul\"");
+        assert_eq!(serde_json::from_str::<Lang>(&serialized).unwrap(), vl);
+        let id = serde_json::to_string(&vl.id()).unwrap();
+        assert_eq!(serde_json::from_str::<Lang>(&id).unwrap(), vl);
+        let quoted_id = format!("\"{}\"", vl.id());
+        assert_eq!(serde_json::from_str::<Lang>(&quoted_id).unwrap(), vl);
+        assert!(serde_json::from_str::<Lang>("\"not-a-lang\"").is_err());
+    }
+
+    #[test]
+    fn lang_script() {
+        let en = Lang::from_str("en").unwrap();
+        assert_eq!(en.script(), Some("Latn"));
+        let ru = Lang::from_str("ru").unwrap();
+        assert_eq!(ru.script(), Some("Cyrl"));
+        let sh = Lang::from_str("sh").unwrap();
+        assert!(sh.scripts().contains(&"Latn") && sh.scripts().contains(&"Cyrl"));
+    }
+
     #[test]
     fn lang_ancestors() {
         let en = Lang::from_str("en").unwrap();
@@ -386,4 +612,30 @@ mod tests {
         assert_eq!(ar.distance_from(latin), None);
         assert_eq!(latin.distance_from(ar), None);
     }
+
+    #[test]
+    fn lang_parse_merge() {
+        let (nb, no) = Lang::parse_merge("nb=no").unwrap();
+        assert_eq!((nb.code(), no.code()), ("nb", "no"));
+        assert!(Lang::parse_merge("nb").is_err());
+        assert!(Lang::parse_merge("nb=not-a-lang").is_err());
+    }
+
+    #[test]
+    fn lang_family() {
+        let en = Lang::from_str("en").unwrap();
+        assert_eq!(en.family(), Some("gmw"));
+        assert_eq!(en.family_name(), Some("West Germanic"));
+        // constructed, so not in an actual family
+        let afh = Lang::from_str("afh").unwrap();
+        assert!(afh.data().families.is_empty());
+        assert_eq!(afh.distance_from(en), None);
+
+        // Neither has known ancestors, but both are Jivaroan.
+        let acu = Lang::from_str("acu").unwrap();
+        let agr = Lang::from_str("agr").unwrap();
+        assert_eq!(acu.family(), Some("sai-jiv"));
+        assert_eq!(acu.distance_from(agr), Some(2));
+        assert_eq!(agr.distance_from(acu), Some(2));
+    }
 }
@@ -3,80 +3,482 @@
 #![feature(let_chains, array_chunks)]
 #![allow(clippy::redundant_closure_for_method_calls)]
 
+mod checkpoint;
+pub use crate::checkpoint::Checkpoints;
+mod coverage;
+pub use crate::coverage::report_template_coverage;
 mod descendants;
+mod diff;
+pub use crate::diff::Diff;
 pub mod embeddings;
+mod embeddings_export;
+pub use crate::embeddings_export::EmbeddingsFormat;
 mod ety_graph;
 mod etymology;
 mod etymology_templates;
+mod evaluation;
+pub mod fine_tune;
 mod gloss;
+mod input;
+pub use crate::input::LineRange;
 mod items;
-pub use crate::items::ItemId;
+pub use crate::items::{CycleStrategy, Granularity, ImputationPolicy, ItemId, MultiWordPolicy};
 mod langterm;
 mod languages;
 use crate::items::Items;
-pub use crate::languages::Lang;
+pub use crate::languages::{merge_langs, Lang};
+mod line_errors;
+pub use crate::line_errors::{LineErrorKind, LineErrorThresholds};
+mod orthography;
+mod paths;
+pub use crate::paths::RandomItems;
 mod pos;
-mod pos_phf;
 mod processed;
-pub use crate::processed::{Data, Search};
+mod progress;
+pub use crate::processed::{
+    sibling_path, Continuation, Data, EtyLink, QueryTimeout, Search, TreeLimits, DEFAULT_ZSTD_LEVEL,
+};
+pub(crate) use crate::progress::progress_bar;
+pub use crate::progress::write_progress_events;
 mod redirects;
 mod root;
+mod shards;
+mod snapshot;
+mod stats;
+#[cfg(feature = "store")]
+mod store;
 mod string_pool;
+mod subset;
 mod turtle;
+mod validation;
 mod wiktextract_json;
+#[cfg(feature = "store")]
+pub use crate::store::{query_store, ResultsFormat, StoreQuery};
 pub use crate::wiktextract_json::wiktextract_lines;
 
-use crate::string_pool::StringPool;
+use crate::{
+    checkpoint::{ItemsCheckpoint, Stage},
+    embeddings::Embeddings,
+    stats::Provenance,
+    string_pool::StringPool,
+};
 
-use std::{convert::TryFrom, path::Path, time::Instant};
+use std::{path::Path, time::Instant};
 
-use anyhow::{Ok, Result};
-use indicatif::{HumanDuration, ProgressBar, ProgressStyle};
+use anyhow::{bail, Ok, Result};
+use indicatif::HumanDuration;
+use itertools::Itertools;
 use xxhash_rust::xxh3::Xxh3Builder;
 
 pub(crate) type HashMap<K, V> = std::collections::HashMap<K, V, Xxh3Builder>;
 pub(crate) type HashSet<T> = std::collections::HashSet<T, Xxh3Builder>;
 
-pub(crate) fn progress_bar(n: usize, message: &str) -> Result<ProgressBar> {
-    let pb = ProgressBar::new(u64::try_from(n)?);
-    let template = format!("{{spinner:.green}} {message}: [{{elapsed}}] [{{bar:.cyan/blue}}] {{human_pos}}/{{human_len}} ({{per_sec}}, {{eta}})");
-    pb.set_style(
-        ProgressStyle::default_bar()
-            .template(&template)?
-            .progress_chars("#>-"),
+/// Options controlling how the ety graph is generated.
+#[derive(Clone, Copy)]
+pub struct ProcessingOptions {
+    pub imputation_policy: ImputationPolicy,
+    pub granularity: Granularity,
+    pub multi_word_policy: MultiWordPolicy,
+    pub cycle_strategy: CycleStrategy,
+    /// Only link an ety item to a candidate item whose similarity to it is
+    /// greater than this.
+    pub similarity_threshold: f32,
+    /// How much the orthographic similarity of terms counts toward ranking
+    /// candidate items whose terms differ (those found by page term or
+    /// romanization), versus the similarity of embeddings. The confidence of a
+    /// link is the similarity of embeddings alone.
+    pub term_similarity_weight: f32,
+    /// Fail if there are more errors in lines of the wiktextract data (e.g.
+    /// unparseable ety templates or unknown lang codes) than
+    /// `max_line_errors` allows, or any ety graph validation anomalies, rather
+    /// than skipping them. Intended for small curated fixtures, or with
+    /// thresholds, for catching regressions in the full data.
+    pub strict: bool,
+    /// How many errors of each kind in lines of the wiktextract data are
+    /// allowed in strict mode.
+    pub max_line_errors: LineErrorThresholds,
+    /// Keep glosses and ety texts as wiktextract gives them, rather than
+    /// cleaning any residual wiki markup from them.
+    pub raw_glosses: bool,
+    /// Write the strings of the data to a separate file, from which the
+    /// server memory-maps them rather than holding them all in memory.
+    pub mapped_strings: bool,
+    /// Write the data as a shard per lang, plus the edges and strings that
+    /// the shards share, so that the server can load only some langs.
+    pub sharded: bool,
+    /// Only process these lines of the wiktextract data.
+    pub line_range: LineRange,
+    /// Keep the interned strings in a temp file rather than in memory.
+    pub spill_strings: bool,
+    /// The level data serialized to a .zst file is compressed at.
+    pub zstd_level: i32,
+    /// Compress data serialized to a .zst file with a dictionary trained on
+    /// its strings, written next to it.
+    pub zstd_dictionary: bool,
+}
+
+impl Default for ProcessingOptions {
+    fn default() -> Self {
+        Self {
+            imputation_policy: ImputationPolicy::default(),
+            granularity: Granularity::default(),
+            multi_word_policy: MultiWordPolicy::default(),
+            cycle_strategy: CycleStrategy::default(),
+            similarity_threshold: embeddings::DEFAULT_SIMILARITY_THRESHOLD,
+            term_similarity_weight: orthography::DEFAULT_TERM_SIMILARITY_WEIGHT,
+            strict: false,
+            max_line_errors: LineErrorThresholds::default(),
+            raw_glosses: false,
+            mapped_strings: false,
+            sharded: false,
+            line_range: LineRange::default(),
+            spill_strings: false,
+            zstd_level: DEFAULT_ZSTD_LEVEL,
+            zstd_dictionary: false,
+        }
+    }
+}
+
+// Parse the wiktextract data into items, or load them from the checkpoint.
+fn parse(
+    wiktextract_path: &Path,
+    options: ProcessingOptions,
+    checkpoints: Option<&Checkpoints>,
+) -> Result<(StringPool, Items)> {
+    if let Some(checkpoints) = checkpoints
+        && let Some(items) = load_items(checkpoints, Stage::Parsed, options)?
+    {
+        return Ok(items);
+    }
+    let t = Instant::now();
+    println!(
+        "Processing raw wiktextract data from {}...",
+        wiktextract_path.display()
     );
-    Ok(pb)
+    let string_pool = if options.spill_strings {
+        StringPool::spilled()?
+    } else {
+        StringPool::new()
+    };
+    let mut items = Items::new(options)?;
+    items.process_wiktextract_lines(&string_pool, wiktextract_path)?;
+    println!("Finished. Took {}.", HumanDuration(t.elapsed()));
+    println!("String pool: {}.", string_pool.usage());
+    if !items.line_errors.is_empty() {
+        println!(
+            "Skipped lines or templates with errors: {}",
+            items.line_errors.summary()
+        );
+    }
+    if options.strict {
+        items.line_errors.ensure_within(options.max_line_errors)?;
+    }
+    let unknown_pos = pos::unknown_pos_counts();
+    if !unknown_pos.is_empty() {
+        println!(
+            "Parsed {} parts of speech missing from pos.json: {}",
+            unknown_pos.len(),
+            unknown_pos
+                .iter()
+                .map(|(name, count)| format!("{name} ({count})"))
+                .join(", ")
+        );
+    }
+    if let Some(checkpoints) = checkpoints {
+        save_items(checkpoints, Stage::Parsed, &string_pool, &items)?;
+    }
+    Ok((string_pool, items))
+}
+
+// Generate the embeddings of the items that need them, or load which ones
+// they are from the checkpoint.
+fn embed(
+    string_pool: &StringPool,
+    items: &Items,
+    wiktextract_path: &Path,
+    embeddings_config: &embeddings::Config,
+    checkpoints: Option<&Checkpoints>,
+) -> Result<Embeddings> {
+    if let Some(checkpoints) = checkpoints
+        && let Some(text_hashes) = checkpoints.load(Stage::Embedded)?
+    {
+        return Embeddings::from_text_hashes(embeddings_config, text_hashes);
+    }
+    let embeddings = items.generate_embeddings(string_pool, wiktextract_path, embeddings_config)?;
+    if let Some(checkpoints) = checkpoints {
+        checkpoints.save(Stage::Embedded, &embeddings.text_hashes())?;
+    }
+    Ok(embeddings)
+}
+
+fn load_items(
+    checkpoints: &Checkpoints,
+    stage: Stage,
+    options: ProcessingOptions,
+) -> Result<Option<(StringPool, Items)>> {
+    checkpoints
+        .load::<ItemsCheckpoint<StringPool, Items>>(stage)?
+        .map(|checkpoint| {
+            // The options that parsing depends on were checked to be the same
+            // when the checkpoints were opened, so only those that later
+            // stages depend on can differ.
+            let mut items = checkpoint.items;
+            items.options = options;
+            let string_pool = if options.spill_strings {
+                checkpoint.string_pool.spill()?
+            } else {
+                checkpoint.string_pool
+            };
+            Ok((string_pool, items))
+        })
+        .transpose()
+}
+
+fn save_items(
+    checkpoints: &Checkpoints,
+    stage: Stage,
+    string_pool: &StringPool,
+    items: &Items,
+) -> Result<()> {
+    checkpoints.save(stage, &ItemsCheckpoint { string_pool, items })
+}
+
+/// Generate embeddings for the items in the wiktextract data that need them,
+/// without going on to generate the ety graph. As embeddings are cached, this
+/// fills the cache for later runs.
+///
+/// # Errors
+///
+/// Will return `Err` if any unexpected issue arises parsing the wiktextract
+/// data or generating embeddings.
+pub fn embed_wiktextract(
+    wiktextract_path: &Path,
+    embeddings_config: &embeddings::Config,
+    options: ProcessingOptions,
+) -> Result<()> {
+    let (string_pool, items) = parse(wiktextract_path, options, None)?;
+    embed(
+        &string_pool,
+        &items,
+        wiktextract_path,
+        embeddings_config,
+        None,
+    )?;
+    Ok(())
 }
 
+/// Write the embeddings of the items in the wiktextract data, along with an
+/// index of the items they are of by stable id, to files named after
+/// `output_path` (see [`EmbeddingsFormat`]). Embeddings that aren't in the
+/// cache yet are generated. With `checkpoints`, the parsed items and which
+/// embeddings they have are loaded from them if they were checkpointed.
+///
 /// # Errors
 ///
 /// Will return `Err` if any unexpected issue arises parsing the wiktextract
-/// data or writing to Turtle file.
-pub fn process_wiktextract(
+/// data, generating embeddings or writing the files.
+pub fn export_embeddings(
     wiktextract_path: &Path,
-    serialization_path: &Path,
-    turtle_path: Option<&Path>,
     embeddings_config: &embeddings::Config,
+    options: ProcessingOptions,
+    checkpoints: Option<&Checkpoints>,
+    output_path: &Path,
+    format: EmbeddingsFormat,
 ) -> Result<()> {
-    let mut t = Instant::now();
-    println!(
-        "Processing raw wiktextract data from {}...",
-        wiktextract_path.display()
-    );
-    let mut string_pool = StringPool::new();
-    let mut items = Items::new()?;
-    items.process_wiktextract_lines(&mut string_pool, wiktextract_path)?;
-    println!("Finished. Took {}.", HumanDuration(t.elapsed()));
-    let embeddings =
-        items.generate_embeddings(&string_pool, wiktextract_path, embeddings_config)?;
-    t = Instant::now();
+    let (string_pool, items) = parse(wiktextract_path, options, checkpoints)?;
+    let embeddings = embed(
+        &string_pool,
+        &items,
+        wiktextract_path,
+        embeddings_config,
+        checkpoints,
+    )?;
+    embeddings_export::write_embeddings(&string_pool, &items, &embeddings, output_path, format)
+}
+
+fn graph(
+    wiktextract_path: &Path,
+    embeddings_config: &embeddings::Config,
+    options: ProcessingOptions,
+    checkpoints: Option<&Checkpoints>,
+) -> Result<(StringPool, Items)> {
+    if let Some(checkpoints) = checkpoints
+        && let Some(graphed) = load_items(checkpoints, Stage::Graph, options)?
+    {
+        return Ok(graphed);
+    }
+    let (string_pool, mut items) = parse(wiktextract_path, options, checkpoints)?;
+    let embeddings = embed(
+        &string_pool,
+        &items,
+        wiktextract_path,
+        embeddings_config,
+        checkpoints,
+    )?;
+    let t = Instant::now();
     println!("Generating ety graph...");
-    items.generate_ety_graph(&embeddings)?;
+    items.generate_ety_graph(&string_pool, &embeddings)?;
     println!("Finished. Took {}.", HumanDuration(t.elapsed()));
-    let data = Data::new(string_pool, items.graph);
+    println!("String pool: {}.", string_pool.usage());
+    if let Some(checkpoints) = checkpoints {
+        save_items(checkpoints, Stage::Graph, &string_pool, &items)?;
+    }
+    Ok((string_pool, items))
+}
+
+/// Fine-tune the embeddings model on the ety links of the graph generated from
+/// the wiktextract data whose parents needed no disambiguation, saving it as
+/// the revision `fine_tuning.revision` of the model in the local Hugging Face
+/// cache. With `checkpoints`, a checkpointed graph is used if there is one.
+///
+/// # Errors
+///
+/// Will return `Err` if any unexpected issue arises generating the graph,
+/// loading or training the model or saving it, or if the model is served from
+/// an embeddings endpoint.
+pub fn fine_tune_model(
+    wiktextract_path: &Path,
+    embeddings_config: &embeddings::Config,
+    options: ProcessingOptions,
+    checkpoints: Option<&Checkpoints>,
+    fine_tuning: &fine_tune::FineTuning,
+) -> Result<()> {
+    let (string_pool, items) = graph(wiktextract_path, embeddings_config, options, checkpoints)?;
+    fine_tune::fine_tune(&string_pool, &items, embeddings_config, fine_tuning)
+}
+
+/// Evaluate disambiguation with the embeddings model of `embeddings_config`
+/// and the similarity threshold and term similarity weight of `options` on
+/// the gold cases at `gold_path`, printing how many were disambiguated
+/// correctly and optionally writing a report of each case to `report_path`.
+///
+/// # Errors
+///
+/// Will return `Err` if the gold cases are malformed, or if any unexpected
+/// issue arises generating embeddings or writing the report.
+pub fn evaluate_disambiguation(
+    gold_path: &Path,
+    embeddings_config: &embeddings::Config,
+    options: ProcessingOptions,
+    report_path: Option<&Path>,
+) -> Result<()> {
+    evaluation::evaluate(gold_path, embeddings_config, options, report_path)
+}
+
+/// Generate the ety graph from the wiktextract data. With `checkpoints`, the
+/// state after each stage is checkpointed, and a resumed run starts after the
+/// last checkpointed stage.
+///
+/// # Errors
+///
+/// Will return `Err` if any unexpected issue arises parsing the wiktextract
+/// data, writing the validation or cycle report or reading or writing
+/// checkpoints, or if anything unexpected is encountered in strict mode.
+pub fn generate_data(
+    wiktextract_path: &Path,
+    validation_report_path: Option<&Path>,
+    cycle_report_path: Option<&Path>,
+    embeddings_config: &embeddings::Config,
+    options: ProcessingOptions,
+    checkpoints: Option<&Checkpoints>,
+) -> Result<Data> {
+    let (string_pool, items) = graph(wiktextract_path, embeddings_config, options, checkpoints)?;
+    if let Some(validation_report_path) = validation_report_path {
+        items.write_validation_report(&string_pool, validation_report_path)?;
+    }
+    if let Some(cycle_report_path) = cycle_report_path {
+        items.write_cycle_report(&string_pool, cycle_report_path)?;
+    }
+    if options.strict {
+        items.ensure_no_anomalies(&string_pool)?;
+    }
+    let aliases = items.aliases();
+    let dump_date = stats::dump_date(wiktextract_path);
+    let provenance = Provenance::new(wiktextract_path, embeddings_config, options);
+    Ok(Data::new(
+        string_pool,
+        items.graph,
+        aliases,
+        dump_date,
+        Some(provenance),
+    ))
+}
+
+/// Write the data to a Turtle file and/or load it into a SPARQL store.
+///
+/// # Errors
+///
+/// Will return `Err` if any unexpected issue arises writing to the Turtle file
+/// or SPARQL store, or if `store_path` is given without the `store` feature.
+pub fn export_data(
+    data: &Data,
+    turtle_path: Option<&Path>,
+    store_path: Option<&Path>,
+) -> Result<()> {
+    if store_path.is_some() && !cfg!(feature = "store") {
+        bail!("loading a SPARQL store requires building with --features store");
+    }
     if let Some(turtle_path) = turtle_path {
         data.write_turtle(turtle_path)?;
     }
-    data.serialize(serialization_path)?;
+    #[cfg(feature = "store")]
+    if let Some(store_path) = store_path {
+        data.load_store(store_path)?;
+    }
     Ok(())
 }
+
+/// Serialize the data for the server, as shards or with mapped strings if
+/// `options` say so.
+///
+/// # Errors
+///
+/// Will return `Err` if any unexpected issue arises in the serialization.
+pub fn serialize_data(
+    data: &mut Data,
+    serialization_path: &Path,
+    options: ProcessingOptions,
+) -> Result<()> {
+    if options.sharded {
+        data.serialize_shards(&sibling_path(serialization_path, "shards"))
+    } else {
+        data.serialize(serialization_path, options)
+    }
+}
+
+/// Run the whole pipeline: generate the ety graph from the wiktextract data,
+/// export it, and serialize it. See [`generate_data`] for `checkpoints`.
+///
+/// # Errors
+///
+/// Will return `Err` if any unexpected issue arises parsing the wiktextract
+/// data or writing to Turtle file, SPARQL store or validation or cycle report, or if
+/// anything unexpected is encountered in strict mode, or if `store_path` is
+/// given without the `store` feature.
+#[allow(clippy::too_many_arguments)]
+pub fn process_wiktextract(
+    wiktextract_path: &Path,
+    serialization_path: &Path,
+    turtle_path: Option<&Path>,
+    store_path: Option<&Path>,
+    validation_report_path: Option<&Path>,
+    cycle_report_path: Option<&Path>,
+    embeddings_config: &embeddings::Config,
+    options: ProcessingOptions,
+    checkpoints: Option<&Checkpoints>,
+) -> Result<()> {
+    // Fail before all the processing, rather than after it in export_data.
+    if store_path.is_some() && !cfg!(feature = "store") {
+        bail!("loading a SPARQL store requires building with --features store");
+    }
+    let mut data = generate_data(
+        wiktextract_path,
+        validation_report_path,
+        cycle_report_path,
+        embeddings_config,
+        options,
+        checkpoints,
+    )?;
+    export_data(&data, turtle_path, store_path)?;
+    serialize_data(&mut data, serialization_path, options)
+}
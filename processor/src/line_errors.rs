@@ -0,0 +1,259 @@
+//! Errors in individual lines of the wiktextract data, e.g. a line that isn't
+//! valid JSON or an item in an unknown lang. These are collected as the data is
+//! parsed, with the line and page they were in, rather than aborting the run
+//! on the first one, so that a single bad line doesn't waste a run over the
+//! whole dump. In strict mode, the run fails once the data is parsed if there
+//! are more errors of any kind than allowed for it.
+
+use crate::{checkpoint::pairs, HashMap};
+
+use anyhow::{bail, ensure, Ok, Result};
+use clap::ValueEnum;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+// At most this many errors of each kind are kept for the report, while all of
+// them are counted, so that a systematic error doesn't fill memory.
+const MAX_RECORDED_PER_KIND: usize = 10_000;
+
+// How many errors are printed when the run fails on them.
+const MAX_PRINTED: usize = 20;
+
+/// Kinds of errors in lines of the wiktextract data.
+#[derive(Serialize, Deserialize, Hash, Eq, PartialEq, Debug, Copy, Clone, ValueEnum)]
+#[serde(rename_all = "camelCase")]
+pub enum LineErrorKind {
+    /// The line is not valid JSON
+    MalformedJson,
+    /// The item's lang code is not a known lang
+    UnknownLang,
+    /// A lang code given in an ety, root or descendants template is not a
+    /// known lang
+    UnknownTemplateLang,
+    /// An ety template of a kind that is handled could not be parsed
+    UnparsedEtyTemplate,
+    /// An ety template was skipped, being of a kind that isn't handled
+    SkippedEtyTemplate,
+    /// A root template could not be parsed
+    UnparsedRootTemplate,
+    /// A descendants template of a kind that is handled could not be parsed
+    UnparsedDescTemplate,
+}
+
+impl LineErrorKind {
+    fn name(self) -> &'static str {
+        self.to_possible_value()
+            .expect("no skipped variants")
+            .get_name()
+    }
+}
+
+/// How many errors of each kind are allowed in strict mode.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Serialize, Deserialize)]
+// Kinds added since stats were first written default to none allowed.
+#[serde(rename_all = "camelCase", default)]
+pub struct LineErrorThresholds {
+    pub malformed_json: usize,
+    pub unknown_lang: usize,
+    pub unknown_template_lang: usize,
+    pub unparsed_ety_template: usize,
+    pub skipped_ety_template: usize,
+    pub unparsed_root_template: usize,
+    pub unparsed_desc_template: usize,
+}
+
+impl LineErrorThresholds {
+    /// Parse the number of errors of a kind allowed, given as e.g.
+    /// "unknown-lang=100".
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is no "=", the kind is unknown, or the
+    /// number is not a number.
+    pub fn parse_max(max: &str) -> Result<(LineErrorKind, usize)> {
+        let Some((kind, count)) = max.split_once('=') else {
+            bail!("expected a maximum number of errors like \"unknown-lang=100\", got \"{max}\"");
+        };
+        let kind = LineErrorKind::from_str(kind.trim(), false).map_err(anyhow::Error::msg)?;
+        Ok((kind, count.trim().parse()?))
+    }
+
+    #[must_use]
+    pub fn with(mut self, kind: LineErrorKind, max: usize) -> Self {
+        *match kind {
+            LineErrorKind::MalformedJson => &mut self.malformed_json,
+            LineErrorKind::UnknownLang => &mut self.unknown_lang,
+            LineErrorKind::UnknownTemplateLang => &mut self.unknown_template_lang,
+            LineErrorKind::UnparsedEtyTemplate => &mut self.unparsed_ety_template,
+            LineErrorKind::SkippedEtyTemplate => &mut self.skipped_ety_template,
+            LineErrorKind::UnparsedRootTemplate => &mut self.unparsed_root_template,
+            LineErrorKind::UnparsedDescTemplate => &mut self.unparsed_desc_template,
+        } = max;
+        self
+    }
+
+    fn max(self, kind: LineErrorKind) -> usize {
+        match kind {
+            LineErrorKind::MalformedJson => self.malformed_json,
+            LineErrorKind::UnknownLang => self.unknown_lang,
+            LineErrorKind::UnknownTemplateLang => self.unknown_template_lang,
+            LineErrorKind::UnparsedEtyTemplate => self.unparsed_ety_template,
+            LineErrorKind::SkippedEtyTemplate => self.skipped_ety_template,
+            LineErrorKind::UnparsedRootTemplate => self.unparsed_root_template,
+            LineErrorKind::UnparsedDescTemplate => self.unparsed_desc_template,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct LineError {
+    kind: LineErrorKind,
+    line: usize,
+    // the word of the page the line is from, if the line could be parsed
+    page: Option<String>,
+    error: String,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub(crate) struct LineErrors {
+    recorded: Vec<LineError>,
+    #[serde(with = "pairs")]
+    counts: HashMap<LineErrorKind, usize>,
+}
+
+impl LineErrors {
+    pub(crate) fn record(
+        &mut self,
+        kind: LineErrorKind,
+        line: usize,
+        page: Option<&str>,
+        error: &anyhow::Error,
+    ) {
+        let count = self.counts.entry(kind).or_default();
+        *count += 1;
+        if *count <= MAX_RECORDED_PER_KIND {
+            self.recorded.push(LineError {
+                kind,
+                line,
+                page: page.map(str::to_string),
+                error: error.to_string(),
+            });
+        }
+    }
+
+    // Record the error of a check, if it failed.
+    pub(crate) fn check(
+        &mut self,
+        kind: LineErrorKind,
+        line: usize,
+        page: Option<&str>,
+        checked: Result<()>,
+    ) {
+        if let Err(e) = checked {
+            self.record(kind, line, page, &e);
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    // The counts in a stable order, for printing and reports.
+    fn sorted_counts(&self) -> Vec<(LineErrorKind, usize)> {
+        self.counts
+            .iter()
+            .map(|(&kind, &count)| (kind, count))
+            .sorted_by_key(|&(kind, _)| kind as usize)
+            .collect()
+    }
+
+    pub(crate) fn summary(&self) -> String {
+        self.sorted_counts()
+            .into_iter()
+            .map(|(kind, count)| format!("{} ({count})", kind.name()))
+            .join(", ")
+    }
+
+    pub(crate) fn json(&self) -> Value {
+        json!({
+            "counts": self
+                .sorted_counts()
+                .into_iter()
+                .map(|(kind, count)| json!({"kind": kind, "count": count}))
+                .collect_vec(),
+            "errors": self.recorded,
+        })
+    }
+
+    /// Fail if there are more errors of any kind than `thresholds` allow.
+    pub(crate) fn ensure_within(&self, thresholds: LineErrorThresholds) -> Result<()> {
+        let exceeded = self
+            .sorted_counts()
+            .into_iter()
+            .filter(|&(kind, count)| count > thresholds.max(kind))
+            .map(|(kind, count)| format!("{} {count} > {}", kind.name(), thresholds.max(kind)))
+            .collect_vec();
+        let printed = self
+            .recorded
+            .iter()
+            .take(MAX_PRINTED)
+            .map(|e| {
+                format!(
+                    "line {} ({}): {}",
+                    e.line,
+                    e.page.as_deref().unwrap_or("?"),
+                    e.error
+                )
+            })
+            .join("\n");
+        ensure!(
+            exceeded.is_empty(),
+            "too many errors in the wiktextract data ({}), e.g.:\n{printed}",
+            exceeded.join(", ")
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use anyhow::anyhow;
+
+    #[test]
+    fn line_errors_within_thresholds() {
+        let mut errors = LineErrors::default();
+        assert!(errors.is_empty());
+        assert!(errors.ensure_within(LineErrorThresholds::default()).is_ok());
+        errors.record(
+            LineErrorKind::UnknownLang,
+            3,
+            Some("water"),
+            &anyhow!("unknown lang code"),
+        );
+        errors.record(
+            LineErrorKind::UnknownLang,
+            5,
+            Some("wine"),
+            &anyhow!("unknown lang code"),
+        );
+        errors.record(LineErrorKind::MalformedJson, 8, None, &anyhow!("syntax"));
+        assert_eq!(errors.summary(), "malformed-json (1), unknown-lang (2)");
+        assert!(errors
+            .ensure_within(LineErrorThresholds::default())
+            .is_err());
+        let (kind, max) = LineErrorThresholds::parse_max("unknown-lang=2").unwrap();
+        let thresholds = LineErrorThresholds::default()
+            .with(kind, max)
+            .with(LineErrorKind::MalformedJson, 1);
+        assert!(errors.ensure_within(thresholds).is_ok());
+        assert!(errors
+            .ensure_within(thresholds.with(LineErrorKind::UnknownLang, 1))
+            .is_err());
+        assert!(LineErrorThresholds::parse_max("unknown-lang").is_err());
+        assert!(LineErrorThresholds::parse_max("unknown=1").is_err());
+        assert_eq!(errors.json()["errors"][0]["page"], "water");
+    }
+}
@@ -1,17 +1,43 @@
 #[global_allocator]
 static ALLOC: snmalloc_rs::SnMalloc = snmalloc_rs::SnMalloc;
 
-use processor::{embeddings, process_wiktextract};
+use processor::{
+    embed_wiktextract, embeddings, evaluate_disambiguation, export_data, export_embeddings,
+    fine_tune, fine_tune_model, generate_data, merge_langs, process_wiktextract,
+    report_template_coverage, serialize_data, sibling_path, write_progress_events, Checkpoints,
+    CycleStrategy, Data, Diff, EmbeddingsFormat, Granularity, ImputationPolicy, Lang,
+    LineErrorKind, LineErrorThresholds, LineRange, MultiWordPolicy, ProcessingOptions,
+    DEFAULT_ZSTD_LEVEL,
+};
+#[cfg(feature = "store")]
+use processor::{query_store, ResultsFormat, StoreQuery};
 
-use std::{env, path::PathBuf, time::Instant};
+#[cfg(feature = "store")]
+use std::io;
+use std::{env, ffi::OsString, fs, path::PathBuf, time::Instant};
 
-use anyhow::Result;
-use clap::Parser;
+use anyhow::{bail, Context, Result};
+use clap::{CommandFactory, Parser, Subcommand};
 use indicatif::HumanDuration;
 
 #[derive(Parser)]
 #[clap(author, version, about, long_about = None)]
 pub struct Args {
+    /// Read options from this TOML config file, rather than wety.toml (if it
+    /// exists). Options given on the command line override those in it
+    #[clap(long, global = true, value_parser)]
+    config: Option<PathBuf>,
+    /// Also write progress events to this file (e.g. /dev/fd/3), as JSON lines
+    #[clap(long, global = true, value_parser)]
+    progress_events: Option<PathBuf>,
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(clap::Args)]
+struct WiktextractArgs {
+    /// The wiktextract data, optionally compressed. Can also be - for stdin, or
+    /// an http(s) URL to stream it from
     #[clap(
         short = 'w',
         long,
@@ -19,16 +45,49 @@ pub struct Args {
         value_parser
     )]
     wiktextract_path: PathBuf,
-    #[clap(short = 's', long, default_value = "data/wety.json.gz", value_parser)]
-    serialization_path: PathBuf,
-    #[clap(short = 't', long, value_parser)]
-    turtle_path: Option<PathBuf>,
+    /// Skip this many lines of the wiktextract data, e.g. to iterate quickly
+    /// on how a part of it is processed
+    #[clap(long, default_value_t = 0, value_parser)]
+    skip_lines: usize,
+    /// Only process this many lines of the wiktextract data (after any
+    /// skipped)
+    #[clap(long, value_parser)]
+    limit_lines: Option<usize>,
+    /// Keep the interned strings in a temp file (under TMPDIR) rather than in
+    /// memory, for processing the full data on machines with less memory
+    #[clap(long, action)]
+    spill_strings: bool,
+}
+
+impl WiktextractArgs {
+    fn line_range(&self) -> LineRange {
+        LineRange {
+            skip: self.skip_lines,
+            limit: self.limit_lines,
+        }
+    }
+}
+
+#[derive(clap::Args)]
+struct EmbeddingsArgs {
     #[clap(short = 'm', long, default_value = embeddings::DEFAULT_MODEL, value_parser)]
     embeddings_model: String,
     #[clap(short = 'r', long, default_value = embeddings::DEFAULT_MODEL_REVISION, value_parser)]
     embeddings_model_revision: String,
     #[clap(short = 'b', long, default_value_t = embeddings::DEFAULT_BATCH_SIZE, value_parser)]
     embeddings_batch_size: usize,
+    /// Get embeddings from the OpenAI-compatible endpoint at this base url
+    /// (e.g. http://localhost:8080/v1) rather than running the model locally.
+    /// The model is then given by the name the endpoint knows it by. An API
+    /// key, if needed, is read from WETY_EMBEDDINGS_API_KEY
+    #[clap(long, value_parser)]
+    embeddings_url: Option<String>,
+    #[clap(flatten)]
+    cache: CacheArgs,
+}
+
+#[derive(clap::Args)]
+struct CacheArgs {
     #[clap(
         short = 'c',
         long,
@@ -38,22 +97,716 @@ pub struct Args {
     embeddings_cache_path: PathBuf,
 }
 
+#[derive(clap::Args)]
+struct SimilarityArgs {
+    /// Only link an ety item to a candidate item more similar than this
+    #[clap(long, default_value_t = ProcessingOptions::default().similarity_threshold, value_parser)]
+    similarity_threshold: f32,
+    /// How much the similarity of terms counts toward ranking candidate items
+    /// whose terms differ, versus the similarity of their embeddings
+    #[clap(long, default_value_t = ProcessingOptions::default().term_similarity_weight, value_parser)]
+    term_similarity_weight: f32,
+}
+
+#[derive(clap::Args)]
+struct GraphArgs {
+    #[clap(flatten)]
+    wiktextract: WiktextractArgs,
+    #[clap(flatten)]
+    embeddings: EmbeddingsArgs,
+    #[clap(short = 'v', long, value_parser)]
+    validation_report_path: Option<PathBuf>,
+    /// Write a report of the ety links removed to break cycles to this path
+    #[clap(long, value_parser)]
+    cycle_report_path: Option<PathBuf>,
+    #[clap(short = 'i', long, default_value = "free", value_enum)]
+    imputation_policy: ImputationPolicy,
+    /// Whether each ety, or each pos under an ety, gets its own item
+    #[clap(long, default_value = "ety", value_enum)]
+    granularity: Granularity,
+    /// Which terms of more than one word get items
+    #[clap(long, default_value = "non-phrases", value_enum)]
+    multi_word_policy: MultiWordPolicy,
+    /// How cycles of ety links are broken
+    #[clap(long, default_value = "feedback-arc-set", value_enum)]
+    cycle_strategy: CycleStrategy,
+    /// Parse a lang as another, merging their items, given as e.g. "nb=no".
+    /// Can be given more than once
+    #[clap(long, value_parser = Lang::parse_merge)]
+    merge_lang: Vec<(Lang, Lang)>,
+    #[clap(flatten)]
+    similarity: SimilarityArgs,
+    /// Fail if there are more errors in lines of the wiktextract data than
+    /// --max-line-errors allows, or any ety graph validation anomalies
+    #[clap(long, action)]
+    strict: bool,
+    /// How many errors of a kind are allowed in strict mode, given as e.g.
+    /// "unknown-lang=100", the kinds being malformed-json, unknown-lang and
+    /// unparsed-ety-template. None are allowed by default. Can be given more
+    /// than once
+    #[clap(long, value_parser = LineErrorThresholds::parse_max, requires = "strict")]
+    max_line_errors: Vec<(LineErrorKind, usize)>,
+    /// Keep glosses and ety texts as given, without cleaning residual wiki
+    /// markup from them
+    #[clap(long, action)]
+    raw_glosses: bool,
+    /// Checkpoint the state after each stage of processing to this directory
+    #[clap(long, value_parser)]
+    checkpoint_dir: Option<PathBuf>,
+    /// Resume from the last stage checkpointed in the checkpoint directory
+    #[clap(long, action, requires = "checkpoint-dir")]
+    resume: bool,
+    /// Only parse the wiktextract data and report how many templates were
+    /// parsed or skipped, without generating embeddings or the ety graph
+    #[clap(long, action)]
+    dry_run: bool,
+    /// Where the dry run writes its template coverage report
+    #[clap(long, default_value = "data/template_coverage.json", value_parser)]
+    coverage_report_path: PathBuf,
+    /// Where the dry run writes examples of the args of skipped templates
+    #[clap(long, default_value = "data/unhandled_templates.json", value_parser)]
+    unhandled_templates_path: PathBuf,
+}
+
+#[derive(clap::Args)]
+struct FineTuneArgs {
+    /// The revision to save the fine-tuned model as, which is then used with
+    /// --embeddings-model-revision
+    #[clap(long, value_parser)]
+    fine_tuned_revision: String,
+    #[clap(long, default_value_t = fine_tune::DEFAULT_EPOCHS, value_parser)]
+    epochs: usize,
+    #[clap(long, default_value_t = fine_tune::DEFAULT_LEARNING_RATE, value_parser)]
+    learning_rate: f64,
+    /// How many pairs to train on at once, each pair's negatives being the
+    /// other pairs in its batch
+    #[clap(long, default_value_t = fine_tune::DEFAULT_BATCH_SIZE, value_parser)]
+    training_batch_size: usize,
+    /// Train on at most this many pairs each of ety texts and glosses
+    #[clap(long, value_parser)]
+    max_pairs: Option<usize>,
+}
+
+impl FineTuneArgs {
+    fn fine_tuning(self) -> fine_tune::FineTuning {
+        fine_tune::FineTuning {
+            revision: self.fine_tuned_revision,
+            epochs: self.epochs,
+            learning_rate: self.learning_rate,
+            batch_size: self.training_batch_size,
+            max_pairs: self.max_pairs,
+        }
+    }
+}
+
+#[derive(clap::Args)]
+struct SerializationArgs {
+    #[clap(short = 's', long, default_value = "data/wety.json.gz", value_parser)]
+    serialization_path: PathBuf,
+}
+
+#[derive(clap::Args)]
+struct ServeArgs {
+    #[clap(long, action)]
+    mapped_strings: bool,
+    #[clap(long, action)]
+    sharded: bool,
+    /// The level to compress the data at if it is serialized to a .zst file,
+    /// from 1 to 22
+    #[clap(long, default_value_t = DEFAULT_ZSTD_LEVEL, value_parser)]
+    zstd_level: i32,
+    /// Compress data serialized to a .zst file with a dictionary trained on
+    /// its strings, which is written next to it (e.g. data/wety.zdict) and
+    /// needed to read it
+    #[clap(long, action)]
+    zstd_dictionary: bool,
+}
+
+#[derive(clap::Args)]
+struct ExportArgs {
+    #[clap(short = 't', long, value_parser)]
+    turtle_path: Option<PathBuf>,
+    #[clap(long, value_parser)]
+    store_path: Option<PathBuf>,
+}
+
+impl EmbeddingsArgs {
+    fn config(&self) -> embeddings::Config {
+        embeddings::Config {
+            model_name: self.embeddings_model.clone(),
+            model_revision: self.embeddings_model_revision.clone(),
+            batch_size: self.embeddings_batch_size,
+            cache_path: self.cache.embeddings_cache_path.clone(),
+            url: self.embeddings_url.clone(),
+        }
+    }
+}
+
+impl GraphArgs {
+    // If a dry run, report the template coverage and return true. Any lang
+    // merges are set first, since they apply to the parsing.
+    fn dry_run(&self) -> Result<bool> {
+        merge_langs(&self.merge_lang)?;
+        if self.dry_run {
+            report_template_coverage(
+                &self.wiktextract.wiktextract_path,
+                self.wiktextract.line_range(),
+                &self.coverage_report_path,
+                &self.unhandled_templates_path,
+            )?;
+        }
+        Ok(self.dry_run)
+    }
+
+    fn checkpoints(&self) -> Result<Option<Checkpoints>> {
+        self.checkpoint_dir
+            .as_ref()
+            .map(|dir| {
+                Checkpoints::new(
+                    dir,
+                    self.resume,
+                    &self.wiktextract.wiktextract_path,
+                    &self.embeddings.config(),
+                    self.options(),
+                )
+            })
+            .transpose()
+    }
+
+    fn options(&self) -> ProcessingOptions {
+        ProcessingOptions {
+            imputation_policy: self.imputation_policy,
+            granularity: self.granularity,
+            multi_word_policy: self.multi_word_policy,
+            cycle_strategy: self.cycle_strategy,
+            similarity_threshold: self.similarity.similarity_threshold,
+            term_similarity_weight: self.similarity.term_similarity_weight,
+            strict: self.strict,
+            max_line_errors: self.max_line_errors.iter().fold(
+                LineErrorThresholds::default(),
+                |thresholds, &(kind, max)| thresholds.with(kind, max),
+            ),
+            raw_glosses: self.raw_glosses,
+            line_range: self.wiktextract.line_range(),
+            spill_strings: self.wiktextract.spill_strings,
+            ..ProcessingOptions::default()
+        }
+    }
+}
+
+impl ServeArgs {
+    fn options(&self, options: ProcessingOptions) -> ProcessingOptions {
+        ProcessingOptions {
+            mapped_strings: self.mapped_strings,
+            sharded: self.sharded,
+            zstd_level: self.zstd_level,
+            zstd_dictionary: self.zstd_dictionary,
+            ..options
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run the whole pipeline: generate the ety graph from the wiktextract
+    /// data, export it, and serialize it for the server
+    Process {
+        #[clap(flatten)]
+        graph: GraphArgs,
+        #[clap(flatten)]
+        serialization: SerializationArgs,
+        #[clap(flatten)]
+        serve: ServeArgs,
+        #[clap(flatten)]
+        export: ExportArgs,
+    },
+    /// Only generate embeddings for the wiktextract data, filling the
+    /// embeddings cache for later runs
+    Embed {
+        #[clap(flatten)]
+        wiktextract: WiktextractArgs,
+        #[clap(flatten)]
+        embeddings: EmbeddingsArgs,
+    },
+    /// Write the embeddings of the items, with a TSV index of their items by
+    /// stable id, for use with other tools. Embeddings are read from the
+    /// cache, or generated if they aren't in it yet
+    ExportEmbeddings {
+        #[clap(flatten)]
+        graph: GraphArgs,
+        /// Where to write the embeddings, e.g. data/embeddings for
+        /// data/embeddings.safetensors and data/embeddings.tsv
+        #[clap(short = 'o', long, value_parser)]
+        output_path: PathBuf,
+        #[clap(short = 'f', long, default_value = "safetensors", value_enum)]
+        format: EmbeddingsFormat,
+    },
+    /// Report how accurately candidate items are disambiguated with the given
+    /// model, similarity threshold and term similarity weight, on a gold set
+    /// of cases whose right parent is known
+    Eval {
+        #[clap(
+            short = 'g',
+            long,
+            default_value = "processor/data/disambiguation_gold.jsonl",
+            value_parser
+        )]
+        gold_path: PathBuf,
+        /// Also write the similarities and outcome of each case to this path,
+        /// as JSON
+        #[clap(short = 'o', long, value_parser)]
+        report_path: Option<PathBuf>,
+        #[clap(flatten)]
+        embeddings: EmbeddingsArgs,
+        #[clap(flatten)]
+        similarity: SimilarityArgs,
+    },
+    /// Fine-tune the embeddings model on the ety links whose parent needed no
+    /// disambiguation, saving it as a new revision of the model in the local
+    /// Hugging Face cache
+    FineTune {
+        #[clap(flatten)]
+        graph: GraphArgs,
+        #[clap(flatten)]
+        fine_tune: FineTuneArgs,
+    },
+    /// Generate the ety graph from the wiktextract data and serialize it,
+    /// without exporting it
+    Graph {
+        #[clap(flatten)]
+        graph: GraphArgs,
+        #[clap(flatten)]
+        serialization: SerializationArgs,
+    },
+    /// Export serialized data to a Turtle file and/or SPARQL store
+    Export {
+        #[clap(flatten)]
+        serialization: SerializationArgs,
+        #[clap(flatten)]
+        export: ExportArgs,
+    },
+    /// Reserialize serialized data in the forms the server can load, e.g. as
+    /// shards
+    ServePrep {
+        #[clap(flatten)]
+        serialization: SerializationArgs,
+        /// Where to write the reserialized data
+        #[clap(short = 'o', long, value_parser)]
+        output_path: PathBuf,
+        #[clap(flatten)]
+        serve: ServeArgs,
+    },
+    /// Write the part of serialized data that some langs need, i.e. their
+    /// items and the items these descend from, e.g. for serving only them
+    Subset {
+        #[clap(flatten)]
+        serialization: SerializationArgs,
+        /// The codes of the langs to keep, separated by commas
+        #[clap(
+            short = 'l',
+            long,
+            required = true,
+            value_delimiter = ',',
+            value_parser
+        )]
+        langs: Vec<Lang>,
+        /// Where to write the pruned data
+        #[clap(short = 'o', long, value_parser)]
+        output_path: PathBuf,
+        #[clap(flatten)]
+        serve: ServeArgs,
+    },
+    /// Compare two serialized datasets, e.g. from consecutive dumps, printing
+    /// a summary of the items and ety links added, removed or changed
+    Diff {
+        old_path: PathBuf,
+        new_path: PathBuf,
+        /// Also write the differences, with examples of each, to this json file
+        #[clap(short = 'o', long, value_parser)]
+        output_path: Option<PathBuf>,
+    },
+    /// Print the stats of serialized data
+    Stats {
+        #[clap(flatten)]
+        serialization: SerializationArgs,
+    },
+    /// Inspect or maintain the embeddings cache
+    Cache {
+        #[clap(flatten)]
+        cache: CacheArgs,
+        #[clap(subcommand)]
+        command: CacheCommand,
+    },
+    /// Run a built-in or custom SPARQL query against the store
+    #[cfg(feature = "store")]
+    QueryStore {
+        #[clap(long, default_value = "data/wety-store", value_parser)]
+        store_path: PathBuf,
+        #[clap(short = 'f', long, default_value = "json", value_enum)]
+        format: ResultsFormat,
+        #[clap(subcommand)]
+        query: QueryCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum CacheCommand {
+    /// Check the embeddings cache for corrupt entries
+    Verify,
+}
+
+#[cfg(feature = "store")]
+#[derive(Subcommand)]
+enum QueryCommand {
+    /// All the items that the term comes from, directly or not
+    Ancestors {
+        #[clap(short = 'l', long, value_parser)]
+        lang: Lang,
+        #[clap(short = 't', long, value_parser)]
+        term: String,
+    },
+    /// All the items that come from the term, e.g. a PIE root
+    Descendants {
+        #[clap(short = 'l', long, default_value = "ine-pro", value_parser)]
+        lang: Lang,
+        #[clap(short = 't', long, value_parser)]
+        term: String,
+    },
+    /// The items in one lang borrowed from items in another
+    Loanwords {
+        #[clap(long, value_parser)]
+        from: Lang,
+        #[clap(long, value_parser)]
+        into: Lang,
+    },
+    /// The SPARQL query in a file
+    File { path: PathBuf },
+}
+
+#[cfg(feature = "store")]
+impl QueryCommand {
+    fn store_query(self) -> Result<StoreQuery> {
+        Ok(match self {
+            QueryCommand::Ancestors { lang, term } => StoreQuery::Ancestors { lang, term },
+            QueryCommand::Descendants { lang, term } => StoreQuery::Descendants { lang, term },
+            QueryCommand::Loanwords { from, into } => StoreQuery::Loanwords { from, into },
+            QueryCommand::File { path } => StoreQuery::Sparql(fs::read_to_string(path)?),
+        })
+    }
+}
+
+// The config file that is read if there is no --config.
+const DEFAULT_CONFIG_PATH: &str = "wety.toml";
+
+// The index of the subcommand in the command line args, skipping the values of
+// any global options before it.
+fn subcommand_index(args: &[OsString]) -> Option<usize> {
+    let command = Args::command();
+    let mut args = args.iter().enumerate().skip(1);
+    while let Some((i, arg)) = args.next() {
+        let arg = arg.to_string_lossy();
+        if let Some(long) = arg.strip_prefix("--") {
+            if command
+                .get_arguments()
+                .any(|a| a.get_long() == Some(long) && a.is_takes_value_set())
+            {
+                args.next();
+            }
+        } else if !arg.starts_with('-') {
+            return Some(i);
+        }
+    }
+    None
+}
+
+fn config_path(args: &[OsString]) -> Option<PathBuf> {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let arg = arg.to_string_lossy();
+        if arg == "--config" {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(path) = arg.strip_prefix("--config=") {
+            return Some(PathBuf::from(path));
+        }
+    }
+    None
+}
+
+fn config_value_args(long: &str, value: &toml::Value) -> Result<Vec<OsString>> {
+    let option = OsString::from(format!("--{long}"));
+    Ok(match value {
+        toml::Value::Boolean(true) => vec![option],
+        toml::Value::Boolean(false) => vec![],
+        toml::Value::String(s) => vec![option, s.into()],
+        toml::Value::Integer(n) => vec![option, n.to_string().into()],
+        toml::Value::Float(x) => vec![option, x.to_string().into()],
+        toml::Value::Array(values) => {
+            let mut args = vec![];
+            for value in values {
+                args.extend(config_value_args(long, value)?);
+            }
+            args
+        }
+        _ => bail!("unsupported value for {long} in config: {value}"),
+    })
+}
+
+// The command line args, with the options in the config file inserted after
+// the subcommand, so that the same options given on the command line override
+// them. Top level options in the config apply to any subcommand that takes
+// them, and those in a table named after a subcommand, e.g. [graph], only to
+// it.
+fn args_with_config() -> Result<Vec<OsString>> {
+    let mut args: Vec<OsString> = env::args_os().collect();
+    let path = config_path(&args);
+    let is_default = path.is_none();
+    let path = path.unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG_PATH));
+    if is_default && !path.exists() {
+        return Ok(args);
+    }
+    let config: toml::Table = fs::read_to_string(&path)
+        .with_context(|| format!("reading config {}", path.display()))?
+        .parse()
+        .with_context(|| format!("parsing config {}", path.display()))?;
+    let command = Args::command();
+    let Some(i) = subcommand_index(&args) else {
+        return Ok(args);
+    };
+    let Some(subcommand) = command.find_subcommand(&args[i]) else {
+        return Ok(args);
+    };
+    let takes = |command: &clap::Command, long: &str| {
+        long != "config" && command.get_arguments().any(|a| a.get_long() == Some(long))
+    };
+    let mut config_args = vec![];
+    for (key, value) in &config {
+        if let Some(table) = value.as_table() {
+            if command.find_subcommand(key).is_none() {
+                bail!("unknown subcommand [{key}] in config {}", path.display());
+            }
+            if key != subcommand.get_name() {
+                continue;
+            }
+            for (key, value) in table {
+                if !takes(subcommand, key) && !takes(&command, key) {
+                    bail!(
+                        "unknown option {key} for {} in config {}",
+                        subcommand.get_name(),
+                        path.display()
+                    );
+                }
+                config_args.extend(config_value_args(key, value)?);
+            }
+        } else if takes(subcommand, key) || takes(&command, key) {
+            config_args.extend(config_value_args(key, value)?);
+        } else if !command.get_subcommands().any(|c| takes(c, key)) {
+            bail!("unknown option {key} in config {}", path.display());
+        }
+    }
+    args.splice(i + 1..i + 1, config_args);
+    Ok(args)
+}
+
 fn main() -> Result<()> {
     env::set_var("RUST_BACKTRACE", "1");
     let total_time = Instant::now();
-    let args = Args::parse();
-    let embeddings_config = embeddings::Config {
-        model_name: args.embeddings_model,
-        model_revision: args.embeddings_model_revision,
-        batch_size: args.embeddings_batch_size,
-        cache_path: args.embeddings_cache_path,
-    };
-    process_wiktextract(
-        &args.wiktextract_path,
-        &args.serialization_path,
-        args.turtle_path.as_deref(),
-        &embeddings_config,
-    )?;
+    let args = Args::parse_from(args_with_config()?);
+    if let Some(progress_events) = &args.progress_events {
+        write_progress_events(progress_events)?;
+    }
+    match args.command {
+        Command::Process {
+            graph,
+            serialization,
+            serve,
+            export,
+        } => {
+            if graph.dry_run()? {
+                return Ok(());
+            }
+            let options = serve.options(graph.options());
+            let checkpoints = graph.checkpoints()?;
+            process_wiktextract(
+                &graph.wiktextract.wiktextract_path,
+                &serialization.serialization_path,
+                export.turtle_path.as_deref(),
+                export.store_path.as_deref(),
+                graph.validation_report_path.as_deref(),
+                graph.cycle_report_path.as_deref(),
+                &graph.embeddings.config(),
+                options,
+                checkpoints.as_ref(),
+            )?;
+        }
+        Command::Embed {
+            wiktextract,
+            embeddings,
+        } => {
+            embed_wiktextract(
+                &wiktextract.wiktextract_path,
+                &embeddings.config(),
+                ProcessingOptions {
+                    line_range: wiktextract.line_range(),
+                    spill_strings: wiktextract.spill_strings,
+                    ..ProcessingOptions::default()
+                },
+            )?;
+        }
+        Command::ExportEmbeddings {
+            graph,
+            output_path,
+            format,
+        } => {
+            if graph.dry_run()? {
+                return Ok(());
+            }
+            let options = graph.options();
+            let checkpoints = graph.checkpoints()?;
+            export_embeddings(
+                &graph.wiktextract.wiktextract_path,
+                &graph.embeddings.config(),
+                options,
+                checkpoints.as_ref(),
+                &output_path,
+                format,
+            )?;
+        }
+        Command::Eval {
+            gold_path,
+            report_path,
+            embeddings,
+            similarity,
+        } => {
+            let options = ProcessingOptions {
+                similarity_threshold: similarity.similarity_threshold,
+                term_similarity_weight: similarity.term_similarity_weight,
+                ..ProcessingOptions::default()
+            };
+            evaluate_disambiguation(
+                &gold_path,
+                &embeddings.config(),
+                options,
+                report_path.as_deref(),
+            )?;
+        }
+        Command::FineTune { graph, fine_tune } => {
+            if graph.dry_run()? {
+                return Ok(());
+            }
+            let options = graph.options();
+            let checkpoints = graph.checkpoints()?;
+            fine_tune_model(
+                &graph.wiktextract.wiktextract_path,
+                &graph.embeddings.config(),
+                options,
+                checkpoints.as_ref(),
+                &fine_tune.fine_tuning(),
+            )?;
+        }
+        Command::Graph {
+            graph,
+            serialization,
+        } => {
+            if graph.dry_run()? {
+                return Ok(());
+            }
+            let options = graph.options();
+            let checkpoints = graph.checkpoints()?;
+            let mut data = generate_data(
+                &graph.wiktextract.wiktextract_path,
+                graph.validation_report_path.as_deref(),
+                graph.cycle_report_path.as_deref(),
+                &graph.embeddings.config(),
+                options,
+                checkpoints.as_ref(),
+            )?;
+            serialize_data(&mut data, &serialization.serialization_path, options)?;
+        }
+        Command::Export {
+            serialization,
+            export,
+        } => {
+            let data = Data::deserialize(&serialization.serialization_path)?;
+            export_data(
+                &data,
+                export.turtle_path.as_deref(),
+                export.store_path.as_deref(),
+            )?;
+        }
+        Command::ServePrep {
+            serialization,
+            output_path,
+            serve,
+        } => {
+            // The input's strings may be memory-mapped from the file that the
+            // output's strings would be written to. Shards have their own.
+            if !serve.sharded
+                && sibling_path(&output_path, "strings")
+                    == sibling_path(&serialization.serialization_path, "strings")
+            {
+                bail!("the output path must not share its name with the serialization path");
+            }
+            let mut data = Data::deserialize(&serialization.serialization_path)?;
+            let options = serve.options(ProcessingOptions::default());
+            serialize_data(&mut data, &output_path, options)?;
+        }
+        Command::Subset {
+            serialization,
+            langs,
+            output_path,
+            serve,
+        } => {
+            if !serve.sharded
+                && sibling_path(&output_path, "strings")
+                    == sibling_path(&serialization.serialization_path, "strings")
+            {
+                bail!("the output path must not share its name with the serialization path");
+            }
+            let mut data =
+                Data::deserialize(&serialization.serialization_path)?.into_subset(&langs);
+            let options = serve.options(ProcessingOptions::default());
+            serialize_data(&mut data, &output_path, options)?;
+        }
+        Command::Diff {
+            old_path,
+            new_path,
+            output_path,
+        } => {
+            let old = Data::deserialize(&old_path)?;
+            let new = Data::deserialize(&new_path)?;
+            let diff = Diff::new(&old, &new);
+            if let Some(output_path) = output_path {
+                serde_json::to_writer_pretty(fs::File::create(output_path)?, &diff.json())?;
+            }
+            print!("{}", diff.summary());
+            return Ok(());
+        }
+        Command::Stats { serialization } => {
+            let data = Data::deserialize(&serialization.serialization_path)?;
+            println!("{}", serde_json::to_string_pretty(&data.stats_json())?);
+            return Ok(());
+        }
+        Command::Cache {
+            cache,
+            command: CacheCommand::Verify,
+        } => {
+            return embeddings::verify_cache(&cache.embeddings_cache_path);
+        }
+        #[cfg(feature = "store")]
+        Command::QueryStore {
+            store_path,
+            format,
+            query,
+        } => {
+            return query_store(
+                &store_path,
+                &query.store_query()?,
+                format,
+                io::stdout().lock(),
+            );
+        }
+    }
 
     println!(
         "All done! Took {} overall. Exiting...",
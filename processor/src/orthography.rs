@@ -0,0 +1,221 @@
+//! Orthographic similarity between terms, used alongside embedding similarity
+//! when choosing between homograph candidates. Embeddings alone frequently
+//! pick the wrong homograph when glosses are short. Also the folding of terms
+//! for search.
+
+use crate::languages::Lang;
+
+use itertools::Itertools;
+use lazy_static::lazy_static;
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+// How much the term similarity counts toward the blended similarity, by default.
+pub(crate) const DEFAULT_TERM_SIMILARITY_WEIGHT: f32 = 0.2;
+
+// The cost of substituting one char for another when the pair is a regular
+// sound correspondence between the langs of the two terms, rather than 1.
+const CORRESPONDENCE_COST: f32 = 0.25;
+
+type Pairs = &'static [(char, char)];
+
+// Pairs of chars that regularly correspond between a term in the ancestor lang
+// (first char) and its reflex in the descendant lang (second char). These also
+// apply to any langs descended from each. Chars are compared after stripping
+// diacritics, so e.g. PIE ḱ is just k here.
+const RAW_SOUND_CORRESPONDENCES: &[(&str, &str, Pairs)] = &[
+    // Grimm's law
+    (
+        "ine-pro",
+        "gem-pro",
+        &[
+            ('p', 'f'),
+            ('t', 'þ'),
+            ('k', 'h'),
+            ('b', 'p'),
+            ('d', 't'),
+            ('g', 'k'),
+        ],
+    ),
+    // High German consonant shift
+    (
+        "gem-pro",
+        "goh",
+        &[('p', 'f'), ('t', 'z'), ('k', 'h'), ('d', 't'), ('þ', 'd')],
+    ),
+    // Western Romance lenition
+    (
+        "la",
+        "es",
+        &[('p', 'b'), ('t', 'd'), ('c', 'g'), ('f', 'h')],
+    ),
+    ("la", "pt", &[('p', 'b'), ('t', 'd'), ('c', 'g')]),
+];
+
+struct SoundCorrespondences {
+    ancestor: Lang,
+    descendant: Lang,
+    pairs: Pairs,
+}
+
+lazy_static! {
+    static ref SOUND_CORRESPONDENCES: Vec<SoundCorrespondences> = RAW_SOUND_CORRESPONDENCES
+        .iter()
+        .map(|&(ancestor, descendant, pairs)| SoundCorrespondences {
+            ancestor: ancestor.parse().expect("known lang code"),
+            descendant: descendant.parse().expect("known lang code"),
+            pairs,
+        })
+        .collect();
+}
+
+// The correspondence pairs that apply going from older_lang to newer_lang.
+fn correspondences(older_lang: Lang, newer_lang: Lang) -> Vec<(char, char)> {
+    SOUND_CORRESPONDENCES
+        .iter()
+        .filter(|c| older_lang.descends_from(c.ancestor) && newer_lang.descends_from(c.descendant))
+        .flat_map(|c| c.pairs.iter().copied())
+        .collect()
+}
+
+// Lowercase, strip diacritics, and drop anything that isn't a letter (e.g. the
+// hyphens on affixes).
+fn fold(term: &str) -> Vec<char> {
+    term.nfd()
+        .filter(|&c| !is_combining_mark(c) && c.is_alphabetic())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Lowercase and strip diacritics (after compatibility decomposition), so that
+/// e.g. "vocō" can be searched for as "voco". Whitespace is collapsed to single
+/// spaces, so that multi-word terms are found however it is typed. Unlike for
+/// similarity, everything else is kept.
+pub(crate) fn search_key(term: &str) -> String {
+    term.nfkd()
+        .filter(|&c| !is_combining_mark(c))
+        .flat_map(char::to_lowercase)
+        .collect::<String>()
+        .split_whitespace()
+        .join(" ")
+}
+
+/// The search key of an IPA transcription, e.g. "woko" for "/ˈwoː.koː/":
+/// folded as term search keys are, and without delimiters, stress and length
+/// marks, syllable breaks or spaces, which users are unlikely to get right.
+pub(crate) fn ipa_search_key(ipa: &str) -> String {
+    search_key(ipa)
+        .chars()
+        .filter(|&c| {
+            !c.is_whitespace()
+                && !matches!(
+                    c,
+                    '/' | '[' | ']' | '(' | ')' | 'ˈ' | 'ˌ' | '.' | 'ː' | 'ˑ' | '‿' | '-'
+                )
+        })
+        .collect()
+}
+
+// Levenshtein distance where substitutions in older_to_newer cost less.
+#[allow(clippy::cast_precision_loss)]
+fn weighted_edit_distance(older: &[char], newer: &[char], older_to_newer: &[(char, char)]) -> f32 {
+    let substitution_cost = |a: char, b: char| {
+        if a == b {
+            0.0
+        } else if older_to_newer.contains(&(a, b)) {
+            CORRESPONDENCE_COST
+        } else {
+            1.0
+        }
+    };
+    let mut prev_row = (0..=newer.len()).map(|j| j as f32).collect::<Vec<_>>();
+    let mut row = vec![0.0; newer.len() + 1];
+    for (i, &a) in older.iter().enumerate() {
+        row[0] = (i + 1) as f32;
+        for (j, &b) in newer.iter().enumerate() {
+            row[j + 1] = (prev_row[j] + substitution_cost(a, b))
+                .min(prev_row[j + 1] + 1.0)
+                .min(row[j] + 1.0);
+        }
+        std::mem::swap(&mut prev_row, &mut row);
+    }
+    prev_row[newer.len()]
+}
+
+/// Similarity of two terms in [0, 1], based on an edit distance that is
+/// lenient toward regular sound correspondences between the terms' langs.
+pub(crate) fn term_similarity(a: (Lang, &str), b: (Lang, &str)) -> f32 {
+    let ((older_lang, older), (newer_lang, newer)) = if a.0.strictly_descends_from(b.0) {
+        (b, a)
+    } else {
+        (a, b)
+    };
+    let (older, newer) = (fold(older), fold(newer));
+    let max_len = older.len().max(newer.len());
+    if max_len == 0 {
+        return 1.0;
+    }
+    let older_to_newer = correspondences(older_lang, newer_lang);
+    let distance = weighted_edit_distance(&older, &newer, &older_to_newer);
+    #[allow(clippy::cast_precision_loss)]
+    let max_len = max_len as f32;
+    1.0 - distance / max_len
+}
+
+/// Blend an embedding similarity with a term similarity, the latter counting
+/// for `term_weight` of the blend.
+pub(crate) fn blend_similarity(
+    embedding_similarity: f32,
+    term_similarity: f32,
+    term_weight: f32,
+) -> f32 {
+    (1.0 - term_weight) * embedding_similarity + term_weight * term_similarity
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lang(code: &str) -> Lang {
+        code.parse().unwrap()
+    }
+
+    #[test]
+    fn fold_strips_diacritics() {
+        assert_eq!(fold("ḱérd-"), vec!['k', 'e', 'r', 'd']);
+        assert_eq!(fold("Mālum"), vec!['m', 'a', 'l', 'u', 'm']);
+    }
+
+    #[test]
+    fn search_key_folds_case_and_diacritics() {
+        assert_eq!(search_key("vocō"), "voco");
+        assert_eq!(search_key("Léver"), "lever");
+        assert_eq!(search_key("ἄνθρωπος"), "ανθρωπος");
+        assert_eq!(search_key("-ﬁcus"), "-ficus");
+    }
+
+    #[test]
+    fn ipa_search_key_drops_marks() {
+        assert_eq!(ipa_search_key("/ˈwoː.koː/"), "woko");
+        assert_eq!(ipa_search_key("[ˈt͡ʃɛl.lo]"), "tʃɛllo");
+    }
+
+    #[test]
+    fn identical_terms() {
+        let la = lang("la");
+        assert!((term_similarity((la, "malum"), (la, "mālum")) - 1.0).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn sound_correspondences() {
+        // PIE pṓds > Proto-Germanic fōts
+        let pie = lang("ine-pro");
+        let gem = lang("gem-pro");
+        let la = lang("la");
+        let with_correspondence = term_similarity((gem, "fōts"), (pie, "pṓds"));
+        let without_correspondence = term_similarity((gem, "fōts"), (la, "pōds"));
+        assert!(with_correspondence > without_correspondence);
+        // The order of the arguments doesn't matter.
+        let reversed = term_similarity((pie, "pṓds"), (gem, "fōts"));
+        assert!((with_correspondence - reversed).abs() < f32::EPSILON);
+    }
+}
@@ -0,0 +1,173 @@
+use crate::{
+    ety_graph::EtyGraph,
+    items::{Item, ItemId},
+    languages::Lang,
+    processed::Data,
+    HashMap,
+};
+
+use std::{
+    cmp::Reverse,
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    slice,
+};
+
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+// Paths through fewer langs than this are too uninteresting to index.
+const MIN_INDEXED_PATH_LANGS: usize = 3;
+
+/// For each lang, the items in that lang that start a quality head path (see
+/// below) through at least `MIN_INDEXED_PATH_LANGS` langs, along with the number
+/// of langs on the path, sorted descending by the latter.
+#[derive(Serialize, Deserialize, Default)]
+pub(crate) struct PathIndex {
+    starts: HashMap<Lang, Vec<(ItemId, u8)>>,
+}
+
+// Items on a quality path must not be imputed and must have glosses, so that
+// e.g. a puzzle built on the path can show what each term means.
+fn is_quality_path_item(item: &Item) -> bool {
+    !item.is_imputed() && item.gloss().is_some_and(|gloss| !gloss.is_empty())
+}
+
+// The head path from item, cut off at the first item that doesn't meet the
+// quality filters.
+fn quality_head_path(graph: &EtyGraph, item: ItemId) -> Vec<ItemId> {
+    graph
+        .head_path(item)
+        .into_iter()
+        .take_while(|&id| is_quality_path_item(graph.item(id)))
+        .collect()
+}
+
+fn path_langs(graph: &EtyGraph, path: &[ItemId]) -> usize {
+    path.iter()
+        .map(|&id| graph.item(id).lang())
+        .unique()
+        .count()
+}
+
+impl PathIndex {
+    pub(crate) fn new(graph: &EtyGraph) -> Self {
+        let mut starts = HashMap::<Lang, Vec<(ItemId, u8)>>::default();
+        for (item_id, item) in graph.iter() {
+            let path = quality_head_path(graph, item_id);
+            let langs = path_langs(graph, &path);
+            if langs >= MIN_INDEXED_PATH_LANGS {
+                starts
+                    .entry(item.lang())
+                    .or_default()
+                    .push((item_id, u8::try_from(langs).unwrap_or(u8::MAX)));
+            }
+        }
+        for lang_starts in starts.values_mut() {
+            lang_starts.sort_unstable_by_key(|&(_, langs)| Reverse(langs));
+        }
+        Self { starts }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.starts.is_empty()
+    }
+}
+
+fn random_index(len: usize) -> usize {
+    // std's RandomState is randomly seeded, which is all we need here.
+    let random = RandomState::new().build_hasher().finish();
+    usize::try_from(random % len as u64).expect("less than len, which is a usize")
+}
+
+// Pick a random item from among those in `index` for `langs` (or all langs if
+// `None`) with a count of at least `min`. Each list in `index` must be sorted
+// descending by count.
+fn random_indexed_item<C: Copy + Into<usize>>(
+    index: &HashMap<Lang, Vec<(ItemId, C)>>,
+    langs: Option<&[Lang]>,
+    min: usize,
+) -> Option<ItemId> {
+    let lists: Vec<&Vec<(ItemId, C)>> = match langs {
+        Some(langs) => langs.iter().filter_map(|lang| index.get(lang)).collect(),
+        None => index.values().collect(),
+    };
+    let candidates = lists
+        .into_iter()
+        .map(|items| {
+            let n = items.partition_point(|&(_, count)| count.into() >= min);
+            &items[..n]
+        })
+        .collect_vec();
+    let total = candidates.iter().map(|c| c.len()).sum::<usize>();
+    if total == 0 {
+        return None;
+    }
+    let mut i = random_index(total);
+    candidates.iter().find_map(|c| {
+        if i < c.len() {
+            return Some(c[i].0);
+        }
+        i -= c.len();
+        None
+    })
+}
+
+impl Data {
+    /// Select a random ancestry path for use in e.g. puzzle games. The path
+    /// starts from an item in one of `langs` and follows the head parent at
+    /// each step back through at least `min_len` langs (and never fewer than
+    /// 3). All items on the path are real and have glosses. Returns `None` if
+    /// there is no such path.
+    #[must_use]
+    pub fn random_path(&self, min_len: usize, langs: &[Lang]) -> Option<Value> {
+        let start = random_indexed_item(&self.path_index.starts, Some(langs), min_len)?;
+        let path = quality_head_path(&self.graph, start);
+        Some(json!({
+            "langs": path_langs(&self.graph, &path),
+            "items": path.iter().map(|&id| self.item_json(id)).collect_vec(),
+        }))
+    }
+
+    /// Build an index for selecting random real items. This is built at
+    /// server startup rather than serialized, since it covers every real item.
+    #[must_use]
+    pub fn build_random_items(&self) -> RandomItems {
+        let mut depths = HashMap::<Lang, Vec<(ItemId, u16)>>::default();
+        for (item_id, item) in self.graph.iter().filter(|(_, item)| !item.is_imputed()) {
+            depths
+                .entry(item.lang())
+                .or_default()
+                .push((item_id, self.depth(item_id)));
+        }
+        for lang_depths in depths.values_mut() {
+            lang_depths.sort_unstable_by_key(|&(_, depth)| Reverse(depth));
+        }
+        RandomItems { depths }
+    }
+}
+
+/// For each lang, the real items in that lang along with their ancestry depth
+/// (see [`Data::depth`]), sorted descending by the latter.
+pub struct RandomItems {
+    depths: HashMap<Lang, Vec<(ItemId, u16)>>,
+}
+
+impl RandomItems {
+    /// Select a random real item, in `lang` if given, whose ancestry depth
+    /// exceeds `min_depth` if given. Returns `None` if there is no such item.
+    #[must_use]
+    pub fn random_item(
+        &self,
+        data: &Data,
+        lang: Option<Lang>,
+        min_depth: Option<usize>,
+    ) -> Option<Value> {
+        let langs = lang.as_ref().map(slice::from_ref);
+        // a depth of at least min_depth + 1, i.e. more than min_depth
+        let min = min_depth.map_or(0, |min_depth| min_depth.saturating_add(1));
+        let item = random_indexed_item(&self.depths, langs, min)?;
+        Some(data.item_json(item))
+    }
+}
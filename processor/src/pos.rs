@@ -1,42 +1,189 @@
-use std::str::FromStr;
+use crate::HashMap;
 
-use anyhow::anyhow;
-use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr, sync::RwLock};
 
-use crate::pos_phf::POS;
+use anyhow::{anyhow, Ok, Result};
+use itertools::Itertools;
+use lazy_static::lazy_static;
+use serde::{
+    de::{self, MapAccess, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
 
-// PosId refers to an index in the POS OrderedSet
-pub(crate) type PosId = u8; // the set has ~50 elements
+// PosId refers to an index in the registry's names
+pub(crate) type PosId = u8; // pos.json has ~50 elements
 
-#[derive(Hash, Eq, PartialEq, Debug, Copy, Clone, Serialize, Deserialize)]
+#[derive(Hash, Eq, PartialEq, Debug, Copy, Clone)]
 pub(crate) struct Pos {
     id: PosId,
 }
 
+// The known parts of speech, from pos.json, plus any others that wiktextract
+// gives, which are registered as they are first parsed.
+struct PosRegistry {
+    names: Vec<&'static str>,
+    ids: HashMap<&'static str, PosId>,
+    // How many of the names are from pos.json.
+    known: usize,
+    // How many times each pos not in pos.json was parsed.
+    unknown_counts: HashMap<&'static str, usize>,
+}
+
+impl PosRegistry {
+    fn new() -> Self {
+        let names: Vec<&'static str> = serde_json::from_str(include_str!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/data/pos.json"
+        )))
+        .expect("well-formed pos.json");
+        let ids = names
+            .iter()
+            .enumerate()
+            .map(|(id, &name)| (name, PosId::try_from(id).expect("fewer than 256 pos")))
+            .collect();
+        Self {
+            known: names.len(),
+            names,
+            ids,
+            unknown_counts: HashMap::default(),
+        }
+    }
+
+    fn register(&mut self, name: &str) -> Result<PosId> {
+        if let Some(&id) = self.ids.get(name) {
+            return Ok(id);
+        }
+        let id = PosId::try_from(self.names.len())
+            .map_err(|_| anyhow!("no room in the pos registry for \"{name}\""))?;
+        // Unknown pos are few, so leaking them is fine.
+        let name: &'static str = Box::leak(name.into());
+        self.names.push(name);
+        self.ids.insert(name, id);
+        Ok(id)
+    }
+}
+
+lazy_static! {
+    static ref POS: RwLock<PosRegistry> = RwLock::new(PosRegistry::new());
+}
+
+/// The parts of speech not in pos.json that were parsed, with how many times
+/// each was, most frequent first.
+pub(crate) fn unknown_pos_counts() -> Vec<(&'static str, usize)> {
+    POS.read()
+        .expect("pos registry not poisoned")
+        .unknown_counts
+        .iter()
+        .map(|(&name, &count)| (name, count))
+        .sorted_unstable_by_key(|&(name, count)| (std::cmp::Reverse(count), name))
+        .collect()
+}
+
 impl From<PosId> for Pos {
     fn from(pos_id: PosId) -> Self {
         Self { id: pos_id }
     }
 }
 
+// A pos not in pos.json is registered rather than rejected, and counted, so
+// that its items aren't dropped and the gap in pos.json is visible.
 impl FromStr for Pos {
     type Err = anyhow::Error;
 
     fn from_str(pos: &str) -> Result<Self, Self::Err> {
-        if let Some(id) = POS.get_index(pos) {
-            return Ok(PosId::try_from(id)?.into());
+        {
+            let registry = POS.read().expect("pos registry not poisoned");
+            if let Some(&id) = registry.ids.get(pos)
+                && usize::from(id) < registry.known
+            {
+                return Ok(id.into());
+            }
         }
-        Err(anyhow!("\"{pos}\" does not exist in POS"))
+        let mut registry = POS.write().expect("pos registry not poisoned");
+        let id = registry.register(pos)?;
+        let name = registry.names[usize::from(id)];
+        *registry.unknown_counts.entry(name).or_default() += 1;
+        Ok(id.into())
     }
 }
 
 impl Pos {
     pub(crate) fn name(self) -> &'static str {
-        POS.index(self.id as usize)
-            .expect("id cannot have been created without being a valid index")
+        POS.read().expect("pos registry not poisoned").names[usize::from(self.id)]
     }
 
     pub(crate) fn root_pos() -> Pos {
         "root".parse().expect("root pos must exist")
     }
 }
+
+// As with langs, pos are serialized by name, since their ids depend on
+// pos.json and on which unknown pos were registered first.
+impl Serialize for Pos {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+struct PosVisitor;
+
+impl<'de> Visitor<'de> for PosVisitor {
+    type Value = Pos;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a pos name or pos id")
+    }
+
+    // Unknown pos in serialized data were already counted when first parsed.
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Pos, E> {
+        POS.write()
+            .expect("pos registry not poisoned")
+            .register(v)
+            .map(Pos::from)
+            .map_err(E::custom)
+    }
+
+    // Data serialized before pos were serialized by name will have {"id": n}.
+    // These are only correct if pos.json hasn't changed since.
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Pos, A::Error> {
+        let mut id = None;
+        while let Some((key, value)) = map.next_entry::<String, PosId>()? {
+            if key == "id" {
+                id = Some(value);
+            }
+        }
+        id.filter(|&id| {
+            usize::from(id) < POS.read().expect("pos registry not poisoned").names.len()
+        })
+        .map(Pos::from)
+        .ok_or_else(|| de::Error::custom("unknown pos id"))
+    }
+}
+
+impl<'de> Deserialize<'de> for Pos {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(PosVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_pos_are_registered_and_counted() {
+        let noun: Pos = "noun".parse().unwrap();
+        assert_eq!(noun.name(), "noun");
+        let unknown: Pos = "test_unknown_pos".parse().unwrap();
+        let again: Pos = "test_unknown_pos".parse().unwrap();
+        assert_eq!(unknown, again);
+        assert_eq!(unknown.name(), "test_unknown_pos");
+        assert!(unknown_pos_counts().contains(&("test_unknown_pos", 2)));
+        assert!(!unknown_pos_counts().iter().any(|&(name, _)| name == "noun"));
+        let serialized = serde_json::to_string(&unknown).unwrap();
+        assert_eq!(serialized, "\"test_unknown_pos\"");
+        assert_eq!(serde_json::from_str::<Pos>(&serialized).unwrap(), unknown);
+        let legacy = format!("{{\"id\":{}}}", noun.id);
+        assert_eq!(serde_json::from_str::<Pos>(&legacy).unwrap(), noun);
+    }
+}
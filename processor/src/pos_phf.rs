@@ -1,50 +0,0 @@
-// This file was generated by data/phf/pos.py, see there for details.
-
-#![allow(clippy::all)]
-
-use phf::{phf_ordered_set, OrderedSet};
-
-pub(crate) static POS: OrderedSet<&'static str> = phf_ordered_set! {
-    "counter",
-    "circumfix",
-    "num",
-    "romanization",
-    "adv",
-    "adj",
-    "circumpos",
-    "proverb",
-    "article",
-    "preverb",
-    "affix",
-    "conj",
-    "contraction",
-    "phrase",
-    "verb",
-    "character",
-    "postp",
-    "converb",
-    "infix",
-    "ambiposition",
-    "root",
-    "noun",
-    "adj_verb",
-    "intj",
-    "particle",
-    "det",
-    "combining_form",
-    "adv_phrase",
-    "interfix",
-    "abbrev",
-    "prefix",
-    "classifier",
-    "prep_phrase",
-    "name",
-    "pron",
-    "syllable",
-    "suffix",
-    "prep",
-    "symbol",
-    "clause",
-    "punct",
-    "adj_noun",
-};
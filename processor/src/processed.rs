@@ -1,20 +1,29 @@
 use crate::{
-    ety_graph::{EtyEdgeAccess, EtyGraph, Progenitors},
-    items::{Item, ItemId},
+    ety_graph::{EtyEdge, EtyEdgeAccess, EtyGraph, Progenitors},
+    items::{Item, ItemId, StableIds},
+    langterm::Term,
     languages::Lang,
+    orthography::{ipa_search_key, search_key},
+    paths::PathIndex,
+    stats::{Provenance, Stats},
     string_pool::StringPool,
-    HashMap, HashSet,
+    HashMap, HashSet, ProcessingOptions,
 };
 
 use std::{
+    cell::Cell,
+    cmp::Reverse,
     collections::hash_map::Entry,
-    fs::File,
-    io::{BufReader, BufWriter, Read, Write},
-    path::Path,
+    fmt,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, Mutex, OnceLock},
     time::Instant,
 };
 
-use anyhow::{Ok, Result};
+use anyhow::{Context, Ok, Result};
 use flate2::{read::GzDecoder, write::GzEncoder, Compression};
 use fuzzy_trie::{Collector, FuzzyTrie};
 use indicatif::HumanDuration;
@@ -22,54 +31,322 @@ use itertools::Itertools;
 use ngrammatic::{Corpus, CorpusBuilder, Pad};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
+use xxhash_rust::xxh3::{xxh3_64, Xxh3};
 
 #[derive(Serialize, Deserialize)]
 pub struct Data {
     pub(crate) string_pool: StringPool,
     pub(crate) graph: EtyGraph,
     pub(crate) progenitors: HashMap<ItemId, Progenitors>,
+    // Missing for data processed before this was recorded, in which case it
+    // is computed on deserialization.
+    #[serde(default)]
+    depths: HashMap<ItemId, u16>,
+    // the items descended from each head progenitor, i.e. its cognate set,
+    // rebuilt from the progenitors on deserialization
+    #[serde(skip)]
+    cognate_sets: HashMap<ItemId, Box<[ItemId]>>,
     descendant_langs: HashMap<ItemId, HashSet<Lang>>,
+    // Missing for data processed before this was recorded, in which case it
+    // is built on deserialization.
+    #[serde(default)]
+    pub(crate) path_index: PathIndex,
+    // titles of redirect pages that lead to each item's page
+    #[serde(default)]
+    pub(crate) aliases: HashMap<ItemId, Vec<Term>>,
+    #[serde(default)]
+    pub(crate) stats: Stats,
+    // the items of each lang and hash of term, built on the first lookup
+    #[serde(skip)]
+    term_index: OnceLock<HashMap<(Lang, u64), Vec<ItemId>>>,
+    // the item with each stable id, built on loading the data, or on the first
+    // lookup in data that wasn't loaded
+    #[serde(skip)]
+    stable_id_index: OnceLock<StableIds>,
+    // hash of the uncompressed serialized data, set on deserialization
+    #[serde(skip)]
+    pub(crate) content_hash: u64,
+}
+
+/// Marks a node in a descendants tree whose children were not expanded because
+/// of the `TreeLimits` for the response. The subtree rooted at the node can
+/// be requested separately by passing this back to the server.
+#[derive(Deserialize, Clone, Copy)]
+#[serde(try_from = "String")]
+pub struct Continuation {
+    item: ItemId,
+    parent: Option<ItemId>,
+}
+
+/// Limits on building a tree json response. Past the budget, at `max_depth`,
+/// or once `max_nodes` nodes have been built, the tree is left partially
+/// expanded, with the unexpanded nodes marked as truncated. Past the timeout,
+/// building is abandoned altogether with a `QueryTimeout`.
+#[derive(Default)]
+pub struct TreeLimits {
+    pub budget: Option<Instant>,
+    pub timeout: Option<Instant>,
+    pub max_depth: Option<usize>,
+    pub max_nodes: Option<usize>,
+    // nodes built so far
+    nodes: Cell<usize>,
+}
+
+impl TreeLimits {
+    #[must_use]
+    pub fn new(
+        budget: Option<Instant>,
+        timeout: Option<Instant>,
+        max_depth: Option<usize>,
+        max_nodes: Option<usize>,
+    ) -> Self {
+        Self {
+            budget,
+            timeout,
+            max_depth,
+            max_nodes,
+            nodes: Cell::new(0),
+        }
+    }
+
+    pub(crate) fn add_node(&self) {
+        self.nodes.set(self.nodes.get() + 1);
+    }
+
+    /// How many nodes have been built with these limits so far.
+    #[must_use]
+    pub fn nodes(&self) -> usize {
+        self.nodes.get()
+    }
+
+    // Whether to expand the children (or parents) of a node at depth, where the
+    // root is at depth 0.
+    pub(crate) fn can_expand(&self, depth: usize) -> bool {
+        !(self.budget.is_some_and(|b| Instant::now() >= b)
+            || self.max_depth.is_some_and(|max| depth >= max)
+            || self.max_nodes.is_some_and(|max| self.nodes.get() >= max))
+    }
+
+    pub(crate) fn check_timeout(&self) -> Result<(), QueryTimeout> {
+        if self.timeout.is_some_and(|t| Instant::now() >= t) {
+            return Err(QueryTimeout);
+        }
+        Result::Ok(())
+    }
+}
+
+/// Returned when building a tree json response runs past its timeout.
+#[derive(Debug, Clone, Copy)]
+pub struct QueryTimeout;
+
+impl fmt::Display for QueryTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "query timed out")
+    }
+}
+
+impl std::error::Error for QueryTimeout {}
+
+impl fmt::Display for Continuation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.parent {
+            Some(parent) => write!(f, "{}.{}", self.item.index(), parent.index()),
+            None => write!(f, "{}", self.item.index()),
+        }
+    }
+}
+
+impl TryFrom<String> for Continuation {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        let parse = |id: &str| -> Result<ItemId> { Ok(ItemId::new(id.parse()?)) };
+        let (item, parent) = match s.split_once('.') {
+            Some((item, parent)) => (parse(item)?, Some(parse(parent)?)),
+            None => (parse(&s)?, None),
+        };
+        Ok(Self { item, parent })
+    }
+}
+
+// Hashes everything read through it.
+pub(crate) struct HashingReader<R> {
+    pub(crate) inner: R,
+    pub(crate) hasher: Xxh3,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        io::Result::Ok(n)
+    }
 }
 
 // methods for use within processor
+// Group the items with progenitors by their head progenitor.
+fn cognate_sets(progenitors: &HashMap<ItemId, Progenitors>) -> HashMap<ItemId, Box<[ItemId]>> {
+    let mut cognate_sets = HashMap::<ItemId, Vec<ItemId>>::default();
+    for (&item, progenitors) in progenitors {
+        if let Some(head) = progenitors.head {
+            cognate_sets.entry(head).or_default().push(item);
+        }
+    }
+    cognate_sets
+        .into_iter()
+        .map(|(head, mut items)| {
+            items.sort_unstable();
+            (head, items.into_boxed_slice())
+        })
+        .collect()
+}
+
 impl Data {
-    pub(crate) fn new(string_pool: StringPool, graph: EtyGraph) -> Self {
+    pub(crate) fn new(
+        string_pool: StringPool,
+        graph: EtyGraph,
+        aliases: HashMap<ItemId, Vec<Term>>,
+        dump_date: Option<u64>,
+        provenance: Option<Provenance>,
+    ) -> Self {
         let progenitors = graph.all_progenitors();
+        let depths = graph.all_depths();
+        let cognate_sets = cognate_sets(&progenitors);
         let descendant_langs = graph.all_descendant_langs();
+        let path_index = PathIndex::new(&graph);
+        let stats = Stats::new(&graph, &depths, dump_date, provenance);
         Self {
             string_pool,
             graph,
             progenitors,
+            depths,
+            cognate_sets,
             descendant_langs,
+            path_index,
+            aliases,
+            stats,
+            term_index: OnceLock::new(),
+            stable_id_index: OnceLock::new(),
+            content_hash: 0,
         }
     }
 
-    pub(crate) fn serialize(&self, path: &Path) -> Result<()> {
+    // With mapped_strings, the strings are written to a separate file, from
+    // which the server memory-maps them, rather than with the rest of the data.
+    // The data is compressed with gzip or zstd if the path ends in .gz or .zst.
+    pub(crate) fn serialize(&mut self, path: &Path, options: ProcessingOptions) -> Result<()> {
         let t = Instant::now();
         println!("Serializing processed data to {}...", path.display());
+        // The dictionary is trained before the strings are taken out of the
+        // pool, if they are to be mapped, but is used for the whole data.
+        let dictionary_path = sibling_path(path, "zdict");
+        let dictionary = if is_zstd(path) && options.zstd_dictionary {
+            train_zstd_dictionary(&self.string_pool, &dictionary_path)?
+        } else {
+            None
+        };
+        if dictionary.is_none() && dictionary_path.exists() {
+            // A stale dictionary from an earlier serialization would otherwise
+            // be used to decompress this one.
+            fs::remove_file(&dictionary_path)?;
+        }
+        let string_pool = if options.mapped_strings {
+            let strings_path = sibling_path(path, "strings");
+            println!("Writing strings to {}...", strings_path.display());
+            self.string_pool.write_mapped(&strings_path)?;
+            Some(std::mem::take(&mut self.string_pool))
+        } else {
+            None
+        };
         let file = File::create(path)?;
         let should_gz_compress = path.extension().is_some_and(|ext| ext == "gz");
         let writer: Box<dyn Write> = if should_gz_compress {
             Box::new(GzEncoder::new(file, Compression::fast()))
+        } else if is_zstd(path) {
+            let encoder = match &dictionary {
+                Some(dictionary) => {
+                    zstd::Encoder::with_dictionary(file, options.zstd_level, dictionary)?
+                }
+                None => zstd::Encoder::new(file, options.zstd_level)?,
+            };
+            Box::new(encoder.auto_finish())
         } else {
             Box::new(BufWriter::new(file))
         };
-        serde_json::to_writer(writer, &self)?;
+        let serialized = serde_json::to_writer(writer, &self);
+        if let Some(string_pool) = string_pool {
+            self.string_pool = string_pool;
+        }
+        serialized?;
         println!("Finished. Took {}.", HumanDuration(t.elapsed()));
         Ok(())
     }
 }
 
+pub const DEFAULT_ZSTD_LEVEL: i32 = 9;
+
+// The dictionary is trained on at most this many bytes of strings, spread
+// across the pool, which is plenty for a dictionary this size.
+const ZSTD_DICTIONARY_SIZE: usize = 112_640;
+const ZSTD_DICTIONARY_SAMPLE_BYTES: usize = 100 * ZSTD_DICTIONARY_SIZE;
+
+fn is_zstd(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "zst")
+}
+
+// Train a zstd dictionary on the strings of the pool and write it to `path`,
+// from which it is read to decompress the data. Since the strings make up most
+// of the data, the dictionary primes the compressor with its common words and
+// markup. A pool too small to train a dictionary on is compressed without one.
+fn train_zstd_dictionary(string_pool: &StringPool, path: &Path) -> Result<Option<Vec<u8>>> {
+    println!("Training a zstd dictionary on the strings...");
+    let total: usize = string_pool.iter().map(str::len).sum();
+    let step = (total / ZSTD_DICTIONARY_SAMPLE_BYTES).max(1);
+    let samples = string_pool
+        .iter()
+        .step_by(step)
+        .filter(|s| !s.is_empty())
+        .collect_vec();
+    let sizes = samples.iter().map(|s| s.len()).collect_vec();
+    let dictionary = match zstd::dict::from_continuous(
+        samples.concat().as_bytes(),
+        &sizes,
+        ZSTD_DICTIONARY_SIZE,
+    ) {
+        io::Result::Ok(dictionary) => dictionary,
+        Err(e) => {
+            println!("Couldn't train a dictionary ({e}), so compressing without one.");
+            return Ok(None);
+        }
+    };
+    println!("Writing the zstd dictionary to {}...", path.display());
+    fs::write(path, &dictionary)?;
+    Ok(Some(dictionary))
+}
+
+/// The path next to the data file `data_path` with the extension `extension`
+/// in place of .json, .json.gz or .json.zst, e.g. data/wety.strings for
+/// data/wety.json.gz and "strings". This is where the data's strings are
+/// written if they are to be memory-mapped.
+#[must_use]
+pub fn sibling_path(data_path: &Path, extension: &str) -> PathBuf {
+    let mut path = data_path.to_path_buf();
+    while path
+        .extension()
+        .is_some_and(|ext| ext == "gz" || ext == "zst" || ext == "json")
+    {
+        path.set_extension("");
+    }
+    path.set_extension(extension);
+    path
+}
+
 // private methods for use within pub methods below
 impl Data {
     fn item(&self, id: ItemId) -> &Item {
         self.graph.item(id)
     }
 
-    fn term(&self, item: ItemId) -> &str {
-        self.item(item).term().resolve(&self.string_pool)
-    }
-
     fn ety_num(&self, item: ItemId) -> u8 {
         self.item(item).ety_num()
     }
@@ -82,14 +359,28 @@ impl Data {
         self.item(item).lang()
     }
 
+    /// Whether there is an item with this id, for validating ids from clients.
+    #[must_use]
+    pub fn contains(&self, item: ItemId) -> bool {
+        self.graph.contains(item)
+    }
+
     #[must_use]
     pub fn ancestors_in_langs(&self, item: ItemId, langs: &[Lang]) -> Vec<ItemId> {
         self.graph.ancestors_in_langs(item, langs).collect()
     }
 
+    /// If the data was serialized with its strings in a separate file (e.g.
+    /// data/wety.strings for data/wety.json.gz), they are memory-mapped from
+    /// it, and only paged into memory as they are used. Data compressed with
+    /// zstd is decompressed with the dictionary next to it (e.g.
+    /// data/wety.zdict for data/wety.json.zst), if it was compressed with one.
+    ///
     /// # Errors
     ///
-    /// Will return `Err` if any unexpected issue arises in the deserialization.
+    /// Will return `Err` if any unexpected issue arises in the deserialization,
+    /// or if the data's strings are in a separate file that is missing or
+    /// malformed, or the data needs a zstd dictionary that is missing.
     pub fn deserialize(path: &Path) -> Result<Self> {
         let t = Instant::now();
         println!("Deserializing processed data {}...", path.display());
@@ -98,15 +389,60 @@ impl Data {
         let is_gz_compressed = path.extension().is_some_and(|ext| ext == "gz");
         let uncompressed: Box<dyn Read> = if is_gz_compressed {
             Box::new(GzDecoder::new(reader))
+        } else if is_zstd(path) {
+            let dictionary_path = sibling_path(path, "zdict");
+            if dictionary_path.exists() {
+                let dictionary = fs::read(&dictionary_path)?;
+                Box::new(zstd::Decoder::with_dictionary(reader, &dictionary)?)
+            } else {
+                Box::new(zstd::Decoder::with_buffer(reader)?)
+            }
         } else {
             Box::new(reader)
         };
-        let data = serde_json::from_reader(uncompressed)?;
+        let mut reader = HashingReader {
+            inner: uncompressed,
+            hasher: Xxh3::new(),
+        };
+        let mut data: Self = serde_json::from_reader(&mut reader)?;
+        if data.string_pool.is_empty() && data.graph.len() > 0 {
+            let strings_path = sibling_path(path, "strings");
+            println!("Mapping strings from {}...", strings_path.display());
+            data.string_pool = StringPool::map(&strings_path)
+                .with_context(|| format!("mapping strings from {}", strings_path.display()))?;
+            if let Some(bytes) = data.string_pool.mapped_bytes() {
+                reader.hasher.update(bytes);
+            }
+        }
+        if data.depths.is_empty() {
+            data.depths = data.graph.all_depths();
+        }
+        if data.path_index.is_empty() {
+            data.path_index = PathIndex::new(&data.graph);
+        }
+        data.cognate_sets = cognate_sets(&data.progenitors);
+        data.stable_id_index();
+        data.content_hash = reader.hasher.digest();
         println!("Finished. Took {:#?}.", t.elapsed());
         Ok(data)
     }
 
-    fn item_json(&self, item_id: ItemId) -> Value {
+    /// A hash of the serialized data that this was deserialized from, for use
+    /// as a cache validator. The same for compressed and uncompressed data.
+    #[must_use]
+    pub fn content_hash(&self) -> u64 {
+        self.content_hash
+    }
+
+    /// The number of ety links in the longest chain from the item up to one
+    /// of its progenitors, 0 if it has no parents.
+    #[must_use]
+    pub fn depth(&self, item: ItemId) -> u16 {
+        self.depths.get(&item).copied().unwrap_or(0)
+    }
+
+    #[must_use]
+    pub fn item_json(&self, item_id: ItemId) -> Value {
         let item = self.item(item_id);
         json!({
             "id": item_id,
@@ -116,44 +452,224 @@ impl Data {
             "imputed": item.is_imputed(),
             "reconstructed": item.is_reconstructed(),
             "url": item.url(&self.string_pool),
+            "pageTitle": item.page_title(&self.string_pool),
+            "pageId": item.page_id(),
             "pos": item.pos().as_ref().map(|pos| pos.iter().map(|p| p.name()).collect_vec()),
             "gloss": item.gloss().as_ref().map(|gloss| gloss.iter().map(|g| g.to_string(&self.string_pool)).collect_vec()),
             "romanization": item.romanization().map(|r| r.resolve(&self.string_pool)),
+            "ipa": item.ipa().as_ref().map(|ipa| ipa.iter().map(|t| t.resolve(&self.string_pool)).collect_vec()),
+            "etyText": item.ety_text().map(|t| t.to_string(&self.string_pool)),
+            "etyDepth": self.depth(item_id),
+            "cognateSet": self.cognate_set_id(item_id),
+            "stableId": self.stable_id(item_id),
+            "surfaceAnalysis": self.surface_analysis_json(item_id),
         })
     }
 
+    // The surface analysis of the item, e.g. "equivalent to X + Y", which is
+    // kept apart from its ety. Its items are only given by their ids, langs
+    // and terms, since their own items can be requested if wanted.
+    fn surface_analysis_json(&self, item_id: ItemId) -> Option<Value> {
+        let analysis = self.graph.surface_analysis(item_id)?;
+        Some(json!({
+            "etyMode": analysis.mode.as_str(),
+            "confidence": analysis.confidence(),
+            "head": analysis.head,
+            "provenance": analysis.provenance.map(|p| p.json(&self.string_pool)),
+            "items": analysis
+                .items
+                .iter()
+                .map(|&item| {
+                    json!({
+                        "id": item,
+                        "lang": self.item(item).lang().code(),
+                        "term": self.item(item).term().resolve(&self.string_pool),
+                    })
+                })
+                .collect_vec(),
+        }))
+    }
+
+    // The head progenitor of the cognate set the item is in, which may be
+    // the item itself.
+    fn cognate_set_head(&self, item: ItemId) -> Option<ItemId> {
+        if self.cognate_sets.contains_key(&item) {
+            return Some(item);
+        }
+        self.progenitors.get(&item)?.head
+    }
+
+    /// An id for the cognate set of the item, i.e. of the items that share
+    /// its head progenitor: the stable id of the head progenitor. Unlike item
+    /// ids, it stays the same across processing runs, as long as the head
+    /// progenitor's stable id does.
+    #[must_use]
+    pub fn cognate_set_id(&self, item: ItemId) -> Option<String> {
+        Some(self.stable_id(self.cognate_set_head(item)?))
+    }
+
+    pub(crate) fn stable_id_hash(&self, item: ItemId) -> u64 {
+        self.item(item).stable_id_hash(&self.string_pool)
+    }
+
+    /// An id for the item that, unlike its `ItemId`, stays the same across
+    /// processing runs, as long as its lang, term, ety num and parts of
+    /// speech do: a hash of these. Items that share all of these, e.g.
+    /// imputed items in different etymologies, are told apart by a suffix,
+    /// e.g. "-2", numbered in order of the page they are from.
+    #[must_use]
+    pub fn stable_id(&self, item: ItemId) -> String {
+        self.stable_id_index().id(item, self.stable_id_hash(item))
+    }
+
+    /// The item with the stable id `id` (see [`Data::stable_id`]), if any.
+    #[must_use]
+    pub fn item_by_stable_id(&self, id: &str) -> Option<ItemId> {
+        self.stable_id_index().get(id)
+    }
+
+    // Hashing every item takes a while, so loaded data builds this up front
+    // rather than on the first request for a stable id.
+    pub(crate) fn stable_id_index(&self) -> &StableIds {
+        self.stable_id_index
+            .get_or_init(|| StableIds::new(&self.graph, &self.string_pool))
+    }
+
+    /// The items in the cognate set of the item, other than its head
+    /// progenitor, including the item itself unless it is the head.
+    #[must_use]
+    pub fn cognate_set(&self, item: ItemId) -> &[ItemId] {
+        self.cognate_set_head(item)
+            .and_then(|head| self.cognate_sets.get(&head))
+            .map_or(&[], |items| items)
+    }
+
+    /// The cognate set of the item, with its id, head progenitor and items.
+    /// Returns `None` if the item is in none.
     #[must_use]
+    pub fn cognate_set_json(&self, item: ItemId) -> Option<Value> {
+        if !self.contains(item) {
+            return None;
+        }
+        let head = self.cognate_set_head(item)?;
+        Some(json!({
+            "id": self.cognate_set_id(item),
+            "head": self.item_json(head),
+            "items": self
+                .cognate_set(item)
+                .iter()
+                .map(|&item| self.item_json(item))
+                .collect_vec(),
+        }))
+    }
+
+    /// # Errors
+    ///
+    /// Will return `Err` if building the tree runs past `limits.timeout`.
     pub fn item_descendants_json(
         &self,
         item_id: ItemId,
         dist_lang: Lang,
         desc_langs: &[Lang],
         req_item_ancestors_within_desc_langs: &[ItemId],
-    ) -> Value {
+        limits: &TreeLimits,
+    ) -> Result<Value, QueryTimeout> {
         self.item_descendants_json_inner(
             item_id,
             dist_lang,
             desc_langs,
             req_item_ancestors_within_desc_langs,
             None,
-            None,
+            0,
+            limits,
         )
     }
 
-    fn item_descendants_json_inner(
+    /// Like `item_descendants_json`, but for the subtree rooted at a node that
+    /// was truncated in a previous response.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if building the tree runs past `limits.timeout`.
+    pub fn item_descendants_continuation_json(
+        &self,
+        continuation: Continuation,
+        dist_lang: Lang,
+        desc_langs: &[Lang],
+        req_item_ancestors_within_desc_langs: &[ItemId],
+        limits: &TreeLimits,
+    ) -> Result<Value, QueryTimeout> {
+        self.item_descendants_json_inner(
+            continuation.item,
+            dist_lang,
+            desc_langs,
+            req_item_ancestors_within_desc_langs,
+            self.continuation_parent_edge(continuation),
+            0,
+            limits,
+        )
+    }
+
+    /// Like `item_descendants_json` (or `item_descendants_continuation_json`,
+    /// if `continuation` is given), but rather than building the whole tree,
+    /// passes each node to `emit` as soon as it is built, parents before
+    /// children. Instead of "children", each node has "node", its index in the
+    /// order of emission, and "parent", the index of its parent node (null for
+    /// the root). Stops early if `emit` returns false.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if building the tree runs past `limits.timeout`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn item_descendants_nodes(
         &self,
         item_id: ItemId,
         dist_lang: Lang,
         desc_langs: &[Lang],
         req_item_ancestors_within_desc_langs: &[ItemId],
-        item_parent_id: Option<ItemId>,
-        item_parent_ety_order: Option<u8>,
-    ) -> Value {
-        let item = self.item(item_id);
-        let item_lang = item.lang();
+        continuation: Option<Continuation>,
+        limits: &TreeLimits,
+        emit: &mut dyn FnMut(Value) -> bool,
+    ) -> Result<(), QueryTimeout> {
+        let (item_id, parent_edge) = match continuation {
+            Some(continuation) => (
+                continuation.item,
+                self.continuation_parent_edge(continuation),
+            ),
+            None => (item_id, None),
+        };
+        self.item_descendants_nodes_inner(
+            item_id,
+            dist_lang,
+            desc_langs,
+            req_item_ancestors_within_desc_langs,
+            parent_edge,
+            None,
+            &mut 0,
+            0,
+            limits,
+            emit,
+        )?;
+        Result::Ok(())
+    }
 
-        let children = self
-            .graph
+    fn continuation_parent_edge(&self, continuation: Continuation) -> Option<EtyEdge<'_>> {
+        continuation.parent.and_then(|parent| {
+            self.graph
+                .parent_edges(continuation.item)
+                .find(|e| e.parent() == parent)
+        })
+    }
+
+    // The edges to the children of item_id to include in a descendants tree.
+    fn descendants_child_edges(
+        &self,
+        item_id: ItemId,
+        desc_langs: &[Lang],
+        req_item_ancestors_within_desc_langs: &[ItemId],
+    ) -> Vec<EtyEdge<'_>> {
+        let item_lang = self.item(item_id).lang();
+        self.graph
             .child_edges(item_id)
             .filter(|e| {
                 let child = e.child();
@@ -184,17 +700,27 @@ impl Data {
                             .any(|dl| dl != &item_lang && cdl.contains(dl))
                     })
             })
-            .map(|e| {
-                self.item_descendants_json_inner(
-                    e.child(),
-                    dist_lang,
-                    desc_langs,
-                    req_item_ancestors_within_desc_langs,
-                    Some(item_id),
-                    Some(e.order()),
-                )
-            })
-            .collect_vec();
+            .collect_vec()
+    }
+
+    // A node of a descendants tree, without its children. If truncated, the
+    // node's children were not expanded because we ran out of time, so we leave
+    // a continuation for the client to request them separately.
+    fn descendants_node_json(
+        &self,
+        item_id: ItemId,
+        dist_lang: Lang,
+        parent_edge: Option<EtyEdge<'_>>,
+        truncated: bool,
+    ) -> Value {
+        let item_parent_id = parent_edge.map(|e| e.parent());
+        let continuation = truncated.then(|| {
+            Continuation {
+                item: item_id,
+                parent: item_parent_id,
+            }
+            .to_string()
+        });
 
         let mut ety_mode = None;
         let other_parents = self
@@ -208,6 +734,7 @@ impl Data {
                 json!({
                     "item": self.item_json(e.parent()),
                     "etyOrder": e.order(),
+                    "etyConfidence": e.confidence(),
                     "langDistance": self.item(e.parent()).lang().distance_from(dist_lang),
                 })
             })
@@ -215,69 +742,463 @@ impl Data {
 
         json!({
             "item": self.item_json(item_id),
-            "children": children,
-            "langDistance": item_lang.distance_from(dist_lang),
+            "langDistance": self.item(item_id).lang().distance_from(dist_lang),
             "etyMode": ety_mode.map(|m| m.as_str()),
             "otherParents": other_parents,
-            "parentEtyOrder": item_parent_ety_order,
+            "parentEtyOrder": parent_edge.map(|e| e.order()),
+            "parentEtyConfidence": parent_edge.map(|e| e.confidence()),
+            "truncated": truncated,
+            "continuation": continuation,
         })
     }
 
-    #[must_use]
+    #[allow(clippy::too_many_arguments)]
+    fn item_descendants_json_inner(
+        &self,
+        item_id: ItemId,
+        dist_lang: Lang,
+        desc_langs: &[Lang],
+        req_item_ancestors_within_desc_langs: &[ItemId],
+        parent_edge: Option<EtyEdge<'_>>,
+        depth: usize,
+        limits: &TreeLimits,
+    ) -> Result<Value, QueryTimeout> {
+        limits.check_timeout()?;
+        limits.add_node();
+        let child_edges =
+            self.descendants_child_edges(item_id, desc_langs, req_item_ancestors_within_desc_langs);
+        let truncated = !child_edges.is_empty() && !limits.can_expand(depth);
+        let children = if truncated {
+            vec![]
+        } else {
+            child_edges
+                .iter()
+                .map(|&e| {
+                    self.item_descendants_json_inner(
+                        e.child(),
+                        dist_lang,
+                        desc_langs,
+                        req_item_ancestors_within_desc_langs,
+                        Some(e),
+                        depth + 1,
+                        limits,
+                    )
+                })
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        let mut node = self.descendants_node_json(item_id, dist_lang, parent_edge, truncated);
+        node["children"] = json!(children);
+        Result::Ok(node)
+    }
+
+    // Returns whether to keep going, i.e. false if emit asked to stop.
+    #[allow(clippy::too_many_arguments)]
+    fn item_descendants_nodes_inner(
+        &self,
+        item_id: ItemId,
+        dist_lang: Lang,
+        desc_langs: &[Lang],
+        req_item_ancestors_within_desc_langs: &[ItemId],
+        parent_edge: Option<EtyEdge<'_>>,
+        parent_node: Option<usize>,
+        next_node: &mut usize,
+        depth: usize,
+        limits: &TreeLimits,
+        emit: &mut dyn FnMut(Value) -> bool,
+    ) -> Result<bool, QueryTimeout> {
+        limits.check_timeout()?;
+        limits.add_node();
+        let child_edges =
+            self.descendants_child_edges(item_id, desc_langs, req_item_ancestors_within_desc_langs);
+        let truncated = !child_edges.is_empty() && !limits.can_expand(depth);
+        let node = *next_node;
+        *next_node += 1;
+        let mut node_json = self.descendants_node_json(item_id, dist_lang, parent_edge, truncated);
+        node_json["node"] = json!(node);
+        node_json["parent"] = json!(parent_node);
+        if !emit(node_json) {
+            return Result::Ok(false);
+        }
+        if truncated {
+            return Result::Ok(true);
+        }
+        for e in child_edges {
+            let keep_going = self.item_descendants_nodes_inner(
+                e.child(),
+                dist_lang,
+                desc_langs,
+                req_item_ancestors_within_desc_langs,
+                Some(e),
+                Some(node),
+                next_node,
+                depth + 1,
+                limits,
+                emit,
+            )?;
+            if !keep_going {
+                return Result::Ok(false);
+            }
+        }
+        Result::Ok(true)
+    }
+
+    /// # Errors
+    ///
+    /// Will return `Err` if building the trees runs past `limits.timeout`.
     pub fn item_cognates_json(
         &self,
         item_id: ItemId,
         dist_lang: Lang,
         desc_langs: &[Lang],
         req_item_ancestors_within_desc_langs: &[ItemId],
-    ) -> Value {
-        self.progenitors.get(&item_id).map_or_else(
-            || json!([]),
-            |progenitors| {
-                json!(progenitors
-                    .items
-                    .iter()
-                    .map(|&p| {
-                        self.item_descendants_json(
-                            p,
-                            dist_lang,
-                            desc_langs,
-                            req_item_ancestors_within_desc_langs,
-                        )
-                    })
-                    .collect_vec())
-            },
-        )
+        limits: &TreeLimits,
+    ) -> Result<Value, QueryTimeout> {
+        let Some(progenitors) = self.progenitors.get(&item_id) else {
+            return Result::Ok(json!([]));
+        };
+        let trees = progenitors
+            .items
+            .iter()
+            .map(|&p| {
+                self.item_descendants_json(
+                    p,
+                    dist_lang,
+                    desc_langs,
+                    req_item_ancestors_within_desc_langs,
+                    limits,
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Result::Ok(json!(trees))
     }
 
-    #[must_use]
+    /// # Errors
+    ///
+    /// Will return `Err` if building the tree runs past `limits.timeout`.
     pub fn item_etymology_json(
         &self,
         item_id: ItemId,
         item_ety_order: u8,
         req_lang: Lang,
-    ) -> Value {
-        let mut ety_mode = None;
-        let parents = self
-            .graph
-            .parent_edges(item_id)
-            .map(|e| {
-                ety_mode = Some(e.mode());
-                self.item_etymology_json(e.parent(), e.order(), req_lang)
-            })
-            .collect_vec();
+        limits: &TreeLimits,
+    ) -> Result<Value, QueryTimeout> {
+        self.item_etymology_json_inner(item_id, item_ety_order, None, req_lang, 0, limits)
+    }
 
-        json!({
+    fn item_etymology_json_inner(
+        &self,
+        item_id: ItemId,
+        item_ety_order: u8,
+        item_edge: Option<EtyEdge<'_>>,
+        req_lang: Lang,
+        depth: usize,
+        limits: &TreeLimits,
+    ) -> Result<Value, QueryTimeout> {
+        limits.check_timeout()?;
+        limits.add_node();
+        let parent_edges = self.graph.parent_edges(item_id).collect_vec();
+        let ety_mode = parent_edges.last().map(|e| e.mode());
+        // If truncated, the client can get the rest by requesting the etymology
+        // of this node's item.
+        let truncated = !parent_edges.is_empty() && !limits.can_expand(depth);
+        let parents = if truncated {
+            vec![]
+        } else {
+            parent_edges
+                .iter()
+                .map(|e| {
+                    self.item_etymology_json_inner(
+                        e.parent(),
+                        e.order(),
+                        Some(*e),
+                        req_lang,
+                        depth + 1,
+                        limits,
+                    )
+                })
+                .collect::<Result<Vec<_>, _>>()?
+        };
+
+        Result::Ok(json!({
             "item": self.item_json(item_id),
             "etyMode": ety_mode.map(|m| m.as_str()),
             "etyOrder": item_ety_order,
+            "etyConfidence": item_edge.map(|e| e.confidence()),
+            "etyProvenance": item_edge
+                .and_then(|e| e.provenance())
+                .map(|p| p.json(&self.string_pool)),
             "parents": parents,
+            "alternatives": self.alternative_etys_json(item_id),
             "langDistance": self.item(item_id).lang().distance_from(req_lang),
+            "truncated": truncated,
+        }))
+    }
+
+    // The alternatives to the item's parents, without their own etymologies,
+    // which the client can request if wanted.
+    fn alternative_etys_json(&self, item_id: ItemId) -> Value {
+        self.graph
+            .alternative_etys(item_id)
+            .iter()
+            .enumerate()
+            .map(|(i, alternative)| {
+                json!({
+                    "index": i + 1,
+                    "etyMode": alternative.mode.as_str(),
+                    "confidence": alternative.confidence(),
+                    "head": alternative.head,
+                    "provenance": alternative.provenance.map(|p| p.json(&self.string_pool)),
+                    "items": alternative
+                        .items
+                        .iter()
+                        .map(|&item| self.item_json(item))
+                        .collect_vec(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// A link from an item to one of its parents or children, for clients that
+/// traverse the graph themselves rather than getting whole trees.
+#[derive(Clone, Copy)]
+pub struct EtyLink {
+    /// The parent or child at the other end of the link.
+    pub item: ItemId,
+    pub mode: &'static str,
+    pub order: u8,
+    pub head: bool,
+    pub confidence: f32,
+}
+
+impl Data {
+    fn ety_link(item: ItemId, edge: EtyEdge<'_>) -> EtyLink {
+        EtyLink {
+            item,
+            mode: edge.mode().as_str(),
+            order: edge.order(),
+            head: edge.head(),
+            confidence: edge.confidence(),
+        }
+    }
+
+    /// The links to the item's parents, in ety order.
+    #[must_use]
+    pub fn parent_links(&self, item: ItemId) -> Vec<EtyLink> {
+        self.graph
+            .parent_edges(item)
+            .map(|e| Self::ety_link(e.parent(), e))
+            .sorted_unstable_by_key(|link| link.order)
+            .collect()
+    }
+
+    /// The links to the item's children.
+    #[must_use]
+    pub fn child_links(&self, item: ItemId) -> Vec<EtyLink> {
+        self.graph
+            .child_edges(item)
+            .map(|e| Self::ety_link(e.child(), e))
+            .collect()
+    }
+}
+
+// Typed queries, for using the data as a library rather than through json.
+impl Data {
+    /// The item's term, as given in etymologies.
+    #[must_use]
+    pub fn term(&self, item: ItemId) -> &str {
+        self.item(item).term().resolve(&self.string_pool)
+    }
+
+    /// The item's glosses. Imputed items have none.
+    pub fn gloss(&self, item: ItemId) -> impl Iterator<Item = String> + '_ {
+        self.item(item)
+            .gloss()
+            .into_iter()
+            .flatten()
+            .map(|gloss| gloss.to_string(&self.string_pool))
+    }
+
+    /// The item's ancestors, breadth-first, each once.
+    pub fn ancestors(&self, item: ItemId) -> impl Iterator<Item = ItemId> + '_ {
+        self.graph.ancestor_edges(item).map(|e| e.parent()).unique()
+    }
+
+    /// The item's descendants, breadth-first, each once.
+    pub fn descendants(&self, item: ItemId) -> impl Iterator<Item = ItemId> + '_ {
+        self.graph
+            .descendant_edges(item)
+            .map(|e| e.child())
+            .unique()
+    }
+
+    /// The other items in the item's cognate set (see [`Data::cognate_set`]),
+    /// including its head progenitor.
+    pub fn cognates(&self, item: ItemId) -> impl Iterator<Item = ItemId> + '_ {
+        let head = self.cognate_set_head(item).filter(|&head| head != item);
+        head.into_iter().chain(
+            self.cognate_set(item)
+                .iter()
+                .copied()
+                .filter(move |&other| other != item),
+        )
+    }
+
+    /// The items in `lang` whose term is exactly `term`, in order of id. The
+    /// index this uses is built on the first call.
+    pub fn lookup<'a>(&'a self, lang: Lang, term: &'a str) -> impl Iterator<Item = ItemId> + 'a {
+        let index = self.term_index.get_or_init(|| {
+            let mut index = HashMap::<(Lang, u64), Vec<ItemId>>::default();
+            for (id, item) in self.graph.iter() {
+                let hash = xxh3_64(item.term().resolve(&self.string_pool).as_bytes());
+                index.entry((item.lang(), hash)).or_default().push(id);
+            }
+            index
+        });
+        index
+            .get(&(lang, xxh3_64(term.as_bytes())))
+            .into_iter()
+            .flatten()
+            .copied()
+            .filter(move |&item| self.term(item) == term)
+    }
+}
+
+impl Data {
+    // A step in a connection chain, describing the link from this item to the
+    // next one in the chain, if any.
+    fn connection_step_json(&self, item: ItemId, link: Option<(&str, EtyEdge<'_>)>) -> Value {
+        json!({
+            "item": self.item_json(item),
+            "link": link.map(|(direction, e)| json!({
+                "direction": direction,
+                "etyMode": e.mode().as_str(),
+                "etyOrder": e.order(),
+                "etyConfidence": e.confidence(),
+            })),
         })
     }
+
+    /// The shortest chain connecting `item_a` and `item_b` via a shared
+    /// ancestor, going from `item_a` up to the ancestor and then down to
+    /// `item_b`. Each step gives the link to the next item in the chain, which
+    /// is the item's parent ("parent") or child ("child"). Returns `None` if
+    /// the items share no ancestor, among the ancestors found before
+    /// `limits.max_nodes` or `limits.max_depth` links up from either item.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if finding the connection runs past `limits.timeout`.
+    pub fn connection_json(
+        &self,
+        item_a: ItemId,
+        item_b: ItemId,
+        limits: &TreeLimits,
+    ) -> Result<Option<Value>, QueryTimeout> {
+        let Some((ancestor, path_a, path_b)) = self.graph.connection(item_a, item_b, limits)?
+        else {
+            return Result::Ok(None);
+        };
+        let up = path_a
+            .iter()
+            .map(|&e| self.connection_step_json(e.child(), Some(("parent", e))));
+        let down = path_b
+            .iter()
+            .rev()
+            .map(|&e| self.connection_step_json(e.parent(), Some(("child", e))));
+        let chain = up
+            .chain(down)
+            .chain([self.connection_step_json(item_b, None)])
+            .collect_vec();
+        Result::Ok(Some(json!({
+            "ancestor": ancestor,
+            "distance": path_a.len() + path_b.len(),
+            "chain": chain,
+        })))
+    }
+
+    /// The items within `radius` links of the item, following links to
+    /// parents and children alike, and all the links among them, as "nodes"
+    /// and "links", for clients that lay out the graph themselves. Each node
+    /// has its "distance" in links from the item, and each link goes from the
+    /// parent ("source") to the child ("target"). Once there are
+    /// `limits.max_nodes` nodes, no more are added, and "truncated" is true.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if finding the neighborhood runs past
+    /// `limits.timeout`.
+    pub fn neighborhood_json(
+        &self,
+        item_id: ItemId,
+        radius: usize,
+        limits: &TreeLimits,
+    ) -> Result<Value, QueryTimeout> {
+        let mut distances = HashMap::<ItemId, usize>::default();
+        distances.insert(item_id, 0);
+        limits.add_node();
+        let mut nodes = vec![item_id];
+        let mut frontier = vec![item_id];
+        let mut truncated = false;
+        'distances: for distance in 1..=radius {
+            let mut next = vec![];
+            for &item in &frontier {
+                limits.check_timeout()?;
+                let neighbors = self
+                    .graph
+                    .parent_edges(item)
+                    .map(|e| e.parent())
+                    .chain(self.graph.child_edges(item).map(|e| e.child()));
+                for neighbor in neighbors {
+                    if distances.contains_key(&neighbor) {
+                        continue;
+                    }
+                    if limits.max_nodes.is_some_and(|max| limits.nodes() >= max) {
+                        truncated = true;
+                        break 'distances;
+                    }
+                    limits.add_node();
+                    distances.insert(neighbor, distance);
+                    nodes.push(neighbor);
+                    next.push(neighbor);
+                }
+            }
+            frontier = next;
+        }
+        let links = nodes
+            .iter()
+            .flat_map(|&item| self.graph.parent_edges(item))
+            .filter(|e| distances.contains_key(&e.parent()))
+            .map(|e| {
+                json!({
+                    "source": e.parent(),
+                    "target": e.child(),
+                    "etyMode": e.mode().as_str(),
+                    "etyOrder": e.order(),
+                    "head": e.head(),
+                    "etyConfidence": e.confidence(),
+                })
+            })
+            .collect_vec();
+        let nodes = nodes
+            .iter()
+            .map(|&item| {
+                json!({
+                    "id": item,
+                    "distance": distances[&item],
+                    "item": self.item_json(item),
+                })
+            })
+            .collect_vec();
+        Result::Ok(json!({
+            "nodes": nodes,
+            "links": links,
+            "truncated": truncated,
+        }))
+    }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone, Copy)]
 struct LangData {
     lang: Lang,
     items: usize,
@@ -286,7 +1207,166 @@ struct LangData {
 pub struct Search {
     normalized_langs: HashMap<String, LangData>,
     langs: Corpus,
-    terms: HashMap<Lang, FuzzyTrie<ItemId>>,
+    terms: Tries,
+    ipa: Tries,
+    // the items, in id order, with a gloss containing each word
+    gloss_words: HashMap<String, Vec<ItemId>>,
+    // the items of each lang, sorted by the search keys of their terms, so
+    // that those with a prefix are a contiguous run
+    completions: HashMap<Lang, Vec<ItemId>>,
+    // corpus frequencies of items' terms, if loaded
+    frequencies: HashMap<ItemId, u64>,
+}
+
+// Words too common in glosses to be worth indexing or searching for.
+const GLOSS_STOPWORDS: &[&str] = &[
+    "a", "an", "and", "as", "at", "by", "for", "from", "in", "of", "on", "or", "the", "to", "with",
+];
+
+const MAX_GLOSS_MATCHES: usize = 100;
+
+const MAX_COMPLETIONS: usize = 50;
+
+// The indexed words of a gloss or gloss query, folded as term search keys are.
+fn gloss_words(gloss: &str) -> Vec<String> {
+    search_key(gloss)
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty() && !GLOSS_STOPWORDS.contains(word))
+        .map(str::to_string)
+        .collect()
+}
+
+// Builds the trie of a lang's items, given in graph order, under some keys.
+type BuildTrie = fn(&Data, &[ItemId]) -> FuzzyTrie<ItemId>;
+
+// The tries of each lang, either all built up front, or each built when its
+// lang is first searched. There are only ever a couple of these, so the size
+// difference between the variants doesn't matter.
+#[allow(clippy::large_enum_variant)]
+enum Tries {
+    Eager(HashMap<Lang, Arc<FuzzyTrie<ItemId>>>),
+    Lazy(LazyTries),
+}
+
+impl Tries {
+    fn new(
+        data: &Data,
+        lang_items: &Arc<HashMap<Lang, Vec<ItemId>>>,
+        resident_langs: Option<usize>,
+        build: BuildTrie,
+    ) -> Self {
+        match resident_langs {
+            None => Tries::Eager(
+                lang_items
+                    .iter()
+                    .map(|(&lang, items)| (lang, Arc::new(build(data, items))))
+                    .collect(),
+            ),
+            Some(resident) => Tries::Lazy(LazyTries {
+                items: Arc::clone(lang_items),
+                build,
+                resident,
+                cache: Mutex::default(),
+            }),
+        }
+    }
+
+    fn get(&self, data: &Data, lang: Lang) -> Option<Arc<FuzzyTrie<ItemId>>> {
+        match self {
+            Tries::Eager(tries) => tries.get(&lang).cloned(),
+            Tries::Lazy(tries) => tries.get(data, lang),
+        }
+    }
+
+    // How many of the tries are built.
+    fn built(&self) -> usize {
+        match self {
+            Tries::Eager(tries) => tries.len(),
+            Tries::Lazy(tries) => tries
+                .cache
+                .lock()
+                .expect("trie cache lock not poisoned")
+                .tries
+                .len(),
+        }
+    }
+}
+
+struct LazyTries {
+    // the items to build each lang's trie from
+    items: Arc<HashMap<Lang, Vec<ItemId>>>,
+    build: BuildTrie,
+    // how many tries to keep built at once
+    resident: usize,
+    cache: Mutex<TrieCache>,
+}
+
+type BuildingTrie = Arc<OnceLock<Arc<FuzzyTrie<ItemId>>>>;
+
+#[derive(Default)]
+struct TrieCache {
+    tries: HashMap<Lang, Arc<FuzzyTrie<ItemId>>>,
+    searches: HashMap<Lang, usize>,
+    // tries being built, which searches in their lang wait on rather than
+    // building them again
+    building: HashMap<Lang, BuildingTrie>,
+}
+
+impl LazyTries {
+    // Get the lang's trie, building it if it isn't resident. It is kept
+    // resident if there is room, or if its lang has been searched more often
+    // than that of some resident trie, which is then dropped.
+    fn get(&self, data: &Data, lang: Lang) -> Option<Arc<FuzzyTrie<ItemId>>> {
+        let items = self.items.get(&lang)?;
+        let (searches, building) = {
+            let mut cache = self.cache.lock().expect("trie cache lock not poisoned");
+            let searches = cache.searches.entry(lang).or_default();
+            *searches += 1;
+            let searches = *searches;
+            if let Some(trie) = cache.tries.get(&lang) {
+                return Some(Arc::clone(trie));
+            }
+            (
+                searches,
+                Arc::clone(cache.building.entry(lang).or_default()),
+            )
+        };
+        // Build without holding the lock, so that searches in resident langs
+        // aren't held up. Concurrent searches in the lang wait for this build.
+        let trie = Arc::clone(building.get_or_init(|| Arc::new((self.build)(data, items))));
+        let mut cache = self.cache.lock().expect("trie cache lock not poisoned");
+        let cache = &mut *cache;
+        if cache
+            .building
+            .get(&lang)
+            .is_some_and(|b| Arc::ptr_eq(b, &building))
+        {
+            cache.building.remove(&lang);
+        }
+        if cache.tries.contains_key(&lang) {
+            return Some(trie);
+        }
+        if cache.tries.len() >= self.resident {
+            let least_searched = cache
+                .tries
+                .keys()
+                .map(|&resident| {
+                    (
+                        resident,
+                        cache.searches.get(&resident).copied().unwrap_or(0),
+                    )
+                })
+                .min_by_key(|&(_, searches)| searches);
+            match least_searched {
+                Some((resident, resident_searches)) if resident_searches < searches => {
+                    cache.tries.remove(&resident);
+                }
+                _ => return Some(trie),
+            }
+        }
+        cache.tries.insert(lang, Arc::clone(&trie));
+        Some(trie)
+    }
 }
 
 fn normalize_lang_name(name: &str) -> String {
@@ -299,9 +1379,33 @@ fn normalize_lang_name(name: &str) -> String {
         .collect()
 }
 
+// Insert the key, and if it has diacritics, its folded form, so that the item
+// can be found by users who don't type them.
+fn insert_search_keys(terms: &mut FuzzyTrie<ItemId>, key: String, item_id: ItemId) {
+    let folded = search_key(&key);
+    terms.insert(&key).insert(item_id);
+    if folded != key {
+        terms.insert(&folded).insert(item_id);
+    }
+}
+
 impl Data {
+    /// Build the lang search and the term tries of every lang.
     #[must_use]
     pub fn build_search(&self) -> Search {
+        self.build_search_with(None)
+    }
+
+    /// Build the lang search, leaving each lang's term trie to be built when
+    /// the lang is first searched. This makes startup much faster and lighter.
+    /// At most `resident_langs` tries are kept built, favoring the langs that
+    /// are searched most.
+    #[must_use]
+    pub fn build_lazy_search(&self, resident_langs: usize) -> Search {
+        self.build_search_with(Some(resident_langs))
+    }
+
+    fn build_search_with(&self, resident_langs: Option<usize>) -> Search {
         let t = Instant::now();
         println!("Building search tries...");
         let mut normalized_langs = HashMap::<String, LangData>::default();
@@ -310,17 +1414,17 @@ impl Data {
             .pad_full(Pad::Auto)
             .key_trans(Box::new(normalize_lang_name))
             .finish();
-        let mut terms = HashMap::<Lang, FuzzyTrie<ItemId>>::default();
+        let mut lang_items = HashMap::<Lang, Vec<ItemId>>::default();
+        let mut gloss_index = HashMap::<String, Vec<ItemId>>::default();
         for (item_id, item) in self.graph.iter().filter(|(_, item)| !item.is_imputed()) {
             let norm_lang = normalize_lang_name(item.lang().name());
-            let term = item.term().resolve(&self.string_pool);
-            match terms.entry(item.lang()) {
-                Entry::Occupied(mut t) => {
-                    t.get_mut().insert(&term.to_lowercase()).insert(item_id);
-                }
-                Entry::Vacant(e) => {
-                    let t = e.insert(FuzzyTrie::new(0, false));
-                    t.insert(term).insert(item_id);
+            lang_items.entry(item.lang()).or_default().push(item_id);
+            for gloss in item.gloss().into_iter().flatten() {
+                for word in gloss_words(&gloss.to_string(&self.string_pool)) {
+                    let items = gloss_index.entry(word).or_default();
+                    if items.last() != Some(&item_id) {
+                        items.push(item_id);
+                    }
                 }
             }
             if let Some(lang_data) = normalized_langs.get_mut(&norm_lang) {
@@ -336,18 +1440,120 @@ impl Data {
                 langs.add_text(item.lang().name());
             }
         }
+        // Also let langs be found by their other common names.
+        for lang_data in normalized_langs.values().copied().collect_vec() {
+            for &alias in lang_data.lang.aliases() {
+                let norm_alias = normalize_lang_name(alias);
+                if let Entry::Vacant(e) = normalized_langs.entry(norm_alias) {
+                    e.insert(lang_data);
+                    langs.add_text(alias);
+                }
+            }
+        }
+        // Only item ids are stored, and the keys computed again when searching,
+        // which is cheap next to a copy of every term.
+        let completions = lang_items
+            .iter()
+            .map(|(&lang, items)| {
+                let mut items = items.clone();
+                items.sort_by_cached_key(|&item| search_key(self.term(item)));
+                (lang, items)
+            })
+            .collect();
+        let lang_items = Arc::new(lang_items);
+        let terms = Tries::new(self, &lang_items, resident_langs, Data::build_lang_terms);
+        let ipa = Tries::new(self, &lang_items, resident_langs, Data::build_lang_ipa);
         println!("Finished. Took {:#?}.", t.elapsed());
         Search {
             normalized_langs,
             langs,
             terms,
+            ipa,
+            gloss_words: gloss_index,
+            completions,
+            frequencies: HashMap::default(),
         }
     }
+
+    // The trie of the terms of a lang's items, given in graph order.
+    fn build_lang_terms(&self, items: &[ItemId]) -> FuzzyTrie<ItemId> {
+        let mut terms = FuzzyTrie::new(0, false);
+        for (i, &item_id) in items.iter().enumerate() {
+            let item = self.graph.item(item_id);
+            let term = item.term().resolve(&self.string_pool);
+            let key = if i == 0 {
+                term.to_string()
+            } else {
+                term.to_lowercase()
+            };
+            insert_search_keys(&mut terms, key, item_id);
+            // Also let the item be found by the titles of pages that redirect
+            // to it.
+            if let Some(aliases) = self.aliases.get(&item_id) {
+                for alias in aliases {
+                    let alias = alias.resolve(&self.string_pool).to_lowercase();
+                    insert_search_keys(&mut terms, alias, item_id);
+                }
+            }
+            // And by its romanization, for langs in other scripts, e.g.
+            // λόγος by "logos".
+            if let Some(romanization) = item.romanization() {
+                let romanization = romanization.resolve(&self.string_pool).to_lowercase();
+                insert_search_keys(&mut terms, romanization, item_id);
+            }
+        }
+        terms
+    }
+
+    // The trie of the search keys of the IPA transcriptions of a lang's items.
+    fn build_lang_ipa(&self, items: &[ItemId]) -> FuzzyTrie<ItemId> {
+        let mut ipa = FuzzyTrie::new(0, false);
+        for &item_id in items {
+            for transcription in self.graph.item(item_id).ipa().into_iter().flatten() {
+                let key = ipa_search_key(transcription.resolve(&self.string_pool));
+                if !key.is_empty() {
+                    ipa.insert(&key).insert(item_id);
+                }
+            }
+        }
+        ipa
+    }
 }
 
 impl Search {
+    /// The size of each of the search indexes, by name, for monitoring: the
+    /// langs searchable by name, the built term and IPA tries (one per lang),
+    /// the indexed gloss words, the items that can be completed, and the items
+    /// with a corpus frequency.
+    #[must_use]
+    pub fn index_sizes(&self) -> [(&'static str, usize); 6] {
+        [
+            ("langs", self.normalized_langs.len()),
+            ("termTries", self.terms.built()),
+            ("ipaTries", self.ipa.built()),
+            ("glossWords", self.gloss_words.len()),
+            ("completions", self.completions.values().map(Vec::len).sum()),
+            ("frequencies", self.frequencies.len()),
+        ]
+    }
+
+    // The lang with this code (e.g. "ang" or "la-vul"), if it has any items.
+    fn lang_by_code(&self, code: &str) -> Option<LangData> {
+        let code = code.trim();
+        let lang = Lang::from_str(code)
+            .or_else(|_| Lang::from_str(&code.to_lowercase()))
+            .ok()?;
+        self.normalized_langs
+            .get(&normalize_lang_name(lang.name()))
+            .filter(|lang_data| lang_data.lang == lang)
+            .copied()
+    }
+
+    /// Langs whose names or other common names are similar to `lang`, best
+    /// first, after the lang with the code `lang`, if any.
     #[must_use]
     pub fn langs(&self, lang: &str) -> Value {
+        let code_match = self.lang_by_code(lang);
         let mut matches = self
             .langs
             .search(lang, 0.4)
@@ -365,9 +1571,13 @@ impl Search {
                 b.0.total_cmp(&a.0)
             }
         });
-        let matches = matches
-            .iter()
-            .map(|(_, lang_data)| lang_data.lang.json())
+        // A lang may match by both its name and its aliases.
+        let matches = code_match
+            .into_iter()
+            .chain(matches.iter().map(|(_, lang_data)| **lang_data))
+            .map(|lang_data| lang_data.lang)
+            .unique()
+            .map(Lang::json)
             .collect_vec();
         json!(matches)
     }
@@ -378,12 +1588,6 @@ struct ItemMatch {
     item: ItemId,
 }
 
-impl ItemMatch {
-    fn json(&self, data: &Data) -> Value {
-        data.item_json(self.item)
-    }
-}
-
 #[derive(Default)]
 pub struct ItemMatches {
     matches: Vec<ItemMatch>,
@@ -398,9 +1602,13 @@ impl ItemMatches {
         self.matches.is_empty()
     }
 
-    fn sort(&mut self, data: &Data) {
+    fn sort(&mut self, data: &Data, frequencies: &HashMap<ItemId, u64>) {
+        let frequency = |item: ItemId| frequencies.get(&item).copied().unwrap_or(0);
         self.matches.sort_unstable_by(|a, b| {
-            if a.distance == b.distance {
+            if a.distance == b.distance && frequency(a.item) != frequency(b.item) {
+                // more common words first, when there is frequency data
+                frequency(b.item).cmp(&frequency(a.item))
+            } else if a.distance == b.distance {
                 let a_term = data.term(a.item);
                 let b_term = data.term(b.item);
                 let a_len = a_term.chars().count();
@@ -421,10 +1629,6 @@ impl ItemMatches {
             }
         });
     }
-
-    fn json(&self, data: &Data) -> Value {
-        json!(self.matches.iter().map(|m| m.json(data)).collect_vec())
-    }
 }
 
 impl<'a> Collector<'a, ItemId> for ItemMatches {
@@ -436,17 +1640,426 @@ impl<'a> Collector<'a, ItemId> for ItemMatches {
     }
 }
 
+// Search both for the term as given and for its folded form, which matches the
+// folded keys of terms with diacritics, whether or not the user typed any.
+fn search_terms(lang_terms: &FuzzyTrie<ItemId>, term: &str, matches: &mut ItemMatches) {
+    let folded = search_key(term);
+    let queries = if folded == term {
+        vec![term]
+    } else {
+        vec![term, folded.as_str()]
+    };
+    for query in &queries {
+        lang_terms.fuzzy_search(query, matches);
+    }
+    if matches.is_empty() && term.chars().count() > 5 {
+        for query in &queries {
+            lang_terms.prefix_fuzzy_search(query, matches);
+        }
+    }
+}
+
 impl Search {
+    /// Rank term and IPA matches that are equally close by the corpus
+    /// frequencies of their terms, read from the headerless csv at `path`,
+    /// whose rows are lang code, term, count. Terms are matched to items
+    /// ignoring case, by their canonical or page terms. Returns a hash of the
+    /// file, since search results change with it as well as with the data.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the file can't be read, or a row doesn't parse.
+    pub fn load_frequencies(&mut self, data: &Data, path: &Path) -> Result<u64> {
+        let t = Instant::now();
+        println!("Loading term frequencies from {}...", path.display());
+        let csv = fs::read(path)?;
+        let mut term_frequencies = HashMap::<(Lang, String), u64>::default();
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(&csv[..]);
+        for row in reader.deserialize() {
+            let (lang, term, count): (Lang, String, u64) = row?;
+            *term_frequencies
+                .entry((lang, term.to_lowercase()))
+                .or_default() += count;
+        }
+        self.frequencies = data
+            .graph
+            .iter()
+            .filter(|(_, item)| !item.is_imputed())
+            .filter_map(|(item_id, item)| {
+                let frequency = |term: Term| {
+                    let term = term.resolve(&data.string_pool).to_lowercase();
+                    term_frequencies.get(&(item.lang(), term)).copied()
+                };
+                frequency(item.term())
+                    .or_else(|| item.page_term().and_then(frequency))
+                    .map(|count| (item_id, count))
+            })
+            .collect();
+        println!(
+            "Finished. Found frequencies for {} items. Took {:#?}.",
+            self.frequencies.len(),
+            t.elapsed()
+        );
+        Ok(xxh3_64(&csv))
+    }
+
+    fn search_tries(&self, tries: &Tries, data: &Data, lang: Lang, query: &str) -> Vec<ItemId> {
+        let mut matches = ItemMatches::new();
+        if let Some(trie) = tries.get(data, lang) {
+            search_terms(&trie, query, &mut matches);
+        }
+        matches.sort(data, &self.frequencies);
+        // An item can match by several keys, so keep only its best match.
+        matches.matches.iter().map(|m| m.item).unique().collect()
+    }
+
+    /// Items in `lang` whose terms fuzzily match `term`, best first.
+    #[must_use]
+    pub fn item_ids(&self, data: &Data, lang: Lang, term: &str) -> Vec<ItemId> {
+        self.search_tries(&self.terms, data, lang, term)
+    }
+
+    /// Items in `lang` with an IPA transcription that fuzzily matches `ipa`,
+    /// best first. Delimiters, stress and length marks, syllable breaks and
+    /// diacritics are ignored, so an approximate transcription of a heard
+    /// word can be given.
+    #[must_use]
+    pub fn ipa_item_ids(&self, data: &Data, lang: Lang, ipa: &str) -> Vec<ItemId> {
+        self.search_tries(&self.ipa, data, lang, &ipa_search_key(ipa))
+    }
+
+    #[must_use]
+    pub fn ipa(&self, data: &Data, lang: Lang, ipa: &str) -> Value {
+        let items = self
+            .ipa_item_ids(data, lang, ipa)
+            .into_iter()
+            .map(|item| data.item_json(item))
+            .collect_vec();
+        json!(items)
+    }
+
+    /// Up to `limit` items in `lang` whose terms start with `prefix`, ignoring
+    /// case and diacritics, for completing a term as it is typed: an item
+    /// whose term is the prefix itself first, then more common terms, then
+    /// shorter ones. Unlike [`Search::item_ids`], there is no fuzzy matching,
+    /// so this is cheap enough to run on every keystroke.
+    #[must_use]
+    pub fn completion_item_ids(
+        &self,
+        data: &Data,
+        lang: Lang,
+        prefix: &str,
+        limit: usize,
+    ) -> Vec<ItemId> {
+        let prefix = search_key(prefix.trim());
+        let Some(items) = self.completions.get(&lang).filter(|_| !prefix.is_empty()) else {
+            return vec![];
+        };
+        let key = |item: &ItemId| search_key(data.term(*item));
+        let start = items.partition_point(|item| key(item) < prefix);
+        let len = items[start..].partition_point(|item| key(item).starts_with(&prefix));
+        let frequency = |item: ItemId| self.frequencies.get(&item).copied().unwrap_or(0);
+        let mut matches = items[start..start + len].to_vec();
+        matches.sort_by_cached_key(|&item| {
+            let term = data.term(item);
+            (
+                search_key(term) != prefix,
+                Reverse(frequency(item)),
+                term.chars().count(),
+                term,
+                data.ety_num(item),
+            )
+        });
+        matches.truncate(limit.min(MAX_COMPLETIONS));
+        matches
+    }
+
+    #[must_use]
+    pub fn completions(&self, data: &Data, lang: Lang, prefix: &str, limit: usize) -> Value {
+        let items = self
+            .completion_item_ids(data, lang, prefix, limit)
+            .into_iter()
+            .map(|item| data.item_json(item))
+            .collect_vec();
+        json!(items)
+    }
+
+    /// Items (in `lang`, if given) with a gloss containing all the words of
+    /// `query`, best first: those whose gloss has the words as a phrase, then
+    /// those with the shortest such gloss.
+    #[must_use]
+    pub fn gloss_item_ids(&self, data: &Data, query: &str, lang: Option<Lang>) -> Vec<ItemId> {
+        let words = gloss_words(query);
+        let Some(mut postings) = words
+            .iter()
+            .map(|word| self.gloss_words.get(word))
+            .collect::<Option<Vec<_>>>()
+        else {
+            return vec![];
+        };
+        postings.sort_unstable_by_key(|items| items.len());
+        let Some((shortest, rest)) = postings.split_first() else {
+            return vec![];
+        };
+        let mut matches = shortest
+            .iter()
+            .copied()
+            .filter(|item| lang.is_none_or(|lang| data.lang(*item) == lang))
+            .filter(|item| rest.iter().all(|items| items.binary_search(item).is_ok()))
+            .filter_map(|item| {
+                // The words may be spread across different glosses, so find
+                // the best single gloss that has them all.
+                data.item(item)
+                    .gloss()?
+                    .iter()
+                    .map(|gloss| gloss_words(&gloss.to_string(&data.string_pool)))
+                    .filter(|gloss| words.iter().all(|word| gloss.contains(word)))
+                    // phrase matches first, then shorter glosses
+                    .map(|gloss| (!gloss.windows(words.len()).any(|w| w == words), gloss.len()))
+                    .min()
+                    .map(|rank| (rank, item))
+            })
+            .collect_vec();
+        matches.sort_unstable_by_key(|&(rank, item)| (rank, data.term(item).chars().count()));
+        matches
+            .into_iter()
+            .take(MAX_GLOSS_MATCHES)
+            .map(|(_, item)| item)
+            .collect()
+    }
+
+    #[must_use]
+    pub fn glosses(&self, data: &Data, query: &str, lang: Option<Lang>) -> Value {
+        let items = self
+            .gloss_item_ids(data, query, lang)
+            .into_iter()
+            .map(|item| data.item_json(item))
+            .collect_vec();
+        json!(items)
+    }
+
     #[must_use]
     pub fn items(&self, data: &Data, lang: Lang, term: &str) -> Value {
-        let mut matches = ItemMatches::new();
-        if let Some(lang_terms) = self.terms.get(&lang) {
-            lang_terms.fuzzy_search(term, &mut matches);
-            if matches.is_empty() && term.chars().count() > 5 {
-                lang_terms.prefix_fuzzy_search(term, &mut matches);
+        let items = self
+            .item_ids(data, lang, term)
+            .into_iter()
+            .map(|item| data.item_json(item))
+            .collect_vec();
+        json!(items)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ety_graph::{EdgeProvenance, EdgeSource},
+        etymology_templates::EtyMode,
+        gloss::Gloss,
+        items::{test_item, ImputedItem, RealItem},
+    };
+
+    fn data(items: usize) -> Data {
+        let string_pool = StringPool::new();
+        let mut graph = EtyGraph::default();
+        for i in 0..items {
+            graph.add(Item::Real(RealItem {
+                ety_num: 1,
+                lang: Lang::from_str("en").unwrap(),
+                term: Term::new(&string_pool, &format!("water{i}")),
+                pos: vec![],
+                gloss: vec![Gloss::new(
+                    &string_pool,
+                    &format!("A clear liquid, number {i}, that falls from the sky as rain."),
+                )],
+                page_term: None,
+                romanization: None,
+                is_reconstructed: false,
+                ipa: vec![],
+                ety_text: None,
+                page_id: None,
+            }));
+        }
+        Data::new(string_pool, graph, HashMap::default(), None, None)
+    }
+
+    #[test]
+    fn neighborhoods() {
+        let mut data = data(5);
+        let [a, b, c, d] = [0, 1, 2, 3].map(ItemId::from);
+        let provenance = EdgeProvenance::new(EdgeSource::Etymology, None, None);
+        // a -> b -> c -> d, with a also a parent of c, and the fifth item
+        // unlinked.
+        data.graph
+            .add_ety(b, EtyMode::Inherited, None, &[a], &[1.0], provenance);
+        data.graph.add_ety(
+            c,
+            EtyMode::Compound,
+            Some(0),
+            &[b, a],
+            &[1.0, 1.0],
+            provenance,
+        );
+        data.graph
+            .add_ety(d, EtyMode::Borrowed, None, &[c], &[1.0], provenance);
+        let neighborhood = |radius, max_nodes| {
+            let limits = TreeLimits::new(None, None, None, max_nodes);
+            data.neighborhood_json(b, radius, &limits).unwrap()
+        };
+        let ids = |neighborhood: &Value| {
+            neighborhood["nodes"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|node| node["id"].as_u64().unwrap())
+                .sorted()
+                .collect_vec()
+        };
+        let one = neighborhood(1, None);
+        assert_eq!(ids(&one), vec![0, 1, 2]);
+        // the induced subgraph, so the link from a to c is included
+        assert_eq!(one["links"].as_array().unwrap().len(), 3);
+        assert_eq!(one["truncated"], false);
+        let two = neighborhood(2, None);
+        assert_eq!(ids(&two), vec![0, 1, 2, 3]);
+        let truncated = neighborhood(2, Some(2));
+        assert_eq!(ids(&truncated).len(), 2);
+        assert_eq!(truncated["truncated"], true);
+        assert_eq!(ids(&neighborhood(0, None)), vec![1]);
+    }
+
+    #[test]
+    fn path_index_is_built_for_data_without_one() {
+        let string_pool = StringPool::new();
+        let mut graph = EtyGraph::default();
+        let items = ["en", "enm", "ang"]
+            .map(|lang| graph.add(Item::Real(test_item(&string_pool, lang, "water"))));
+        let provenance = EdgeProvenance::new(EdgeSource::Etymology, None, None);
+        for pair in items.windows(2) {
+            graph.add_ety(
+                pair[0],
+                EtyMode::Inherited,
+                Some(0),
+                &[pair[1]],
+                &[1.0],
+                provenance,
+            );
+        }
+        let data = Data::new(string_pool, graph, HashMap::default(), None, None);
+        assert!(!data.path_index.is_empty());
+        let mut json = serde_json::to_value(&data).unwrap();
+        json.as_object_mut().unwrap().remove("path_index").unwrap();
+        let path =
+            std::env::temp_dir().join(format!("wety-path-index-{}.json", std::process::id()));
+        fs::write(&path, json.to_string()).unwrap();
+        let deserialized = Data::deserialize(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(!deserialized.path_index.is_empty());
+    }
+
+    #[test]
+    fn zstd_serialization() {
+        let dir = std::env::temp_dir().join(format!("wety-zstd-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("wety.json.zst");
+        // Without a dictionary, the one from before is removed.
+        for zstd_dictionary in [true, false] {
+            let mut data = data(1000);
+            let options = ProcessingOptions {
+                zstd_dictionary,
+                ..ProcessingOptions::default()
+            };
+            Data::serialize(&mut data, &path, options).unwrap();
+            assert_eq!(sibling_path(&path, "zdict").exists(), zstd_dictionary);
+            let deserialized = Data::deserialize(&path).unwrap();
+            assert_eq!(deserialized.snapshot(), data.snapshot());
+            let gz_path = dir.join("wety.json.gz");
+            Data::serialize(&mut data, &gz_path, ProcessingOptions::default()).unwrap();
+            assert_eq!(
+                deserialized.content_hash(),
+                Data::deserialize(&gz_path).unwrap().content_hash()
+            );
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn stable_ids_are_unique_and_the_same_across_runs() {
+        // Two pages with the same canonical term, each with an item imputed
+        // while processing it, added in a different order in each run.
+        let process = |pages: [u64; 2]| {
+            let string_pool = StringPool::new();
+            let mut graph = EtyGraph::default();
+            for page_id in pages {
+                let real = graph.add(Item::Real(RealItem {
+                    page_id: Some(page_id),
+                    ..test_item(&string_pool, "la", "vocō")
+                }));
+                graph.add(Item::Imputed(ImputedItem {
+                    ety_num: 1,
+                    lang: Lang::from_str("itc-pro").unwrap(),
+                    term: Term::new(&string_pool, "*wokʷāō"),
+                    romanization: None,
+                    from: real,
+                }));
             }
+            Data::new(string_pool, graph, HashMap::default(), None, None)
+        };
+        // by page id, and whether imputed
+        let stable_ids = |data: &Data| {
+            data.graph
+                .iter()
+                .map(|(item, _)| {
+                    let id = data.stable_id(item);
+                    assert_eq!(data.item_by_stable_id(&id), Some(item));
+                    let page_id = StableIds::page_id(&data.graph, item);
+                    ((page_id, data.item(item).is_imputed()), id)
+                })
+                .collect::<HashMap<_, _>>()
+        };
+        let mut data = process([1, 2]);
+        let first = stable_ids(&data);
+        assert_eq!(first.values().unique().count(), 4);
+        assert_eq!(first[&(Some(1), false)].len(), 16);
+        assert!(first[&(Some(2), false)].ends_with("-2"));
+        assert_eq!(stable_ids(&process([2, 1])), first);
+        let path =
+            std::env::temp_dir().join(format!("wety-stable-ids-{}.json.gz", std::process::id()));
+        Data::serialize(&mut data, &path, ProcessingOptions::default()).unwrap();
+        let deserialized = Data::deserialize(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(stable_ids(&deserialized), first);
+    }
+
+    #[test]
+    fn lazy_tries_are_built_once_for_concurrent_searches() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static BUILDS: AtomicUsize = AtomicUsize::new(0);
+        fn build(_: &Data, _: &[ItemId]) -> FuzzyTrie<ItemId> {
+            BUILDS.fetch_add(1, Ordering::SeqCst);
+            // long enough for the other searches to arrive during the build
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            FuzzyTrie::new(0, false)
         }
-        matches.sort(data);
-        matches.json(data)
+
+        let data = data(100);
+        let lang = Lang::from_str("en").unwrap();
+        let items = data.graph.iter().map(|(item_id, _)| item_id).collect_vec();
+        let tries = LazyTries {
+            items: Arc::new([(lang, items)].into_iter().collect()),
+            build,
+            resident: 1,
+            cache: Mutex::default(),
+        };
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| assert!(tries.get(&data, lang).is_some()));
+            }
+        });
+        assert_eq!(BUILDS.load(Ordering::SeqCst), 1);
+        assert!(tries.cache.lock().unwrap().building.is_empty());
     }
 }
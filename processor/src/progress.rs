@@ -0,0 +1,93 @@
+//! Progress bars for the stages of processing, which can also be reported as
+//! JSON lines progress events, for scripts or UIs driving the processor.
+
+use std::{
+    cell::Cell,
+    fs::OpenOptions,
+    io::{LineWriter, Write},
+    path::Path,
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Ok, Result};
+use indicatif::{ProgressBar, ProgressStyle};
+use serde_json::json;
+
+// Progress events are written at most this often per progress bar, besides
+// those at the start and finish.
+const EVENT_INTERVAL: Duration = Duration::from_secs(1);
+
+static EVENTS: OnceLock<Mutex<Box<dyn Write + Send>>> = OnceLock::new();
+
+/// Write progress events to the file at `path`, as JSON lines, alongside the
+/// progress bars. `path` can also be e.g. /dev/fd/3 or /dev/stderr. Each event
+/// has the `stage` (the progress bar's message), the `event` ("start",
+/// "progress" or "finish"), the number of things `processed` of the `total`,
+/// and the `elapsedSecs` and `etaSecs` of the stage.
+///
+/// # Errors
+///
+/// Will return `Err` if the file cannot be opened, or if progress events are
+/// already being written.
+pub fn write_progress_events(path: &Path) -> Result<()> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    EVENTS
+        .set(Mutex::new(Box::new(LineWriter::new(file))))
+        .map_err(|_| anyhow!("progress events are already being written"))
+}
+
+pub(crate) struct Progress {
+    bar: ProgressBar,
+    stage: String,
+    last_event: Cell<Instant>,
+}
+
+pub(crate) fn progress_bar(n: usize, message: &str) -> Result<Progress> {
+    let pb = ProgressBar::new(u64::try_from(n)?);
+    let template = format!("{{spinner:.green}} {message}: [{{elapsed}}] [{{bar:.cyan/blue}}] {{human_pos}}/{{human_len}} ({{per_sec}}, {{eta}})");
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template(&template)?
+            .progress_chars("#>-"),
+    );
+    let progress = Progress {
+        bar: pb,
+        stage: message.to_string(),
+        last_event: Cell::new(Instant::now()),
+    };
+    progress.event("start");
+    Ok(progress)
+}
+
+impl Progress {
+    pub(crate) fn inc(&self, delta: u64) {
+        self.bar.inc(delta);
+        if self.last_event.get().elapsed() >= EVENT_INTERVAL {
+            self.event("progress");
+        }
+    }
+
+    pub(crate) fn finish(&self) {
+        self.bar.finish();
+        self.event("finish");
+    }
+
+    fn event(&self, event: &str) {
+        let Some(events) = EVENTS.get() else {
+            return;
+        };
+        self.last_event.set(Instant::now());
+        let line = json!({
+            "stage": self.stage,
+            "event": event,
+            "processed": self.bar.position(),
+            "total": self.bar.length(),
+            "elapsedSecs": self.bar.elapsed().as_secs_f64(),
+            "etaSecs": self.bar.eta().as_secs_f64(),
+        });
+        // Progress events are best effort, and shouldn't stop processing.
+        let mut events = events.lock().expect("progress events lock not poisoned");
+        let _ = writeln!(events, "{line}");
+    }
+}
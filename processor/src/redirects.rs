@@ -1,17 +1,23 @@
 use crate::{
-    items::Items,
+    checkpoint::pairs,
+    items::{ItemId, Items},
     langterm::{LangTerm, Term},
     languages::Lang,
     string_pool::StringPool,
     wiktextract_json::{WiktextractJson, WiktextractJsonValidStr},
-    HashMap,
+    HashMap, HashSet,
 };
 
+use std::hash::Hash;
+
 use phf::{phf_set, Set};
+use serde::{Deserialize, Serialize};
 
-#[derive(Default)]
+#[derive(Default, Serialize, Deserialize)]
 pub(crate) struct Redirects {
+    #[serde(with = "pairs")]
     reconstruction: HashMap<LangTerm, LangTerm>,
+    #[serde(with = "pairs")]
     regular: HashMap<Term, Term>,
 }
 
@@ -35,6 +41,35 @@ impl Redirects {
         // Then we also check if there is a redirect for this lang term combo.
         self.get(LangTerm::new(non_ety_lang, langterm.term))
     }
+
+    // Redirect pages can themselves point to redirect pages. Follow all such
+    // chains to their final target, so that a single lookup in get() suffices.
+    // This must be called after all redirects have been processed.
+    pub(crate) fn resolve_chains(&mut self) {
+        resolve_chains(&mut self.reconstruction);
+        resolve_chains(&mut self.regular);
+    }
+}
+
+// Replace each redirect's target with the end of its chain. Redirects that end
+// up in a cycle have no real target and are dropped.
+fn resolve_chains<T: Copy + Eq + Hash>(redirects: &mut HashMap<T, T>) {
+    let resolved = redirects
+        .iter()
+        .filter_map(|(&from, &to)| {
+            let mut seen = HashSet::default();
+            seen.insert(from);
+            let mut to = to;
+            while seen.insert(to) {
+                match redirects.get(&to) {
+                    Some(&next) => to = next,
+                    None => return Some((from, to)),
+                }
+            }
+            None
+        })
+        .collect();
+    *redirects = resolved;
 }
 
 static IGNORED_REDIRECTS: Set<&'static str> = phf_set! {
@@ -50,7 +85,7 @@ pub(crate) struct WiktextractJsonRedirect<'a> {
 impl Items {
     pub(crate) fn process_redirect(
         &mut self,
-        string_pool: &mut StringPool,
+        string_pool: &StringPool,
         redirect: &WiktextractJsonRedirect,
     ) {
         // cf. https://github.com/tatuylonen/wiktextract/blob/master/wiktwords
@@ -82,7 +117,40 @@ impl Items {
     }
 }
 
-fn process_reconstruction_title(string_pool: &mut StringPool, title: &str) -> Option<LangTerm> {
+impl Items {
+    // For each item whose page is the target of any redirects, get the titles
+    // of the redirecting pages, e.g. obsolete spellings, so that the item can
+    // be found by searching for them too.
+    pub(crate) fn aliases(&self) -> HashMap<ItemId, Vec<Term>> {
+        let mut regular = HashMap::<Term, Vec<Term>>::default();
+        for (&from, &to) in &self.redirects.regular {
+            regular.entry(to).or_default().push(from);
+        }
+        // A reconstruction redirect to a different lang is a merger of
+        // entries rather than an alternative spelling, so we skip those.
+        let mut reconstruction = HashMap::<LangTerm, Vec<Term>>::default();
+        for (&from, &to) in &self.redirects.reconstruction {
+            if from.lang == to.lang {
+                reconstruction.entry(to).or_default().push(from.term);
+            }
+        }
+        let mut aliases = HashMap::default();
+        for (item_id, item) in self.graph.iter().filter(|(_, item)| !item.is_imputed()) {
+            let page_term = item.page_term().unwrap_or(item.term());
+            let item_aliases = if item.is_reconstructed() {
+                reconstruction.get(&LangTerm::new(item.lang(), page_term))
+            } else {
+                regular.get(&page_term)
+            };
+            if let Some(item_aliases) = item_aliases {
+                aliases.insert(item_id, item_aliases.clone());
+            }
+        }
+        aliases
+    }
+}
+
+fn process_reconstruction_title(string_pool: &StringPool, title: &str) -> Option<LangTerm> {
     // e.g. Reconstruction:Proto-Germanic/pīpǭ
     let title = title.strip_prefix("Reconstruction:")?;
     let slash = title.find('/')?;
@@ -91,3 +159,20 @@ fn process_reconstruction_title(string_pool: &mut StringPool, title: &str) -> Op
     let lang = Lang::from_name(lang_name).ok()?;
     Some(lang.new_langterm(string_pool, term))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_redirect_chains() {
+        let mut redirects: HashMap<u32, u32> =
+            [(1, 2), (2, 3), (3, 4), (5, 6), (6, 5), (7, 7), (8, 5)]
+                .into_iter()
+                .collect();
+        resolve_chains(&mut redirects);
+        let mut resolved = redirects.into_iter().collect::<Vec<_>>();
+        resolved.sort_unstable();
+        assert_eq!(resolved, vec![(1, 4), (2, 4), (3, 4)]);
+    }
+}
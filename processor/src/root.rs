@@ -2,6 +2,7 @@ use std::{mem, str::FromStr};
 
 use crate::{
     embeddings::{Comparand, Embeddings, ItemEmbedding},
+    ety_graph::{EdgeProvenance, EdgeSource},
     etymology::validate_ety_template_lang,
     etymology_templates::EtyMode,
     items::{ItemId, Items, Retrieval},
@@ -12,71 +13,174 @@ use crate::{
     wiktextract_json::{WiktextractJson, WiktextractJsonItem, WiktextractJsonValidStr},
 };
 
-use anyhow::{Ok, Result};
+use anyhow::{ensure, Ok, Result};
 use lazy_static::lazy_static;
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 use simd_json::ValueAccess;
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
 
-#[derive(Hash, Eq, PartialEq, Debug)]
+#[derive(Hash, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub(crate) struct RawRoot {
-    pub(crate) langterm: LangTerm,
+    // More than one for a compound reconstruction, e.g.
+    // {{root|en|ine-pro|*ḱel-|*-tro-}}, or when several root templates are
+    // given. The first is the root proper.
+    pub(crate) langterms: Vec<LangTerm>,
+    // Only kept for a single root
     pub(crate) sense_id: Option<Symbol>,
+    // None if found from a category rather than a template
+    #[serde(default)]
+    pub(crate) name: Option<Symbol>,
 }
 
+// Derivational suffixes of reconstructed langs, cited as on Wiktionary. An
+// ancestor in the same lang as its root is decomposed into the root and one of
+// these, e.g. PIE *bʰértos into *bʰer- and *-tós. Longer suffixes come first,
+// so that they are preferred to the endings they end in.
+const DERIVATIONAL_SUFFIXES: &[(&str, &[&str])] = &[(
+    "ine-pro",
+    &[
+        "-tḗr", "-tōr", "-tlom", "-trom", "-tós", "-tis", "-tus", "-mn̥", "-mō", "-nós", "-yós",
+        "-wós", "-lós", "-rós", "-éh₂", "-ós", "-is", "-us", "-s",
+    ],
+)];
+
 enum RootKind {
     Root,
     Word,
 }
 
 impl WiktextractJsonItem<'_> {
-    // cf. https://en.wiktionary.org/wiki/Template:root. For now we skip
-    // attempting to deal with multiple roots listed in a root template or
-    // multiple root templates being listed. In both cases we just take the
-    // first root term seen. If we discover it is common, we will handle it. We
-    // also handle https://en.wiktionary.org/wiki/Template:PIE_word and
+    // cf. https://en.wiktionary.org/wiki/Template:root. Multiple roots listed
+    // in a root template or in multiple root templates (or categories) are
+    // all kept, in the order they are given, with the name and sense id of the
+    // first root template. We also handle https://en.wiktionary.org/wiki/Template:PIE_word and
     // https://en.wiktionary.org/wiki/Template:word here. These are both used in
     // the same way as {{root}}; namely, they are placed at the top of the
     // etymology section before the body of the etymology where the "normal" ety
     // templates are found. And all three are used to indicate ultimate descent
     // from a term in a proto-language. For expedience, we gloss over the
     // distinction among them and categorize them all as "root" etys.
-    pub(crate) fn get_root(&self, string_pool: &mut StringPool, lang: Lang) -> Option<RawRoot> {
+    pub(crate) fn get_root(&self, string_pool: &StringPool, lang: Lang) -> Option<RawRoot> {
+        let mut raw_root: Option<RawRoot> = None;
         if let Some(templates) = self.json.get_array("etymology_templates") {
             for template in templates {
-                if let Some(name) = template.get_valid_str("name")
-                    && let Some(args) = template.get("args")
+                if template
+                    .get_valid_str("name")
+                    .is_some_and(|name| ROOT_TEMPLATES.contains(&name))
+                    && template.get("args").is_some()
+                    && let Some(next) = process_json_root_template(string_pool, template, lang)
                 {
-                    match name {
-                        "root" => {
-                            return process_root_template(string_pool, args, lang, &RootKind::Root);
-                        }
-                        "word" => {
-                            return process_root_template(string_pool, args, lang, &RootKind::Word);
-                        }
-                        "PIE word" => {
-                            return process_pie_word_template(string_pool, args, lang);
-                        }
-                        _ => {}
-                    }
+                    merge_raw_root(&mut raw_root, next);
                 }
             }
         }
+        if raw_root.is_some() {
+            return raw_root;
+        }
 
-        // if no {root} found in ety section, look for a category of the form
+        // if no {root} found in ety section, look for categories of the form
         // e.g. "English terms derived from the Proto-Indo-European root *dʰeh₁-"
         // or "English terms derived from the Proto-Indo-European root *bʰel- (shiny)"
         if let Some(categories) = self.json.get_array("categories") {
             for category in categories.iter().filter_map(|c| c.as_str()) {
-                if let Some(raw_root) = process_json_root_category(string_pool, category, lang) {
-                    return Some(raw_root);
+                if let Some(next) = process_json_root_category(string_pool, category, lang) {
+                    merge_raw_root(&mut raw_root, next);
                 }
             }
         }
 
-        None
+        raw_root
+    }
+
+    // For the line errors. Root templates that fail to parse are otherwise
+    // silently ignored, leaving the item to get its root from a category, if
+    // at all.
+    pub(crate) fn ensure_root_templates_parse(
+        &self,
+        string_pool: &StringPool,
+        lang: Lang,
+    ) -> Result<()> {
+        for template in self.root_templates() {
+            ensure!(
+                process_json_root_template(string_pool, template, lang).is_some(),
+                "could not parse root template {template}"
+            );
+        }
+        Ok(())
+    }
+
+    // For the line errors. The item's own lang in "1" isn't checked, since it
+    // must match the item's.
+    pub(crate) fn ensure_known_root_template_langs(&self) -> Result<()> {
+        for template in self.root_templates() {
+            let Some(args) = template.get("args") else {
+                continue;
+            };
+            let code = match template.get_valid_str("name") {
+                Some("root") => args.get_valid_str("2").map(str::to_string),
+                Some("word") => args.get_valid_str("2").map(|code| format!("{code}-pro")),
+                _ => None,
+            };
+            if let Some(code) = code {
+                ensure!(
+                    Lang::from_str(&code).is_ok(),
+                    "unknown lang code {code} in root template {template}"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn root_templates(&self) -> impl Iterator<Item = &WiktextractJson<'_>> {
+        self.json
+            .get_array("etymology_templates")
+            .into_iter()
+            .flatten()
+            .filter(|template| {
+                template
+                    .get_valid_str("name")
+                    .is_some_and(|name| ROOT_TEMPLATES.contains(&name))
+                    && template.get("args").is_some()
+            })
     }
 }
 
+// Add the roots of `next` not already in `raw_root`. A sense id is only kept
+// when there is a single root.
+fn merge_raw_root(raw_root: &mut Option<RawRoot>, next: RawRoot) {
+    let Some(raw_root) = raw_root else {
+        *raw_root = Some(next);
+        return;
+    };
+    for langterm in next.langterms {
+        if !raw_root.langterms.contains(&langterm) {
+            raw_root.langterms.push(langterm);
+            raw_root.sense_id = None;
+        }
+    }
+}
+
+/// The names of the templates in the ety section that give the root.
+pub(crate) const ROOT_TEMPLATES: &[&str] = &["root", "word", "PIE word"];
+
+pub(crate) fn process_json_root_template(
+    string_pool: &StringPool,
+    template: &WiktextractJson,
+    lang: Lang,
+) -> Option<RawRoot> {
+    let name = template.get_valid_str("name")?;
+    let args = template.get("args")?;
+    let mut raw_root = match name {
+        "root" => process_root_template(string_pool, args, lang, &RootKind::Root),
+        "word" => process_root_template(string_pool, args, lang, &RootKind::Word),
+        "PIE word" => process_pie_word_template(string_pool, args, lang),
+        _ => None,
+    }?;
+    raw_root.name = Some(string_pool.get_or_intern(name));
+    Some(raw_root)
+}
+
 // https://en.wiktionary.org/wiki/Template:root
 // https://en.wiktionary.org/wiki/Template:word
 // This handles both {{root}} and {{word}}. The templates work the same, with
@@ -84,7 +188,7 @@ impl WiktextractJsonItem<'_> {
 // standard language code in the case of {{root}}, while "-pro" is clipped off
 // in the case of {{word}}.
 fn process_root_template(
-    string_pool: &mut StringPool,
+    string_pool: &StringPool,
     args: &WiktextractJson,
     lang: Lang,
     kind: &RootKind,
@@ -96,28 +200,36 @@ fn process_root_template(
         RootKind::Word => Lang::from_str(&format!("{root_lang}-pro")).ok()?,
     };
     let raw_root_term = args.get_valid_str("3")?;
-    let root_term = args.get_valid_term("3")?;
-    // we don't deal with multi-roots for now:
-    args.get_valid_term("4").is_none().then_some(())?;
+    // Further roots of a compound reconstruction are given in args 4, 5, etc.
+    let langterms = (3..)
+        .map_while(|i| args.get_valid_term(&i.to_string()))
+        .map(|term| root_lang.new_langterm(string_pool, term))
+        .collect::<Vec<_>>();
+    (!langterms.is_empty()).then_some(())?;
 
     let mut sense_id = "";
-    // Sometimes a root's senseid is given in parentheses after the term in
-    // the 3 arg slot, see e.g. https://en.wiktionary.org/wiki/blaze.
-    if let Some(right_paren_idx) = raw_root_term.rfind(')')
-        && let Some(left_paren_idx) = raw_root_term.rfind(" (")
-    {
-        sense_id = &raw_root_term[left_paren_idx + 2..right_paren_idx];
-    } else if let Some(id) = args.get_valid_str("id") {
-        sense_id = id;
+    if langterms.len() == 1 {
+        // Sometimes a root's senseid is given in parentheses after the term
+        // in the 3 arg slot, see e.g. https://en.wiktionary.org/wiki/blaze.
+        if let Some(right_paren_idx) = raw_root_term.rfind(')')
+            && let Some(left_paren_idx) = raw_root_term.rfind(" (")
+        {
+            sense_id = &raw_root_term[left_paren_idx + 2..right_paren_idx];
+        } else if let Some(id) = args.get_valid_str("id") {
+            sense_id = id;
+        }
     }
     let sense_id = (!sense_id.is_empty()).then(|| string_pool.get_or_intern(sense_id));
-    let langterm = root_lang.new_langterm(string_pool, root_term);
-    Some(RawRoot { langterm, sense_id })
+    Some(RawRoot {
+        langterms,
+        sense_id,
+        name: None,
+    })
 }
 
 // https://en.wiktionary.org/wiki/Template:PIE_word
 fn process_pie_word_template(
-    string_pool: &mut StringPool,
+    string_pool: &StringPool,
     args: &WiktextractJson,
     lang: Lang,
 ) -> Option<RawRoot> {
@@ -126,13 +238,14 @@ fn process_pie_word_template(
     let pie_word = args.get_valid_term("2")?;
     let pie_langterm = pie_lang.new_langterm(string_pool, pie_word);
     Some(RawRoot {
-        langterm: pie_langterm,
+        langterms: vec![pie_langterm],
         sense_id: None,
+        name: None,
     })
 }
 
 fn process_json_root_category(
-    string_pool: &mut StringPool,
+    string_pool: &StringPool,
     category: &str,
     lang: Lang,
 ) -> Option<RawRoot> {
@@ -152,60 +265,219 @@ fn process_json_root_category(
         .get(4)
         .map(|cap| string_pool.get_or_intern(cap.as_str()));
     Some(RawRoot {
-        langterm: LangTerm::new(cat_root_lang, cat_root_term),
+        langterms: vec![LangTerm::new(cat_root_lang, cat_root_term)],
         sense_id: cat_root_sense_id,
+        name: None,
+    })
+}
+
+// Lowercase and strip diacritics, hyphens and asterisks, so that e.g. ablaut
+// grades and accents don't get in the way of matching.
+fn fold(term: &str) -> String {
+    term.nfd()
+        .filter(|&c| !is_combining_mark(c) && c != '-' && c != '*')
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+// The e-, o- and zero grades of a root cited in the e-grade, e.g. bʰer, bʰor
+// and bʰr for *bʰer-.
+fn ablaut_grades(root: &str) -> Vec<String> {
+    let mut grades = vec![root.to_string()];
+    if let Some(i) = root.find('e') {
+        grades.push(format!("{}o{}", &root[..i], &root[i + 1..]));
+        grades.push(format!("{}{}", &root[..i], &root[i + 1..]));
+    }
+    grades
+}
+
+// The suffix that, added to some ablaut grade of the root, gives the derived
+// term, if there is one.
+fn derivational_suffix(lang: Lang, root: &str, derived: &str) -> Option<&'static str> {
+    let (_, suffixes) = DERIVATIONAL_SUFFIXES
+        .iter()
+        .find(|(code, _)| *code == lang.code())?;
+    let root = fold(root);
+    if root.is_empty() {
+        return None;
+    }
+    let derived = fold(derived);
+    let grades = ablaut_grades(&root);
+    suffixes.iter().copied().find(|suffix| {
+        let suffix = fold(suffix);
+        grades
+            .iter()
+            .any(|grade| derived.strip_prefix(grade.as_str()) == Some(suffix.as_str()))
     })
 }
 
 impl Items {
+    // If the item's head progenitor (or the item itself, if it has none) is in
+    // the same lang as its root, i.e. its templated ancestors stop short of
+    // the root, try to decompose it into the root and a known suffix, and link
+    // it to them as a suffixed derivation of the root. This is only done when
+    // the suffix is already cited somewhere. Returns whether it was done.
+    fn impute_root_derivation(
+        &mut self,
+        string_pool: &StringPool,
+        embeddings: &Embeddings,
+        item_id: ItemId,
+        root_item_id: ItemId,
+        root_confidence: f32,
+        provenance: EdgeProvenance,
+    ) -> Result<bool> {
+        let derived_id = match self.graph.progenitors(item_id) {
+            None => item_id,
+            Some(progenitors) => match progenitors.head {
+                Some(head) if !progenitors.items.contains(&root_item_id) => head,
+                _ => return Ok(false),
+            },
+        };
+        let derived = self.get(derived_id);
+        let root = self.get(root_item_id);
+        let lang = root.lang();
+        if derived_id == root_item_id || derived.lang() != lang {
+            return Ok(false);
+        }
+        let Some(suffix) = derivational_suffix(
+            lang,
+            root.term().resolve(string_pool),
+            derived.term().resolve(string_pool),
+        ) else {
+            return Ok(false);
+        };
+        let Some(suffix_symbol) = string_pool.get(suffix) else {
+            return Ok(false);
+        };
+        let derived_embedding = embeddings.get(derived, derived_id)?;
+        let root_confidence = if derived_id == item_id {
+            root_confidence
+        } else {
+            let root_embedding = embeddings.get(root, root_item_id)?;
+            derived_embedding.cosine_similarity(&root_embedding)
+        };
+        let Some(Retrieval {
+            item_id: suffix_item_id,
+            confidence: suffix_confidence,
+        }) = self.get_or_impute_item(
+            string_pool,
+            embeddings,
+            &derived_embedding,
+            derived_id,
+            derived_id,
+            LangTerm::new(lang, suffix_symbol.into()),
+            true,
+        )?
+        else {
+            return Ok(false);
+        };
+        self.add_ety(
+            derived_id,
+            EtyMode::Suffix,
+            Some(0u8),
+            &[root_item_id, suffix_item_id],
+            &[root_confidence, suffix_confidence],
+            provenance,
+        );
+        Ok(true)
+    }
+
     fn impute_item_root_ety(
         &mut self,
+        string_pool: &StringPool,
         embeddings: &Embeddings,
         embedding: &ItemEmbedding,
         item_id: ItemId,
+        line: Option<usize>,
         raw_root: &RawRoot,
     ) -> Result<()> {
-        let Retrieval {
-            item_id: root_item_id,
-            confidence,
-        } = self.get_or_impute_item(embeddings, embedding, item_id, raw_root.langterm)?;
+        let provenance = EdgeProvenance::new(EdgeSource::RootImputation, raw_root.name, line);
+        let mut roots = vec![];
+        for &langterm in &raw_root.langterms {
+            if let Some(Retrieval {
+                item_id: root_item_id,
+                confidence,
+            }) = self.get_or_impute_item(
+                string_pool,
+                embeddings,
+                embedding,
+                item_id,
+                item_id,
+                langterm,
+                true,
+            )? && !roots.iter().any(|&(id, _)| id == root_item_id)
+            {
+                roots.push((root_item_id, confidence));
+            }
+        }
 
-        let root_lang = self.get(root_item_id).lang();
+        if let [(root_item_id, confidence)] = roots[..]
+            && self.impute_root_derivation(
+                string_pool,
+                embeddings,
+                item_id,
+                root_item_id,
+                confidence,
+                provenance,
+            )?
+        {
+            return Ok(());
+        }
 
+        // Multiple roots are linked together as a compound, headed by the first
+        // root that qualifies.
         match self.graph.progenitors(item_id) {
             None => {
                 let item = self.get(item_id);
                 let item_lang = item.lang();
-                if item_lang.strictly_descends_from(root_lang)
-                    || (item.is_imputed() && item_lang.descends_from(root_lang))
-                {
-                    self.graph.add_ety(
+                let is_imputed = item.is_imputed();
+                roots.retain(|&(root_item_id, _)| {
+                    let root_lang = self.get(root_item_id).lang();
+                    item_lang.strictly_descends_from(root_lang)
+                        || (is_imputed && item_lang.descends_from(root_lang))
+                });
+                if !roots.is_empty() {
+                    let (root_items, confidences): (Vec<_>, Vec<_>) = roots.into_iter().unzip();
+                    self.add_ety(
                         item_id,
                         EtyMode::Root,
                         Some(0u8),
-                        &[root_item_id],
-                        &[confidence],
+                        &root_items,
+                        &confidences,
+                        provenance,
                     );
                 }
             }
             Some(progenitors) => {
-                if let Some(head_progenitor_id) = progenitors.head
-                    && let head_progenitor = self.get(head_progenitor_id)
-                    && !progenitors.items.contains(&root_item_id)
-                    && let head_progenitor_lang = head_progenitor.lang()
-                    && (head_progenitor_lang.strictly_descends_from(root_lang)
-                        || (head_progenitor.is_imputed()
-                            && head_progenitor_lang.descends_from(root_lang)))
-                {
-                    let root_embedding = embeddings.get(self.get(root_item_id), root_item_id)?;
-                    let hp_embedding = embeddings.get(head_progenitor, head_progenitor_id)?;
-                    let similarity = hp_embedding.cosine_similarity(&root_embedding);
-                    self.graph.add_ety(
+                let Some(head_progenitor_id) = progenitors.head else {
+                    return Ok(());
+                };
+                let head_progenitor = self.get(head_progenitor_id);
+                let head_progenitor_lang = head_progenitor.lang();
+                let hp_embedding = embeddings.get(head_progenitor, head_progenitor_id)?;
+                let mut root_items = vec![];
+                let mut similarities = vec![];
+                for (root_item_id, _) in roots {
+                    let root_lang = self.get(root_item_id).lang();
+                    if !progenitors.items.contains(&root_item_id)
+                        && (head_progenitor_lang.strictly_descends_from(root_lang)
+                            || (head_progenitor.is_imputed()
+                                && head_progenitor_lang.descends_from(root_lang)))
+                    {
+                        let root_embedding =
+                            embeddings.get(self.get(root_item_id), root_item_id)?;
+                        root_items.push(root_item_id);
+                        similarities.push(hp_embedding.cosine_similarity(&root_embedding));
+                    }
+                }
+                if !root_items.is_empty() {
+                    self.add_ety(
                         head_progenitor_id,
                         EtyMode::Root,
                         Some(0u8),
-                        &[root_item_id],
-                        &[similarity],
+                        &root_items,
+                        &similarities,
+                        provenance,
                     );
                 }
             }
@@ -214,16 +486,60 @@ impl Items {
         Ok(())
     }
 
-    pub(crate) fn impute_root_etys(&mut self, embeddings: &Embeddings) -> Result<()> {
+    pub(crate) fn impute_root_etys(
+        &mut self,
+        string_pool: &StringPool,
+        embeddings: &Embeddings,
+    ) -> Result<()> {
         let n = self.raw_templates.root.len();
         let pb = progress_bar(n, "Imputing root etys")?;
         let raw_templates_root = mem::take(&mut self.raw_templates.root);
+        let item_lines = self.item_lines();
         for (item_id, root) in raw_templates_root {
             let embedding = embeddings.get(self.get(item_id), item_id)?;
-            self.impute_item_root_ety(embeddings, &embedding, item_id, &root)?;
+            let line = item_lines.get(&item_id).copied();
+            self.impute_item_root_ety(string_pool, embeddings, &embedding, item_id, line, &root)?;
             pb.inc(1);
         }
         pb.finish();
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simd_json::json;
+
+    #[test]
+    fn root_keeps_multiple_roots() {
+        let string_pool = StringPool::new();
+        let en = Lang::from_str("en").unwrap();
+        let json: WiktextractJson = json!({
+            "etymology_templates": [
+                {"name": "root", "args": {"1": "en", "2": "ine-pro", "3": "*bʰer-", "4": "*-tro-"}},
+                {"name": "root", "args": {"1": "en", "2": "ine-pro", "3": "*bʰer-"}},
+                {"name": "PIE word", "args": {"1": "en", "2": "*bʰréh₂tēr"}}
+            ]
+        })
+        .into();
+        let item = WiktextractJsonItem { json };
+        let raw_root = item.get_root(&string_pool, en).unwrap();
+        let terms = raw_root
+            .langterms
+            .iter()
+            .map(|langterm| langterm.term.resolve(&string_pool))
+            .collect::<Vec<_>>();
+        assert_eq!(terms, ["bʰer-", "-tro-", "bʰréh₂tēr"]);
+        assert_eq!(raw_root.sense_id, None);
+    }
+
+    #[test]
+    fn derivational_suffix_across_ablaut_grades() {
+        let pie = Lang::from_str("ine-pro").unwrap();
+        assert_eq!(derivational_suffix(pie, "bʰer-", "bʰértos"), Some("-tós"));
+        assert_eq!(derivational_suffix(pie, "ǵenh₁-", "ǵn̥h₁tós"), Some("-tós"));
+        assert_eq!(derivational_suffix(pie, "men-", "mn̥tis"), Some("-tis"));
+        assert_eq!(derivational_suffix(pie, "bʰer-", "bʰréh₂tēr"), None);
+    }
+}
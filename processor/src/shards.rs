@@ -0,0 +1,339 @@
+//! Serialization of the data split into a shard per lang, so that a server
+//! can load only the langs it cares about. A shards directory holds:
+//!
+//! - `manifest.json`: the langs that have shards, and the dates of the data
+//! - `strings`: the string pool, memory-mapped by the server
+//! - `edges.json.gz`: every ety edge, with the langs of the items they link
+//! - `<lang code>.json.gz`: the items of the lang and their aliases
+
+use crate::{
+    ety_graph::{AlternativeEty, EtyEdgeAccess, EtyEdgeData, EtyGraph},
+    items::{Item, ItemId},
+    langterm::Term,
+    languages::Lang,
+    processed::{Data, HashingReader},
+    stats::Provenance,
+    string_pool::StringPool,
+    HashMap, HashSet,
+};
+
+use std::{
+    fs::{self, File},
+    io::{BufReader, Read, Write},
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use anyhow::{bail, Context, Ok, Result};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use indicatif::HumanDuration;
+use itertools::Itertools;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use xxhash_rust::xxh3::Xxh3;
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Manifest {
+    langs: Vec<Lang>,
+    dump_date: Option<u64>,
+    processed_date: Option<u64>,
+    provenance: Option<Provenance>,
+}
+
+// Generic over the edge data, alternatives, surface analyses and items, so
+// that they can be serialized by reference and deserialized by value.
+#[derive(Serialize, Deserialize)]
+struct Edges<E, A, S> {
+    // child, parent
+    edges: Vec<(ItemId, ItemId, E)>,
+    // the alternatives to the parents of each item that has any
+    #[serde(default)]
+    alternatives: HashMap<ItemId, A>,
+    // the surface analysis of each item that has one (a path is given for the
+    // default so that serde doesn't require S: Default)
+    #[serde(default = "HashMap::default")]
+    surface_analyses: HashMap<ItemId, S>,
+    // the lang of every item with an edge, for finding which shards the
+    // parents of loaded items are in
+    langs: HashMap<ItemId, Lang>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct LangShard<I, A> {
+    items: Vec<(ItemId, I)>,
+    aliases: HashMap<ItemId, A>,
+}
+
+fn write_gz<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    let mut writer = GzEncoder::new(File::create(path)?, Compression::fast());
+    serde_json::to_writer(&mut writer, value)?;
+    writer.finish()?.flush()?;
+    Ok(())
+}
+
+// Read a gz-compressed json file, hashing the uncompressed json.
+fn read_gz<T: DeserializeOwned>(path: &Path, hasher: &mut Xxh3) -> Result<T> {
+    let file = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+    let mut reader = HashingReader {
+        inner: GzDecoder::new(BufReader::new(file)),
+        hasher: Xxh3::new(),
+    };
+    let value = serde_json::from_reader(&mut reader)
+        .with_context(|| format!("deserializing {}", path.display()))?;
+    hasher.update(&reader.hasher.digest().to_le_bytes());
+    Ok(value)
+}
+
+fn shard_path(dir: &Path, lang: Lang) -> PathBuf {
+    dir.join(format!("{}.json.gz", lang.code()))
+}
+
+impl Data {
+    /// Write the data to the directory `dir` as a shard per lang, plus the
+    /// edges and strings that all shards share.
+    pub(crate) fn serialize_shards(&self, dir: &Path) -> Result<()> {
+        let t = Instant::now();
+        println!(
+            "Serializing processed data as shards to {}...",
+            dir.display()
+        );
+        fs::create_dir_all(dir)?;
+        self.string_pool.write_mapped(&dir.join("strings"))?;
+        let mut shards = HashMap::<Lang, LangShard<&Item, &Vec<Term>>>::default();
+        for (item_id, item) in self.graph.iter() {
+            let shard = shards.entry(item.lang()).or_insert_with(|| LangShard {
+                items: vec![],
+                aliases: HashMap::default(),
+            });
+            shard.items.push((item_id, item));
+            if let Some(aliases) = self.aliases.get(&item_id) {
+                shard.aliases.insert(item_id, aliases);
+            }
+        }
+        let mut edges = Edges {
+            edges: vec![],
+            alternatives: self
+                .graph
+                .alternatives
+                .iter()
+                .map(|(&id, a)| (id, a))
+                .collect(),
+            surface_analyses: self
+                .graph
+                .surface_analyses
+                .iter()
+                .map(|(&id, a)| (id, a))
+                .collect(),
+            langs: HashMap::default(),
+        };
+        for edge in self.graph.edges() {
+            edges
+                .edges
+                .push((edge.child(), edge.parent(), edge.weight()));
+            for item in [edge.child(), edge.parent()] {
+                edges.langs.insert(item, self.graph.item(item).lang());
+            }
+        }
+        write_gz(&dir.join("edges.json.gz"), &edges)?;
+        for (&lang, shard) in &shards {
+            write_gz(&shard_path(dir, lang), shard)?;
+        }
+        let manifest = Manifest {
+            langs: shards
+                .keys()
+                .copied()
+                .sorted_by_key(|lang| lang.code())
+                .collect(),
+            dump_date: self.stats.dump_date,
+            processed_date: self.stats.processed_date,
+            provenance: self.stats.provenance.clone(),
+        };
+        serde_json::to_writer(File::create(dir.join("manifest.json"))?, &manifest)?;
+        println!("Finished. Took {}.", HumanDuration(t.elapsed()));
+        Ok(())
+    }
+
+    /// Load only the shards of `langs` from the shards directory `dir`, along
+    /// with the items in other langs that their items descend from, so that
+    /// their etymologies are complete. Items get new ids, which are only
+    /// stable for the same data and `langs`.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if any of `langs` has no shard, or if any unexpected
+    /// issue arises in the deserialization.
+    pub fn deserialize_shards(dir: &Path, langs: &[Lang]) -> Result<Self> {
+        let t = Instant::now();
+        println!(
+            "Deserializing processed data shards for {} from {}...",
+            langs.iter().map(|lang| lang.code()).join(", "),
+            dir.display()
+        );
+        let mut hasher = Xxh3::new();
+        let mut manifest_json = vec![];
+        File::open(dir.join("manifest.json"))
+            .with_context(|| format!("opening the manifest of {}", dir.display()))?
+            .read_to_end(&mut manifest_json)?;
+        hasher.update(&manifest_json);
+        let manifest: Manifest = serde_json::from_slice(&manifest_json)?;
+        if let Some(missing) = langs.iter().find(|lang| !manifest.langs.contains(lang)) {
+            bail!("there is no shard for {}", missing.code());
+        }
+        let string_pool = StringPool::map(&dir.join("strings"))?;
+        if let Some(bytes) = string_pool.mapped_bytes() {
+            hasher.update(bytes);
+        }
+        let edges: Edges<EtyEdgeData, Vec<AlternativeEty>, AlternativeEty> =
+            read_gz(&dir.join("edges.json.gz"), &mut hasher)?;
+        let mut parents = HashMap::<ItemId, Vec<ItemId>>::default();
+        for &(child, parent, _) in &edges.edges {
+            parents.entry(child).or_default().push(parent);
+        }
+
+        let mut items = HashMap::<ItemId, Item>::default();
+        let mut aliases = HashMap::<ItemId, Vec<Term>>::default();
+        // Load the requested langs in full, then in each round, the parents
+        // of the items loaded in the last round that aren't loaded yet, from
+        // the shards of their langs.
+        let mut wanted = langs
+            .iter()
+            .map(|&lang| (lang, None))
+            .collect::<HashMap<Lang, Option<HashSet<ItemId>>>>();
+        while !wanted.is_empty() {
+            let mut loaded = vec![];
+            for (lang, ids) in wanted.drain().sorted_by_key(|(lang, _)| lang.code()) {
+                let shard: LangShard<Item, Vec<Term>> =
+                    read_gz(&shard_path(dir, lang), &mut hasher)?;
+                for (id, item) in shard.items {
+                    if ids.as_ref().is_none_or(|ids| ids.contains(&id)) {
+                        loaded.push(id);
+                        items.insert(id, item);
+                    }
+                }
+                aliases.extend(
+                    shard
+                        .aliases
+                        .into_iter()
+                        .filter(|(id, _)| items.contains_key(id)),
+                );
+            }
+            for parent in loaded
+                .iter()
+                .filter_map(|id| parents.get(id))
+                .flatten()
+                .filter(|parent| !items.contains_key(parent))
+            {
+                let lang = edges.langs[parent];
+                wanted
+                    .entry(lang)
+                    .or_insert_with(|| Some(HashSet::default()))
+                    .get_or_insert_with(HashSet::default)
+                    .insert(*parent);
+            }
+        }
+
+        let mut graph = EtyGraph::default();
+        let mut new_ids = HashMap::<ItemId, ItemId>::default();
+        for (old_id, item) in items.into_iter().sorted_by_key(|(id, _)| *id) {
+            let new_id = graph.add(item);
+            // The item that an imputed item was imputed from is only used in
+            // processing, and may not be loaded, so just point it at itself.
+            if let Item::Imputed(imputed) = graph.item_mut(new_id) {
+                imputed.from = new_id;
+            }
+            new_ids.insert(old_id, new_id);
+        }
+        for (child, parent, data) in edges.edges {
+            if let (Some(&child), Some(&parent)) = (new_ids.get(&child), new_ids.get(&parent)) {
+                graph.graph.add_edge(child, parent, data);
+            }
+        }
+        // Alternatives and surface analyses aren't followed when loading
+        // parents, so only those whose items were all loaded anyway are kept.
+        let remap = |alternative: AlternativeEty| {
+            let items = alternative
+                .items
+                .iter()
+                .map(|item| new_ids.get(item).copied())
+                .collect::<Option<_>>()?;
+            Some(AlternativeEty {
+                items,
+                ..alternative
+            })
+        };
+        for (child, alternatives) in edges.alternatives {
+            let Some(&child) = new_ids.get(&child) else {
+                continue;
+            };
+            let alternatives = alternatives.into_iter().filter_map(remap).collect_vec();
+            if !alternatives.is_empty() {
+                graph.alternatives.insert(child, alternatives);
+            }
+        }
+        for (item, analysis) in edges.surface_analyses {
+            if let Some(&item) = new_ids.get(&item)
+                && let Some(analysis) = remap(analysis)
+            {
+                graph.surface_analyses.insert(item, analysis);
+            }
+        }
+        let aliases = aliases
+            .into_iter()
+            .map(|(id, aliases)| (new_ids[&id], aliases))
+            .collect();
+        let mut data = Data::new(
+            string_pool,
+            graph,
+            aliases,
+            manifest.dump_date,
+            manifest.provenance,
+        );
+        data.stats.processed_date = manifest.processed_date;
+        data.content_hash = hasher.digest();
+        data.stable_id_index();
+        println!(
+            "Finished. Loaded {} items. Took {}.",
+            data.graph.len(),
+            HumanDuration(t.elapsed())
+        );
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ety_graph::{EdgeProvenance, EdgeSource},
+        etymology_templates::EtyMode,
+        items::test_item,
+    };
+
+    use std::str::FromStr;
+
+    fn add_item(graph: &mut EtyGraph, string_pool: &StringPool, lang: &str, term: &str) -> ItemId {
+        graph.add(Item::Real(test_item(string_pool, lang, term)))
+    }
+
+    #[test]
+    fn shards_load_requested_langs_and_ancestors() {
+        let string_pool = StringPool::new();
+        let mut graph = EtyGraph::default();
+        let gem = add_item(&mut graph, &string_pool, "gem-pro", "watōr");
+        let en = add_item(&mut graph, &string_pool, "en", "water");
+        let la = add_item(&mut graph, &string_pool, "la", "aqua");
+        let es = add_item(&mut graph, &string_pool, "es", "agua");
+        let provenance = EdgeProvenance::new(EdgeSource::Etymology, None, None);
+        graph.add_ety(en, EtyMode::Inherited, Some(0), &[gem], &[1.0], provenance);
+        graph.add_ety(es, EtyMode::Inherited, Some(0), &[la], &[1.0], provenance);
+        let data = Data::new(string_pool, graph, HashMap::default(), None, None);
+        let dir = std::env::temp_dir().join(format!("wety-{}.shards", std::process::id()));
+        data.serialize_shards(&dir).unwrap();
+        let loaded = Data::deserialize_shards(&dir, &[Lang::from_str("en").unwrap()]).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            loaded.snapshot(),
+            "en water 1 []\n  inherited 0 head <- gem-pro watōr 1\ngem-pro watōr 1 []\n"
+        );
+    }
+}
@@ -0,0 +1,69 @@
+use crate::{ety_graph::EtyEdgeAccess, items::ItemId, processed::Data};
+
+use std::fmt::Write;
+
+use itertools::Itertools;
+
+impl Data {
+    // A label for the item that does not depend on its id, which in turn
+    // depends on the order in which items were processed.
+    pub(crate) fn snapshot_label(&self, item_id: ItemId) -> String {
+        let item = self.graph.item(item_id);
+        let mut label = format!(
+            "{} {} {}",
+            item.lang().code(),
+            item.term().resolve(&self.string_pool),
+            item.ety_num()
+        );
+        if item.is_imputed() {
+            label.push_str(" imputed");
+        }
+        label
+    }
+
+    /// A canonical plain text rendering of the ety graph, for comparing the
+    /// results of processing across changes. Items are listed in sorted order,
+    /// each followed by its parents in ety order, e.g.
+    ///
+    /// ```text
+    /// en water 1 [noun, verb]
+    ///   inh 0 head <- enm water 1
+    /// ```
+    ///
+    /// Ety confidences are left out, since they depend on the embeddings and
+    /// may differ slightly across devices.
+    #[must_use]
+    pub fn snapshot(&self) -> String {
+        let mut items = self
+            .graph
+            .iter()
+            .map(|(item_id, item)| {
+                let mut lines = self.snapshot_label(item_id);
+                if let Some(pos) = item.pos() {
+                    let pos = pos.iter().map(|p| p.name()).join(", ");
+                    write!(lines, " [{pos}]").unwrap();
+                }
+                for edge in self
+                    .graph
+                    .parent_edges(item_id)
+                    .sorted_by_key(|e| e.order())
+                {
+                    write!(
+                        lines,
+                        "\n  {} {}{} <- {}",
+                        edge.mode().as_str(),
+                        edge.order(),
+                        if edge.head() { " head" } else { "" },
+                        self.snapshot_label(edge.parent())
+                    )
+                    .unwrap();
+                }
+                lines
+            })
+            .collect_vec();
+        items.sort_unstable();
+        let mut snapshot = items.join("\n");
+        snapshot.push('\n');
+        snapshot
+    }
+}
@@ -0,0 +1,296 @@
+use crate::{
+    embeddings,
+    ety_graph::{EtyEdgeAccess, EtyGraph},
+    input::LineRange,
+    items::ItemId,
+    languages::{self, Lang},
+    line_errors::LineErrorThresholds,
+    processed::Data,
+    HashMap, ProcessingOptions,
+};
+
+use std::{
+    cmp::Reverse,
+    fs,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use clap::ValueEnum;
+use itertools::Itertools;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+#[derive(Serialize, Deserialize, Default, Clone, Copy)]
+pub(crate) struct LangStats {
+    pub(crate) items: usize,
+    pub(crate) imputed_items: usize,
+    // edges whose child is in the lang
+    pub(crate) edges: usize,
+}
+
+/// Metadata about the processed data, for display by clients and monitors.
+#[derive(Serialize, Deserialize, Default)]
+pub(crate) struct Stats {
+    // The modification time of the raw wiktextract data file, in seconds since
+    // the unix epoch, as a stand-in for the date of the dump itself.
+    pub(crate) dump_date: Option<u64>,
+    // When the data was processed, in seconds since the unix epoch.
+    pub(crate) processed_date: Option<u64>,
+    pub(crate) langs: HashMap<Lang, LangStats>,
+    // The number of items at each ety depth, indexed by depth.
+    #[serde(default)]
+    pub(crate) depth_counts: Vec<usize>,
+    // Missing for data processed before this was recorded.
+    #[serde(default)]
+    pub(crate) provenance: Option<Provenance>,
+}
+
+/// Where the data came from and how it was processed, so that anything derived
+/// from it can be traced back to its source dump.
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct Provenance {
+    // The file name of the raw wiktextract data.
+    pub(crate) source_file: Option<String>,
+    // The date of the dump as given in the file name, e.g. 2024-03-01 for
+    // raw-wiktextract-data-20240301.json.gz, if there is one.
+    pub(crate) declared_dump_date: Option<String>,
+    pub(crate) processor_version: String,
+    pub(crate) imputation_policy: String,
+    pub(crate) strict: bool,
+    #[serde(default)]
+    pub(crate) max_line_errors: LineErrorThresholds,
+    // Missing for data processed before these were configurable.
+    #[serde(default)]
+    pub(crate) granularity: Option<String>,
+    #[serde(default)]
+    pub(crate) multi_word_policy: Option<String>,
+    #[serde(default)]
+    pub(crate) cycle_strategy: Option<String>,
+    #[serde(default)]
+    pub(crate) similarity_threshold: Option<f32>,
+    #[serde(default)]
+    pub(crate) term_similarity_weight: Option<f32>,
+    #[serde(default)]
+    pub(crate) raw_glosses: bool,
+    // "from=into" pairs of lang codes
+    #[serde(default)]
+    pub(crate) lang_merges: Vec<String>,
+    pub(crate) embeddings_model: String,
+    pub(crate) embeddings_model_revision: String,
+    #[serde(default)]
+    pub(crate) embeddings_url: Option<String>,
+    // Only these lines of the raw wiktextract data were processed.
+    #[serde(default)]
+    pub(crate) line_range: LineRange,
+}
+
+impl Provenance {
+    pub(crate) fn new(
+        wiktextract_path: &Path,
+        embeddings_config: &embeddings::Config,
+        options: ProcessingOptions,
+    ) -> Self {
+        let source_file = wiktextract_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned());
+        let declared_dump_date = source_file.as_deref().and_then(|name| {
+            let date = Regex::new(r"(\d{4})-?(\d{2})-?(\d{2})").expect("valid regex");
+            date.captures(name)
+                .map(|c| format!("{}-{}-{}", &c[1], &c[2], &c[3]))
+        });
+        Self {
+            source_file,
+            declared_dump_date,
+            processor_version: env!("CARGO_PKG_VERSION").to_string(),
+            imputation_policy: options
+                .imputation_policy
+                .to_possible_value()
+                .expect("no skipped variants")
+                .get_name()
+                .to_string(),
+            strict: options.strict,
+            max_line_errors: options.max_line_errors,
+            granularity: options
+                .granularity
+                .to_possible_value()
+                .map(|value| value.get_name().to_string()),
+            multi_word_policy: options
+                .multi_word_policy
+                .to_possible_value()
+                .map(|value| value.get_name().to_string()),
+            cycle_strategy: options
+                .cycle_strategy
+                .to_possible_value()
+                .map(|value| value.get_name().to_string()),
+            similarity_threshold: Some(options.similarity_threshold),
+            term_similarity_weight: Some(options.term_similarity_weight),
+            raw_glosses: options.raw_glosses,
+            lang_merges: languages::lang_merges(),
+            embeddings_model: embeddings_config.model_name.clone(),
+            embeddings_model_revision: embeddings_config.model_revision.clone(),
+            embeddings_url: embeddings_config.url.clone(),
+            line_range: options.line_range,
+        }
+    }
+
+    pub(crate) fn json(&self) -> Value {
+        json!({
+            "sourceFile": self.source_file,
+            "declaredDumpDate": self.declared_dump_date,
+            "processorVersion": self.processor_version,
+            "options": {
+                "imputationPolicy": self.imputation_policy,
+                "strict": self.strict,
+                "maxLineErrors": self.max_line_errors,
+                "granularity": self.granularity,
+                "multiWordPolicy": self.multi_word_policy,
+                "cycleStrategy": self.cycle_strategy,
+                "langMerges": self.lang_merges,
+                "similarityThreshold": self.similarity_threshold,
+                "termSimilarityWeight": self.term_similarity_weight,
+                "rawGlosses": self.raw_glosses,
+                "embeddingsModel": self.embeddings_model,
+                "embeddingsModelRevision": self.embeddings_model_revision,
+                "embeddingsUrl": self.embeddings_url,
+                "skipLines": self.line_range.skip,
+                "limitLines": self.line_range.limit,
+            },
+        })
+    }
+}
+
+fn unix_seconds(time: SystemTime) -> Option<u64> {
+    time.duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs())
+}
+
+/// Get the modification time of the raw wiktextract data file at `path`, as
+/// expected by `Stats::new`.
+pub(crate) fn dump_date(path: &Path) -> Option<u64> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(unix_seconds)
+}
+
+impl Stats {
+    pub(crate) fn new(
+        graph: &EtyGraph,
+        depths: &HashMap<ItemId, u16>,
+        dump_date: Option<u64>,
+        provenance: Option<Provenance>,
+    ) -> Self {
+        let mut langs = HashMap::<Lang, LangStats>::default();
+        let mut depth_counts = vec![];
+        for (id, item) in graph.iter() {
+            let depth = depths.get(&id).map_or(0, |&depth| usize::from(depth));
+            if depth_counts.len() <= depth {
+                depth_counts.resize(depth + 1, 0);
+            }
+            depth_counts[depth] += 1;
+            let lang_stats = langs.entry(item.lang()).or_default();
+            lang_stats.items += 1;
+            if item.is_imputed() {
+                lang_stats.imputed_items += 1;
+            }
+        }
+        for edge in graph.edges() {
+            langs
+                .entry(graph.item(edge.child()).lang())
+                .or_default()
+                .edges += 1;
+        }
+        Self {
+            dump_date,
+            processed_date: unix_seconds(SystemTime::now()),
+            langs,
+            depth_counts,
+            provenance,
+        }
+    }
+}
+
+impl Data {
+    /// Metadata about the data being served: the dump and processing dates
+    /// (in seconds since the unix epoch), where the data came from and how it
+    /// was processed, item and edge counts overall and per lang, the latter
+    /// sorted descending by item count, and the number of items at each ety
+    /// depth.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn stats_json(&self) -> Value {
+        let stats = &self.stats;
+        let total = stats
+            .langs
+            .values()
+            .fold(LangStats::default(), |total, lang_stats| LangStats {
+                items: total.items + lang_stats.items,
+                imputed_items: total.imputed_items + lang_stats.imputed_items,
+                edges: total.edges + lang_stats.edges,
+            });
+        let imputed_ratio = if total.items == 0 {
+            0.0
+        } else {
+            total.imputed_items as f64 / total.items as f64
+        };
+        let langs = stats
+            .langs
+            .iter()
+            .sorted_unstable_by_key(|(lang, lang_stats)| (Reverse(lang_stats.items), lang.code()))
+            .map(|(lang, lang_stats)| {
+                json!({
+                    "lang": lang.json(),
+                    "items": lang_stats.items,
+                    "imputedItems": lang_stats.imputed_items,
+                    "edges": lang_stats.edges,
+                })
+            })
+            .collect_vec();
+        json!({
+            "dumpDate": stats.dump_date,
+            "processedDate": stats.processed_date,
+            "provenance": stats.provenance.as_ref().map(Provenance::json),
+            "items": total.items,
+            "imputedItems": total.imputed_items,
+            "imputedRatio": imputed_ratio,
+            "edges": total.edges,
+            "maxDepth": stats.depth_counts.len().checked_sub(1),
+            "depthCounts": stats.depth_counts,
+            "langs": langs,
+        })
+    }
+
+    /// All langs that have items in the data, sorted by name, with their kind
+    /// and item count, and the codes of their ancestor langs from oldest to
+    /// most recent.
+    #[must_use]
+    pub fn langs_json(&self) -> Value {
+        let langs = self
+            .stats
+            .langs
+            .iter()
+            .filter(|(_, lang_stats)| lang_stats.items > 0)
+            .sorted_unstable_by_key(|(lang, _)| (lang.name(), lang.code()))
+            .map(|(&lang, lang_stats)| {
+                let ancestors = lang.ancestors();
+                // a lang's ancestors end with the lang itself
+                let ancestors = &ancestors[..ancestors.len().saturating_sub(1)];
+                json!({
+                    "id": lang.id(),
+                    "code": lang.code(),
+                    "name": lang.name(),
+                    "kind": lang.kind(),
+                    "scripts": lang.scripts(),
+                    "items": lang_stats.items,
+                    "ancestors": ancestors.iter().map(|a| a.code()).collect_vec(),
+                    "family": lang.family().map(|code| json!({
+                        "code": code,
+                        "name": lang.family_name(),
+                    })),
+                })
+            })
+            .collect_vec();
+        json!(langs)
+    }
+}
@@ -0,0 +1,219 @@
+//! Loading the data straight into an oxigraph store for SPARQL queries, as the
+//! triples that would otherwise be written to the Turtle file, without the
+//! multi-GB file in between. Also running queries against the store.
+
+use crate::{
+    etymology_templates::EtyMode,
+    languages::Lang,
+    processed::Data,
+    progress_bar,
+    turtle::{item_subject, ontology_turtle, write_quoted_str, Object, Props, DATASET, PRED_TYPE},
+};
+
+use std::{io::Write, path::Path, time::Instant};
+
+use anyhow::{bail, Ok, Result};
+use clap::ValueEnum;
+use indicatif::HumanDuration;
+use oxigraph::{
+    io::GraphFormat,
+    model::{
+        vocab::{rdf, xsd},
+        BlankNode, GraphName, GraphNameRef, Literal, NamedNode, Quad, Subject, Term,
+    },
+    sparql::{QueryResults, QueryResultsFormat},
+    store::Store,
+};
+
+// The p: and w: prefixes are declared as their own IRIs, so prefixed names
+// with them are already IRIs.
+fn named_node(name: &str) -> NamedNode {
+    if name == PRED_TYPE {
+        rdf::TYPE.into()
+    } else {
+        NamedNode::new_unchecked(name)
+    }
+}
+
+fn push_quads(quads: &mut Vec<Quad>, subject: &Subject, props: Props) {
+    for (pred, object) in props {
+        let object: Term = match object {
+            Object::Item(id) => named_node(&item_subject(id)).into(),
+            Object::Named(name) => named_node(name).into(),
+            Object::String(s) => Literal::new_simple_literal(s).into(),
+            Object::Integer(n) => Literal::new_typed_literal(n.to_string(), xsd::INTEGER).into(),
+            Object::Boolean(b) => Literal::new_typed_literal(b.to_string(), xsd::BOOLEAN).into(),
+            Object::Decimal(d) => {
+                Literal::new_typed_literal(format!("{d:.4}"), xsd::DECIMAL).into()
+            }
+            Object::Blank(props) => {
+                let blank = BlankNode::default();
+                push_quads(quads, &blank.clone().into(), props);
+                blank.into()
+            }
+        };
+        quads.push(Quad::new(
+            subject.clone(),
+            named_node(pred),
+            object,
+            GraphName::DefaultGraph,
+        ));
+    }
+}
+
+impl Data {
+    /// Load the data into a new oxigraph store at `store_path`, as the same
+    /// triples that [`Data::write_turtle`] writes.
+    pub(crate) fn load_store(&self, store_path: &Path) -> Result<()> {
+        let t = Instant::now();
+        let store = Store::open(store_path)?;
+        if !store.is_empty()? {
+            bail!("the SPARQL store {} is not empty", store_path.display());
+        }
+        let loader = store.bulk_loader();
+        loader.load_graph(
+            ontology_turtle()?.as_slice(),
+            GraphFormat::Turtle,
+            GraphNameRef::DefaultGraph,
+            None,
+        )?;
+        let mut quads = vec![];
+        if let Some(props) = self.dataset_props() {
+            push_quads(&mut quads, &named_node(DATASET).into(), props);
+        }
+        let pb = progress_bar(
+            self.graph.len(),
+            &format!("Loading RDF into SPARQL store {}", store_path.display()),
+        )?;
+        loader.load_quads(
+            quads
+                .into_iter()
+                .chain(self.graph.iter().flat_map(|(id, item)| {
+                    let mut quads = vec![];
+                    let subject = named_node(&item_subject(id)).into();
+                    push_quads(&mut quads, &subject, self.item_props(id, item));
+                    pb.inc(1);
+                    quads
+                })),
+        )?;
+        pb.finish();
+        println!("Finished. Took {}.", HumanDuration(t.elapsed()));
+        Ok(())
+    }
+}
+
+/// A query to run against the store, either built in or any SPARQL query.
+pub enum StoreQuery {
+    /// All the items that the term in the lang comes from, directly or not.
+    Ancestors {
+        lang: Lang,
+        term: String,
+    },
+    /// All the items that come from the term in the lang, e.g. a PIE root.
+    Descendants {
+        lang: Lang,
+        term: String,
+    },
+    /// The items in lang `into` that were borrowed from an item in lang `from`.
+    Loanwords {
+        from: Lang,
+        into: Lang,
+    },
+    Sparql(String),
+}
+
+// The modes that the loanwords query counts as borrowings.
+const BORROWING_MODES: &[EtyMode] = &[
+    EtyMode::Borrowed,
+    EtyMode::LearnedBorrowing,
+    EtyMode::SemiLearnedBorrowing,
+    EtyMode::UnadaptedBorrowing,
+    EtyMode::OrthographicBorrowing,
+];
+
+// Link an item to each of its parents in the ety graph. Oxigraph doesn't infer
+// the per-mode subproperties of p:etyParent, so go through the sources.
+const ETY_PARENT_PATH: &str = "p:source/p:item";
+
+fn quoted(s: &str) -> Result<String> {
+    let mut quoted = vec![];
+    write_quoted_str(&mut quoted, s)?;
+    Ok(String::from_utf8(quoted)?)
+}
+
+impl StoreQuery {
+    fn sparql(&self) -> Result<String> {
+        let prefix = "PREFIX p: <p:>\n";
+        Ok(match self {
+            StoreQuery::Ancestors { lang, term } => format!(
+                "{prefix}SELECT DISTINCT ?ancestor ?lang ?term WHERE {{
+  ?item p:lang {} ; p:term {} .
+  ?item ({ETY_PARENT_PATH})+ ?ancestor .
+  ?ancestor p:lang ?lang ; p:term ?term .
+}}",
+                quoted(lang.name())?,
+                quoted(term)?,
+            ),
+            StoreQuery::Descendants { lang, term } => format!(
+                "{prefix}SELECT DISTINCT ?descendant ?lang ?term WHERE {{
+  ?root p:lang {} ; p:term {} .
+  ?descendant ({ETY_PARENT_PATH})+ ?root .
+  ?descendant p:lang ?lang ; p:term ?term .
+}}",
+                quoted(lang.name())?,
+                quoted(term)?,
+            ),
+            StoreQuery::Loanwords { from, into } => format!(
+                "{prefix}SELECT ?item ?term ?mode ?source ?sourceTerm WHERE {{
+  ?item p:lang {} ; p:term ?term ; p:mode ?mode ; {ETY_PARENT_PATH} ?source .
+  ?source p:lang {} ; p:term ?sourceTerm .
+  FILTER(?mode IN ({}))
+}}",
+                quoted(into.name())?,
+                quoted(from.name())?,
+                BORROWING_MODES
+                    .iter()
+                    .map(|mode| quoted(mode.as_ref()))
+                    .collect::<Result<Vec<_>>>()?
+                    .join(", "),
+            ),
+            StoreQuery::Sparql(query) => query.clone(),
+        })
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ResultsFormat {
+    Json,
+    Csv,
+}
+
+/// Run `query` against the oxigraph store at `store_path`, writing the results
+/// to `writer` in `format`.
+///
+/// # Errors
+///
+/// Will return `Err` if the store cannot be opened, if the query is invalid or
+/// fails, or if it is a CONSTRUCT or DESCRIBE query, whose results are triples
+/// rather than rows.
+pub fn query_store(
+    store_path: &Path,
+    query: &StoreQuery,
+    format: ResultsFormat,
+    writer: impl Write,
+) -> Result<()> {
+    let store = Store::open(store_path)?;
+    if store.is_empty()? {
+        bail!("the SPARQL store {} is empty", store_path.display());
+    }
+    let results = store.query(query.sparql()?.as_str())?;
+    if let QueryResults::Graph(_) = results {
+        bail!("only SELECT and ASK queries are supported");
+    }
+    let format = match format {
+        ResultsFormat::Json => QueryResultsFormat::Json,
+        ResultsFormat::Csv => QueryResultsFormat::Csv,
+    };
+    results.write(writer, format)?;
+    Ok(())
+}
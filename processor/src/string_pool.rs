@@ -1,18 +1,40 @@
-use serde::{Deserialize, Serialize};
-use string_interner::{
-    backend::StringBackend, symbol::SymbolU32, StringInterner, Symbol as SymbolTrait,
+use crate::HashMap;
+
+use std::{
+    collections::hash_map::Entry,
+    env, fmt,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    mem,
+    path::{Path, PathBuf},
+    process, ptr, slice,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        RwLock,
+    },
+};
+
+use anyhow::{bail, Result};
+use indicatif::HumanBytes;
+use lasso::{Key, Spur, ThreadedRodeo};
+use memmap2::{Mmap, MmapOptions, MmapRaw};
+use serde::{
+    de::{SeqAccess, Visitor},
+    ser::SerializeStruct,
+    Deserialize, Serialize,
 };
+use xxhash_rust::xxh3::{xxh3_64, Xxh3Builder};
 
 #[derive(Hash, Eq, PartialEq, Debug, Copy, Clone)]
-pub(crate) struct Symbol(SymbolU32);
+pub(crate) struct Symbol(Spur);
 
-impl SymbolTrait for Symbol {
-    fn try_from_usize(index: usize) -> Option<Self> {
-        let symbol_u32 = SymbolU32::try_from_usize(index)?;
-        Some(Self(symbol_u32))
+impl Symbol {
+    pub(crate) fn try_from_usize(index: usize) -> Option<Self> {
+        Spur::try_from_usize(index).map(Self)
     }
-    fn to_usize(self) -> usize {
-        self.0.to_usize()
+
+    pub(crate) fn to_usize(self) -> usize {
+        self.0.into_usize()
     }
 }
 
@@ -31,20 +53,131 @@ impl<'de> Deserialize<'de> for Symbol {
         D: serde::Deserializer<'de>,
     {
         let s = usize::deserialize(deserializer)?;
-        Ok(Self::try_from_usize(s)
-            .expect("this was a SymbolU32 converted to a usize for serialize"))
+        Ok(Self::try_from_usize(s).expect("this was a symbol converted to a usize for serialize"))
     }
 }
 
-#[derive(Serialize, Deserialize)]
+// Symbols are handed out in the order strings are first interned, from any
+// number of threads, which is also the order they are serialized in.
+type Interner = ThreadedRodeo<Spur, Xxh3Builder>;
+
+/// The strings of the data. Interned while processing, and on the server
+/// either deserialized along with the rest of the data, or memory-mapped from
+/// a separate strings file, so that they are paged in as they are used rather
+/// than all held in memory. Interning only needs a shared reference, so the
+/// pool can be shared between threads processing items in parallel. For
+/// processing with less memory, the strings can instead be spilled to a temp
+/// file as they are interned (see [`StringPool::spilled`]).
 pub(crate) struct StringPool {
-    pool: StringInterner<StringBackend<Symbol>>,
+    strings: Strings,
+}
+
+enum Strings {
+    Interned(Box<Interner>),
+    Spilled(Box<SpilledStrings>),
+    Mapped(MappedStrings),
+}
+
+/// How many strings a pool has and how much memory it takes.
+pub(crate) struct PoolUsage {
+    pub(crate) strings: usize,
+    // the total length of the strings
+    pub(crate) bytes: usize,
+    // roughly how much memory the pool takes, counting the strings themselves
+    // only if they are held in memory rather than in a file
+    pub(crate) memory: usize,
+}
+
+impl fmt::Display for PoolUsage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} strings of {}, taking {} of memory",
+            self.strings,
+            HumanBytes(self.bytes as u64),
+            HumanBytes(self.memory as u64)
+        )
+    }
 }
 
 impl Default for StringPool {
     fn default() -> Self {
-        let pool: StringInterner<StringBackend<Symbol>> = StringInterner::new();
-        Self { pool }
+        Self {
+            strings: Strings::Interned(Box::new(Interner::with_hasher(Xxh3Builder::new()))),
+        }
+    }
+}
+
+// Both kinds of pool serialize as the sequence of the strings in symbol order,
+// under "pool".
+impl Serialize for StringPool {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        struct Pool<'a>(&'a StringPool);
+        impl Serialize for Pool<'_> {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: serde::Serializer,
+            {
+                serializer.collect_seq(self.0.iter())
+            }
+        }
+        let mut state = serializer.serialize_struct("StringPool", 1)?;
+        state.serialize_field("pool", &Pool(self))?;
+        state.end()
+    }
+}
+
+// The strings are interned as they are deserialized, in order, so that each
+// gets back the symbol it was serialized with.
+struct InternedSeq(Box<Interner>);
+
+impl<'de> Deserialize<'de> for InternedSeq {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct SeqVisitor;
+        impl<'de> Visitor<'de> for SeqVisitor {
+            type Value = InternedSeq;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a sequence of strings")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: SeqAccess<'de>,
+            {
+                let pool = StringPool::default();
+                while let Some(s) = seq.next_element::<String>()? {
+                    pool.get_or_intern(&s);
+                }
+                let Strings::Interned(interner) = pool.strings else {
+                    unreachable!("a new pool is interned")
+                };
+                Ok(InternedSeq(interner))
+            }
+        }
+        deserializer.deserialize_seq(SeqVisitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for StringPool {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Interned {
+            pool: InternedSeq,
+        }
+        let interned = Interned::deserialize(deserializer)?;
+        Ok(Self {
+            strings: Strings::Interned(interned.pool.0),
+        })
     }
 }
 
@@ -55,14 +188,309 @@ impl StringPool {
         }
     }
 
+    /// A pool that writes the strings to a temp file as they are interned,
+    /// holding only an index of them in memory. The file is memory-mapped, so
+    /// the strings are paged in as they are used, and can be paged out again
+    /// under memory pressure. It is in the temp dir (set by TMPDIR), which
+    /// should then be on disk rather than in memory.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the temp file cannot be created or mapped.
+    pub(crate) fn spilled() -> Result<Self> {
+        Ok(Self {
+            strings: Strings::Spilled(Box::new(SpilledStrings::new()?)),
+        })
+    }
+
+    /// This pool as a spilled one, with the same symbols.
+    pub(crate) fn spill(self) -> Result<Self> {
+        if let Strings::Spilled(_) = self.strings {
+            return Ok(self);
+        }
+        let spilled = Self::spilled()?;
+        for s in self.iter() {
+            spilled.get_or_intern(s);
+        }
+        Ok(spilled)
+    }
+
     pub(crate) fn resolve(&self, symbol: Symbol) -> &str {
-        self.pool
-            .resolve(symbol)
-            .expect("Resolve interned string from symbol")
+        match &self.strings {
+            Strings::Interned(pool) => pool.resolve(&symbol.0),
+            Strings::Spilled(strings) => strings.resolve(symbol),
+            Strings::Mapped(strings) => strings.get(symbol.to_usize()),
+        }
+    }
+
+    pub(crate) fn get_or_intern(&self, s: &str) -> Symbol {
+        match &self.strings {
+            Strings::Interned(pool) => Symbol(pool.get_or_intern(s)),
+            Strings::Spilled(strings) => strings.get_or_intern(s),
+            Strings::Mapped(_) => panic!("can't intern into a mapped string pool"),
+        }
     }
 
-    pub(crate) fn get_or_intern(&mut self, s: &str) -> Symbol {
-        self.pool.get_or_intern(s)
+    /// The symbol of the string if it has been interned.
+    pub(crate) fn get(&self, s: &str) -> Option<Symbol> {
+        match &self.strings {
+            Strings::Interned(pool) => pool.get(s).map(Symbol),
+            Strings::Spilled(strings) => {
+                let index = strings.index.read().expect("spill index not poisoned");
+                strings.find(&index, xxh3_64(s.as_bytes()), s)
+            }
+            Strings::Mapped(_) => panic!("can't look up strings in a mapped string pool"),
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        match &self.strings {
+            Strings::Interned(pool) => pool.len(),
+            Strings::Spilled(strings) => strings.len(),
+            Strings::Mapped(strings) => strings.len,
+        }
+    }
+
+    pub(crate) fn usage(&self) -> PoolUsage {
+        let strings = self.len();
+        let bytes = self.iter().map(str::len).sum();
+        let memory = match &self.strings {
+            // The interner's arena of the strings, plus its two maps between
+            // strings and symbols.
+            Strings::Interned(pool) => {
+                pool.current_memory_usage()
+                    + 2 * pool.len() * (mem::size_of::<&str>() + mem::size_of::<Spur>())
+            }
+            Strings::Spilled(strings) => strings.index_memory(),
+            // Paged in as needed, and out again as needed.
+            Strings::Mapped(_) => 0,
+        };
+        PoolUsage {
+            strings,
+            bytes,
+            memory,
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &str> {
+        (0..self.len())
+            .map(|i| self.resolve(Symbol::try_from_usize(i).expect("index of an existing string")))
+    }
+
+    /// Write the strings to `path` in the form that [`StringPool::map`] reads.
+    /// They are written to a temp file beside it, which then replaces it, so
+    /// that a server that has mapped the file at `path` keeps its mapping of
+    /// the old file, rather than seeing it truncated under it.
+    pub(crate) fn write_mapped(&self, path: &Path) -> Result<()> {
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_path);
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        writer.write_all(MAPPED_MAGIC)?;
+        writer.write_all(&u64::try_from(self.len())?.to_le_bytes())?;
+        let mut offset = 0u64;
+        writer.write_all(&offset.to_le_bytes())?;
+        for s in self.iter() {
+            offset += u64::try_from(s.len())?;
+            writer.write_all(&offset.to_le_bytes())?;
+        }
+        for s in self.iter() {
+            writer.write_all(s.as_bytes())?;
+        }
+        writer.flush()?;
+        drop(writer);
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    /// Memory-map the strings written to `path` by
+    /// [`StringPool::write_mapped`].
+    pub(crate) fn map(path: &Path) -> Result<Self> {
+        let file = File::open(path)?;
+        // SAFETY: the strings file is only ever written by the processor, which
+        // replaces it with a new file rather than writing to it, so the mapped
+        // file is never modified, even if the data is reprocessed while the
+        // server is running.
+        let mmap = unsafe { Mmap::map(&file)? };
+        let header_len = MAPPED_MAGIC.len() + 8;
+        if mmap.len() < header_len || &mmap[..MAPPED_MAGIC.len()] != MAPPED_MAGIC {
+            bail!("{} is not a strings file", path.display());
+        }
+        let len = usize::try_from(read_u64(&mmap, MAPPED_MAGIC.len()))?;
+        let strings = MappedStrings {
+            mmap,
+            len,
+            bytes_start: header_len + (len + 1) * 8,
+        };
+        if strings.mmap.len() < strings.bytes_start
+            || strings.mmap.len() != strings.bytes_start + strings.offset(len)
+        {
+            bail!("strings file {} is truncated", path.display());
+        }
+        Ok(Self {
+            strings: Strings::Mapped(strings),
+        })
+    }
+
+    /// The bytes of the strings file, if the pool is mapped, for hashing.
+    pub(crate) fn mapped_bytes(&self) -> Option<&[u8]> {
+        match &self.strings {
+            Strings::Interned(_) | Strings::Spilled(_) => None,
+            Strings::Mapped(strings) => Some(&strings.mmap),
+        }
+    }
+}
+
+// The spill file is mapped at this size up front, so that the mapping never has
+// to move as strings are added. The file is sparse, so only the strings written
+// to it take up disk space.
+const SPILL_CAPACITY: usize = 1 << 38;
+
+// for naming the spill files of pools in the same process
+static SPILL_FILES: AtomicUsize = AtomicUsize::new(0);
+
+struct SpilledStrings {
+    mmap: MmapRaw,
+    index: RwLock<SpillIndex>,
+}
+
+struct SpillIndex {
+    // the n + 1 offsets of the strings' bytes in the file
+    offsets: Vec<usize>,
+    // symbols by the hash of their string, and those of any later strings
+    // with the same hash
+    by_hash: HashMap<u64, Symbol>,
+    colliding: HashMap<u64, Vec<Symbol>>,
+}
+
+impl SpilledStrings {
+    fn new() -> Result<Self> {
+        let path = env::temp_dir().join(format!(
+            "wety-strings-{}-{}",
+            process::id(),
+            SPILL_FILES.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        // The file only lives as long as the mapping. Where an open file can't
+        // be removed, it is left in the temp dir.
+        let _ = fs::remove_file(&path);
+        file.set_len(SPILL_CAPACITY as u64)?;
+        let mmap = MmapOptions::new().map_raw(&file)?;
+        Ok(Self {
+            mmap,
+            index: RwLock::new(SpillIndex {
+                offsets: vec![0],
+                by_hash: HashMap::default(),
+                colliding: HashMap::default(),
+            }),
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.index
+            .read()
+            .expect("spill index not poisoned")
+            .offsets
+            .len()
+            - 1
+    }
+
+    fn get(&self, index: &SpillIndex, i: usize) -> &str {
+        let (start, end) = (index.offsets[i], index.offsets[i + 1]);
+        // SAFETY: the bytes of a string are written before its offsets are
+        // added to the index, and are never written again. The mapping lives
+        // as long as self.
+        let bytes = unsafe { slice::from_raw_parts(self.mmap.as_ptr().add(start), end - start) };
+        std::str::from_utf8(bytes).expect("spilled strings are utf-8")
+    }
+
+    fn resolve(&self, symbol: Symbol) -> &str {
+        let index = self.index.read().expect("spill index not poisoned");
+        self.get(&index, symbol.to_usize())
+    }
+
+    fn find(&self, index: &SpillIndex, hash: u64, s: &str) -> Option<Symbol> {
+        let &first = index.by_hash.get(&hash)?;
+        std::iter::once(first)
+            .chain(index.colliding.get(&hash).into_iter().flatten().copied())
+            .find(|symbol| self.get(index, symbol.to_usize()) == s)
+    }
+
+    fn get_or_intern(&self, s: &str) -> Symbol {
+        let hash = xxh3_64(s.as_bytes());
+        if let Some(symbol) = self.find(
+            &self.index.read().expect("spill index not poisoned"),
+            hash,
+            s,
+        ) {
+            return symbol;
+        }
+        let mut index = self.index.write().expect("spill index not poisoned");
+        // It may have been interned by another thread in the meantime.
+        if let Some(symbol) = self.find(&index, hash, s) {
+            return symbol;
+        }
+        let start = *index.offsets.last().expect("offsets start with 0");
+        let end = start + s.len();
+        assert!(end <= SPILL_CAPACITY, "the spilled string pool is full");
+        // SAFETY: the bytes after the last string are only written here, under
+        // the write lock, and are within the mapping.
+        unsafe {
+            ptr::copy_nonoverlapping(s.as_ptr(), self.mmap.as_mut_ptr().add(start), s.len());
+        }
+        let symbol =
+            Symbol::try_from_usize(index.offsets.len() - 1).expect("fewer than 2^32 strings");
+        index.offsets.push(end);
+        let index = &mut *index;
+        match index.by_hash.entry(hash) {
+            Entry::Occupied(_) => index.colliding.entry(hash).or_default().push(symbol),
+            Entry::Vacant(entry) => {
+                entry.insert(symbol);
+            }
+        }
+        symbol
+    }
+
+    fn index_memory(&self) -> usize {
+        let index = self.index.read().expect("spill index not poisoned");
+        index.offsets.capacity() * mem::size_of::<usize>()
+            + index.by_hash.capacity() * (mem::size_of::<u64>() + mem::size_of::<Symbol>())
+    }
+}
+
+// The header of a strings file, followed by the number of strings n, the n + 1
+// offsets of the strings' bytes, then the bytes. All numbers are little-endian
+// u64s.
+const MAPPED_MAGIC: &[u8] = b"WETYSTR1";
+
+struct MappedStrings {
+    mmap: Mmap,
+    len: usize,
+    bytes_start: usize,
+}
+
+fn read_u64(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().expect("8 bytes"))
+}
+
+impl MappedStrings {
+    #[allow(clippy::cast_possible_truncation)]
+    fn offset(&self, i: usize) -> usize {
+        read_u64(&self.mmap, MAPPED_MAGIC.len() + 8 + i * 8) as usize
+    }
+
+    fn get(&self, i: usize) -> &str {
+        let bytes =
+            &self.mmap[self.bytes_start + self.offset(i)..self.bytes_start + self.offset(i + 1)];
+        std::str::from_utf8(bytes).expect("mapped strings are utf-8")
     }
 }
 
@@ -72,7 +500,7 @@ mod tests {
 
     #[test]
     fn symbol_serialize() {
-        let s = Symbol(SymbolU32::try_from_usize(1337).unwrap());
+        let s = Symbol::try_from_usize(1337).unwrap();
         assert_eq!("1337", serde_json::to_string(&s).unwrap());
     }
 
@@ -81,4 +509,75 @@ mod tests {
         let s: Symbol = serde_json::from_str("1337").unwrap();
         assert_eq!(1337, s.to_usize());
     }
+
+    #[test]
+    fn interning_from_threads() {
+        let pool = StringPool::new();
+        let words = ["vocō", "vox", "λόγος", "word", "wort"];
+        let symbols = std::thread::scope(|scope| {
+            let handles = (0..4)
+                .map(|_| scope.spawn(|| words.map(|word| pool.get_or_intern(word))))
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+        assert_eq!(pool.len(), words.len());
+        assert!(symbols.iter().all(|s| *s == symbols[0]));
+        for (word, symbol) in words.iter().zip(symbols[0]) {
+            assert_eq!(pool.resolve(symbol), *word);
+        }
+        let deserialized: StringPool =
+            serde_json::from_str(&serde_json::to_string(&pool).unwrap()).unwrap();
+        for symbol in symbols[0] {
+            assert_eq!(deserialized.resolve(symbol), pool.resolve(symbol));
+        }
+    }
+
+    #[test]
+    fn spilled_strings() {
+        let pool = StringPool::new();
+        let spilled = StringPool::spilled().unwrap();
+        for s in ["vocō", "", "λόγος", "vocō", "vox"] {
+            assert_eq!(spilled.get_or_intern(s), pool.get_or_intern(s));
+        }
+        assert_eq!(spilled.len(), 4);
+        assert_eq!(spilled.get("vox"), pool.get("vox"));
+        assert_eq!(spilled.get("vocem"), None);
+        let symbol = pool.get("λόγος").unwrap();
+        assert_eq!(spilled.resolve(symbol), "λόγος");
+        assert_eq!(
+            serde_json::to_string(&spilled).unwrap(),
+            serde_json::to_string(&pool).unwrap()
+        );
+        let usage = spilled.usage();
+        assert_eq!((usage.strings, usage.bytes), (4, 18));
+        assert_eq!(pool.usage().bytes, 18);
+        let respilled = pool.spill().unwrap();
+        assert_eq!(respilled.resolve(symbol), "λόγος");
+    }
+
+    #[test]
+    fn mapped_strings() {
+        let pool = StringPool::new();
+        let symbols = ["vocō", "", "λόγος"].map(|s| pool.get_or_intern(s));
+        let path = std::env::temp_dir().join(format!("wety-{}.strings", std::process::id()));
+        pool.write_mapped(&path).unwrap();
+        let mapped = StringPool::map(&path).unwrap();
+        // e.g. the data is reprocessed while the server has the file mapped
+        let other = StringPool::new();
+        other.get_or_intern("aqua");
+        other.write_mapped(&path).unwrap();
+        assert_eq!(StringPool::map(&path).unwrap().len(), 1);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(mapped.len(), 3);
+        for symbol in symbols {
+            assert_eq!(mapped.resolve(symbol), pool.resolve(symbol));
+        }
+        assert_eq!(
+            serde_json::to_string(&mapped).unwrap(),
+            serde_json::to_string(&pool).unwrap()
+        );
+    }
 }
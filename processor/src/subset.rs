@@ -0,0 +1,105 @@
+//! Extraction of the part of the data that a few langs need, for serving only
+//! those langs without reprocessing the wiktextract data.
+
+use crate::{ety_graph::EtyEdgeAccess, items::ItemId, languages::Lang, processed::Data, HashSet};
+
+use std::time::Instant;
+
+use indicatif::HumanDuration;
+use itertools::Itertools;
+
+impl Data {
+    /// Prune the data to the items in `langs`, along with the items in other
+    /// langs that they descend from (including any imputed items in between),
+    /// so that their etymologies are complete. Unlike with shards, items keep
+    /// their ids. The string pool is kept as is.
+    #[must_use]
+    pub fn into_subset(self, langs: &[Lang]) -> Self {
+        let t = Instant::now();
+        println!(
+            "Extracting the items of {} and their ancestors...",
+            langs.iter().map(|lang| lang.code()).join(", ")
+        );
+        let mut kept = HashSet::<ItemId>::default();
+        let mut stack = self
+            .graph
+            .iter()
+            .filter(|(_, item)| langs.contains(&item.lang()))
+            .map(|(id, _)| id)
+            .collect_vec();
+        while let Some(id) = stack.pop() {
+            if kept.insert(id) {
+                stack.extend(self.graph.parent_edges(id).map(|e| e.parent()));
+            }
+        }
+
+        let Self {
+            string_pool,
+            mut graph,
+            mut aliases,
+            stats,
+            ..
+        } = self;
+        // This also removes all the edges of the removed items.
+        graph.graph.retain_nodes(|_, id| kept.contains(&id));
+        // As with shards, alternatives are only kept if all their items are.
+        graph.alternatives.retain(|id, alternatives| {
+            alternatives.retain(|a| a.items.iter().all(|item| kept.contains(item)));
+            kept.contains(id) && !alternatives.is_empty()
+        });
+        graph.surface_analyses.retain(|id, analysis| {
+            kept.contains(id) && analysis.items.iter().all(|item| kept.contains(item))
+        });
+        aliases.retain(|id, _| kept.contains(id));
+        let mut data = Self::new(
+            string_pool,
+            graph,
+            aliases,
+            stats.dump_date,
+            stats.provenance,
+        );
+        data.stats.processed_date = stats.processed_date;
+        println!(
+            "Finished. Kept {} items. Took {}.",
+            data.graph.len(),
+            HumanDuration(t.elapsed())
+        );
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ety_graph::{EdgeProvenance, EdgeSource, EtyGraph},
+        etymology_templates::EtyMode,
+        items::{test_item, Item},
+        string_pool::StringPool,
+        HashMap,
+    };
+
+    use std::str::FromStr;
+
+    fn add_item(graph: &mut EtyGraph, string_pool: &StringPool, lang: &str, term: &str) -> ItemId {
+        graph.add(Item::Real(test_item(string_pool, lang, term)))
+    }
+
+    #[test]
+    fn subset_keeps_langs_and_ancestors_with_their_ids() {
+        let string_pool = StringPool::new();
+        let mut graph = EtyGraph::default();
+        let la = add_item(&mut graph, &string_pool, "la", "aqua");
+        let gem = add_item(&mut graph, &string_pool, "gem-pro", "watōr");
+        let en = add_item(&mut graph, &string_pool, "en", "water");
+        let es = add_item(&mut graph, &string_pool, "es", "agua");
+        let provenance = EdgeProvenance::new(EdgeSource::Etymology, None, None);
+        graph.add_ety(en, EtyMode::Inherited, Some(0), &[gem], &[1.0], provenance);
+        graph.add_ety(es, EtyMode::Inherited, Some(0), &[la], &[1.0], provenance);
+        let data = Data::new(string_pool, graph, HashMap::default(), None, None);
+        let subset = data.into_subset(&[Lang::from_str("en").unwrap()]);
+        assert_eq!(subset.graph.len(), 2);
+        assert!(!subset.contains(la) && !subset.contains(es));
+        assert_eq!(subset.progenitors[&en].head, Some(gem));
+    }
+}
@@ -1,4 +1,7 @@
-use crate::{items::Item, processed::Data, progress_bar, ItemId};
+use crate::{
+    ety_graph::EtyEdgeAccess, etymology_templates::EtyMode, items::Item, processed::Data,
+    progress_bar, HashMap, ItemId,
+};
 
 use std::{
     fs::File,
@@ -7,6 +10,9 @@ use std::{
 };
 
 use anyhow::{Ok, Result};
+use itertools::Itertools;
+use lazy_static::lazy_static;
+use strum::IntoEnumIterator;
 
 const WIKTIONARY_PRE: &str = "k:";
 const WIKTIONARY_URL: &str = "https://en.wiktionary.org/wiki/";
@@ -23,6 +29,8 @@ const PRED_PAGE_TERM: &str = "p:pageTerm";
 const PRED_LANG: &str = "p:lang";
 const PRED_ROMANIZATION: &str = "p:romanization";
 const PRED_URL: &str = "p:url";
+const PRED_PAGE_TITLE: &str = "p:pageTitle";
+const PRED_PAGE_ID: &str = "p:pageId";
 const PRED_POS: &str = "p:pos";
 const PRED_GLOSS: &str = "p:gloss";
 const PRED_ETY_NUM: &str = "p:etyNum";
@@ -31,16 +39,43 @@ const PRED_MODE: &str = "p:mode";
 const PRED_HEAD: &str = "p:head";
 const PRED_HEAD_PROGENITOR: &str = "p:headProgenitor";
 const PRED_PROGENITOR: &str = "p:progenitor";
+const PRED_COGNATE_SET: &str = "p:cognateSet";
+// The super-property of the per-mode properties linking an item directly to
+// each of its parents, e.g. p:inherited.
+const PRED_ETY_PARENT: &str = "p:etyParent";
+
+const CLASS_ITEM: &str = "p:Item";
+const CLASS_SOURCE: &str = "p:Source";
+const CLASS_DATASET: &str = "p:Dataset";
 
-// These two are used in every blank node defining a source.
+// These are used in every blank node defining a source, which carries the
+// data of the ety link to the parent.
 const PRED_ITEM: &str = "p:item";
 const PRED_ORDER: &str = "p:order";
+const PRED_IS_HEAD: &str = "p:isHead";
+const PRED_CONFIDENCE: &str = "p:confidence";
+const PRED_EVIDENCE: &str = "p:evidence";
+const PRED_TEMPLATE: &str = "p:template";
+const PRED_LINE: &str = "p:line";
+const PRED_VARIETY: &str = "p:variety";
+
+// Describes the data as a whole, e.g. which dump it came from.
+pub(crate) const DATASET: &str = "w:dataset";
+const PRED_DUMP_DATE: &str = "p:dumpDate";
+const PRED_PROCESSED_DATE: &str = "p:processedDate";
+const PRED_SOURCE_FILE: &str = "p:sourceFile";
+const PRED_DECLARED_DUMP_DATE: &str = "p:declaredDumpDate";
+const PRED_PROCESSOR_VERSION: &str = "p:processorVersion";
+const PRED_IMPUTATION_POLICY: &str = "p:imputationPolicy";
+const PRED_STRICT: &str = "p:strict";
+const PRED_EMBEDDINGS_MODEL: &str = "p:embeddingsModel";
+const PRED_EMBEDDINGS_MODEL_REVISION: &str = "p:embeddingsModelRevision";
 
-fn write_prefix(f: &mut BufWriter<File>, prefix: &str, iri: &str) -> Result<()> {
+fn write_prefix(f: &mut impl Write, prefix: &str, iri: &str) -> Result<()> {
     writeln!(f, "@prefix {prefix} <{iri}> .")?;
     Ok(())
 }
-fn write_prefixes(f: &mut BufWriter<File>) -> Result<()> {
+fn write_prefixes(f: &mut impl Write) -> Result<()> {
     write_prefix(f, WIKTIONARY_PRE, WIKTIONARY_URL)?;
     write_prefix(
         f,
@@ -49,10 +84,363 @@ fn write_prefixes(f: &mut BufWriter<File>) -> Result<()> {
     )?;
     write_prefix(f, PRED_PRE, PRED_PRE)?;
     write_prefix(f, ITEM_PRE, ITEM_PRE)?;
+    write_prefix(f, "rdf:", "http://www.w3.org/1999/02/22-rdf-syntax-ns#")?;
+    write_prefix(f, "rdfs:", "http://www.w3.org/2000/01/rdf-schema#")?;
+    write_prefix(f, "owl:", "http://www.w3.org/2002/07/owl#")?;
+    write_prefix(f, "xsd:", "http://www.w3.org/2001/XMLSchema#")?;
+    Ok(())
+}
+
+lazy_static! {
+    // The property linking an item directly to a parent by each mode, e.g.
+    // p:learnedBorrowing.
+    static ref MODE_PROPERTIES: HashMap<EtyMode, String> = EtyMode::iter()
+        .map(|mode| {
+            let name = format!("{mode:?}");
+            let mut chars = name.chars();
+            let first = chars.next().expect("mode name is not empty");
+            let property = format!("{PRED_PRE}{}{}", first.to_ascii_lowercase(), chars.as_str());
+            (mode, property)
+        })
+        .collect();
+}
+
+fn mode_property(mode: EtyMode) -> &'static str {
+    &MODE_PROPERTIES[&mode]
+}
+
+// (class, label, comment)
+const CLASSES: &[(&str, &str, &str)] = &[
+    (
+        CLASS_ITEM,
+        "item",
+        "An etymologically distinct term in a lang, e.g. one numbered etymology of a Wiktionary entry.",
+    ),
+    (
+        CLASS_SOURCE,
+        "ety source",
+        "One of the parents of an item in its immediate etymology.",
+    ),
+    (
+        CLASS_DATASET,
+        "dataset",
+        "The data as a whole, with where it came from and how it was processed.",
+    ),
+];
+
+// (property, domain, range, label, comment), for properties with literal
+// values
+const DATATYPE_PROPERTIES: &[(&str, &str, &str, &str, &str)] = &[
+    (
+        PRED_TERM,
+        CLASS_ITEM,
+        "xsd:string",
+        "term",
+        "The term, as given in etymologies.",
+    ),
+    (
+        PRED_PAGE_TERM,
+        CLASS_ITEM,
+        "xsd:string",
+        "page term",
+        "The term as in the title of its Wiktionary page, if different.",
+    ),
+    (
+        PRED_LANG,
+        CLASS_ITEM,
+        "xsd:string",
+        "lang",
+        "The name of the item's lang.",
+    ),
+    (
+        PRED_ROMANIZATION,
+        CLASS_ITEM,
+        "xsd:string",
+        "romanization",
+        "The romanization of a term in a non-Latin script.",
+    ),
+    (
+        PRED_URL,
+        CLASS_ITEM,
+        "xsd:string",
+        "url",
+        "The url of the item's Wiktionary entry.",
+    ),
+    (
+        PRED_PAGE_TITLE,
+        CLASS_ITEM,
+        "xsd:string",
+        "page title",
+        "The title of the item's Wiktionary page, by which it can be found in Wiktionary dumps.",
+    ),
+    (
+        PRED_PAGE_ID,
+        CLASS_ITEM,
+        "xsd:integer",
+        "page id",
+        "The id of the item's Wiktionary page, if the wiktextract data had it.",
+    ),
+    (
+        PRED_POS,
+        CLASS_ITEM,
+        "xsd:string",
+        "part of speech",
+        "A part of speech of the item.",
+    ),
+    (
+        PRED_GLOSS,
+        CLASS_ITEM,
+        "xsd:string",
+        "gloss",
+        "A gloss of the item.",
+    ),
+    (
+        PRED_ETY_NUM,
+        CLASS_ITEM,
+        "xsd:integer",
+        "etymology number",
+        "The number of the item's etymology among those of its term in its lang.",
+    ),
+    (
+        PRED_IS_IMPUTED,
+        CLASS_ITEM,
+        "xsd:boolean",
+        "is imputed",
+        "Whether the item has no Wiktionary entry of its own, but was imputed from a mention in another item's etymology.",
+    ),
+    (
+        PRED_IS_RECONSTRUCTED,
+        CLASS_ITEM,
+        "xsd:boolean",
+        "is reconstructed",
+        "Whether the item is a reconstructed term.",
+    ),
+    (
+        PRED_MODE,
+        CLASS_ITEM,
+        "xsd:string",
+        "ety mode",
+        "How the item came from its parents, e.g. \"inherited\".",
+    ),
+    (
+        PRED_HEAD,
+        CLASS_ITEM,
+        "xsd:integer",
+        "head",
+        "The order of the head parent among the item's parents, e.g. of the root in a compound.",
+    ),
+    (
+        PRED_COGNATE_SET,
+        CLASS_ITEM,
+        "xsd:string",
+        "cognate set",
+        "The id of the set of items sharing the item's head progenitor, which is stable across runs.",
+    ),
+    (
+        PRED_ORDER,
+        CLASS_SOURCE,
+        "xsd:integer",
+        "order",
+        "The position of the parent among the item's parents.",
+    ),
+    (
+        PRED_IS_HEAD,
+        CLASS_SOURCE,
+        "xsd:boolean",
+        "is head",
+        "Whether the parent is the head of the item's parents, e.g. the root in a compound.",
+    ),
+    (
+        PRED_CONFIDENCE,
+        CLASS_SOURCE,
+        "xsd:decimal",
+        "confidence",
+        "How confident processing was in disambiguating the parent among the items with its term.",
+    ),
+    (
+        PRED_EVIDENCE,
+        CLASS_SOURCE,
+        "xsd:string",
+        "evidence",
+        "The kind of section the link was found in, e.g. \"descendants\".",
+    ),
+    (
+        PRED_TEMPLATE,
+        CLASS_SOURCE,
+        "xsd:string",
+        "template",
+        "The name of the template the link was parsed from.",
+    ),
+    (
+        PRED_LINE,
+        CLASS_SOURCE,
+        "xsd:integer",
+        "line",
+        "The line in the raw wiktextract data of the entry whose section the link was found in.",
+    ),
+    (
+        PRED_VARIETY,
+        CLASS_SOURCE,
+        "xsd:string",
+        "variety",
+        "The code of the etymology-only variety, e.g. a dialect, that the child was given in, if it was found as a term of the variety's regular lang.",
+    ),
+    (
+        PRED_DUMP_DATE,
+        CLASS_DATASET,
+        "xsd:integer",
+        "dump date",
+        "The modification time of the raw wiktextract data, in seconds since the unix epoch.",
+    ),
+    (
+        PRED_PROCESSED_DATE,
+        CLASS_DATASET,
+        "xsd:integer",
+        "processed date",
+        "When the data was processed, in seconds since the unix epoch.",
+    ),
+    (
+        PRED_SOURCE_FILE,
+        CLASS_DATASET,
+        "xsd:string",
+        "source file",
+        "The file name of the raw wiktextract data.",
+    ),
+    (
+        PRED_DECLARED_DUMP_DATE,
+        CLASS_DATASET,
+        "xsd:string",
+        "declared dump date",
+        "The dump date given in the file name of the raw wiktextract data.",
+    ),
+    (
+        PRED_PROCESSOR_VERSION,
+        CLASS_DATASET,
+        "xsd:string",
+        "processor version",
+        "The version of the processor that generated the data.",
+    ),
+    (
+        PRED_IMPUTATION_POLICY,
+        CLASS_DATASET,
+        "xsd:string",
+        "imputation policy",
+        "When items were imputed.",
+    ),
+    (
+        PRED_STRICT,
+        CLASS_DATASET,
+        "xsd:boolean",
+        "strict",
+        "Whether processing aborted on anomalies rather than skipping them.",
+    ),
+    (
+        PRED_EMBEDDINGS_MODEL,
+        CLASS_DATASET,
+        "xsd:string",
+        "embeddings model",
+        "The embeddings model used for word sense disambiguation.",
+    ),
+    (
+        PRED_EMBEDDINGS_MODEL_REVISION,
+        CLASS_DATASET,
+        "xsd:string",
+        "embeddings model revision",
+        "The revision of the embeddings model.",
+    ),
+];
+
+// (property, domain, range, label, comment), for properties linking resources
+const OBJECT_PROPERTIES: &[(&str, &str, &str, &str, &str)] = &[
+    (
+        PRED_SOURCE,
+        CLASS_ITEM,
+        CLASS_SOURCE,
+        "ety source",
+        "One of the parents of the item, with its position among them.",
+    ),
+    (
+        PRED_ITEM,
+        CLASS_SOURCE,
+        CLASS_ITEM,
+        "item",
+        "The parent item of an ety source.",
+    ),
+    (
+        PRED_ETY_PARENT,
+        CLASS_ITEM,
+        CLASS_ITEM,
+        "ety parent",
+        "A parent of the item in its immediate etymology. Only its subproperties, one per ety mode, are used.",
+    ),
+    (
+        PRED_PROGENITOR,
+        CLASS_ITEM,
+        CLASS_ITEM,
+        "progenitor",
+        "An ultimate ancestor of the item, i.e. a leaf of its ancestry tree.",
+    ),
+    (
+        PRED_HEAD_PROGENITOR,
+        CLASS_ITEM,
+        CLASS_ITEM,
+        "head progenitor",
+        "The ultimate ancestor reached by following head parents.",
+    ),
+];
+
+fn write_label_and_comment(f: &mut impl Write, label: &str, comment: &str) -> Result<()> {
+    write_item_quoted_prop(f, "rdfs:label", label)?;
+    write_item_quoted_prop(f, "rdfs:comment", comment)?;
+    Ok(())
+}
+
+// Define the classes and properties used, so that the data is self-describing
+// and can be loaded into ontology tools and reasoners.
+fn write_ontology(f: &mut impl Write) -> Result<()> {
+    writeln!(f, "<{PRED_PRE}> a owl:Ontology ;")?;
+    write_label_and_comment(
+        f,
+        "wety",
+        "Etymologies of the terms on Wiktionary, as an ety graph of items.",
+    )?;
+    writeln!(f, ".")?;
+    for &(class, label, comment) in CLASSES {
+        writeln!(f, "{class} a owl:Class ;")?;
+        write_label_and_comment(f, label, comment)?;
+        writeln!(f, ".")?;
+    }
+    for (kind, properties) in [
+        ("owl:DatatypeProperty", DATATYPE_PROPERTIES),
+        ("owl:ObjectProperty", OBJECT_PROPERTIES),
+    ] {
+        for &(property, domain, range, label, comment) in properties {
+            writeln!(f, "{property} a {kind} ;")?;
+            writeln!(f, "  rdfs:domain {domain} ;")?;
+            writeln!(f, "  rdfs:range {range} ;")?;
+            write_label_and_comment(f, label, comment)?;
+            writeln!(f, ".")?;
+        }
+    }
+    for mode in EtyMode::iter() {
+        writeln!(f, "{} a owl:ObjectProperty ;", mode_property(mode))?;
+        writeln!(f, "  rdfs:subPropertyOf {PRED_ETY_PARENT} ;")?;
+        writeln!(f, "  rdfs:domain {CLASS_ITEM} ;")?;
+        writeln!(f, "  rdfs:range {CLASS_ITEM} ;")?;
+        write_label_and_comment(
+            f,
+            mode.as_ref(),
+            &format!(
+                "A parent of the item, from which it came by {}.",
+                mode.as_ref()
+            ),
+        )?;
+        writeln!(f, ".")?;
+    }
     Ok(())
 }
 // cf. https://www.w3.org/TR/turtle/#turtle-literals
-fn write_quoted_str(f: &mut BufWriter<File>, s: &str) -> Result<()> {
+pub(crate) fn write_quoted_str(f: &mut impl Write, s: &str) -> Result<()> {
     write!(f, "\"")?;
     for c in s.chars() {
         match c {
@@ -66,111 +454,228 @@ fn write_quoted_str(f: &mut BufWriter<File>, s: &str) -> Result<()> {
     write!(f, "\"")?;
     Ok(())
 }
-fn write_item_quoted_prop(f: &mut BufWriter<File>, pred: &str, obj: &str) -> Result<()> {
+fn write_item_quoted_prop(f: &mut impl Write, pred: &str, obj: &str) -> Result<()> {
     write!(f, "  {pred} ")?;
     write_quoted_str(f, obj)?;
     writeln!(f, " ;")?;
     Ok(())
 }
 
-fn write_list_delim(f: &mut BufWriter<File>, i: usize, len: usize) -> Result<()> {
-    if i + 1 < len {
-        write!(f, ", ")?;
-    } else {
-        writeln!(f, " ;")?;
-    }
-    Ok(())
+/// The prefixes and ontology that start the Turtle file.
+#[cfg(feature = "store")]
+pub(crate) fn ontology_turtle() -> Result<Vec<u8>> {
+    let mut turtle = vec![];
+    write_prefixes(&mut turtle)?;
+    write_ontology(&mut turtle)?;
+    Ok(turtle)
 }
 
-impl Data {
-    fn write_turtle_item(&self, f: &mut BufWriter<File>, id: ItemId, item: &Item) -> Result<()> {
-        writeln!(f, "{ITEM_PRE}{}", id.index())?;
-
-        write_item_quoted_prop(f, PRED_LANG, item.lang().name())?;
+/// The object of a triple, for writing as Turtle or loading into a store.
+pub(crate) enum Object {
+    Item(ItemId),
+    // a class or other resource, by its prefixed name
+    Named(&'static str),
+    String(String),
+    Integer(u64),
+    Boolean(bool),
+    Decimal(f32),
+    // a blank node with these props
+    Blank(Props),
+}
 
-        let term = item.term().resolve(&self.string_pool);
-        write_item_quoted_prop(f, PRED_TERM, term)?;
+/// The (predicate, object) pairs of the triples about some subject, in the
+/// order they are written. The predicate is a prefixed name, or [`PRED_TYPE`].
+pub(crate) type Props = Vec<(&'static str, Object)>;
 
-        if let Some(page_term) = item.page_term() {
-            write_item_quoted_prop(f, PRED_PAGE_TERM, page_term.resolve(&self.string_pool))?;
-        };
+pub(crate) const PRED_TYPE: &str = "a";
 
-        if let Some(romanization) = item.romanization() {
-            write_item_quoted_prop(
-                f,
-                PRED_ROMANIZATION,
-                romanization.resolve(&self.string_pool),
-            )?;
-        };
-
-        writeln!(f, "  {PRED_ETY_NUM} {} ;", item.ety_num())?;
+pub(crate) fn item_subject(id: ItemId) -> String {
+    format!("{ITEM_PRE}{}", id.index())
+}
 
-        if let Some(pos) = &item.pos() {
-            write!(f, "  {PRED_POS} ")?;
-            for (p_i, p) in pos.iter().map(|p| p.name()).enumerate() {
-                write_quoted_str(f, p)?;
-                write_list_delim(f, p_i, pos.len())?;
+fn write_object(f: &mut impl Write, object: &Object) -> Result<()> {
+    match object {
+        Object::Item(id) => write!(f, "{}", item_subject(*id))?,
+        Object::Named(name) => write!(f, "{name}")?,
+        Object::String(s) => write_quoted_str(f, s)?,
+        Object::Integer(n) => write!(f, "{n}")?,
+        Object::Boolean(b) => write!(f, "{b}")?,
+        Object::Decimal(d) => write!(f, "{d:.4}")?,
+        Object::Blank(props) => {
+            write!(f, "[ ")?;
+            for (i, (pred, object)) in props.iter().enumerate() {
+                if i > 0 {
+                    write!(f, "; ")?;
+                }
+                write!(f, "{pred} ")?;
+                write_object(f, object)?;
             }
-        };
+            write!(f, " ]")?;
+        }
+    }
+    Ok(())
+}
 
-        if let Some(gloss) = &item.gloss() {
-            write!(f, "  {PRED_GLOSS} ")?;
-            for (g_i, g) in gloss.iter().enumerate() {
-                write_quoted_str(f, &g.to_string(&self.string_pool))?;
-                write_list_delim(f, g_i, gloss.len())?;
+// Write the props of the subject, with the objects of consecutive props with
+// the same predicate as a list.
+fn write_subject(f: &mut impl Write, subject: &str, props: &Props) -> Result<()> {
+    writeln!(f, "{subject}")?;
+    for (pred, objects) in &props.iter().group_by(|(pred, _)| *pred) {
+        write!(f, "  {pred} ")?;
+        for (i, (_, object)) in objects.enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
             }
+            write_object(f, object)?;
         }
+        writeln!(f, " ;")?;
+    }
+    writeln!(f, ".")?;
+    Ok(())
+}
 
-        if let Some(url) = item.url(&self.string_pool) {
-            write_item_quoted_prop(f, PRED_URL, &url)?;
-        };
+impl Data {
+    // Only for data that records its provenance, which is always the case for
+    // data being processed now.
+    pub(crate) fn dataset_props(&self) -> Option<Props> {
+        let provenance = self.stats.provenance.as_ref()?;
+        let mut props = vec![(PRED_TYPE, Object::Named(CLASS_DATASET))];
+        if let Some(dump_date) = self.stats.dump_date {
+            props.push((PRED_DUMP_DATE, Object::Integer(dump_date)));
+        }
+        if let Some(processed_date) = self.stats.processed_date {
+            props.push((PRED_PROCESSED_DATE, Object::Integer(processed_date)));
+        }
+        if let Some(source_file) = &provenance.source_file {
+            props.push((PRED_SOURCE_FILE, Object::String(source_file.clone())));
+        }
+        if let Some(declared_dump_date) = &provenance.declared_dump_date {
+            props.push((
+                PRED_DECLARED_DUMP_DATE,
+                Object::String(declared_dump_date.clone()),
+            ));
+        }
+        props.extend([
+            (
+                PRED_PROCESSOR_VERSION,
+                Object::String(provenance.processor_version.clone()),
+            ),
+            (
+                PRED_IMPUTATION_POLICY,
+                Object::String(provenance.imputation_policy.clone()),
+            ),
+            (PRED_STRICT, Object::Boolean(provenance.strict)),
+            (
+                PRED_EMBEDDINGS_MODEL,
+                Object::String(provenance.embeddings_model.clone()),
+            ),
+            (
+                PRED_EMBEDDINGS_MODEL_REVISION,
+                Object::String(provenance.embeddings_model_revision.clone()),
+            ),
+        ]);
+        Some(props)
+    }
 
+    pub(crate) fn item_props(&self, id: ItemId, item: &Item) -> Props {
+        let string = |s: &str| Object::String(s.to_string());
+        let mut props = vec![
+            (PRED_TYPE, Object::Named(CLASS_ITEM)),
+            (PRED_LANG, string(item.lang().name())),
+            (PRED_TERM, string(item.term().resolve(&self.string_pool))),
+        ];
+        if let Some(page_term) = item.page_term() {
+            props.push((PRED_PAGE_TERM, string(page_term.resolve(&self.string_pool))));
+        }
+        if let Some(romanization) = item.romanization() {
+            props.push((
+                PRED_ROMANIZATION,
+                string(romanization.resolve(&self.string_pool)),
+            ));
+        }
+        props.push((PRED_ETY_NUM, Object::Integer(item.ety_num().into())));
+        for pos in item.pos().into_iter().flatten() {
+            props.push((PRED_POS, string(pos.name())));
+        }
+        for gloss in item.gloss().into_iter().flatten() {
+            props.push((
+                PRED_GLOSS,
+                Object::String(gloss.to_string(&self.string_pool)),
+            ));
+        }
+        if let Some(url) = item.url(&self.string_pool) {
+            props.push((PRED_URL, Object::String(url)));
+        }
+        if let Some(page_title) = item.page_title(&self.string_pool) {
+            props.push((PRED_PAGE_TITLE, Object::String(page_title)));
+        }
+        if let Some(page_id) = item.page_id() {
+            props.push((PRED_PAGE_ID, Object::Integer(page_id)));
+        }
         if item.is_imputed() {
-            writeln!(f, "  {PRED_IS_IMPUTED} true ;")?;
+            props.push((PRED_IS_IMPUTED, Object::Boolean(true)));
         }
-
         if item.is_reconstructed() {
-            writeln!(f, "  {PRED_IS_RECONSTRUCTED} true ;")?;
+            props.push((PRED_IS_RECONSTRUCTED, Object::Boolean(true)));
         }
-
         if let Some(immediate_ety) = self.graph.immediate_ety(id) {
-            let mode = immediate_ety.mode.as_ref();
-            write_item_quoted_prop(f, PRED_MODE, mode)?;
+            props.push((PRED_MODE, string(immediate_ety.mode.as_ref())));
             if let Some(head) = immediate_ety.head {
-                writeln!(f, "  {PRED_HEAD} {head} ;",)?;
+                props.push((PRED_HEAD, Object::Integer(head.into())));
             }
-            write!(f, "  {PRED_SOURCE} ")?;
-            for (e_i, ety_item) in immediate_ety.items.iter().enumerate() {
-                write!(
-                    f,
-                    "[ {PRED_ITEM} {ITEM_PRE}{}; {PRED_ORDER} {e_i} ]",
-                    ety_item.index()
-                )?;
-                write_list_delim(f, e_i, immediate_ety.items.len())?;
+            for edge in self.graph.parent_edges(id).sorted_by_key(|e| e.order()) {
+                let mut source_props = vec![
+                    (PRED_TYPE, Object::Named(CLASS_SOURCE)),
+                    (PRED_ITEM, Object::Item(edge.parent())),
+                    (PRED_ORDER, Object::Integer(edge.order().into())),
+                    (PRED_IS_HEAD, Object::Boolean(edge.head())),
+                    (PRED_CONFIDENCE, Object::Decimal(edge.confidence())),
+                ];
+                if let Some(provenance) = edge.provenance() {
+                    source_props.push((PRED_EVIDENCE, string(provenance.source.as_str())));
+                    if let Some(template) = provenance.template {
+                        source_props
+                            .push((PRED_TEMPLATE, string(self.string_pool.resolve(template))));
+                    }
+                    if let Some(line) = provenance.line {
+                        source_props.push((PRED_LINE, Object::Integer(line as u64)));
+                    }
+                    if let Some(variety) = provenance.variety {
+                        source_props.push((PRED_VARIETY, string(variety.code())));
+                    }
+                }
+                props.push((PRED_SOURCE, Object::Blank(source_props)));
+            }
+            let mode_property = mode_property(immediate_ety.mode);
+            for &ety_item in &immediate_ety.items {
+                props.push((mode_property, Object::Item(ety_item)));
             }
         }
-
         if let Some(progenitors) = self.progenitors.get(&id) {
             if let Some(head) = progenitors.head {
-                writeln!(f, "  {PRED_HEAD_PROGENITOR} {ITEM_PRE}{} ;", head.index())?;
+                props.push((PRED_HEAD_PROGENITOR, Object::Item(head)));
             }
-            write!(f, "  {PRED_PROGENITOR} ")?;
-            for (p_i, progenitor) in progenitors.items.iter().enumerate() {
-                write!(f, "{ITEM_PRE}{}", progenitor.index())?;
-                write_list_delim(f, p_i, progenitors.items.len())?;
+            for &progenitor in &progenitors.items {
+                props.push((PRED_PROGENITOR, Object::Item(progenitor)));
             }
         }
-        writeln!(f, ".")?;
-        Ok(())
+        if let Some(cognate_set) = self.cognate_set_id(id) {
+            props.push((PRED_COGNATE_SET, Object::String(cognate_set)));
+        }
+        props
     }
 
     pub(crate) fn write_turtle(&self, path: &Path) -> Result<()> {
         let mut f = BufWriter::new(File::create(path)?);
         write_prefixes(&mut f)?;
+        write_ontology(&mut f)?;
+        if let Some(props) = self.dataset_props() {
+            write_subject(&mut f, DATASET, &props)?;
+        }
         let n = self.graph.len();
         let pb = progress_bar(n, &format!("Writing RDF to Turtle file {}", path.display()))?;
         for (id, item) in self.graph.iter() {
-            self.write_turtle_item(&mut f, id, item)?;
+            write_subject(&mut f, &item_subject(id), &self.item_props(id, item))?;
             pb.inc(1);
         }
         f.flush()?;
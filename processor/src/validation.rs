@@ -0,0 +1,230 @@
+use crate::{
+    ety_graph::EtyEdgeAccess,
+    items::{Item, ItemId, Items},
+    string_pool::StringPool,
+    HashMap, HashSet,
+};
+
+use std::{fs::File, io::BufWriter, path::Path, time::Instant};
+
+use anyhow::{ensure, Ok, Result};
+use clap::ValueEnum;
+use indicatif::HumanDuration;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// Kinds of anomalies that are recorded while generating the ety graph, or
+/// found by inspecting it afterwards.
+#[derive(Serialize, Deserialize, Hash, Eq, PartialEq, Debug, Copy, Clone)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum AnomalyKind {
+    // The item had parsed etymology or root templates, but ended up with no
+    // parents in the graph.
+    NoParents,
+    // The item had a descendants section, but ended up with no children in the
+    // graph.
+    NoChildren,
+    // The parent's lang descends from the child's lang, i.e. the child lang
+    // predates the parent lang.
+    ParentLangDescendsFromChildLang,
+    // The item was listed as one of its own etymological sources. Such links
+    // are dropped rather than added to the graph.
+    SelfLoop,
+    // Processing of the item's etymology templates stopped at an imputed item,
+    // or at a term for which the imputation policy disallowed imputing an
+    // item, so the rest of the templated chain was not used.
+    ImputationTruncation,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Anomaly {
+    kind: AnomalyKind,
+    item: ItemId,
+    // the other item involved, if any, e.g. the parent for edge anomalies
+    other: Option<ItemId>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub(crate) struct Anomalies {
+    recorded: Vec<Anomaly>,
+    // Items that are expected to have parents (children) because they had raw
+    // etymology or root (descendants) templates. These must be noted before
+    // the raw templates are consumed during ety graph generation.
+    expect_parents: HashSet<ItemId>,
+    expect_children: HashSet<ItemId>,
+}
+
+impl Anomalies {
+    pub(crate) fn record(&mut self, kind: AnomalyKind, item: ItemId, other: Option<ItemId>) {
+        self.recorded.push(Anomaly { kind, item, other });
+    }
+
+    // Point the recorded anomalies at the items that the items they involve
+    // were merged into, if any.
+    pub(crate) fn rectify_items(&mut self, rectify: impl Fn(&mut ItemId)) {
+        for anomaly in &mut self.recorded {
+            rectify(&mut anomaly.item);
+            if let Some(other) = &mut anomaly.other {
+                rectify(other);
+            }
+        }
+    }
+}
+
+impl Items {
+    /// Note which items should end up with parents or children, for later
+    /// comparison against the generated graph.
+    pub(crate) fn note_expected_connections(&mut self) {
+        let expect_parents = self
+            .raw_templates
+            .ety
+            .iter()
+            .filter(|(_, raw_etymology)| raw_etymology.has_parsed_template())
+            .map(|(&item_id, _)| item_id)
+            .chain(self.raw_templates.root.keys().copied())
+            .collect();
+        let expect_children = self.raw_templates.desc.keys().copied().collect();
+        self.anomalies.expect_parents = expect_parents;
+        self.anomalies.expect_children = expect_children;
+    }
+
+    fn find_anomalies(&self) -> Vec<Anomaly> {
+        let mut found = vec![];
+        for &item in &self.anomalies.expect_parents {
+            if self.graph.parent_edges(item).next().is_none() {
+                found.push(Anomaly {
+                    kind: AnomalyKind::NoParents,
+                    item,
+                    other: None,
+                });
+            }
+        }
+        for &item in &self.anomalies.expect_children {
+            if self.graph.child_edges(item).next().is_none() {
+                found.push(Anomaly {
+                    kind: AnomalyKind::NoChildren,
+                    item,
+                    other: None,
+                });
+            }
+        }
+        for edge in self.graph.edges() {
+            let child_lang = self.get(edge.child()).lang();
+            let parent_lang = self.get(edge.parent()).lang();
+            if parent_lang.strictly_descends_from(child_lang) {
+                found.push(Anomaly {
+                    kind: AnomalyKind::ParentLangDescendsFromChildLang,
+                    item: edge.child(),
+                    other: Some(edge.parent()),
+                });
+            }
+        }
+        found
+    }
+
+    fn anomaly_item_json(&self, string_pool: &StringPool, item_id: ItemId) -> Value {
+        let item = self.get(item_id);
+        // Imputed items have no page of their own, so we point to the page
+        // whose processing led to the imputation.
+        let url = match item {
+            Item::Real(real_item) => Some(real_item.url(string_pool)),
+            Item::Imputed(imputed_item) => self.get(imputed_item.from).url(string_pool),
+        };
+        json!({
+            "id": item_id,
+            "lang": item.lang().code(),
+            "term": item.term().resolve(string_pool),
+            "etyNum": item.ety_num(),
+            "imputed": item.is_imputed(),
+            "url": url,
+        })
+    }
+
+    fn validation_report(&self, string_pool: &StringPool) -> Value {
+        let found = self.find_anomalies();
+        let anomalies = self.anomalies.recorded.iter().chain(found.iter());
+        let mut counts = HashMap::<AnomalyKind, usize>::default();
+        let anomalies_json = anomalies
+            .map(|anomaly| {
+                *counts.entry(anomaly.kind).or_default() += 1;
+                json!({
+                    "kind": anomaly.kind,
+                    "item": self.anomaly_item_json(string_pool, anomaly.item),
+                    "other": anomaly.other.map(|other| self.anomaly_item_json(string_pool, other)),
+                })
+            })
+            .collect_vec();
+        json!({
+            "counts": counts
+                .into_iter()
+                .map(|(kind, count)| json!({"kind": kind, "count": count}))
+                .collect_vec(),
+            "anomalies": anomalies_json,
+            "lineErrors": self.line_errors.json(),
+        })
+    }
+
+    pub(crate) fn ensure_no_anomalies(&self, string_pool: &StringPool) -> Result<()> {
+        let report = self.validation_report(string_pool);
+        ensure!(
+            report["anomalies"].as_array().is_some_and(Vec::is_empty),
+            "ety graph validation found anomalies:\n{report:#}"
+        );
+        Ok(())
+    }
+
+    pub(crate) fn write_validation_report(
+        &self,
+        string_pool: &StringPool,
+        path: &Path,
+    ) -> Result<()> {
+        let t = Instant::now();
+        println!(
+            "Writing ety graph validation report to {}...",
+            path.display()
+        );
+        let report = self.validation_report(string_pool);
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(writer, &report)?;
+        println!("Finished. Took {}.", HumanDuration(t.elapsed()));
+        Ok(())
+    }
+
+    // Every ety link removed to break a cycle, with both of the items it
+    // linked, so that the cycles can be fixed on Wiktionary.
+    fn cycle_report(&self, string_pool: &StringPool) -> Value {
+        json!({
+            "strategy": self
+                .options
+                .cycle_strategy
+                .to_possible_value()
+                .map(|value| value.get_name().to_string()),
+            "count": self.removed_cycle_edges.len(),
+            "removed": self
+                .removed_cycle_edges
+                .iter()
+                .map(|edge| {
+                    json!({
+                        "child": self.anomaly_item_json(string_pool, edge.child),
+                        "parent": self.anomaly_item_json(string_pool, edge.parent),
+                        "etyMode": edge.mode.as_str(),
+                        "confidence": edge.confidence,
+                        "inCycle": edge.in_cycle,
+                        "provenance": edge.provenance.map(|p| p.json(string_pool)),
+                    })
+                })
+                .collect_vec(),
+        })
+    }
+
+    pub(crate) fn write_cycle_report(&self, string_pool: &StringPool, path: &Path) -> Result<()> {
+        let t = Instant::now();
+        println!("Writing ety link cycle report to {}...", path.display());
+        let report = self.cycle_report(string_pool);
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(writer, &report)?;
+        println!("Finished. Took {}.", HumanDuration(t.elapsed()));
+        Ok(())
+    }
+}
@@ -1,42 +1,34 @@
 use crate::{
     descendants::RawDescendants,
-    gloss::Gloss,
-    items::{Items, RealItem},
+    gloss::{self, Gloss},
+    input,
+    items::{AddedReal, Items, MultiWordPolicy, RealItem},
     langterm::Term,
     languages::Lang,
+    line_errors::LineErrorKind,
     pos::Pos,
     redirects::WiktextractJsonRedirect,
     string_pool::StringPool,
 };
 
-use std::{
-    borrow::Cow,
-    fs::File,
-    io::{BufReader, Read},
-    mem,
-    path::Path,
-};
+use std::{borrow::Cow, mem, path::Path};
 
 use anyhow::{Ok, Result};
 use bytelines::ByteLines;
-use flate2::read::GzDecoder;
 use simd_json::{to_borrowed_value, ValueAccess};
 
-/// Returns an iterator over the lines in the file at the given path.
+// Items keep at most this many chars of their ety text for display, which is
+// plenty for all but a few sprawling ety sections.
+const MAX_ETY_TEXT_CHARS: usize = 1000;
+
+/// Returns an iterator over the lines of the wiktextract data at the given
+/// path, which can also be `-` for stdin or an http(s) URL to stream it from.
 ///
 /// # Errors
 ///
-/// This function will return an error if the file at the given path cannot be opened.
+/// This function will return an error if the data at the given path cannot be opened.
 pub fn wiktextract_lines(path: &Path) -> Result<impl Iterator<Item = Vec<u8>>> {
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
-    let is_gz_compressed = path.extension().is_some_and(|ext| ext == "gz");
-    let uncompressed: Box<dyn Read> = if is_gz_compressed {
-        Box::new(GzDecoder::new(reader))
-    } else {
-        Box::new(reader)
-    };
-    let reader = BufReader::new(uncompressed);
+    let reader = input::open(path)?;
     let lines = ByteLines::new(reader);
     // We use into_iter() here and thereby allocate a Vec<u8> for each line, so
     // that we have the convenience of returning an iterator. These allocations
@@ -47,11 +39,23 @@ pub fn wiktextract_lines(path: &Path) -> Result<impl Iterator<Item = Vec<u8>>> {
 impl Items {
     pub(crate) fn process_wiktextract_lines(
         &mut self,
-        string_pool: &mut StringPool,
+        string_pool: &StringPool,
         path: &Path,
     ) -> Result<()> {
-        for (line_number, mut line) in wiktextract_lines(path)?.enumerate() {
-            let json = to_borrowed_value(&mut line)?;
+        let line_range = self.options.line_range;
+        for (line_number, mut line) in line_range.numbered(wiktextract_lines(path)?) {
+            let json = match to_borrowed_value(&mut line) {
+                Result::Ok(json) => json,
+                Err(e) => {
+                    self.line_errors.record(
+                        LineErrorKind::MalformedJson,
+                        line_number,
+                        None,
+                        &e.into(),
+                    );
+                    continue;
+                }
+            };
             self.total_ok_lines_in_file += 1;
             // Some wiktionary pages are redirects. These are actually used somewhat
             // heavily, so we need to take them into account
@@ -64,6 +68,8 @@ impl Items {
                 self.process_item(string_pool, &item, line_number);
             }
         }
+        self.redirects.resolve_chains();
+        self.expand_desctrees(string_pool);
         Ok(())
     }
 }
@@ -139,16 +145,40 @@ pub(crate) struct WiktextractJsonItem<'a> {
 impl Items {
     fn process_item(
         &mut self,
-        string_pool: &mut StringPool,
+        string_pool: &StringPool,
         json_item: &WiktextractJsonItem,
         line_number: usize,
     ) {
+        let page = json_item.json.get_valid_str("word");
+        self.line_errors.check(
+            LineErrorKind::UnknownLang,
+            line_number,
+            page,
+            json_item.ensure_known_lang(),
+        );
         if let Some(page_term) = json_item.get_page_term(string_pool)
             && let Some(term) = json_item.get_canonical_term(string_pool)
             && let Some(lang) = json_item.get_lang()
-            && let Some(pos) = json_item.get_pos()
-            && let Some(gloss) = json_item.get_gloss(string_pool)
+            && let Some(pos) = json_item.get_pos(self.options.multi_word_policy)
+            && self
+                .options
+                .multi_word_policy
+                .allows(term.resolve(string_pool))
+            && let Some(gloss) = json_item.get_gloss(string_pool, self.options.raw_glosses)
         {
+            // Every pos of an item has its own descendants, which are kept.
+            self.line_errors.check(
+                LineErrorKind::UnknownTemplateLang,
+                line_number,
+                page,
+                json_item.ensure_known_desc_template_langs(),
+            );
+            self.line_errors.check(
+                LineErrorKind::UnparsedDescTemplate,
+                line_number,
+                page,
+                json_item.ensure_desc_templates_parse(string_pool),
+            );
             let item = RealItem {
                 ety_num: json_item.get_ety_num(),
                 lang,
@@ -158,18 +188,56 @@ impl Items {
                 page_term: (page_term != term).then_some(page_term),
                 romanization: json_item.get_romanization(string_pool),
                 is_reconstructed: json_item.is_reconstructed(),
+                ipa: json_item.get_ipa(string_pool),
+                ety_text: json_item.get_ety_text(string_pool, self.options.raw_glosses),
+                page_id: json_item.json.get_u64("page_id"),
             };
-            let (item_id, is_new_ety) = self.add_real(item);
-            if is_new_ety { // a new item was added
+            let (item_id, added) = self.add_real(item);
+            if let AddedReal::Pos = added {
+                // A new pos item under an existing ety item, which keeps its
+                // own descendants, but shares the ety item's ety and root.
+                self.lines.insert(line_number, item_id);
+                if let Some(raw_descendants) = json_item.get_descendants(string_pool) {
+                    self.raw_templates.desc.insert(item_id, raw_descendants);
+                }
+                return;
+            }
+            if let AddedReal::Ety = added { // a new item was added
                 // This means that the glosses embedding for a multi-pos item
                 // will be based on the glosses for whichever pos happens to
                 // first in the wiktextract data. $$ This may be good enough or
                 // may require better handling in the future...
                 self.lines.insert(line_number, item_id);
+                self.line_errors.check(
+                    LineErrorKind::UnknownTemplateLang,
+                    line_number,
+                    page,
+                    json_item
+                        .ensure_known_ety_template_langs()
+                        .and_then(|()| json_item.ensure_known_root_template_langs()),
+                );
+                self.line_errors.check(
+                    LineErrorKind::UnparsedEtyTemplate,
+                    line_number,
+                    page,
+                    json_item.ensure_ety_templates_parse(string_pool, lang),
+                );
+                self.line_errors.check(
+                    LineErrorKind::UnparsedRootTemplate,
+                    line_number,
+                    page,
+                    json_item.ensure_root_templates_parse(string_pool, lang),
+                );
                 if let Some(raw_root) = json_item.get_root(string_pool, lang) {
                     self.raw_templates.root.insert(item_id, raw_root);
                 }
                 if let Some(raw_etymology) = json_item.get_etymology(string_pool, lang) {
+                    self.line_errors.check(
+                        LineErrorKind::SkippedEtyTemplate,
+                        line_number,
+                        page,
+                        json_item.ensure_no_skipped_ety_templates(&raw_etymology),
+                    );
                     self.raw_templates.ety.insert(item_id, raw_etymology);
                 }
                 if let Some(raw_descendants) = json_item.get_descendants(string_pool) {
@@ -197,13 +265,21 @@ impl Items {
 }
 
 impl WiktextractJsonItem<'_> {
-    fn get_lang(&self) -> Option<Lang> {
+    pub(crate) fn get_lang(&self) -> Option<Lang> {
         let lang_code = self.json.get_valid_str("lang_code")?;
         lang_code.parse().ok()
     }
 
+    // For strict mode, since get_lang() silently skips unknown lang codes.
+    fn ensure_known_lang(&self) -> Result<()> {
+        if let Some(lang_code) = self.json.get_valid_str("lang_code") {
+            lang_code.parse::<Lang>()?;
+        }
+        Ok(())
+    }
+
     // The form of the term used in the page url, e.g. "voco"
-    fn get_page_term(&self, string_pool: &mut StringPool) -> Option<Term> {
+    fn get_page_term(&self, string_pool: &StringPool) -> Option<Term> {
         let term = self.json.get_valid_term("word")?;
         if !should_ignore_term(term) {
             return Some(Term::new(string_pool, term));
@@ -215,7 +291,7 @@ impl WiktextractJsonItem<'_> {
     // used in ety templates, which gets converted under the hood by wiktionary
     // Module:languages into the page_term "link" version. See notes.md for
     // more.
-    fn get_canonical_term(&self, string_pool: &mut StringPool) -> Option<Term> {
+    fn get_canonical_term(&self, string_pool: &StringPool) -> Option<Term> {
         if let Some(forms) = self.json.get_array("forms") {
             let mut f = 0;
             while let Some(form) = forms.get(f) {
@@ -239,9 +315,9 @@ impl WiktextractJsonItem<'_> {
         self.get_page_term(string_pool)
     }
 
-    fn get_pos(&self) -> Option<Pos> {
+    fn get_pos(&self, multi_word_policy: MultiWordPolicy) -> Option<Pos> {
         let pos = self.json.get_valid_str("pos")?;
-        if !should_ignore_pos(pos) {
+        if multi_word_policy.allows_phrases() || !should_ignore_pos(pos) {
             return pos.parse().ok();
         }
         None
@@ -259,19 +335,26 @@ impl WiktextractJsonItem<'_> {
         self.json.get_u8("etymology_number").unwrap_or(1)
     }
 
-    fn get_gloss(&self, string_pool: &mut StringPool) -> Option<Gloss> {
+    // Cleaned of residual wiki markup unless `raw`.
+    fn get_gloss(&self, string_pool: &StringPool, raw: bool) -> Option<Gloss> {
         // 'senses' key should always be present with non-empty value, but glosses
         // may be missing or empty.
-        self.json
+        let gloss = self
+            .json
             .get_array("senses")
             .and_then(|senses| senses.first())
             .and_then(|sense| sense.get_array("glosses"))
             .and_then(|glosses| glosses.first())
-            .and_then(|gloss| gloss.as_str())
-            .and_then(|gloss| (!gloss.is_empty()).then(|| Gloss::new(string_pool, gloss)))
+            .and_then(|gloss| gloss.as_str())?;
+        let gloss = if raw {
+            gloss.to_string()
+        } else {
+            gloss::clean(gloss)
+        };
+        (!gloss.is_empty()).then(|| Gloss::new_raw(string_pool, &gloss))
     }
 
-    fn get_romanization(&self, string_pool: &mut StringPool) -> Option<Term> {
+    fn get_romanization(&self, string_pool: &StringPool) -> Option<Term> {
         for form in self.json.get_array("forms")? {
             if form.get_array("tags").is_some_and(|tags| {
                 tags.iter()
@@ -286,6 +369,43 @@ impl WiktextractJsonItem<'_> {
         None
     }
 
+    fn get_ipa(&self, string_pool: &StringPool) -> Vec<Term> {
+        let mut ipa = vec![];
+        for sound in self.json.get_array("sounds").into_iter().flatten() {
+            if let Some(transcription) = sound.get_str("ipa")
+                && !transcription.is_empty()
+            {
+                let transcription = Term::new(string_pool, transcription);
+                if !ipa.contains(&transcription) {
+                    ipa.push(transcription);
+                }
+            }
+        }
+        ipa
+    }
+
+    // Truncated to MAX_ETY_TEXT_CHARS, with an ellipsis if it was. Cleaned of
+    // residual wiki markup unless `raw`.
+    fn get_ety_text(&self, string_pool: &StringPool, raw: bool) -> Option<Gloss> {
+        let text = self.json.get_valid_str("etymology_text")?;
+        let text = if raw {
+            text.to_string()
+        } else {
+            gloss::clean(text)
+        };
+        let text = text.trim();
+        if text.is_empty() {
+            return None;
+        }
+        match text.char_indices().nth(MAX_ETY_TEXT_CHARS) {
+            Some((end, _)) => {
+                let truncated = format!("{}…", text[..end].trim_end());
+                Some(Gloss::new_raw(string_pool, &truncated))
+            }
+            None => Some(Gloss::new_raw(string_pool, text)),
+        }
+    }
+
     fn is_reconstructed(&self) -> bool {
         self.json
             .get_array("senses")
@@ -0,0 +1,108 @@
+// End-to-end regression test. Processes a checked-in fixture of wiktextract
+// lines and compares a canonical rendering of the resulting ety graph against
+// an expected snapshot. Any change in the processing heuristics that changes
+// the graph will show up as a diff.
+//
+// If a change in the graph is intended, update the snapshot with:
+//
+// WETY_BLESS=1 cargo test --release --test regression -- --ignored
+//
+// and review the snapshot diff before committing it. Without a snapshot, the
+// test fails rather than passing vacuously, so the first one must be blessed
+// the same way.
+//
+// The fixture is to be generated from the full wiktextract data with
+// create-test-file, for the lang_code,term pairs in regression.csv:
+//
+// cargo run --release --bin create-test-file -- processor/tests/fixtures/regression.csv
+//
+// followed by the redirect lines at the end of the current fixture, which
+// create-test-file doesn't select. Until then, the fixture is a few hand-picked
+// lines and there is no snapshot, so the test is ignored. Once the fixture is
+// generated and the snapshot blessed, remove the #[ignore].
+
+use processor::{embeddings, process_wiktextract, Data, ProcessingOptions};
+
+use std::{env, fs, path::Path};
+
+const FIXTURE: &str = "tests/fixtures/regression.jsonl";
+const EXPECTED: &str = "tests/fixtures/regression.expected";
+
+fn process_fixture() -> String {
+    let tmp = Path::new(env!("CARGO_TARGET_TMPDIR"));
+    let serialization_path = tmp.join("regression.json");
+    let embeddings_config = embeddings::Config {
+        model_name: embeddings::DEFAULT_MODEL.to_string(),
+        model_revision: embeddings::DEFAULT_MODEL_REVISION.to_string(),
+        batch_size: embeddings::DEFAULT_BATCH_SIZE,
+        cache_path: tmp.join("regression_embeddings_cache"),
+        url: None,
+    };
+    process_wiktextract(
+        Path::new(FIXTURE),
+        &serialization_path,
+        None,
+        None,
+        None,
+        None,
+        &embeddings_config,
+        ProcessingOptions::default(),
+        None,
+    )
+    .unwrap();
+    Data::deserialize(&serialization_path).unwrap().snapshot()
+}
+
+fn blocks(snapshot: &str) -> Vec<String> {
+    let mut blocks: Vec<String> = vec![];
+    for line in snapshot.lines() {
+        match blocks.last_mut() {
+            Some(block) if line.starts_with(' ') => {
+                block.push('\n');
+                block.push_str(line);
+            }
+            _ => blocks.push(line.to_string()),
+        }
+    }
+    blocks
+}
+
+#[test]
+#[ignore = "the fixture hasn't been generated from regression.csv or its snapshot blessed yet"]
+fn regression() {
+    let actual = process_fixture();
+    let expected_path = Path::new(EXPECTED);
+    if env::var_os("WETY_BLESS").is_some() {
+        fs::write(expected_path, &actual).unwrap();
+        println!("Wrote snapshot {EXPECTED}.");
+        return;
+    }
+    assert!(
+        expected_path.exists(),
+        "no snapshot {EXPECTED}. Run with WETY_BLESS=1 to write it, and review it before committing it."
+    );
+    let expected = fs::read_to_string(expected_path).unwrap();
+    if actual == expected {
+        return;
+    }
+    // Each item and its parents form a block in the snapshot, so listing the
+    // blocks unique to each snapshot is enough to see what changed.
+    let actual_blocks = blocks(&actual);
+    let expected_blocks = blocks(&expected);
+    let mut diff = String::new();
+    for block in expected_blocks
+        .iter()
+        .filter(|b| !actual_blocks.contains(b))
+    {
+        diff.push_str(&format!("- {}\n", block.replace('\n', "\n- ")));
+    }
+    for block in actual_blocks
+        .iter()
+        .filter(|b| !expected_blocks.contains(b))
+    {
+        diff.push_str(&format!("+ {}\n", block.replace('\n', "\n+ ")));
+    }
+    panic!(
+        "ety graph differs from snapshot {EXPECTED}:\n{diff}\nIf this is intended, rerun with WETY_BLESS=1 to update the snapshot."
+    );
+}
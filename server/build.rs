@@ -0,0 +1,29 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/wety.proto");
+        // The messages are filled in from the json built by processor, so they
+        // deserialize from its camelCase keys. Json nulls for lists become
+        // empty repeated fields.
+        tonic_build::configure()
+            .build_client(false)
+            .type_attribute(
+                ".wety",
+                "#[derive(serde::Deserialize)] #[serde(rename_all = \"camelCase\")]",
+            )
+            .field_attribute(
+                ".wety.Item.pos",
+                "#[serde(deserialize_with = \"crate::grpc::null_as_empty\")]",
+            )
+            .field_attribute(
+                ".wety.Item.gloss",
+                "#[serde(deserialize_with = \"crate::grpc::null_as_empty\")]",
+            )
+            .field_attribute(
+                ".wety.Item.ipa",
+                "#[serde(deserialize_with = \"crate::grpc::null_as_empty\")]",
+            )
+            .compile(&["proto/wety.proto"], &["proto"])?;
+    }
+    Ok(())
+}
@@ -0,0 +1,58 @@
+//! Serving over TLS with certificates obtained and renewed from Let's Encrypt
+//! (or another ACME directory), rather than provisioned outside the server.
+//! Certificates are validated by the TLS-ALPN-01 challenge, so the server must
+//! be reachable on port 443 at each of its domains.
+
+use std::{env, net::SocketAddr};
+
+use anyhow::{ensure, Result};
+use axum::Router;
+use rustls_acme::{caches::DirCache, AcmeConfig};
+use tokio_stream::StreamExt;
+use tracing::{error, info};
+
+/// Serve `app` at `addr` for `domains`, a comma-separated list, with certificates from Let's Encrypt,
+/// or from the ACME directory at `WETY_ACME_DIRECTORY` if set. Certificates
+/// and the account key are cached in `WETY_ACME_CACHE_DIR` (by default
+/// `data/acme`), so that they aren't requested again on every restart. If set,
+/// `WETY_ACME_CONTACT` is the email address given for the account.
+///
+/// # Errors
+///
+/// Will return `Err` if there are no domains, or the server fails.
+pub async fn serve(app: Router, addr: SocketAddr, domains: &str) -> Result<()> {
+    let domains = domains
+        .split(',')
+        .map(str::trim)
+        .filter(|domain| !domain.is_empty())
+        .collect::<Vec<_>>();
+    ensure!(!domains.is_empty(), "ACME needs at least one domain");
+    let cache_dir = env::var("WETY_ACME_CACHE_DIR").unwrap_or_else(|_| "data/acme".to_string());
+    let config = AcmeConfig::new(domains)
+        .contact(
+            env::var("WETY_ACME_CONTACT")
+                .ok()
+                .map(|email| format!("mailto:{email}")),
+        )
+        .cache(DirCache::new(cache_dir));
+    let config = match env::var("WETY_ACME_DIRECTORY") {
+        Ok(directory) => config.directory(directory),
+        Err(_) => config.directory_lets_encrypt(true),
+    };
+    let mut state = config.state();
+    let acceptor = state.axum_acceptor(state.default_rustls_config());
+    // Certificates are ordered and renewed as the state is polled.
+    tokio::spawn(async move {
+        while let Some(event) = state.next().await {
+            match event {
+                Ok(event) => info!("ACME: {event:?}"),
+                Err(e) => error!("ACME failed: {e:?}"),
+            }
+        }
+    });
+    axum_server::bind(addr)
+        .acceptor(acceptor)
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+        .await?;
+    Ok(())
+}
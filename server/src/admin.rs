@@ -0,0 +1,63 @@
+use crate::health::{not_ready, Readiness};
+
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::Json,
+};
+use serde_json::{json, Value};
+
+/// State for the admin endpoints, which are only served if a token is
+/// configured.
+#[derive(Clone)]
+pub struct AdminState {
+    pub token: String,
+    pub readiness: Arc<Readiness>,
+}
+
+// Compare in constant time, so that the token can't be guessed byte by byte
+// from response times.
+fn token_matches(headers: &HeaderMap, token: &str) -> bool {
+    let Some(given) = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|authorization| authorization.to_str().ok())
+        .and_then(|authorization| authorization.strip_prefix("Bearer "))
+    else {
+        return false;
+    };
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Ask for the data file to be reloaded. The old data keeps being served until
+/// the new data has loaded.
+///
+/// # Errors
+///
+/// Will return `Err` with a 401 if the request doesn't carry the admin token,
+/// or a 503 if the data has not loaded in the first place yet.
+pub async fn reload_data(
+    State(admin): State<AdminState>,
+    headers: HeaderMap,
+) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> {
+    if !token_matches(&headers, &admin.token) {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            Json(json!({
+                "error": "unauthorized",
+                "message": "missing or wrong admin token",
+            })),
+        ));
+    }
+    if admin.readiness.state().is_none() {
+        return Err(not_ready());
+    }
+    admin.readiness.request_reload();
+    Ok((StatusCode::ACCEPTED, Json(json!({"status": "reloading"}))))
+}
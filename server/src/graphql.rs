@@ -0,0 +1,211 @@
+use crate::{internal_error, parse_lang, AppState};
+
+use processor::{Data, EtyLink, ItemId};
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use async_graphql::{
+    http::parse_query_string, ComplexObject, Context, EmptyMutation, EmptySubscription, Error,
+    Object, Result, Schema, SimpleObject,
+};
+use axum::{
+    extract::{RawQuery, State},
+    http::StatusCode,
+    response::Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+// Queries nesting deeper than this are rejected before being run. Each
+// generation of parents or children takes two levels (the link and its item).
+const MAX_DEPTH: usize = 32;
+const MAX_COMPLEXITY: usize = 1000;
+
+pub type WetySchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+#[must_use]
+pub fn schema() -> WetySchema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .limit_depth(MAX_DEPTH)
+        .limit_complexity(MAX_COMPLEXITY)
+        .finish()
+}
+
+// Static limits can't bound traversals through items with many children, so
+// resolvers that traverse also check the time limit.
+struct Deadline {
+    at: Instant,
+    timeout: Duration,
+}
+
+fn data<'a>(ctx: &Context<'a>) -> &'a Data {
+    &ctx.data_unchecked::<Arc<AppState>>().data
+}
+
+fn check_deadline(ctx: &Context<'_>) -> Result<()> {
+    let deadline = ctx.data_unchecked::<Deadline>();
+    if Instant::now() >= deadline.at {
+        return Err(Error::new(format!(
+            "query exceeded the {} ms time limit",
+            deadline.timeout.as_millis()
+        )));
+    }
+    Ok(())
+}
+
+#[derive(Deserialize, SimpleObject)]
+#[graphql(name = "Lang")]
+struct LangObject {
+    id: u32,
+    name: String,
+    script: Option<String>,
+}
+
+// Filled in from the json built by processor, so that the fields match those
+// of items in the HTTP API.
+#[derive(Deserialize, SimpleObject)]
+#[serde(rename_all = "camelCase")]
+#[graphql(name = "Item", complex)]
+struct ItemObject {
+    id: u32,
+    ety_num: u8,
+    lang: LangObject,
+    term: String,
+    imputed: bool,
+    reconstructed: bool,
+    url: Option<String>,
+    pos: Option<Vec<String>>,
+    gloss: Option<Vec<String>>,
+    romanization: Option<String>,
+    ipa: Option<Vec<String>>,
+}
+
+impl ItemObject {
+    fn new(data: &Data, item: ItemId) -> Result<Self> {
+        Ok(serde_json::from_value(data.item_json(item))?)
+    }
+
+    fn item_id(&self) -> ItemId {
+        ItemId::new(self.id as usize)
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(name = "Link")]
+struct LinkObject {
+    /// The parent or child at the other end of the link.
+    item: ItemObject,
+    ety_mode: &'static str,
+    ety_order: u8,
+    head: bool,
+    ety_confidence: f32,
+}
+
+fn links(data: &Data, links: Vec<EtyLink>) -> Result<Vec<LinkObject>> {
+    links
+        .into_iter()
+        .map(|link| {
+            Ok(LinkObject {
+                item: ItemObject::new(data, link.item)?,
+                ety_mode: link.mode,
+                ety_order: link.order,
+                head: link.head,
+                ety_confidence: link.confidence,
+            })
+        })
+        .collect()
+}
+
+#[ComplexObject]
+impl ItemObject {
+    /// The item's parents, in ety order. Nest to get ancestors further back.
+    async fn parents(&self, ctx: &Context<'_>) -> Result<Vec<LinkObject>> {
+        check_deadline(ctx)?;
+        let data = data(ctx);
+        links(data, data.parent_links(self.item_id()))
+    }
+
+    /// The item's children. Nest to get descendants further down.
+    async fn children(&self, ctx: &Context<'_>) -> Result<Vec<LinkObject>> {
+        check_deadline(ctx)?;
+        let data = data(ctx);
+        links(data, data.child_links(self.item_id()))
+    }
+}
+
+pub struct QueryRoot;
+
+#[Object(name = "Query")]
+impl QueryRoot {
+    /// The item with this id, if any.
+    async fn item(&self, ctx: &Context<'_>, id: u32) -> Result<Option<ItemObject>> {
+        let data = data(ctx);
+        let item = ItemId::new(id as usize);
+        if !data.contains(item) {
+            return Ok(None);
+        }
+        ItemObject::new(data, item).map(Some)
+    }
+
+    /// Items in the lang (code or id) whose terms match the term, best first.
+    async fn search_items(
+        &self,
+        ctx: &Context<'_>,
+        lang: String,
+        term: String,
+    ) -> Result<Vec<ItemObject>> {
+        let state = ctx.data_unchecked::<Arc<AppState>>();
+        let lang = parse_lang(&lang)?;
+        state
+            .search
+            .item_ids(&state.data, lang, &term)
+            .into_iter()
+            .map(|item| ItemObject::new(&state.data, item))
+            .collect()
+    }
+}
+
+type GraphQLResponse =
+    std::result::Result<Json<async_graphql::Response>, (StatusCode, Json<Value>)>;
+
+// Run the query on the blocking thread pool, since the resolvers never yield.
+async fn execute(state: Arc<AppState>, request: async_graphql::Request) -> GraphQLResponse {
+    let request = request.data(Arc::clone(&state)).data(Deadline {
+        at: Instant::now() + state.query_timeout,
+        timeout: state.query_timeout,
+    });
+    let runtime = tokio::runtime::Handle::current();
+    tokio::task::spawn_blocking(move || runtime.block_on(state.graphql.execute(request)))
+        .await
+        .map(Json)
+        .map_err(|_| internal_error())
+}
+
+/// # Errors
+///
+/// Will return `Err` with a 400 if the query string is not a GraphQL request.
+pub async fn graphql_get(
+    State(state): State<Arc<AppState>>,
+    RawQuery(query): RawQuery,
+) -> GraphQLResponse {
+    let request = parse_query_string(&query.unwrap_or_default()).map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "request", "message": e.to_string()})),
+        )
+    })?;
+    execute(state, request).await
+}
+
+/// # Errors
+///
+/// Will return `Err` with a 500 if running the query fails unexpectedly.
+pub async fn graphql_post(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<async_graphql::Request>,
+) -> GraphQLResponse {
+    execute(state, request).await
+}
@@ -0,0 +1,213 @@
+// tonic::Status is large, but it is what the service methods return anyway.
+#![allow(clippy::result_large_err)]
+
+use crate::{build_tree, health::Readiness, parse_lang, AppState, TreeError};
+
+use processor::{Continuation, ItemId, Lang, QueryTimeout, TreeLimits};
+
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use serde::{de::DeserializeOwned, Deserialize, Deserializer};
+use serde_json::Value;
+use tonic::{transport::Server, Request, Response, Status};
+use tracing::info;
+
+#[allow(clippy::pedantic)]
+mod proto {
+    tonic::include_proto!("wety");
+}
+
+use proto::{
+    wety_server::{Wety, WetyServer},
+    CognatesRequest, DescendantsNode, DescendantsRequest, EtymologyNode, EtymologyRequest,
+    ItemSearchRequest, ItemSearchResponse, LangSearchRequest, LangSearchResponse,
+};
+
+// For repeated fields whose json may be null.
+pub(crate) fn null_as_empty<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Ok(Option::<Vec<T>>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+// Fill in a message from the json built by processor.
+fn message<T: DeserializeOwned>(json: Value) -> Result<T, Status> {
+    serde_json::from_value(json).map_err(|e| Status::internal(e.to_string()))
+}
+
+fn lang(s: &str) -> Result<Lang, Status> {
+    parse_lang(s).map_err(|e| Status::invalid_argument(e.to_string()))
+}
+
+fn langs(s: &[String]) -> Result<Vec<Lang>, Status> {
+    s.iter().map(|s| lang(s)).collect()
+}
+
+fn item(state: &AppState, id: u32) -> Result<ItemId, Status> {
+    let item_id = ItemId::new(id as usize);
+    if !state.data.contains(item_id) {
+        return Err(Status::not_found(format!("no item with id {id}")));
+    }
+    Ok(item_id)
+}
+
+fn limits(budget_ms: Option<u64>, max_depth: Option<u64>, max_nodes: Option<u64>) -> TreeLimits {
+    let budget = budget_ms.map(|budget| Instant::now() + Duration::from_millis(budget));
+    // saturating, since a limit past usize::MAX is no limit at all
+    let to_usize = |n: u64| usize::try_from(n).unwrap_or(usize::MAX);
+    TreeLimits::new(
+        budget,
+        None,
+        max_depth.map(to_usize),
+        max_nodes.map(to_usize),
+    )
+}
+
+async fn tree<F>(
+    state: &Arc<AppState>,
+    tree: &'static str,
+    limits: TreeLimits,
+    build: F,
+) -> Result<Value, Status>
+where
+    F: FnOnce(&AppState, &TreeLimits) -> Result<Value, QueryTimeout> + Send + 'static,
+{
+    build_tree(Arc::clone(state), tree, limits, build)
+        .await
+        .map_err(|e| match e {
+            TreeError::Timeout(timeout) => Status::deadline_exceeded(format!(
+                "query exceeded the {} ms time limit",
+                timeout.as_millis()
+            )),
+            TreeError::Internal => Status::internal("query failed unexpectedly"),
+        })
+}
+
+pub struct WetyService {
+    readiness: Arc<Readiness>,
+}
+
+impl WetyService {
+    // The state of the app being served, which changes if the data is
+    // reloaded.
+    fn state(&self) -> Result<Arc<AppState>, Status> {
+        self.readiness
+            .state()
+            .ok_or_else(|| Status::unavailable("the server is still loading its data"))
+    }
+}
+
+#[tonic::async_trait]
+impl Wety for WetyService {
+    async fn search_langs(
+        &self,
+        request: Request<LangSearchRequest>,
+    ) -> Result<Response<LangSearchResponse>, Status> {
+        let matches = self.state()?.search.langs(&request.into_inner().name);
+        Ok(Response::new(LangSearchResponse {
+            langs: message(matches)?,
+        }))
+    }
+
+    async fn search_items(
+        &self,
+        request: Request<ItemSearchRequest>,
+    ) -> Result<Response<ItemSearchResponse>, Status> {
+        let state = self.state()?;
+        let request = request.into_inner();
+        let lang = lang(&request.lang)?;
+        let matches = state.search.items(&state.data, lang, &request.term);
+        Ok(Response::new(ItemSearchResponse {
+            items: message(matches)?,
+        }))
+    }
+
+    async fn etymology(
+        &self,
+        request: Request<EtymologyRequest>,
+    ) -> Result<Response<EtymologyNode>, Status> {
+        let state = self.state()?;
+        let request = request.into_inner();
+        let item_id = item(&state, request.item)?;
+        let limits = limits(None, request.max_depth, request.max_nodes);
+        let tree = tree(&state, "etymology", limits, move |state, limits| {
+            let lang = state.data.lang(item_id);
+            state.data.item_etymology_json(item_id, 0, lang, limits)
+        })
+        .await?;
+        Ok(Response::new(message(tree)?))
+    }
+
+    async fn descendants(
+        &self,
+        request: Request<DescendantsRequest>,
+    ) -> Result<Response<DescendantsNode>, Status> {
+        let state = self.state()?;
+        let request = request.into_inner();
+        let item_id = item(&state, request.item)?;
+        let desc_langs = langs(&request.desc_langs)?;
+        let dist_lang = request.dist_lang.as_deref().map(lang).transpose()?;
+        let continuation = request
+            .continuation
+            .map(Continuation::try_from)
+            .transpose()
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+        let tree = tree(
+            &state,
+            "descendants",
+            limits(request.budget_ms, request.max_depth, request.max_nodes),
+            move |state, limits| {
+                state.descendants_json(item_id, &desc_langs, dist_lang, continuation, limits)
+            },
+        )
+        .await?;
+        Ok(Response::new(message(tree)?))
+    }
+
+    type CognatesStream = tokio_stream::Iter<std::vec::IntoIter<Result<DescendantsNode, Status>>>;
+
+    async fn cognates(
+        &self,
+        request: Request<CognatesRequest>,
+    ) -> Result<Response<Self::CognatesStream>, Status> {
+        let state = self.state()?;
+        let request = request.into_inner();
+        let item_id = item(&state, request.item)?;
+        let desc_langs = langs(&request.desc_langs)?;
+        let dist_lang = request.dist_lang.as_deref().map(lang).transpose()?;
+        let trees = tree(
+            &state,
+            "cognates",
+            limits(request.budget_ms, request.max_depth, request.max_nodes),
+            move |state, limits| state.cognates_json(item_id, &desc_langs, dist_lang, limits),
+        )
+        .await?;
+        let Value::Array(trees) = trees else {
+            return Err(Status::internal("cognates are not a list of trees"));
+        };
+        let trees = trees.into_iter().map(message).collect::<Vec<_>>();
+        Ok(Response::new(tokio_stream::iter(trees)))
+    }
+}
+
+/// Serve the core queries over gRPC at `addr`, on the same data as the HTTP
+/// server, once `readiness` has it loaded.
+///
+/// # Errors
+///
+/// Will return `Err` if the server fails to bind or stops unexpectedly.
+pub async fn serve(readiness: Arc<Readiness>, addr: SocketAddr) -> Result<()> {
+    info!("Running wety gRPC server at {addr}...");
+    Server::builder()
+        .add_service(WetyServer::new(WetyService { readiness }))
+        .serve(addr)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,163 @@
+use crate::AppState;
+
+use std::{
+    sync::{Arc, Mutex, OnceLock},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::{Request, StatusCode},
+    response::{IntoResponse, Json, Response},
+    Router,
+};
+use serde_json::{json, Value};
+use tokio::sync::Notify;
+use tower::ServiceExt;
+use tracing::info;
+
+/// Tracks whether the server has finished loading its data. Until it has, only
+/// the health endpoints are served, and everything else gets a 503. Once it
+/// has, the loaded app can be swapped out for one with reloaded data.
+pub struct Readiness {
+    started_at: SystemTime,
+    started: Instant,
+    startup_time: OnceLock<Duration>,
+    loaded: Mutex<Option<Loaded>>,
+    reload: Notify,
+}
+
+// Router is Send but not Sync, so it is kept behind a lock and each request
+// clones it out.
+#[derive(Clone)]
+struct Loaded {
+    app: Router,
+    state: Arc<AppState>,
+    loaded_at: SystemTime,
+}
+
+impl Default for Readiness {
+    fn default() -> Self {
+        Self {
+            started_at: SystemTime::now(),
+            started: Instant::now(),
+            startup_time: OnceLock::new(),
+            loaded: Mutex::new(None),
+            reload: Notify::new(),
+        }
+    }
+}
+
+pub(crate) fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+impl Readiness {
+    fn loaded(&self) -> Option<Loaded> {
+        self.loaded
+            .lock()
+            .expect("loaded app lock not poisoned")
+            .clone()
+    }
+
+    /// Start serving `app`, which is built on the loaded `state`, in place of
+    /// any app served before. Requests already being handled finish on the old
+    /// app.
+    pub fn set_loaded(&self, app: Router, state: Arc<AppState>) {
+        let loaded = Loaded {
+            app,
+            state,
+            loaded_at: SystemTime::now(),
+        };
+        let previous = self
+            .loaded
+            .lock()
+            .expect("loaded app lock not poisoned")
+            .replace(loaded);
+        if previous.is_none() {
+            let startup_time = *self.startup_time.get_or_init(|| self.started.elapsed());
+            info!(
+                "Data loaded in {:.1} s, ready to serve requests",
+                startup_time.as_secs_f64()
+            );
+        } else {
+            info!("Data reloaded, now serving the new data");
+        }
+    }
+
+    /// The state of the app being served, if the data has loaded.
+    #[must_use]
+    pub fn state(&self) -> Option<Arc<AppState>> {
+        self.loaded().map(|loaded| loaded.state)
+    }
+
+    /// When the data being served was loaded, if it has loaded.
+    #[must_use]
+    pub fn loaded_at(&self) -> Option<SystemTime> {
+        self.loaded().map(|loaded| loaded.loaded_at)
+    }
+
+    /// How long it took to start up and load the data, once it has.
+    #[must_use]
+    pub fn startup_time(&self) -> Option<Duration> {
+        self.startup_time.get().copied()
+    }
+
+    /// Ask for the data to be reloaded, by whatever waits on
+    /// [`Readiness::reload_requested`].
+    pub fn request_reload(&self) {
+        self.reload.notify_one();
+    }
+
+    pub async fn reload_requested(&self) {
+        self.reload.notified().await;
+    }
+}
+
+pub(crate) fn not_ready() -> (StatusCode, Json<Value>) {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(json!({
+            "error": "notReady",
+            "message": "the server is still loading its data",
+        })),
+    )
+}
+
+/// Liveness: the process is up and serving requests, though it may not have
+/// loaded its data yet.
+pub async fn healthz() -> Json<Value> {
+    Json(json!({"status": "ok"}))
+}
+
+/// Readiness: 503 while the data is loading, then the data version (its
+/// content hash, as in etags), how long startup took, and when the data being
+/// served was loaded.
+pub async fn readyz(State(readiness): State<Arc<Readiness>>) -> Response {
+    let Some(loaded) = readiness.loaded() else {
+        return not_ready().into_response();
+    };
+    Json(json!({
+        "status": "ready",
+        "dataVersion": format!("{:016x}", loaded.state.content_hash),
+        "startedAt": unix_seconds(readiness.started_at),
+        "startupSecs": readiness.startup_time.get().map(Duration::as_secs_f64),
+        "loadedAt": unix_seconds(loaded.loaded_at),
+    }))
+    .into_response()
+}
+
+/// Fallback that passes requests on to the app once it is ready.
+pub async fn when_ready(
+    State(readiness): State<Arc<Readiness>>,
+    request: Request<Body>,
+) -> Response {
+    let Some(loaded) = readiness.loaded() else {
+        return not_ready().into_response();
+    };
+    let Ok(response) = loaded.app.oneshot(request).await;
+    response
+}
@@ -1,17 +1,46 @@
 #![allow(clippy::unused_async)]
 
-use processor::{Data, ItemId, Lang, Search};
-use serde::Deserialize;
+#[cfg(feature = "acme")]
+pub mod acme;
+pub mod admin;
+pub mod graphql;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod health;
+pub mod monitoring;
+pub mod rate_limit;
+pub mod request_tracing;
+pub mod schema_version;
+#[cfg(feature = "sparql")]
+pub mod sparql;
 
-use std::{str::FromStr, sync::Arc};
+use crate::{
+    monitoring::record_tree_nodes,
+    schema_version::{requested_schema_version, DEFAULT_SCHEMA_VERSION},
+};
+
+use processor::{Continuation, Data, ItemId, Lang, QueryTimeout, RandomItems, Search, TreeLimits};
+use serde::{de::value::StrDeserializer, Deserialize};
+
+use std::{
+    convert::Infallible,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use anyhow::Result;
 use axum::{
+    body::StreamBody,
     extract::{Path, Query, State},
-    response::Json,
+    http::{header, HeaderValue, Method, Request, StatusCode},
+    middleware::Next,
+    response::{Html, IntoResponse, Json, Response},
 };
 use axum_extra::extract::Query as ExtraQuery;
-use serde_json::Value;
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
 
 pub enum Environment {
     Development,
@@ -32,19 +61,277 @@ impl FromStr for Environment {
 pub struct AppState {
     pub data: Data,
     pub search: Search,
+    pub random_items: RandomItems,
+    // Tree queries that take longer than this are abandoned with a 503.
+    pub query_timeout: Duration,
+    pub graphql: graphql::WetySchema,
+    // Responses only change when the data or term frequencies do (and with
+    // the schema version requested), so they are validated by their hashes.
+    pub content_hash: u64,
 }
 
 impl AppState {
     /// # Errors
     ///
-    /// Will return `Err` if deserializing the data file fails.
-    pub fn new(data_path: &std::path::Path) -> Result<Self> {
+    /// Will return `Err` if deserializing the data file, or reading the term
+    /// frequencies file, fails.
+    pub fn new(
+        data_path: &std::path::Path,
+        frequencies_path: Option<&std::path::Path>,
+        query_timeout: Duration,
+        resident_search_langs: Option<usize>,
+    ) -> Result<Self> {
         let data = Data::deserialize(data_path)?;
-        let search = data.build_search();
-        Ok(Self { data, search })
+        Self::with_data(data, frequencies_path, query_timeout, resident_search_langs)
+    }
+
+    /// Like [`AppState::new`], but with only the shards of `langs` loaded
+    /// from the shards directory `shards_path`.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if deserializing the shards, or reading the term
+    /// frequencies file, fails.
+    pub fn with_shards(
+        shards_path: &std::path::Path,
+        langs: &[Lang],
+        frequencies_path: Option<&std::path::Path>,
+        query_timeout: Duration,
+        resident_search_langs: Option<usize>,
+    ) -> Result<Self> {
+        let data = Data::deserialize_shards(shards_path, langs)?;
+        Self::with_data(data, frequencies_path, query_timeout, resident_search_langs)
+    }
+
+    fn with_data(
+        data: Data,
+        frequencies_path: Option<&std::path::Path>,
+        query_timeout: Duration,
+        resident_search_langs: Option<usize>,
+    ) -> Result<Self> {
+        let mut search = match resident_search_langs {
+            Some(resident) => data.build_lazy_search(resident),
+            None => data.build_search(),
+        };
+        let mut content_hash = data.content_hash();
+        if let Some(frequencies_path) = frequencies_path {
+            content_hash ^= search.load_frequencies(&data, frequencies_path)?;
+        }
+        let random_items = data.build_random_items();
+        Ok(Self {
+            data,
+            search,
+            random_items,
+            query_timeout,
+            graphql: graphql::schema(),
+            content_hash,
+        })
     }
 }
 
+impl AppState {
+    // The descendants tree of the item (or of a continuation from a previous
+    // response), with lang distances measured from dist_lang, which defaults
+    // to the item's lang.
+    fn descendants_json(
+        &self,
+        item_id: ItemId,
+        desc_langs: &[Lang],
+        dist_lang: Option<Lang>,
+        continuation: Option<Continuation>,
+        limits: &TreeLimits,
+    ) -> Result<Value, QueryTimeout> {
+        let dist_lang = dist_lang.unwrap_or(self.data.lang(item_id));
+        let head_ancestors_within_lang = self.data.ancestors_in_langs(item_id, desc_langs);
+        if let Some(continuation) = continuation {
+            return self.data.item_descendants_continuation_json(
+                continuation,
+                dist_lang,
+                desc_langs,
+                &head_ancestors_within_lang,
+                limits,
+            );
+        }
+        self.data.item_descendants_json(
+            item_id,
+            dist_lang,
+            desc_langs,
+            &head_ancestors_within_lang,
+            limits,
+        )
+    }
+
+    // Like descendants_json, but passing each node to emit as it is built.
+    fn descendants_nodes(
+        &self,
+        item_id: ItemId,
+        desc_langs: &[Lang],
+        dist_lang: Option<Lang>,
+        continuation: Option<Continuation>,
+        limits: &TreeLimits,
+        emit: &mut dyn FnMut(Value) -> bool,
+    ) -> Result<(), QueryTimeout> {
+        let dist_lang = dist_lang.unwrap_or(self.data.lang(item_id));
+        let head_ancestors_within_lang = self.data.ancestors_in_langs(item_id, desc_langs);
+        self.data.item_descendants_nodes(
+            item_id,
+            dist_lang,
+            desc_langs,
+            &head_ancestors_within_lang,
+            continuation,
+            limits,
+            emit,
+        )
+    }
+
+    fn cognates_json(
+        &self,
+        item_id: ItemId,
+        desc_langs: &[Lang],
+        dist_lang: Option<Lang>,
+        limits: &TreeLimits,
+    ) -> Result<Value, QueryTimeout> {
+        let dist_lang = dist_lang.unwrap_or(self.data.lang(item_id));
+        let head_ancestors_within_lang = self.data.ancestors_in_langs(item_id, desc_langs);
+        self.data.item_cognates_json(
+            item_id,
+            dist_lang,
+            desc_langs,
+            &head_ancestors_within_lang,
+            limits,
+        )
+    }
+}
+
+// Parse a lang given by code or id, as langs in paths and queries are.
+pub(crate) fn parse_lang(s: &str) -> Result<Lang, serde::de::value::Error> {
+    Lang::deserialize(StrDeserializer::new(s))
+}
+
+pub(crate) enum TreeError {
+    Timeout(Duration),
+    Internal,
+}
+
+// Build a tree json on the blocking thread pool, so that long traversals don't
+// starve the async runtime. The builder checks the timeout cooperatively and
+// gives up once it has passed. The size of each tree built is recorded under
+// the kind of tree, e.g. "etymology".
+pub(crate) async fn build_tree<F>(
+    state: Arc<AppState>,
+    tree: &'static str,
+    mut limits: TreeLimits,
+    build: F,
+) -> Result<Value, TreeError>
+where
+    F: FnOnce(&AppState, &TreeLimits) -> Result<Value, QueryTimeout> + Send + 'static,
+{
+    let query_timeout = state.query_timeout;
+    limits.timeout = Some(Instant::now() + query_timeout);
+    let built = tokio::task::spawn_blocking(move || {
+        let built = build(&state, &limits);
+        if built.is_ok() {
+            record_tree_nodes(tree, limits.nodes());
+        }
+        built
+    });
+    match built.await {
+        Ok(Ok(tree)) => Ok(tree),
+        Ok(Err(QueryTimeout)) => Err(TreeError::Timeout(query_timeout)),
+        Err(_) => Err(TreeError::Internal),
+    }
+}
+
+type TreeResponse = Result<Json<Value>, (StatusCode, Json<Value>)>;
+
+pub(crate) fn timeout_error(timeout: Duration) -> (StatusCode, Json<Value>) {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(json!({
+            "error": "timeout",
+            "message": format!("query exceeded the {} ms time limit", timeout.as_millis()),
+        })),
+    )
+}
+
+pub(crate) fn internal_error() -> (StatusCode, Json<Value>) {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(json!({
+            "error": "internal",
+            "message": "query failed unexpectedly",
+        })),
+    )
+}
+
+async fn tree_response<F>(
+    state: Arc<AppState>,
+    tree: &'static str,
+    limits: TreeLimits,
+    build: F,
+) -> TreeResponse
+where
+    F: FnOnce(&AppState, &TreeLimits) -> Result<Value, QueryTimeout> + Send + 'static,
+{
+    match build_tree(state, tree, limits, build).await {
+        Ok(tree) => Ok(Json(tree)),
+        Err(TreeError::Timeout(timeout)) => Err(timeout_error(timeout)),
+        Err(TreeError::Internal) => Err(internal_error()),
+    }
+}
+
+// Whether an If-None-Match header value matches the etag, using weak
+// comparison.
+fn etag_matches(if_none_match: &HeaderValue, etag: &HeaderValue) -> bool {
+    let Result::Ok(if_none_match) = if_none_match.to_str() else {
+        return false;
+    };
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    let etag = opaque(etag.to_str().unwrap_or_default());
+    if_none_match
+        .split(',')
+        .any(|tag| tag.trim() == "*" || opaque(tag) == etag)
+}
+
+// A weak validator, since responses may be compressed.
+fn etag(state: &AppState, schema_version: u32) -> HeaderValue {
+    HeaderValue::from_str(&format!(
+        "W/\"{:016x}-v{schema_version}\"",
+        state.content_hash
+    ))
+    .expect("etag is ascii")
+}
+
+/// Middleware that tags successful GET responses with an etag for the data and
+/// the requested schema version, and answers requests whose `If-None-Match`
+/// matches it with a 304. Only for routes whose responses are determined by
+/// the request and the data.
+pub async fn conditional_get<B>(
+    State(state): State<Arc<AppState>>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    if request.method() != Method::GET {
+        return next.run(request).await;
+    }
+    let etag = etag(
+        &state,
+        requested_schema_version(request.headers()).unwrap_or(DEFAULT_SCHEMA_VERSION),
+    );
+    if request
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .is_some_and(|if_none_match| etag_matches(if_none_match, &etag))
+    {
+        return (StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response();
+    }
+    let mut response = next.run(request).await;
+    if response.status().is_success() {
+        response.headers_mut().insert(header::ETAG, etag);
+    }
+    response
+}
+
 #[derive(Deserialize)]
 pub struct LangSearch {
     name: String,
@@ -63,21 +350,157 @@ pub struct ItemSearch {
     term: String,
 }
 
+// Run a search on the blocking thread pool, since with lazy search, searching
+// a lang whose trie isn't resident builds it first.
+async fn search_blocking<F>(
+    state: Arc<AppState>,
+    search: F,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)>
+where
+    F: FnOnce(&AppState) -> Value + Send + 'static,
+{
+    tokio::task::spawn_blocking(move || search(&state))
+        .await
+        .map(Json)
+        .map_err(|_| internal_error())
+}
+
+/// # Errors
+///
+/// Will return `Err` with a 500 if the search fails unexpectedly.
 pub async fn item_search_matches(
     State(state): State<Arc<AppState>>,
     Path(lang): Path<Lang>,
     Query(item_search): Query<ItemSearch>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    search_blocking(state, move |state| {
+        state.search.items(&state.data, lang, &item_search.term)
+    })
+    .await
+}
+
+#[derive(Deserialize)]
+pub struct Completion {
+    prefix: String,
+    // how many completions to return, at most 50
+    limit: Option<usize>,
+}
+
+const DEFAULT_COMPLETIONS: usize = 10;
+
+pub async fn item_completions(
+    State(state): State<Arc<AppState>>,
+    Path(lang): Path<Lang>,
+    Query(completion): Query<Completion>,
+) -> Json<Value> {
+    let matches = state.search.completions(
+        &state.data,
+        lang,
+        &completion.prefix,
+        completion.limit.unwrap_or(DEFAULT_COMPLETIONS),
+    );
+    Json(matches)
+}
+
+#[derive(Deserialize)]
+pub struct IpaSearch {
+    q: String,
+}
+
+/// # Errors
+///
+/// Will return `Err` with a 500 if the search fails unexpectedly.
+pub async fn ipa_search_matches(
+    State(state): State<Arc<AppState>>,
+    Path(lang): Path<Lang>,
+    Query(ipa_search): Query<IpaSearch>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    search_blocking(state, move |state| {
+        state.search.ipa(&state.data, lang, &ipa_search.q)
+    })
+    .await
+}
+
+#[derive(Deserialize)]
+pub struct GlossSearch {
+    q: String,
+    lang: Option<Lang>,
+}
+
+pub async fn gloss_search_matches(
+    State(state): State<Arc<AppState>>,
+    Query(gloss_search): Query<GlossSearch>,
 ) -> Json<Value> {
-    let matches = state.search.items(&state.data, lang, &item_search.term);
+    let matches = state
+        .search
+        .glosses(&state.data, &gloss_search.q, gloss_search.lang);
     Json(matches)
 }
 
+#[derive(Deserialize)]
+pub struct EtymologyQueries {
+    // Leave nodes at this depth (the item being at depth 0), or past this many
+    // nodes, unexpanded and marked as truncated.
+    #[serde(rename = "maxDepth")]
+    max_depth: Option<usize>,
+    #[serde(rename = "maxNodes")]
+    max_nodes: Option<usize>,
+}
+
+/// # Errors
+///
+/// Will return `Err` with a 503 if building the tree runs past the time limit.
 pub async fn item_etymology(
     State(state): State<Arc<AppState>>,
     Path(item_id): Path<ItemId>,
-) -> Json<Value> {
-    let lang = state.data.lang(item_id);
-    Json(state.data.item_etymology_json(item_id, 0, lang))
+    Query(etymology_queries): Query<EtymologyQueries>,
+) -> TreeResponse {
+    let limits = TreeLimits::new(
+        None,
+        None,
+        etymology_queries.max_depth,
+        etymology_queries.max_nodes,
+    );
+    tree_response(state, "etymology", limits, move |state, limits| {
+        let lang = state.data.lang(item_id);
+        state.data.item_etymology_json(item_id, 0, lang, limits)
+    })
+    .await
+}
+
+#[derive(Deserialize)]
+pub struct NeighborhoodQueries {
+    // How many links from the item to follow, to parents and children alike.
+    radius: Option<usize>,
+    #[serde(rename = "maxNodes")]
+    max_nodes: Option<usize>,
+}
+
+const DEFAULT_NEIGHBORHOOD_RADIUS: usize = 2;
+// Past this, neighborhoods are mostly cut off by the node limit anyway.
+const MAX_NEIGHBORHOOD_RADIUS: usize = 10;
+
+/// The items within a radius of links of the item, and the links among them,
+/// as nodes and links for graph visualizations.
+///
+/// # Errors
+///
+/// Will return `Err` with a 503 if finding the neighborhood runs past the time
+/// limit.
+pub async fn item_neighborhood(
+    State(state): State<Arc<AppState>>,
+    Path(item_id): Path<ItemId>,
+    Query(neighborhood_queries): Query<NeighborhoodQueries>,
+) -> TreeResponse {
+    let radius = neighborhood_queries
+        .radius
+        .unwrap_or(DEFAULT_NEIGHBORHOOD_RADIUS)
+        .min(MAX_NEIGHBORHOOD_RADIUS);
+    let limits = TreeLimits::new(None, None, None, neighborhood_queries.max_nodes);
+    tree_response(state, "neighborhood", limits, move |state, limits| {
+        state.data.neighborhood_json(item_id, radius, limits)
+    })
+    .await
 }
 
 #[derive(Deserialize)]
@@ -86,38 +509,249 @@ pub struct TreeQueries {
     desc_langs: Vec<Lang>,
     #[serde(rename = "distLang")]
     dist_lang: Option<Lang>,
+    // Stop expanding the tree once this much time has passed, or at this depth
+    // (the item being at depth 0), or past this many nodes, leaving
+    // continuations on the unexpanded nodes.
+    budget_ms: Option<u64>,
+    #[serde(rename = "maxDepth")]
+    max_depth: Option<usize>,
+    #[serde(rename = "maxNodes")]
+    max_nodes: Option<usize>,
+    // Request the subtree of a node that was truncated in a previous response.
+    continuation: Option<Continuation>,
+}
+
+impl TreeQueries {
+    fn limits(&self) -> TreeLimits {
+        let budget = self
+            .budget_ms
+            .map(|budget| Instant::now() + Duration::from_millis(budget));
+        TreeLimits::new(budget, None, self.max_depth, self.max_nodes)
+    }
 }
 
+/// # Errors
+///
+/// Will return `Err` with a 503 if building the tree runs past the time limit.
 pub async fn item_descendants(
     State(state): State<Arc<AppState>>,
     Path(item_id): Path<ItemId>,
     ExtraQuery(tree_queries): ExtraQuery<TreeQueries>,
-) -> Json<Value> {
-    let dist_lang = tree_queries.dist_lang.unwrap_or(state.data.lang(item_id));
-    let head_ancestors_within_lang = state
-        .data
-        .ancestors_in_langs(item_id, &tree_queries.desc_langs);
-    Json(state.data.item_descendants_json(
-        item_id,
-        dist_lang,
-        &tree_queries.desc_langs,
-        &head_ancestors_within_lang,
-    ))
+) -> TreeResponse {
+    let limits = tree_queries.limits();
+    tree_response(state, "descendants", limits, move |state, limits| {
+        state.descendants_json(
+            item_id,
+            &tree_queries.desc_langs,
+            tree_queries.dist_lang,
+            tree_queries.continuation,
+            limits,
+        )
+    })
+    .await
+}
+
+// How many nodes of a streamed tree to build ahead of the client.
+const STREAM_BUFFER: usize = 256;
+
+/// Like `item_descendants`, but streams the tree as newline-delimited json, one
+/// node per line, parents before children. Each node has "node", its index in
+/// the stream, and "parent", the index of its parent node (null for the root),
+/// instead of "children". If building the tree runs past the time limit, the
+/// stream ends with an error line instead.
+pub async fn item_descendants_stream(
+    State(state): State<Arc<AppState>>,
+    Path(item_id): Path<ItemId>,
+    ExtraQuery(tree_queries): ExtraQuery<TreeQueries>,
+) -> impl IntoResponse {
+    let (tx, rx) = mpsc::channel::<String>(STREAM_BUFFER);
+    let query_timeout = state.query_timeout;
+    let mut limits = tree_queries.limits();
+    limits.timeout = Some(Instant::now() + query_timeout);
+    tokio::task::spawn_blocking(move || {
+        // Sending fails once the client has gone away, which stops the build.
+        let mut emit = |node: Value| tx.blocking_send(format!("{node}\n")).is_ok();
+        let built = state.descendants_nodes(
+            item_id,
+            &tree_queries.desc_langs,
+            tree_queries.dist_lang,
+            tree_queries.continuation,
+            &limits,
+            &mut emit,
+        );
+        match built {
+            Ok(()) => record_tree_nodes("descendantsStream", limits.nodes()),
+            Err(_) => {
+                let (_, Json(error)) = timeout_error(query_timeout);
+                emit(error);
+            }
+        }
+    });
+    let body = StreamBody::new(ReceiverStream::new(rx).map(Ok::<_, Infallible>));
+    ([(header::CONTENT_TYPE, "application/x-ndjson")], body)
 }
 
+/// # Errors
+///
+/// Will return `Err` with a 503 if building the tree runs past the time limit.
 pub async fn item_cognates(
     State(state): State<Arc<AppState>>,
     Path(item_id): Path<ItemId>,
     ExtraQuery(tree_queries): ExtraQuery<TreeQueries>,
-) -> Json<Value> {
-    let dist_lang = tree_queries.dist_lang.unwrap_or(state.data.lang(item_id));
-    let head_ancestors_within_lang = state
+) -> TreeResponse {
+    let limits = tree_queries.limits();
+    tree_response(state, "cognates", limits, move |state, limits| {
+        state.cognates_json(
+            item_id,
+            &tree_queries.desc_langs,
+            tree_queries.dist_lang,
+            limits,
+        )
+    })
+    .await
+}
+
+/// # Errors
+///
+/// Will return `Err` with a 404 if no item has the stable id.
+pub async fn item_by_stable_id(
+    State(state): State<Arc<AppState>>,
+    Path(stable_id): Path<String>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    state
         .data
-        .ancestors_in_langs(item_id, &tree_queries.desc_langs);
-    Json(state.data.item_cognates_json(
-        item_id,
-        dist_lang,
-        &tree_queries.desc_langs,
-        &head_ancestors_within_lang,
+        .item_by_stable_id(&stable_id)
+        .map(|item| Json(state.data.item_json(item)))
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            Json(json!({
+                "error": "noItem",
+                "message": "no item has the stable id",
+            })),
+        ))
+}
+
+/// # Errors
+///
+/// Will return `Err` with a 404 if the item has no head progenitor.
+pub async fn item_cognate_set(
+    State(state): State<Arc<AppState>>,
+    Path(item): Path<ItemId>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    state.data.cognate_set_json(item).map(Json).ok_or((
+        StatusCode::NOT_FOUND,
+        Json(json!({
+            "error": "noCognateSet",
+            "message": "the item is in no cognate set",
+        })),
     ))
 }
+
+#[derive(Deserialize)]
+pub struct ConnectionQueries {
+    // How many links up from each item to look for a shared ancestor.
+    #[serde(rename = "maxDepth")]
+    max_depth: Option<usize>,
+    #[serde(rename = "maxNodes")]
+    max_nodes: Option<usize>,
+}
+
+/// # Errors
+///
+/// Will return `Err` with a 404 if either item doesn't exist or the items
+/// share no ancestor within the limits, or with a 503 if finding the
+/// connection runs past the time limit.
+pub async fn items_connection(
+    State(state): State<Arc<AppState>>,
+    Path((item_a, item_b)): Path<(ItemId, ItemId)>,
+    Query(connection_queries): Query<ConnectionQueries>,
+) -> TreeResponse {
+    for item in [item_a, item_b] {
+        if !state.data.contains(item) {
+            return Err((
+                StatusCode::NOT_FOUND,
+                Json(json!({
+                    "error": "noItem",
+                    "message": format!("no item with id {}", item.index()),
+                })),
+            ));
+        }
+    }
+    let limits = TreeLimits::new(
+        None,
+        None,
+        connection_queries.max_depth,
+        connection_queries.max_nodes,
+    );
+    // no connection is built as null, to be told apart from a timeout
+    let connection = tree_response(state, "connection", limits, move |state, limits| {
+        Result::Ok(
+            state
+                .data
+                .connection_json(item_a, item_b, limits)?
+                .unwrap_or(Value::Null),
+        )
+    })
+    .await?;
+    if connection.0.is_null() {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({
+                "error": "notConnected",
+                "message": "the items share no ancestor within the limits",
+            })),
+        ));
+    }
+    Ok(connection)
+}
+
+#[derive(Deserialize)]
+pub struct RandomQueries {
+    lang: Option<Lang>,
+    #[serde(rename = "minDepth")]
+    min_depth: Option<usize>,
+}
+
+/// # Errors
+///
+/// Will return `Err` with a 404 if no item meets the constraints.
+pub async fn random_item(
+    State(state): State<Arc<AppState>>,
+    Query(random_queries): Query<RandomQueries>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    state
+        .random_items
+        .random_item(&state.data, random_queries.lang, random_queries.min_depth)
+        .map(Json)
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            Json(json!({
+                "error": "noMatch",
+                "message": "no item meets the constraints",
+            })),
+        ))
+}
+
+pub async fn stats(State(state): State<Arc<AppState>>) -> Json<Value> {
+    Json(state.data.stats_json())
+}
+
+pub async fn langs(State(state): State<Arc<AppState>>) -> Json<Value> {
+    Json(state.data.langs_json())
+}
+
+// Hand-maintained, so keep it in sync with the routes in main.rs and the json
+// built in processor.
+const OPENAPI: &str = include_str!("../openapi.json");
+
+pub async fn openapi() -> ([(header::HeaderName, &'static str); 1], &'static str) {
+    ([(header::CONTENT_TYPE, "application/json")], OPENAPI)
+}
+
+// Swagger UI for the spec above, loaded from a CDN so as not to have to serve
+// its assets.
+const API_DOCS: &str = include_str!("../docs.html");
+
+pub async fn api_docs() -> Html<&'static str> {
+    Html(API_DOCS)
+}
@@ -1,80 +1,372 @@
 use server::{
-    item_cognates, item_descendants, item_etymology, item_search_matches, lang_search_matches,
-    AppState, Environment,
+    admin::{reload_data, AdminState},
+    api_docs, conditional_get, gloss_search_matches,
+    graphql::{graphql_get, graphql_post},
+    health::{healthz, readyz, when_ready, Readiness},
+    ipa_search_matches, item_by_stable_id, item_cognate_set, item_cognates, item_completions,
+    item_descendants, item_descendants_stream, item_etymology, item_neighborhood,
+    item_search_matches, items_connection, lang_search_matches, langs,
+    monitoring::{self, metrics, track_requests, MetricsState},
+    openapi, random_item,
+    rate_limit::{RateLimitedRoutes, RateLimits},
+    request_tracing::{log_query, request_span, REQUEST_ID_HEADER},
+    schema_version::{negotiate_schema_version, SCHEMA_VERSION_HEADER},
+    stats, AppState, Environment,
 };
 
-use std::{env, net::SocketAddr, path::Path, str::FromStr, sync::Arc};
+use std::{
+    env,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
 
-use anyhow::Result;
+use anyhow::{ensure, Context, Result};
 use axum::{
-    error_handling::HandleErrorLayer,
-    http::{HeaderValue, Method},
-    routing::get,
-    BoxError, Router,
+    http::{header, HeaderValue, Method},
+    middleware,
+    routing::{get, post},
+    Router,
 };
 use axum_server::tls_rustls::RustlsConfig;
+use processor::Lang;
+use tokio::signal::unix::{signal, SignalKind};
 use tower::ServiceBuilder;
-use tower_governor::{errors::display_error, GovernorLayer};
 use tower_http::{
     compression::CompressionLayer,
     cors::{AllowOrigin, CorsLayer},
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
     trace::TraceLayer,
 };
+use tracing::{error, info};
 
 #[tokio::main]
 async fn main() -> Result<()> {
     env::set_var("RUST_BACKTRACE", "1");
 
-    env::set_var("RUST_LOG", "tower_http=trace,tower_governor=trace");
+    // server=info for the server's own logs, including the queries logged by
+    // request_tracing::log_query
+    let log_filter = "server=info,tower_http=trace,tower_governor=trace";
+    env::set_var("RUST_LOG", log_filter);
     tracing_subscriber::fmt::init();
 
     let environment = Environment::from_str(
         &env::var("WETY_ENVIRONMENT").unwrap_or_else(|_| "development".to_string()),
     )?;
 
-    let origins: AllowOrigin = match environment {
-        Environment::Development => tower_http::cors::Any.into(),
-        Environment::Production => vec![
-            "https://wety.org".parse::<HeaderValue>()?,
-            "https://www.wety.org".parse::<HeaderValue>()?,
-        ]
-        .into(),
+    // If set, a comma-separated list of the origins allowed to make requests
+    // from browsers, or "*" for any. Otherwise any origin is allowed in
+    // development, and only wety.org in production.
+    let origins: AllowOrigin = match env::var("WETY_ALLOWED_ORIGINS") {
+        Ok(origins) if origins.trim() == "*" => tower_http::cors::Any.into(),
+        Ok(origins) => origins
+            .split(',')
+            .map(str::trim)
+            .filter(|origin| !origin.is_empty())
+            .map(HeaderValue::from_str)
+            .collect::<Result<Vec<_>, _>>()?
+            .into(),
+        Err(_) => match environment {
+            Environment::Development => tower_http::cors::Any.into(),
+            Environment::Production => vec![
+                "https://wety.org".parse::<HeaderValue>()?,
+                "https://www.wety.org".parse::<HeaderValue>()?,
+            ]
+            .into(),
+        },
     };
 
+    let query_timeout = Duration::from_millis(
+        env::var("WETY_QUERY_TIMEOUT_MS").map_or(Ok(10_000), |ms| ms.parse())?,
+    );
+
+    // If set, build each lang's search trie on its first search, keeping at
+    // most this many built, rather than building them all at startup.
+    let resident_search_langs = env::var("WETY_RESIDENT_SEARCH_LANGS")
+        .ok()
+        .map(|n| n.parse())
+        .transpose()?;
+
+    // If set, a comma-separated list of lang codes to load the shards of,
+    // rather than loading all of the data.
+    let shard_langs = env::var("WETY_LANGS")
+        .ok()
+        .map(|codes| {
+            codes
+                .split(',')
+                .map(|code| Lang::from_str(code.trim()))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .transpose()?;
+
+    // If set, a comma-separated list of other data files to serve alongside
+    // the main data, each under /v/<name>, given as <name>=<path>.
+    let datasets =
+        env::var("WETY_DATASETS").map_or(Ok(vec![]), |datasets| parse_datasets(&datasets))?;
+
+    // The health, admin and metrics endpoints are not rate limited, so that
+    // e.g. a load balancer's probes are never turned away.
+    let rate_limits = RateLimits::from_env()?;
+    let metrics_handle = monitoring::install()?;
+
+    // Serve the health endpoints while the data loads, which can take minutes.
+    let readiness = Arc::new(Readiness::default());
+    let app = Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .fallback(when_ready)
+        .with_state(readiness.clone());
+    let app = match env::var("WETY_ADMIN_TOKEN") {
+        Ok(token) => app.merge(
+            Router::new()
+                .route("/admin/reload", post(reload_data))
+                .with_state(AdminState {
+                    token,
+                    readiness: readiness.clone(),
+                }),
+        ),
+        Err(_) => app,
+    };
+    let app = app.merge(
+        Router::new()
+            .route("/metrics", get(metrics))
+            .with_state(MetricsState {
+                handle: metrics_handle,
+                readiness: readiness.clone(),
+            }),
+    );
+    let app = app.layer(
+        ServiceBuilder::new()
+            .layer(SetRequestIdLayer::new(REQUEST_ID_HEADER, MakeRequestUuid))
+            .layer(TraceLayer::new_for_http().make_span_with(request_span))
+            .layer(PropagateRequestIdLayer::new(REQUEST_ID_HEADER))
+            .layer(CompressionLayer::new())
+            .layer(
+                CorsLayer::new()
+                    .allow_methods([Method::GET, Method::POST])
+                    .allow_headers([header::CONTENT_TYPE])
+                    .expose_headers([REQUEST_ID_HEADER, SCHEMA_VERSION_HEADER])
+                    .allow_origin(origins),
+            ),
+    );
+    let server = tokio::spawn(serve(app, environment));
+
+    // $$$ make this configurable
+    #[cfg(feature = "grpc")]
+    {
+        let grpc_addr = SocketAddr::from_str("0.0.0.0:50051")?;
+        let grpc_readiness = readiness.clone();
+        tokio::spawn(async move {
+            if let Err(e) = server::grpc::serve(grpc_readiness, grpc_addr).await {
+                error!("gRPC server failed: {e}");
+            }
+        });
+    }
+
+    // Routes on other data than the data file, which are not reloaded with it.
+    let extra = Router::new();
+    // $$$ make these configurable
+    #[cfg(feature = "sparql")]
+    let extra = {
+        // Loading the Turtle file into an empty store takes a while.
+        let store = tokio::task::spawn_blocking(|| {
+            server::sparql::build_store(Path::new("data/wety-store"), Path::new("data/wety.ttl"))
+        })
+        .await??;
+        extra.merge(server::sparql::router(server::sparql::SparqlState {
+            store,
+            query_timeout,
+        }))
+    };
+    let extra = rate_limits.limit_router(extra);
+
+    let load = LoadOptions {
+        query_timeout,
+        resident_search_langs,
+        shard_langs,
+        datasets,
+        rate_limits,
+    };
+    let states = load_states(load.clone()).await?;
+    readiness.set_loaded(states.router(extra.clone(), &load.rate_limits), states.main);
+    tokio::spawn(reload_on_request(readiness, load, extra));
+
+    server.await?
+}
+
+#[derive(Clone)]
+struct LoadOptions {
+    query_timeout: Duration,
+    resident_search_langs: Option<usize>,
+    shard_langs: Option<Vec<Lang>>,
+    datasets: Vec<(String, PathBuf)>,
+    rate_limits: RateLimits,
+}
+
+// e.g. "2024-06=data/wety-2024-06.json.gz,en=data/wety-en.json.gz"
+fn parse_datasets(datasets: &str) -> Result<Vec<(String, PathBuf)>> {
+    datasets
+        .split(',')
+        .filter(|dataset| !dataset.trim().is_empty())
+        .map(|dataset| {
+            let (name, path) = dataset.split_once('=').with_context(|| {
+                format!("expected a dataset like \"en=data/wety-en.json.gz\", got \"{dataset}\"")
+            })?;
+            let name = name.trim();
+            ensure!(
+                !name.is_empty()
+                    && name
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')),
+                "dataset names can only have letters, digits, '-', '_' and '.', got \"{name}\""
+            );
+            Ok((name.to_string(), PathBuf::from(path.trim())))
+        })
+        .collect()
+}
+
+// The main data, and that of each other dataset, served under /v/<name>.
+struct States {
+    main: Arc<AppState>,
+    datasets: Vec<(String, Arc<AppState>)>,
+}
+
+impl States {
+    fn router(&self, extra: Router, limits: &RateLimits) -> Router {
+        self.datasets.iter().fold(
+            app_router(self.main.clone(), extra, limits),
+            |app, (name, state)| {
+                app.nest(
+                    &format!("/v/{name}"),
+                    app_router(state.clone(), Router::new(), limits),
+                )
+            },
+        )
+    }
+}
+
+async fn load_states(load: LoadOptions) -> Result<States> {
+    let main = load_state(load.clone()).await?;
+    let mut datasets = vec![];
+    for (name, path) in load.datasets {
+        let query_timeout = load.query_timeout;
+        let resident_search_langs = load.resident_search_langs;
+        let state = tokio::task::spawn_blocking(move || {
+            AppState::new(&path, None, query_timeout, resident_search_langs)
+                .with_context(|| format!("loading dataset from {}", path.display()))
+        })
+        .await??;
+        datasets.push((name, Arc::new(state)));
+    }
+    Ok(States { main, datasets })
+}
+
+async fn load_state(load: LoadOptions) -> Result<Arc<AppState>> {
     // $$$ make this configurable
-    let data_path = Path::new("data/wety.json");
-    let state = if data_path.exists() {
-        Arc::new(AppState::new(data_path)?)
+    let data_path = if Path::new("data/wety.json").exists() {
+        Path::new("data/wety.json")
+    } else if Path::new("data/wety.json.zst").exists() {
+        Path::new("data/wety.json.zst")
     } else {
-        Arc::new(AppState::new(Path::new("data/wety.json.gz"))?)
+        Path::new("data/wety.json.gz")
     };
+    // $$$ make this configurable
+    let frequencies_path = Some(Path::new("data/frequencies.csv")).filter(|path| path.exists());
+    let state = tokio::task::spawn_blocking(move || match &load.shard_langs {
+        Some(langs) => AppState::with_shards(
+            Path::new("data/wety.shards"),
+            langs,
+            frequencies_path,
+            load.query_timeout,
+            load.resident_search_langs,
+        ),
+        None => AppState::new(
+            data_path,
+            frequencies_path,
+            load.query_timeout,
+            load.resident_search_langs,
+        ),
+    })
+    .await??;
+    Ok(Arc::new(state))
+}
 
-    let app = Router::new()
-        .route("/search/lang", get(lang_search_matches))
-        .route("/search/item/:lang", get(item_search_matches))
-        .route("/cognates/:item", get(item_cognates))
-        .route("/etymology/:item", get(item_etymology))
-        .route("/descendants/:item", get(item_descendants))
+// Reload the data file on SIGHUP or when asked to through the admin endpoint,
+// swapping in the new data once it has loaded. Until then, and if loading
+// fails, the old data keeps being served.
+async fn reload_on_request(readiness: Arc<Readiness>, load: LoadOptions, extra: Router) {
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => Some(hangups),
+        Err(e) => {
+            error!("Can't listen for SIGHUP, so reloading only by admin endpoint: {e}");
+            None
+        }
+    };
+    loop {
+        tokio::select! {
+            () = readiness.reload_requested() => {}
+            Some(()) = async { hangups.as_mut()?.recv().await } => {}
+        }
+        info!("Reloading data...");
+        match load_states(load.clone()).await {
+            Ok(states) => {
+                readiness.set_loaded(states.router(extra.clone(), &load.rate_limits), states.main)
+            }
+            Err(e) => error!("Reloading data failed, still serving the old data: {e}"),
+        }
+    }
+}
+
+fn app_router(state: Arc<AppState>, extra: Router, limits: &RateLimits) -> Router {
+    // The json data routes, whose responses take the shape of the requested
+    // schema version.
+    let data = Router::new()
+        .limited_route("/search/lang", get(lang_search_matches), limits)
+        .limited_route("/search/item/:lang", get(item_search_matches), limits)
+        .limited_route("/complete/item/:lang", get(item_completions), limits)
+        .limited_route("/search/ipa/:lang", get(ipa_search_matches), limits)
+        .limited_route("/search/gloss", get(gloss_search_matches), limits)
+        .limited_route("/item/:stable_id", get(item_by_stable_id), limits)
+        .limited_route("/cognates/:item", get(item_cognates), limits)
+        .limited_route("/cognate-set/:item", get(item_cognate_set), limits)
+        .limited_route("/etymology/:item", get(item_etymology), limits)
+        .limited_route("/descendants/:item", get(item_descendants), limits)
+        .limited_route("/path/:item_a/:item_b", get(items_connection), limits)
+        .limited_route("/neighborhood/:item", get(item_neighborhood), limits)
+        .limited_route("/stats", get(stats), limits)
+        .limited_route("/langs", get(langs), limits)
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            conditional_get,
+        ))
+        // not cacheable by etag, since these vary independently of the data
+        // or, for streams, may fail after the response has started
+        .limited_route("/random", get(random_item), limits)
+        .limited_route(
+            "/descendants/:item/stream",
+            get(item_descendants_stream),
+            limits,
+        )
+        .route_layer(middleware::from_fn(negotiate_schema_version))
+        .route_layer(middleware::from_fn(log_query));
+
+    Router::new()
+        // not cacheable by etag, since a query that fails, e.g. by timing out,
+        // still gets a 200, with the errors in the body
+        .limited_route("/graphql", get(graphql_get).post(graphql_post), limits)
+        .limited_route("/openapi.json", get(openapi), limits)
+        .limited_route("/docs", get(api_docs), limits)
+        .merge(data)
         .with_state(state)
-        .layer(
-            ServiceBuilder::new()
-                .layer(TraceLayer::new_for_http())
-                .layer(HandleErrorLayer::new(|e: BoxError| async move {
-                    display_error(e)
-                }))
-                .layer(GovernorLayer {
-                    config: Box::leak(Box::default()),
-                })
-                .layer(CompressionLayer::new())
-                .layer(
-                    CorsLayer::new()
-                        .allow_methods([Method::GET])
-                        .allow_origin(origins),
-                ),
-        );
+        .merge(extra)
+        .route_layer(middleware::from_fn(track_requests))
+}
 
+async fn serve(app: Router, environment: Environment) -> Result<()> {
     let addr = SocketAddr::from_str("0.0.0.0:3000")?;
-    println!("Running wety server at http://{addr}...");
+    info!("Running wety server at http://{addr}...");
 
     match environment {
         Environment::Development => {
@@ -83,6 +375,14 @@ async fn main() -> Result<()> {
                 .await?;
         }
         Environment::Production => {
+            // If set, a comma-separated list of the domains to get certificates
+            // for through ACME, rather than reading them from files.
+            if let Ok(domains) = env::var("WETY_ACME_DOMAINS") {
+                #[cfg(feature = "acme")]
+                return server::acme::serve(app, addr, &domains).await;
+                #[cfg(not(feature = "acme"))]
+                anyhow::bail!("ACME for {domains} needs the server built with --features acme");
+            }
             let cert_path = env::var("WETY_CERT_PATH")
                 .expect("WETY_CERT_PATH environment variable set in production");
             let key_path = env::var("WETY_KEY_PATH")
@@ -0,0 +1,143 @@
+//! Metrics for monitoring the server, served at `/metrics` in the Prometheus
+//! text format: request counts and latencies by route, the number of nodes in
+//! tree responses, the sizes of the search indexes, and the version of the
+//! data being served.
+
+use crate::health::{unix_seconds, Readiness};
+
+use std::{fmt::Write, sync::Arc, time::Instant};
+
+use anyhow::Result;
+use axum::{
+    extract::{MatchedPath, State},
+    http::{header, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use metrics::{counter, describe_counter, describe_histogram, histogram, Unit};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+
+const REQUESTS: &str = "wety_http_requests_total";
+const REQUEST_DURATION: &str = "wety_http_request_duration_seconds";
+const TREE_NODES: &str = "wety_tree_nodes";
+
+/// Install the global recorder of the metrics, returning a handle to render
+/// them with.
+///
+/// # Errors
+///
+/// Will return `Err` if a recorder is already installed.
+pub fn install() -> Result<PrometheusHandle> {
+    let handle = PrometheusBuilder::new()
+        .set_buckets_for_metric(
+            Matcher::Full(REQUEST_DURATION.to_string()),
+            &[
+                0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+            ],
+        )?
+        .set_buckets_for_metric(
+            Matcher::Full(TREE_NODES.to_string()),
+            &[1.0, 10.0, 100.0, 1_000.0, 10_000.0, 100_000.0],
+        )?
+        .install_recorder()?;
+    describe_counter!(REQUESTS, "Requests handled, by method, route and status.");
+    describe_histogram!(
+        REQUEST_DURATION,
+        Unit::Seconds,
+        "Time until the response started, by method and route."
+    );
+    describe_histogram!(TREE_NODES, "Nodes in built trees, by kind of tree.");
+    Ok(handle)
+}
+
+/// Middleware that counts and times requests by route, as it was routed (e.g.
+/// `/etymology/:item`), rather than by path, which would give a series per
+/// item.
+pub async fn track_requests<B>(
+    matched_path: MatchedPath,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let started = Instant::now();
+    let method = request.method().to_string();
+    let route = matched_path.as_str().to_string();
+    let response = next.run(request).await;
+    let status = response.status().as_u16().to_string();
+    counter!(
+        REQUESTS,
+        1,
+        "method" => method.clone(),
+        "route" => route.clone(),
+        "status" => status
+    );
+    histogram!(
+        REQUEST_DURATION,
+        started.elapsed().as_secs_f64(),
+        "method" => method,
+        "route" => route
+    );
+    response
+}
+
+// e.g. "descendants", for a tree that was built with this many nodes.
+#[allow(clippy::cast_precision_loss)]
+pub(crate) fn record_tree_nodes(tree: &'static str, nodes: usize) {
+    histogram!(TREE_NODES, nodes as f64, "tree" => tree);
+}
+
+#[derive(Clone)]
+pub struct MetricsState {
+    pub handle: PrometheusHandle,
+    pub readiness: Arc<Readiness>,
+}
+
+// Gauges of the data being served, which are read from it as they are
+// scraped, so that they never outlive a reload.
+fn data_gauges(readiness: &Readiness) -> String {
+    let mut gauges = String::new();
+    let mut gauge = |name: &str, help: &str, samples: &[(String, String)]| {
+        let _ = writeln!(gauges, "# HELP {name} {help}\n# TYPE {name} gauge");
+        for (labels, value) in samples {
+            let _ = writeln!(gauges, "{name}{labels} {value}");
+        }
+    };
+    if let Some(startup_time) = readiness.startup_time() {
+        gauge(
+            "wety_startup_seconds",
+            "Time from starting up to first serving the data.",
+            &[(String::new(), startup_time.as_secs_f64().to_string())],
+        );
+    }
+    let (Some(state), Some(loaded_at)) = (readiness.state(), readiness.loaded_at()) else {
+        return gauges;
+    };
+    gauge(
+        "wety_data_info",
+        "The version (content hash) of the data being served.",
+        &[(
+            format!("{{version=\"{:016x}\"}}", state.content_hash),
+            "1".to_string(),
+        )],
+    );
+    gauge(
+        "wety_data_loaded_timestamp_seconds",
+        "When the data being served was loaded.",
+        &[(String::new(), unix_seconds(loaded_at).to_string())],
+    );
+    gauge(
+        "wety_search_index_size",
+        "Entries in each of the search indexes.",
+        &state
+            .search
+            .index_sizes()
+            .map(|(index, size)| (format!("{{index=\"{index}\"}}"), size.to_string())),
+    );
+    gauges
+}
+
+pub async fn metrics(State(metrics): State<MetricsState>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics.handle.render() + &data_gauges(&metrics.readiness),
+    )
+}
@@ -0,0 +1,207 @@
+//! Rate limiting of the data routes, configured through the environment:
+//!
+//! - `WETY_RATE_LIMIT`: the limit of every route, as requests per second and
+//!   burst size, e.g. `2/8`, or `off` for none. Defaults to `2/8`.
+//! - `WETY_RATE_LIMIT_ROUTES`: comma-separated limits for particular routes,
+//!   given as they are routed, e.g. `/descendants/:item=1/4,/langs=off`. A
+//!   route with its own limit doesn't count against the limit of the others.
+//! - `WETY_RATE_LIMIT_KEY`: whom a limit applies to, `peer-ip` (the default)
+//!   for each connecting address, `forwarded` for each address given by a
+//!   proxy in the `X-Forwarded-For`, `X-Real-IP` or `Forwarded` headers, or
+//!   `global` for all requests together.
+
+use std::{collections::HashMap, env, str::FromStr};
+
+use anyhow::{bail, ensure, Context, Ok, Result};
+use axum::{error_handling::HandleErrorLayer, routing::MethodRouter, BoxError, Router};
+use tower::ServiceBuilder;
+use tower_governor::{
+    errors::display_error,
+    governor::{GovernorConfig, GovernorConfigBuilder},
+    key_extractor::{GlobalKeyExtractor, PeerIpKeyExtractor, SmartIpKeyExtractor},
+    GovernorLayer,
+};
+
+// That of tower_governor's default config, which was used before limits were
+// configurable.
+const DEFAULT_LIMIT: Limit = Limit {
+    per_second: 2,
+    burst: 8,
+};
+
+#[derive(Clone, Copy)]
+enum Key {
+    PeerIp,
+    Forwarded,
+    Global,
+}
+
+impl FromStr for Key {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "peer-ip" => Ok(Self::PeerIp),
+            "forwarded" => Ok(Self::Forwarded),
+            "global" => Ok(Self::Global),
+            _ => bail!("unknown rate limit key \"{s}\", expected peer-ip, forwarded or global"),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Limit {
+    per_second: u64,
+    burst: u32,
+}
+
+// e.g. "2/8" -> 2 per second with bursts of 8, "off" -> no limit
+fn parse_limit(s: &str) -> Result<Option<Limit>> {
+    if s.trim() == "off" {
+        return Ok(None);
+    }
+    let Some((per_second, burst)) = s.split_once('/') else {
+        bail!("expected a rate limit like \"2/8\" or \"off\", got \"{s}\"");
+    };
+    let limit = Limit {
+        per_second: per_second.trim().parse()?,
+        burst: burst.trim().parse()?,
+    };
+    ensure!(
+        (1..=1000).contains(&limit.per_second) && limit.burst > 0,
+        "rate limits need 1 to 1000 requests per second and a burst size of at least 1, got \"{s}\""
+    );
+    Ok(Some(limit))
+}
+
+// A limiter's config holds the state of its limit, so routes limited by the
+// same config share a limit. Configs are only made at startup, and live as
+// long as the server.
+#[derive(Clone, Copy)]
+enum Limiter {
+    PeerIp(&'static GovernorConfig<PeerIpKeyExtractor>),
+    Forwarded(&'static GovernorConfig<SmartIpKeyExtractor>),
+    Global(&'static GovernorConfig<GlobalKeyExtractor>),
+}
+
+impl Limiter {
+    fn new(key: Key, limit: Limit) -> Result<Self> {
+        // The quota is replenished by one request at a time.
+        let period_ms = 1000 / limit.per_second;
+        let mut builder = GovernorConfigBuilder::default();
+        builder.per_millisecond(period_ms).burst_size(limit.burst);
+        let invalid = "invalid rate limit";
+        Ok(match key {
+            Key::PeerIp => Self::PeerIp(Box::leak(Box::new(builder.finish().context(invalid)?))),
+            Key::Forwarded => Self::Forwarded(Box::leak(Box::new(
+                builder
+                    .key_extractor(SmartIpKeyExtractor)
+                    .finish()
+                    .context(invalid)?,
+            ))),
+            Key::Global => Self::Global(Box::leak(Box::new(
+                builder
+                    .key_extractor(GlobalKeyExtractor)
+                    .finish()
+                    .context(invalid)?,
+            ))),
+        })
+    }
+}
+
+// The governor's errors, e.g. too many requests, are turned into responses.
+macro_rules! governed {
+    ($config:expr) => {
+        ServiceBuilder::new()
+            .layer(HandleErrorLayer::new(|e: BoxError| async move {
+                display_error(e)
+            }))
+            .layer(GovernorLayer { config: $config })
+    };
+}
+
+/// The rate limiters of the routes, as configured through the environment (see
+/// the module docs).
+#[derive(Clone)]
+pub struct RateLimits {
+    default: Option<Limiter>,
+    routes: HashMap<String, Option<Limiter>>,
+}
+
+impl RateLimits {
+    /// # Errors
+    ///
+    /// Will return `Err` if any of the rate limit environment variables is
+    /// malformed.
+    pub fn from_env() -> Result<Self> {
+        let key = env::var("WETY_RATE_LIMIT_KEY").map_or(Ok(Key::PeerIp), |key| key.parse())?;
+        let limiter =
+            |limit: Option<Limit>| limit.map(|limit| Limiter::new(key, limit)).transpose();
+        let default = match env::var("WETY_RATE_LIMIT") {
+            Result::Ok(limit) => parse_limit(&limit)?,
+            Err(_) => Some(DEFAULT_LIMIT),
+        };
+        let mut routes = HashMap::new();
+        if let Result::Ok(route_limits) = env::var("WETY_RATE_LIMIT_ROUTES") {
+            for route_limit in route_limits.split(',').filter(|r| !r.trim().is_empty()) {
+                let Some((route, limit)) = route_limit.rsplit_once('=') else {
+                    bail!(
+                        "expected a route's rate limit like \"/langs=2/8\", got \"{route_limit}\""
+                    );
+                };
+                routes.insert(route.trim().to_string(), limiter(parse_limit(limit)?)?);
+            }
+        }
+        Ok(Self {
+            default: limiter(default)?,
+            routes,
+        })
+    }
+
+    fn limiter(&self, path: &str) -> Option<Limiter> {
+        self.routes.get(path).copied().unwrap_or(self.default)
+    }
+
+    /// Limit the route at `path` by its own limit, if it has one, or else by
+    /// the limit of every route.
+    pub fn limit_route<S: Clone + Send + Sync + 'static>(
+        &self,
+        path: &str,
+        method_router: MethodRouter<S>,
+    ) -> MethodRouter<S> {
+        match self.limiter(path) {
+            None => method_router,
+            Some(Limiter::PeerIp(config)) => method_router.layer(governed!(config)),
+            Some(Limiter::Forwarded(config)) => method_router.layer(governed!(config)),
+            Some(Limiter::Global(config)) => method_router.layer(governed!(config)),
+        }
+    }
+
+    /// Limit all of the router's routes by the limit of every route.
+    pub fn limit_router(&self, router: Router) -> Router {
+        match self.default {
+            None => router,
+            Some(Limiter::PeerIp(config)) => router.layer(governed!(config)),
+            Some(Limiter::Forwarded(config)) => router.layer(governed!(config)),
+            Some(Limiter::Global(config)) => router.layer(governed!(config)),
+        }
+    }
+}
+
+/// Adding routes limited by [`RateLimits`].
+pub trait RateLimitedRoutes<S> {
+    #[must_use]
+    fn limited_route(self, path: &str, method_router: MethodRouter<S>, limits: &RateLimits)
+        -> Self;
+}
+
+impl<S: Clone + Send + Sync + 'static> RateLimitedRoutes<S> for Router<S> {
+    fn limited_route(
+        self,
+        path: &str,
+        method_router: MethodRouter<S>,
+        limits: &RateLimits,
+    ) -> Self {
+        self.route(path, limits.limit_route(path, method_router))
+    }
+}
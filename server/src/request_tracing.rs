@@ -0,0 +1,55 @@
+//! Request ids and the tracing of requests by them. Each request gets an id in
+//! `X-Request-Id`, unless a proxy in front of the server already gave it one,
+//! which is returned in the response's `X-Request-Id` and recorded on the span
+//! of everything traced while handling it, so that e.g. a slow tree request
+//! can be picked out of the logs by the id a client reports.
+
+use std::time::Instant;
+
+use axum::{
+    extract::MatchedPath,
+    http::{HeaderName, Request},
+    middleware::Next,
+    response::Response,
+};
+use tracing::{info, info_span, Span};
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// The span that a request is traced in, with its id.
+pub fn request_span<B>(request: &Request<B>) -> Span {
+    let id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|id| id.to_str().ok())
+        .unwrap_or_default();
+    info_span!(
+        "request",
+        id,
+        method = %request.method(),
+        uri = %request.uri(),
+    )
+}
+
+/// Middleware that logs the route, path and query of each request, and how
+/// long it took to respond, within its span. For the search and tree routes,
+/// whose cost depends on their parameters.
+pub async fn log_query<B>(
+    matched_path: MatchedPath,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let started = Instant::now();
+    let path = request.uri().path().to_string();
+    let query = request.uri().query().unwrap_or_default().to_string();
+    let response = next.run(request).await;
+    info!(
+        route = matched_path.as_str(),
+        path,
+        query,
+        status = response.status().as_u16(),
+        secs = started.elapsed().as_secs_f64(),
+        "query"
+    );
+    response
+}
@@ -0,0 +1,118 @@
+use crate::internal_error;
+
+use axum::{
+    body::{boxed, Full, HttpBody},
+    http::{header, HeaderMap, HeaderName, HeaderValue, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use serde_json::{json, Value};
+
+/// The versions of the json response schema that the server can produce,
+/// requested with e.g. `Accept: application/vnd.wety.v2+json`:
+///
+/// 1. responses as built by processor, which have no `schemaVersion` field;
+///    like every version, it is given in the `wety-schema-version` header
+/// 2. responses wrapped as `{"schemaVersion": 2, "data": ...}`, or for errors,
+///    with `"schemaVersion": 2` added to the error object
+///
+/// When the shape of a response changes, add a version, and keep producing the
+/// old shape for clients that ask for an older version.
+pub const SCHEMA_VERSIONS: [u32; 2] = [1, 2];
+
+// For requests that don't ask for a version. This is the first version, so as
+// not to break clients written before versions existed.
+pub(crate) const DEFAULT_SCHEMA_VERSION: u32 = 1;
+
+pub const SCHEMA_VERSION_HEADER: HeaderName = HeaderName::from_static("wety-schema-version");
+
+// e.g. "application/vnd.wety.v2+json; q=0.9" -> 2
+fn media_range_version(media_range: &str) -> Option<u32> {
+    let media_type = media_range.split(';').next()?.trim();
+    media_type
+        .strip_prefix("application/vnd.wety.v")?
+        .strip_suffix("+json")?
+        .parse()
+        .ok()
+}
+
+/// The latest supported schema version asked for in the `Accept` headers, or
+/// the default if none is asked for. Returns `None` if only unsupported
+/// versions are asked for.
+#[must_use]
+pub fn requested_schema_version(headers: &HeaderMap) -> Option<u32> {
+    let requested = headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|accept| accept.to_str().ok())
+        .flat_map(|accept| accept.split(','))
+        .filter_map(media_range_version)
+        .collect::<Vec<_>>();
+    if requested.is_empty() {
+        return Some(DEFAULT_SCHEMA_VERSION);
+    }
+    requested
+        .into_iter()
+        .filter(|version| SCHEMA_VERSIONS.contains(version))
+        .max()
+}
+
+// Rewrite a json response body into the shape of the given schema version.
+async fn convert(response: Response, version: u32) -> Response {
+    if version == 1 {
+        return response;
+    }
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|content_type| content_type == "application/json");
+    if !is_json {
+        return response;
+    }
+    let (mut parts, mut body) = response.into_parts();
+    let mut bytes = vec![];
+    while let Some(chunk) = body.data().await {
+        let Ok(chunk) = chunk else {
+            return internal_error().into_response();
+        };
+        bytes.extend_from_slice(&chunk);
+    }
+    let bytes = if parts.status.is_success() {
+        // Splice rather than reparse, since trees can be large.
+        let mut wrapped = format!("{{\"schemaVersion\":{version},\"data\":").into_bytes();
+        wrapped.extend_from_slice(&bytes);
+        wrapped.push(b'}');
+        wrapped
+    } else {
+        match serde_json::from_slice::<Value>(&bytes) {
+            Ok(Value::Object(mut error)) => {
+                error.insert("schemaVersion".to_string(), json!(version));
+                Value::Object(error).to_string().into_bytes()
+            }
+            _ => bytes,
+        }
+    };
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, boxed(Full::from(bytes)))
+}
+
+/// Middleware that produces json responses in the schema version requested by
+/// the client, answering with a 406 if it is not supported.
+pub async fn negotiate_schema_version<B>(request: Request<B>, next: Next<B>) -> Response {
+    let Some(version) = requested_schema_version(request.headers()) else {
+        let supported = SCHEMA_VERSIONS.map(|v| v.to_string()).join(", ");
+        return (
+            StatusCode::NOT_ACCEPTABLE,
+            Json(json!({
+                "error": "schemaVersion",
+                "message": format!("supported schema versions are {supported}"),
+            })),
+        )
+            .into_response();
+    };
+    let mut response = convert(next.run(request).await, version).await;
+    let headers = response.headers_mut();
+    headers.insert(SCHEMA_VERSION_HEADER, HeaderValue::from(version));
+    headers.append(header::VARY, HeaderValue::from_static("accept"));
+    response
+}
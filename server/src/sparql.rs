@@ -0,0 +1,189 @@
+use crate::{internal_error, timeout_error};
+
+use std::{
+    fs::File,
+    io::BufReader,
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::Json,
+    routing::get,
+    Router,
+};
+use oxigraph::{
+    io::GraphFormat,
+    model::{GraphNameRef, Term},
+    sparql::QueryResults,
+    store::Store,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::info;
+
+/// Stop returning results after this many rows (or triples).
+pub const MAX_ROWS: usize = 10_000;
+
+pub struct SparqlState {
+    pub store: Store,
+    // Queries that take longer than this are abandoned with a 503.
+    pub query_timeout: Duration,
+}
+
+/// Open the oxigraph store at `store_path`, loading the Turtle file written by
+/// `processor` into it if the store is empty.
+///
+/// # Errors
+///
+/// Will return `Err` if the store cannot be opened or the Turtle file cannot be
+/// loaded.
+pub fn build_store(store_path: &Path, turtle_path: &Path) -> Result<Store> {
+    let store = Store::open(store_path)?;
+    if store.is_empty()? {
+        let t = Instant::now();
+        info!(
+            "Loading {} into SPARQL store {}...",
+            turtle_path.display(),
+            store_path.display()
+        );
+        let reader = BufReader::new(File::open(turtle_path)?);
+        store.bulk_loader().load_graph(
+            reader,
+            GraphFormat::Turtle,
+            GraphNameRef::DefaultGraph,
+            None,
+        )?;
+        info!(
+            "Finished loading the SPARQL store. Took {:#?}.",
+            t.elapsed()
+        );
+    }
+    Ok(store)
+}
+
+pub fn router(state: SparqlState) -> Router {
+    Router::new()
+        .route("/sparql", get(sparql))
+        .with_state(Arc::new(state))
+}
+
+#[derive(Deserialize)]
+pub struct SparqlQuery {
+    query: String,
+}
+
+enum SparqlError {
+    Query(String),
+    Timeout,
+}
+
+// cf. https://www.w3.org/TR/sparql11-results-json/
+fn term_json(term: &Term) -> Value {
+    match term {
+        Term::NamedNode(node) => json!({"type": "uri", "value": node.as_str()}),
+        Term::BlankNode(node) => json!({"type": "bnode", "value": node.as_str()}),
+        Term::Literal(literal) => match literal.language() {
+            Some(lang) => json!({"type": "literal", "value": literal.value(), "xml:lang": lang}),
+            None => json!({
+                "type": "literal",
+                "value": literal.value(),
+                "datatype": literal.datatype().as_str(),
+            }),
+        },
+        Term::Triple(_) => json!({"type": "triple", "value": term.to_string()}),
+    }
+}
+
+// Run the query, collecting at most MAX_ROWS results and checking the deadline
+// between rows. Note that oxigraph cannot interrupt a query that does a lot of
+// work before yielding its first row (e.g. a large aggregation), so in such
+// cases the deadline is only checked once that row arrives.
+fn run_query(store: &Store, query: &str, deadline: Instant) -> Result<Value, SparqlError> {
+    let check_deadline = || {
+        if Instant::now() >= deadline {
+            return Err(SparqlError::Timeout);
+        }
+        Ok(())
+    };
+    let results = store
+        .query(query)
+        .map_err(|e| SparqlError::Query(e.to_string()))?;
+    check_deadline()?;
+    Ok(match results {
+        QueryResults::Boolean(boolean) => json!({"head": {}, "boolean": boolean}),
+        QueryResults::Solutions(solutions) => {
+            let vars = solutions
+                .variables()
+                .iter()
+                .map(|v| v.as_str().to_string())
+                .collect::<Vec<_>>();
+            let mut bindings = vec![];
+            let mut truncated = false;
+            for solution in solutions {
+                check_deadline()?;
+                if bindings.len() >= MAX_ROWS {
+                    truncated = true;
+                    break;
+                }
+                let solution = solution.map_err(|e| SparqlError::Query(e.to_string()))?;
+                let binding = solution
+                    .iter()
+                    .map(|(var, term)| (var.as_str().to_string(), term_json(term)))
+                    .collect::<serde_json::Map<_, _>>();
+                bindings.push(Value::Object(binding));
+            }
+            json!({
+                "head": {"vars": vars},
+                "results": {"bindings": bindings},
+                "truncated": truncated,
+            })
+        }
+        QueryResults::Graph(triples) => {
+            let mut out = vec![];
+            let mut truncated = false;
+            for triple in triples {
+                check_deadline()?;
+                if out.len() >= MAX_ROWS {
+                    truncated = true;
+                    break;
+                }
+                let triple = triple.map_err(|e| SparqlError::Query(e.to_string()))?;
+                out.push(json!({
+                    "subject": term_json(&triple.subject.into()),
+                    "predicate": term_json(&triple.predicate.into()),
+                    "object": term_json(&triple.object),
+                }));
+            }
+            json!({"triples": out, "truncated": truncated})
+        }
+    })
+}
+
+/// # Errors
+///
+/// Will return `Err` with a 400 if the query is invalid, or a 503 if it runs
+/// past the time limit.
+pub async fn sparql(
+    State(state): State<Arc<SparqlState>>,
+    Query(sparql_query): Query<SparqlQuery>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let deadline = Instant::now() + state.query_timeout;
+    let query_timeout = state.query_timeout;
+    let result =
+        tokio::task::spawn_blocking(move || run_query(&state.store, &sparql_query.query, deadline))
+            .await;
+    match result {
+        Ok(Ok(results)) => Ok(Json(results)),
+        Ok(Err(SparqlError::Query(message))) => Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "query", "message": message})),
+        )),
+        Ok(Err(SparqlError::Timeout)) => Err(timeout_error(query_timeout)),
+        Err(_) => Err(internal_error()),
+    }
+}